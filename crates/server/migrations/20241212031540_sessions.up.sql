@@ -0,0 +1,88 @@
+CREATE TABLE sessions (
+    submitter_number TEXT PRIMARY KEY NOT NULL,
+    state TEXT NOT NULL CHECK (
+        state IN ('deletion', 'deferred_contacts', 'group')
+    ),
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    expires_at INTEGER NOT NULL,
+    FOREIGN KEY(submitter_number) REFERENCES users(number) ON DELETE CASCADE
+);
+INSERT INTO sessions (submitter_number, state, created_at, expires_at)
+SELECT submitter_number,
+    action_type,
+    created_at,
+    created_at + 300
+FROM pending_actions;
+CREATE TABLE new_pending_deletions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_submitter TEXT NOT NULL,
+    contact_id INTEGER,
+    group_id INTEGER,
+    FOREIGN KEY(session_submitter) REFERENCES sessions(submitter_number) ON DELETE CASCADE,
+    FOREIGN KEY(contact_id) REFERENCES contacts(id) ON DELETE CASCADE,
+    FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE,
+    CHECK (
+        (
+            contact_id IS NULL
+            AND group_id IS NOT NULL
+        )
+        OR (
+            contact_id IS NOT NULL
+            AND group_id IS NULL
+        )
+    )
+);
+INSERT INTO new_pending_deletions (id, session_submitter, contact_id, group_id)
+SELECT id,
+    pending_action_submitter,
+    contact_id,
+    group_id
+FROM pending_deletions;
+CREATE TABLE new_pending_group_members (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_submitter TEXT NOT NULL,
+    contact_id INTEGER NOT NULL,
+    FOREIGN KEY(session_submitter) REFERENCES sessions(submitter_number) ON DELETE CASCADE,
+    FOREIGN KEY(contact_id) REFERENCES contacts(id) ON DELETE CASCADE
+);
+INSERT INTO new_pending_group_members (id, session_submitter, contact_id)
+SELECT id,
+    pending_action_submitter,
+    contact_id
+FROM pending_group_members;
+-- Deferred contacts only make sense while their import session is alive
+CREATE TABLE new_deferred_contacts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    submitter_number TEXT NOT NULL,
+    contact_name TEXT NOT NULL,
+    phone_number TEXT NOT NULL,
+    phone_description TEXT,
+    FOREIGN KEY(submitter_number) REFERENCES sessions(submitter_number) ON DELETE CASCADE
+);
+INSERT INTO new_deferred_contacts (
+        id,
+        submitter_number,
+        contact_name,
+        phone_number,
+        phone_description
+    )
+SELECT d.id,
+    d.submitter_number,
+    d.contact_name,
+    d.phone_number,
+    d.phone_description
+FROM deferred_contacts d
+    JOIN sessions s ON s.submitter_number = d.submitter_number
+WHERE s.state = 'deferred_contacts';
+DROP TABLE pending_deletions;
+DROP TABLE pending_group_members;
+DROP TABLE deferred_contacts;
+DROP TABLE pending_actions;
+ALTER TABLE new_pending_deletions
+    RENAME TO pending_deletions;
+ALTER TABLE new_pending_group_members
+    RENAME TO pending_group_members;
+ALTER TABLE new_deferred_contacts
+    RENAME TO deferred_contacts;
+CREATE INDEX idx_pending_deletions_session ON pending_deletions(session_submitter);
+CREATE INDEX idx_pending_group_members_session ON pending_group_members(session_submitter);
@@ -0,0 +1,6 @@
+CREATE TABLE rate_limits (
+    number TEXT PRIMARY KEY NOT NULL,
+    tokens REAL NOT NULL,
+    updated_at REAL NOT NULL,
+    notified BOOLEAN NOT NULL DEFAULT FALSE
+);
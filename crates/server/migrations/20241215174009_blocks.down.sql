@@ -0,0 +1,2 @@
+DROP TABLE blocklist;
+DROP TABLE blocks;
@@ -0,0 +1,14 @@
+-- Senders a user has chosen not to hear from
+CREATE TABLE blocks (
+    blocker_number TEXT NOT NULL,
+    blocked_number TEXT NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    PRIMARY KEY (blocker_number, blocked_number),
+    FOREIGN KEY(blocker_number) REFERENCES users(number) ON DELETE CASCADE
+);
+-- Numbers the operator has cut off entirely; messages from them are dropped at the webhook
+CREATE TABLE blocklist (
+    number TEXT PRIMARY KEY NOT NULL,
+    reason TEXT,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch())
+);
@@ -0,0 +1,8 @@
+CREATE TABLE exports (
+    token TEXT PRIMARY KEY NOT NULL,
+    number TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    expires_at INTEGER NOT NULL,
+    FOREIGN KEY(number) REFERENCES users(number) ON DELETE CASCADE
+);
@@ -0,0 +1,16 @@
+-- Append-only record of inbound messages, parsed commands and the changes they made.
+-- Not tied to users, so entries outlive the accounts they describe.
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    number TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('message', 'command', 'mutation')),
+    detail TEXT NOT NULL
+);
+CREATE INDEX idx_audit_log_number ON audit_log(number, created_at);
+CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log BEGIN
+SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
+CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log BEGIN
+SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
@@ -0,0 +1,15 @@
+CREATE TABLE jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- JSON-serialized job, tagged with its type
+    payload TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'done', 'failed')),
+    run_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    -- Set while a worker is running the job; a job whose lease lapses is picked up again
+    leased_until INTEGER,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 5,
+    last_error TEXT,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    finished_at INTEGER
+);
+CREATE INDEX idx_jobs_due ON jobs(status, run_at);
@@ -0,0 +1,8 @@
+-- Inbound messages by Twilio MessageSid, so a webhook Twilio retries isn't handled twice
+CREATE TABLE processed_messages (
+    sid TEXT PRIMARY KEY NOT NULL,
+    -- What we replied, sealed. NULL while the message is being handled, or if there was no reply.
+    response TEXT,
+    received_at INTEGER NOT NULL DEFAULT (unixepoch())
+);
+CREATE INDEX idx_processed_messages_received ON processed_messages(received_at);
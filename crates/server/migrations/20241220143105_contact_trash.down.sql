@@ -0,0 +1,3 @@
+DELETE FROM contacts WHERE deleted_at IS NOT NULL;
+DROP INDEX idx_contacts_deleted_at;
+ALTER TABLE contacts DROP COLUMN deleted_at;
@@ -0,0 +1,3 @@
+-- Deleted contacts stay in the trash for a while before they're purged
+ALTER TABLE contacts ADD COLUMN deleted_at INTEGER;
+CREATE INDEX idx_contacts_deleted_at ON contacts(deleted_at);
@@ -0,0 +1,9 @@
+DROP INDEX idx_contacts_recent;
+DROP TRIGGER contacts_updated;
+DROP TRIGGER contacts_created;
+DROP TRIGGER users_updated;
+DROP TRIGGER users_created;
+ALTER TABLE contacts DROP COLUMN updated_at;
+ALTER TABLE contacts DROP COLUMN created_at;
+ALTER TABLE users DROP COLUMN updated_at;
+ALTER TABLE users DROP COLUMN created_at;
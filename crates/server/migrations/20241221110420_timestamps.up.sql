@@ -0,0 +1,29 @@
+ALTER TABLE users ADD COLUMN created_at INTEGER;
+ALTER TABLE users ADD COLUMN updated_at INTEGER;
+ALTER TABLE contacts ADD COLUMN created_at INTEGER;
+ALTER TABLE contacts ADD COLUMN updated_at INTEGER;
+
+-- Rows from before timestamps were kept get the time of this migration
+UPDATE users SET created_at = unixepoch(), updated_at = unixepoch();
+UPDATE contacts SET created_at = unixepoch(), updated_at = unixepoch();
+
+-- Added columns can't default to unixepoch(), so triggers fill them in.
+-- The update triggers skip statements that set updated_at themselves, which includes their own.
+CREATE TRIGGER users_created AFTER INSERT ON users WHEN NEW.created_at IS NULL
+BEGIN
+    UPDATE users SET created_at = unixepoch(), updated_at = unixepoch() WHERE number = NEW.number;
+END;
+CREATE TRIGGER users_updated AFTER UPDATE ON users WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE users SET updated_at = unixepoch() WHERE number = NEW.number;
+END;
+CREATE TRIGGER contacts_created AFTER INSERT ON contacts WHEN NEW.created_at IS NULL
+BEGIN
+    UPDATE contacts SET created_at = unixepoch(), updated_at = unixepoch() WHERE id = NEW.id;
+END;
+CREATE TRIGGER contacts_updated AFTER UPDATE ON contacts WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE contacts SET updated_at = unixepoch() WHERE id = NEW.id;
+END;
+
+CREATE INDEX idx_contacts_recent ON contacts(submitter_number, updated_at);
@@ -0,0 +1,19 @@
+-- Messages scheduled with `remind`, sent by the job runner once send_at arrives
+CREATE TABLE scheduled_messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    creator_number TEXT NOT NULL,
+    -- Exactly one of these says who it goes to
+    group_id INTEGER,
+    recipient_number TEXT,
+    -- Who the creator said it was for, for listing
+    target TEXT NOT NULL,
+    body TEXT NOT NULL,
+    send_at INTEGER NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'sent', 'cancelled')),
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    FOREIGN KEY(creator_number) REFERENCES users(number) ON DELETE CASCADE,
+    FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE,
+    FOREIGN KEY(recipient_number) REFERENCES users(number) ON DELETE CASCADE,
+    CHECK ((group_id IS NULL) != (recipient_number IS NULL))
+);
+CREATE INDEX idx_scheduled_messages_creator ON scheduled_messages(creator_number, status, send_at);
@@ -0,0 +1,2 @@
+-- IANA name of the user's time zone; NULL means UTC
+ALTER TABLE users ADD COLUMN timezone TEXT;
@@ -0,0 +1,10 @@
+DROP TABLE sessions;
+CREATE TABLE sessions (
+    submitter_number TEXT PRIMARY KEY NOT NULL,
+    state TEXT NOT NULL CHECK (
+        state IN ('deletion', 'deferred_contacts', 'group')
+    ),
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    expires_at INTEGER NOT NULL,
+    FOREIGN KEY(submitter_number) REFERENCES users(number) ON DELETE CASCADE
+);
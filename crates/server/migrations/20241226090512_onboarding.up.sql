@@ -0,0 +1,15 @@
+-- Sign-up is a session too, but it happens before there's a users row, so sessions no longer
+-- reference users; deleting an account ends its session explicitly instead.
+-- Changing the constraints means rebuilding the table. Sessions only last minutes, so any in
+-- progress are dropped (along with their pending rows, by the cascade) rather than copied.
+DROP TABLE sessions;
+CREATE TABLE sessions (
+    submitter_number TEXT PRIMARY KEY NOT NULL,
+    state TEXT NOT NULL CHECK (
+        state IN ('deletion', 'deferred_contacts', 'group', 'onboarding')
+    ),
+    -- The name a new user is being asked to confirm
+    pending_name TEXT,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    expires_at INTEGER NOT NULL
+);
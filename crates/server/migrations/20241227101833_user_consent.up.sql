@@ -0,0 +1,8 @@
+-- Whether someone has agreed to get messages other people send through the bot.
+-- NULL means they haven't been asked yet, which is where people added from a contact card start.
+ALTER TABLE users ADD COLUMN consent TEXT CHECK (
+    consent IN ('requested', 'granted', 'declined')
+);
+-- Existing users who have texted the bot themselves keep getting messages as before
+UPDATE users SET consent = 'granted'
+WHERE number IN (SELECT number FROM audit_log WHERE kind = 'message');
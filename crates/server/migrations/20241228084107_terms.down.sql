@@ -0,0 +1,2 @@
+ALTER TABLE users DROP COLUMN terms_accepted_at;
+ALTER TABLE users DROP COLUMN terms_version;
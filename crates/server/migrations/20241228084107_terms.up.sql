@@ -0,0 +1,3 @@
+-- Which version of the terms of service each user accepted, and when
+ALTER TABLE users ADD COLUMN terms_version TEXT;
+ALTER TABLE users ADD COLUMN terms_accepted_at INTEGER;
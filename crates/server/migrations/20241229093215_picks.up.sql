@@ -0,0 +1,11 @@
+-- Random choices made with `decide`, kept so a bare `decide` can show the last few
+CREATE TABLE picks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    creator_number TEXT NOT NULL,
+    -- What it was picked from, as the creator listed it
+    options TEXT NOT NULL,
+    choice TEXT NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    FOREIGN KEY(creator_number) REFERENCES users(number) ON DELETE CASCADE
+);
+CREATE INDEX idx_picks_creator ON picks(creator_number, created_at);
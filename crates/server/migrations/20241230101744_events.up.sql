@@ -0,0 +1,26 @@
+-- Events announced to a group with `event`
+CREATE TABLE events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    organizer_number TEXT NOT NULL,
+    -- Cleared if the group is deleted; the name is kept for listing
+    group_id INTEGER,
+    group_name TEXT NOT NULL,
+    title TEXT NOT NULL,
+    starts_at INTEGER NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    FOREIGN KEY(organizer_number) REFERENCES users(number) ON DELETE CASCADE,
+    FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE SET NULL
+);
+CREATE INDEX idx_events_organizer ON events(organizer_number, starts_at);
+
+-- Everyone an event's invitation went to, and their answer once they give one
+CREATE TABLE rsvps (
+    event_id INTEGER NOT NULL,
+    number TEXT NOT NULL,
+    response TEXT CHECK (response IN ('yes', 'no', 'maybe')),
+    updated_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    PRIMARY KEY(event_id, number),
+    FOREIGN KEY(event_id) REFERENCES events(id) ON DELETE CASCADE,
+    FOREIGN KEY(number) REFERENCES users(number) ON DELETE CASCADE
+);
+CREATE INDEX idx_rsvps_number ON rsvps(number);
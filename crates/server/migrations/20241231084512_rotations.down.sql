@@ -0,0 +1,2 @@
+DROP TABLE rotation_members;
+DROP TABLE rotations;
@@ -0,0 +1,25 @@
+-- Turn-taking tracked with `rotation`, e.g. whose turn it is to do the dishes
+CREATE TABLE rotations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    creator_number TEXT NOT NULL,
+    name TEXT NOT NULL,
+    -- How many times it's moved on; the current member is at position turn % members
+    turn INTEGER NOT NULL DEFAULT 0,
+    -- Moves on by itself this often, if set
+    interval_days INTEGER,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    FOREIGN KEY(creator_number) REFERENCES users(number) ON DELETE CASCADE,
+    UNIQUE(name, creator_number)
+);
+
+-- Everyone taking turns, in order. Members who are users (the creator or one of their
+-- contacts) have a number and are told when it's their turn; others are just names.
+CREATE TABLE rotation_members (
+    rotation_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    number TEXT,
+    PRIMARY KEY(rotation_id, position),
+    FOREIGN KEY(rotation_id) REFERENCES rotations(id) ON DELETE CASCADE,
+    FOREIGN KEY(number) REFERENCES users(number) ON DELETE SET NULL
+);
@@ -0,0 +1,12 @@
+-- Tokens for the operator's HTTP endpoints, issued with `decisionbot-admin token`.
+-- Only a hash is kept, so a leaked database doesn't leak working tokens.
+CREATE TABLE api_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- What it's for, e.g. "dashboard", so it can be told apart and revoked
+    name TEXT NOT NULL UNIQUE,
+    token_hash TEXT NOT NULL UNIQUE,
+    scope TEXT NOT NULL CHECK (scope IN ('read', 'admin')),
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    last_used_at INTEGER,
+    revoked_at INTEGER
+);
@@ -0,0 +1,9 @@
+-- Every text in and out, without who or what, for the operator's `stats`
+CREATE TABLE message_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    direction TEXT NOT NULL CHECK (direction IN ('in', 'out')),
+    -- Outbound texts are failed if Twilio wouldn't take them; replies count as sent
+    status TEXT NOT NULL CHECK (status IN ('received', 'sent', 'failed')),
+    created_at INTEGER NOT NULL DEFAULT (unixepoch())
+);
+CREATE INDEX idx_message_log_created ON message_log(created_at);
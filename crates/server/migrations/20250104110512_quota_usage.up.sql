@@ -0,0 +1,10 @@
+-- When each user last did something that's limited per hour or day, e.g. importing contacts.
+-- Only the last day's worth is kept.
+CREATE TABLE quota_usage (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    number TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('import', 'scheduled')),
+    used_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    FOREIGN KEY(number) REFERENCES users(number) ON DELETE CASCADE
+);
+CREATE INDEX idx_quota_usage_number ON quota_usage(number, kind, used_at);
@@ -0,0 +1,3 @@
+DROP TABLE budget_alerts;
+ALTER TABLE message_log DROP COLUMN price;
+ALTER TABLE message_log DROP COLUMN segments;
@@ -0,0 +1,9 @@
+-- Estimated cost of each outbound text, from its segments and SEGMENT_PRICE at the time
+ALTER TABLE message_log ADD COLUMN segments INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE message_log ADD COLUMN price REAL NOT NULL DEFAULT 0;
+
+-- Months the operator has been told the spend passed MONTHLY_BUDGET, so it's only once each
+CREATE TABLE budget_alerts (
+    month TEXT PRIMARY KEY,
+    sent_at INTEGER NOT NULL DEFAULT (unixepoch())
+);
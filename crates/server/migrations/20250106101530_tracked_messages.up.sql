@@ -0,0 +1,10 @@
+-- Texts sent to someone on a user's behalf, e.g. a reminder to their group, so the user can be
+-- told if Twilio later reports one undeliverable. Kept until then, or for a few days.
+CREATE TABLE tracked_messages (
+    sid TEXT PRIMARY KEY,
+    creator_number TEXT NOT NULL,
+    recipient_number TEXT NOT NULL,
+    sent_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    FOREIGN KEY(creator_number) REFERENCES users(number) ON DELETE CASCADE
+);
+CREATE INDEX idx_tracked_messages_sent ON tracked_messages(sent_at);
@@ -0,0 +1,3 @@
+-- What kind of line each number is, from Twilio Lookup: mobile, landline, voip or other.
+-- NULL until it's been looked up, which only happens with LINE_TYPE_LOOKUP set.
+ALTER TABLE users ADD COLUMN line_type TEXT CHECK (line_type IN ('mobile', 'landline', 'voip', 'other'));
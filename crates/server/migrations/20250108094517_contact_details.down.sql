@@ -0,0 +1,4 @@
+ALTER TABLE deferred_contacts DROP COLUMN title;
+ALTER TABLE deferred_contacts DROP COLUMN organization;
+ALTER TABLE contacts DROP COLUMN title;
+ALTER TABLE contacts DROP COLUMN organization;
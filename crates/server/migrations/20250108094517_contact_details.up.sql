@@ -0,0 +1,6 @@
+-- Where a contact works and what they do there, from their card's ORG and TITLE, to tell
+-- people with the same name apart. Kept on deferred contacts until a number is picked.
+ALTER TABLE contacts ADD COLUMN organization TEXT;
+ALTER TABLE contacts ADD COLUMN title TEXT;
+ALTER TABLE deferred_contacts ADD COLUMN organization TEXT;
+ALTER TABLE deferred_contacts ADD COLUMN title TEXT;
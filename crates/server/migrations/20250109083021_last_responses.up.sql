@@ -0,0 +1,7 @@
+-- The bot's most recent reply to each user, for `again` to send once more
+CREATE TABLE last_responses (
+    number TEXT PRIMARY KEY,
+    response TEXT NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    FOREIGN KEY(number) REFERENCES users(number) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+DROP TABLE later_deliveries;
+DROP TABLE later_messages;
@@ -0,0 +1,26 @@
+-- Messages scheduled with `sendlater`, for a time in each recipient's own time zone
+CREATE TABLE later_messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    creator_number TEXT NOT NULL,
+    -- Who the creator said it was for, for listing
+    target TEXT NOT NULL,
+    body TEXT NOT NULL,
+    -- The time as the creator wrote it, e.g. "8am"
+    time TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'sent', 'cancelled')),
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    FOREIGN KEY(creator_number) REFERENCES users(number) ON DELETE CASCADE
+);
+CREATE INDEX idx_later_messages_creator ON later_messages(creator_number, status);
+
+-- One for each recipient of a later message, sent by the job runner once send_at arrives
+CREATE TABLE later_deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL,
+    recipient_number TEXT NOT NULL,
+    send_at INTEGER NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'sent', 'cancelled')),
+    FOREIGN KEY(message_id) REFERENCES later_messages(id) ON DELETE CASCADE,
+    FOREIGN KEY(recipient_number) REFERENCES users(number) ON DELETE CASCADE
+);
+CREATE INDEX idx_later_deliveries_message ON later_deliveries(message_id, status);
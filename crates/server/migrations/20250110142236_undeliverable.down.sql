@@ -0,0 +1,2 @@
+ALTER TABLE users DROP COLUMN undeliverable_at;
+ALTER TABLE users DROP COLUMN delivery_failures;
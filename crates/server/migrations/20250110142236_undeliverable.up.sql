@@ -0,0 +1,4 @@
+-- Texts to each number that Twilio reported permanently undeliverable in a row, and when the
+-- bot stopped sending to it. Cleared once a text gets through or the number texts the bot.
+ALTER TABLE users ADD COLUMN delivery_failures INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN undeliverable_at INTEGER;
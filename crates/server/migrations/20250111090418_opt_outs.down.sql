@@ -0,0 +1 @@
+DROP TABLE opt_outs;
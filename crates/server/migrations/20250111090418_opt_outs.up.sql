@@ -0,0 +1,6 @@
+-- Numbers that opted out of texts from the bot's numbers with STOP, as Twilio sees it. Kept
+-- apart from users, since opting out can delete the account but must outlast it.
+CREATE TABLE opt_outs (
+    number TEXT PRIMARY KEY,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch())
+);
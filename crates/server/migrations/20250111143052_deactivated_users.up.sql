@@ -0,0 +1,3 @@
+-- When a user texted `stop`. Their account and contacts are kept, unused, until they text
+-- `start` or delete it all with `stop purge`.
+ALTER TABLE users ADD COLUMN deactivated_at INTEGER;
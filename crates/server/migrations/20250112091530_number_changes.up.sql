@@ -0,0 +1,11 @@
+-- Moves to a new number started with `movenumber`, waiting on the code texted there
+CREATE TABLE number_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    number TEXT NOT NULL UNIQUE,
+    new_number TEXT NOT NULL,
+    code TEXT NOT NULL,
+    -- Wrong codes tried so far
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at INTEGER NOT NULL,
+    FOREIGN KEY(number) REFERENCES users(number) ON DELETE CASCADE
+);
@@ -0,0 +1,10 @@
+DROP TABLE verification_codes;
+CREATE TABLE number_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    number TEXT NOT NULL UNIQUE,
+    new_number TEXT NOT NULL,
+    code TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at INTEGER NOT NULL,
+    FOREIGN KEY(number) REFERENCES users(number) ON DELETE CASCADE
+);
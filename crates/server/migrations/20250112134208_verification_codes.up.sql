@@ -0,0 +1,19 @@
+-- One-time codes texted to prove someone can read texts at a number. Moves to a new number
+-- waiting on a code start over, since codes are no longer stored as they were sent.
+DROP TABLE number_changes;
+CREATE TABLE verification_codes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- Whose code it is
+    number TEXT NOT NULL,
+    purpose TEXT NOT NULL CHECK (purpose IN ('move_number')),
+    -- Where the code is texted
+    send_to TEXT NOT NULL,
+    salt TEXT NOT NULL,
+    -- Set once the code's been made up and sent
+    code_hash TEXT,
+    -- Wrong codes tried so far
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at INTEGER NOT NULL,
+    UNIQUE(number, purpose),
+    FOREIGN KEY(number) REFERENCES users(number) ON DELETE CASCADE
+);
@@ -0,0 +1,14 @@
+-- Optional PINs guarding destructive commands
+CREATE TABLE pins (
+    number TEXT PRIMARY KEY NOT NULL,
+    salt TEXT NOT NULL,
+    pin_hash TEXT NOT NULL,
+    -- Wrong PINs in a row
+    failures INTEGER NOT NULL DEFAULT 0,
+    -- Set after too many wrong PINs; nothing the PIN guards works until then
+    locked_until INTEGER,
+    -- The command waiting on the PIN, as it was sent
+    pending TEXT,
+    pending_at INTEGER,
+    FOREIGN KEY(number) REFERENCES users(number) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+-- Lets deleting an account take its audit log entries with it. The log stays append-only
+-- otherwise: deletes only go through for a number listed here, which audit::erase does for
+-- the length of the deleting transaction.
+CREATE TABLE audit_erasures (
+    number TEXT PRIMARY KEY NOT NULL
+);
+DROP TRIGGER audit_log_no_delete;
+CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log
+WHEN OLD.number NOT IN (SELECT number FROM audit_erasures) BEGIN
+SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
@@ -0,0 +1,205 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use chrono::Utc;
+use chrono_tz::Tz;
+use sqlx::{query, Pool, Sqlite};
+
+use crate::{
+    audit::{self, Kind},
+    consent::{self, Consent},
+    i18n::{t, Lang},
+    pii, session, timezone,
+    util::E164,
+};
+
+/// Pauses an account with `stop`, keeping everything in it for if they come back.
+/// Ends their session, since nothing they started can finish while they're away.
+pub async fn deactivate(pool: &Pool<Sqlite>, number: &str) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    query!(
+        "UPDATE users SET deactivated_at = unixepoch()
+         WHERE number = ? AND deactivated_at IS NULL",
+        number
+    )
+    .execute(&mut *tx)
+    .await?;
+    session::end(&mut *tx, number).await?;
+    audit::record(&mut *tx, number, Kind::Mutation, "deactivated account").await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Whether `number` paused their account with `stop`
+pub async fn is_deactivated(pool: &Pool<Sqlite>, number: &str) -> Result<bool> {
+    Ok(query!(
+        "SELECT 1 as found FROM users WHERE number = ? AND deactivated_at IS NOT NULL",
+        number
+    )
+    .fetch_optional(pool)
+    .await?
+    .is_some())
+}
+
+/// What a returning user still has, for welcoming them back
+#[derive(Debug, PartialEq, Eq)]
+pub struct Kept {
+    pub contacts: u64,
+    pub groups: u64,
+}
+
+/// Picks a paused account back up with `start`. None if it wasn't paused.
+pub async fn reactivate(pool: &Pool<Sqlite>, number: &str) -> Result<Option<Kept>> {
+    let mut tx = pool.begin().await?;
+    let reactivated = query!(
+        "UPDATE users SET deactivated_at = NULL WHERE number = ? AND deactivated_at IS NOT NULL",
+        number
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if reactivated == 0 {
+        return Ok(None);
+    }
+    let kept = Kept {
+        contacts: query!(
+            "SELECT COUNT(*) as count FROM contacts
+             WHERE submitter_number = ? AND deleted_at IS NULL",
+            number
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .count as u64,
+        groups: query!(
+            "SELECT COUNT(*) as count FROM groups WHERE creator_number = ?",
+            number
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .count as u64,
+    };
+    audit::record(&mut *tx, number, Kind::Mutation, "reactivated account").await?;
+    tx.commit().await?;
+    Ok(Some(kept))
+}
+
+/// What [delete_account] removed, for reporting back to the user
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Removed {
+    /// Contacts the user had submitted
+    pub contacts: u64,
+    /// Groups the user had created
+    pub groups: u64,
+    /// Entries for the user in other people's contact lists
+    pub listings: u64,
+    /// Memberships in groups
+    pub memberships: u64,
+}
+
+/// Removes a user along with everything that refers to them, for `stop purge`.
+/// Rows the user owns (contacts, groups, exports, blocks) go by foreign key cascade; rows in
+/// other people's data that point at the user are deleted explicitly, since those references
+/// don't cascade, as is their session (which can exist before the user does).
+pub async fn delete_account(pool: &Pool<Sqlite>, number: &str) -> Result<Removed> {
+    let mut tx = pool.begin().await?;
+
+    // Counted up front, since the cascade doesn't report what it removed
+    let contacts = query!(
+        "SELECT COUNT(*) as count FROM contacts WHERE submitter_number = ?",
+        number
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .count as u64;
+    let groups = query!(
+        "SELECT COUNT(*) as count FROM groups WHERE creator_number = ?",
+        number
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .count as u64;
+
+    let listings = query!(
+        "DELETE FROM contacts WHERE contact_user_number = ? AND submitter_number != ?",
+        number,
+        number
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    let memberships = query!("DELETE FROM group_members WHERE member_number = ?", number)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    session::end(&mut *tx, number).await?;
+    query!("DELETE FROM users WHERE number = ?", number)
+        .execute(&mut *tx)
+        .await?;
+
+    let removed = Removed {
+        contacts,
+        groups,
+        listings,
+        memberships,
+    };
+    // Their history goes too, since it quotes their messages. What's left says an account
+    // went, under a stand-in that can't be traced back to the number.
+    audit::erase(&mut tx, number).await?;
+    audit::record(
+        &mut *tx,
+        &pii::fingerprint(&pii::open(number)?),
+        Kind::Mutation,
+        format!("deleted account: {removed:?}"),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(removed)
+}
+
+/// `me` shows what the bot has stored about the sender, so they can check it's right
+pub async fn handle_me(pool: &Pool<Sqlite>, from: &str, lang: Lang, zone: Tz) -> Result<String> {
+    let user = query!(
+        r#"SELECT name,
+            (SELECT COUNT(*) FROM contacts
+             WHERE submitter_number = ?1 AND deleted_at IS NULL) as "contacts!: i64",
+            (SELECT COUNT(*) FROM groups WHERE creator_number = ?1) as "groups!: i64",
+            (SELECT COUNT(*) FROM blocks WHERE blocker_number = ?1) as "blocked!: i64",
+            (SELECT COUNT(*) FROM scheduled_messages
+             WHERE creator_number = ?1 AND status = 'pending') as "reminders!: i64",
+            (SELECT COUNT(*) FROM later_messages
+             WHERE creator_number = ?1 AND status = 'pending') as "later!: i64",
+            (SELECT COUNT(*) FROM picks WHERE creator_number = ?1) as "picks!: i64",
+            (SELECT COUNT(*) FROM events
+             WHERE organizer_number = ?1 AND starts_at > unixepoch()) as "events!: i64",
+            (SELECT COUNT(*) FROM rotations WHERE creator_number = ?1) as "rotations!: i64"
+         FROM users WHERE number = ?1"#,
+        from
+    )
+    .fetch_one(pool)
+    .await?;
+    let number = pii::open(from)?;
+    let number = E164::from_str(&number).map_or(number, |e164| e164.display_for(Some(&e164)));
+    let consent = match consent::status(pool, from).await? {
+        Consent::Unknown => t!(lang, "me_consent_unknown"),
+        Consent::Requested => t!(lang, "me_consent_requested"),
+        Consent::Granted => t!(lang, "me_consent_granted"),
+        Consent::Declined => t!(lang, "me_consent_declined"),
+    };
+    Ok(t!(
+        lang,
+        "me",
+        name = pii::open(&user.name)?,
+        number = number,
+        language = lang.name(),
+        zone = zone.name(),
+        time = timezone::display(Utc::now(), zone),
+        consent = consent,
+        contacts = user.contacts,
+        groups = user.groups,
+        blocked = user.blocked,
+        scheduled = user.reminders + user.later,
+        picks = user.picks,
+        events = user.events,
+        rotations = user.rotations
+    ))
+}
@@ -0,0 +1,40 @@
+//! `again`: sends the bot's last reply once more, for when a long one arrived cut off or
+//! never arrived at all
+
+use anyhow::Result;
+use sqlx::{query, Pool, Sqlite};
+
+use crate::pii;
+
+/// How long a reply can still be asked for again
+pub const RETENTION_SECS: i64 = 24 * 60 * 60;
+
+/// Keeps `response` as the last thing said to `number` (sealed), if they're a user
+pub async fn remember(pool: &Pool<Sqlite>, number: &str, response: &str) -> Result<()> {
+    let response = pii::seal(response);
+    query!(
+        "INSERT INTO last_responses (number, response)
+         SELECT ?, ? WHERE EXISTS (SELECT 1 FROM users WHERE number = ?)
+         ON CONFLICT(number) DO UPDATE
+         SET response = excluded.response, created_at = excluded.created_at",
+        number,
+        response,
+        number
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// The last reply to `number` (sealed), unless it's too old
+pub async fn last(pool: &Pool<Sqlite>, number: &str) -> Result<Option<String>> {
+    query!(
+        "SELECT response FROM last_responses WHERE number = ? AND created_at > unixepoch() - ?",
+        number,
+        RETENTION_SECS
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| pii::open(&row.response))
+    .transpose()
+}
@@ -0,0 +1,136 @@
+//! Tokens for the operator's HTTP endpoints, so each dashboard or script gets its own access
+//! that can be limited and revoked without touching the others.
+//!
+//! `decisionbot-admin token issue NAME [read|admin]` prints a new token once; only its hash is
+//! stored. Requests send it as `Authorization: Bearer <token>`. `ADMIN_TOKEN`, if set, still
+//! works as a token with the admin scope. With several bot numbers, tokens live in the first
+//! one's database. Shared with `decisionbot-admin`, so nothing here can depend on the server.
+
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::{query, Pool, Sqlite};
+use tracing::*;
+
+use crate::config;
+
+/// What a token may do. Admin can do everything read can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Read,
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "read" => Ok(Scope::Read),
+            "admin" => Ok(Scope::Admin),
+            _ => bail!("Unknown scope \"{s}\" (expected read or admin)"),
+        }
+    }
+}
+
+fn hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Creates a token named `name`, returning it. It can't be recovered later.
+#[allow(dead_code)] // Only decisionbot-admin issues and revokes tokens
+pub async fn issue(pool: &Pool<Sqlite>, name: &str, scope: Scope) -> Result<String> {
+    // Makes tokens recognizable, e.g. to secret scanners
+    const PREFIX: &str = "dbt_";
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = format!("{PREFIX}{}", URL_SAFE_NO_PAD.encode(bytes));
+    let (token_hash, scope) = (hash(&token), scope.as_str());
+    query!(
+        "INSERT INTO api_tokens (name, token_hash, scope) VALUES (?, ?, ?)",
+        name,
+        token_hash,
+        scope
+    )
+    .execute(pool)
+    .await?;
+    Ok(token)
+}
+
+/// Stops a token from working. False if there's no such token, or it was already revoked.
+#[allow(dead_code)]
+pub async fn revoke(pool: &Pool<Sqlite>, name: &str) -> Result<bool> {
+    Ok(query!(
+        "UPDATE api_tokens SET revoked_at = unixepoch() WHERE name = ? AND revoked_at IS NULL",
+        name
+    )
+    .execute(pool)
+    .await?
+    .rows_affected()
+        > 0)
+}
+
+/// The scope of the bearer token in `headers`, if it's one that works
+pub async fn scope(pool: &Pool<Sqlite>, headers: &HeaderMap) -> Result<Option<Scope>> {
+    let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return Ok(None);
+    };
+    if config::get().admin_token.as_deref() == Some(token) {
+        return Ok(Some(Scope::Admin));
+    }
+    let token_hash = hash(token);
+    let Some(row) = query!(
+        "UPDATE api_tokens SET last_used_at = unixepoch()
+         WHERE token_hash = ? AND revoked_at IS NULL RETURNING name, scope",
+        token_hash
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    debug!("Request with API token \"{}\"", row.name);
+    Ok(Some(row.scope.parse()?))
+}
+
+/// Middleware turning away requests without a token allowing `needed`,
+/// for `route_layer(middleware::from_fn_with_state((pool, needed), api_tokens::require))`
+pub async fn require(
+    State((pool, needed)): State<(Pool<Sqlite>, Scope)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match scope(&pool, request.headers()).await {
+        Ok(Some(scope)) if scope >= needed => next.run(request).await,
+        Ok(Some(_)) => (StatusCode::FORBIDDEN, "Forbidden").into_response(),
+        Ok(None) => (StatusCode::UNAUTHORIZED, "Unauthorized").into_response(),
+        Err(error) => {
+            error!("Error checking an API token: {error:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use sqlx::{query, Sqlite, SqliteExecutor};
+
+use crate::pii;
+
+/// What an audit log entry describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// A message as it arrived at the webhook
+    Message,
+    /// The command a message was parsed into
+    Command,
+    /// A change to stored data made on someone's behalf
+    Mutation,
+}
+
+impl Kind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Message => "message",
+            Self::Command => "command",
+            Self::Mutation => "mutation",
+        }
+    }
+}
+
+/// Appends an entry to the audit log on behalf of `number` (sealed, like all sender numbers).
+/// The detail is sealed here, since it usually quotes names and numbers.
+/// For mutations, pass the transaction making the change (where there is one)
+/// so the entry commits or rolls back along with it.
+pub async fn record(
+    executor: impl SqliteExecutor<'_>,
+    number: &str,
+    kind: Kind,
+    detail: impl Display,
+) -> Result<()> {
+    let kind = kind.as_str();
+    let detail = pii::seal(&detail.to_string());
+    query!(
+        "INSERT INTO audit_log (number, kind, detail) VALUES (?, ?, ?)",
+        number,
+        kind,
+        detail
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Deletes every entry recorded for `number` (sealed), for deleting their account. The only way
+/// anything leaves the log; pass the transaction deleting the account, so the entries only go
+/// if it does.
+pub async fn erase(tx: &mut sqlx::Transaction<'_, Sqlite>, number: &str) -> Result<u64> {
+    query!("INSERT INTO audit_erasures (number) VALUES (?)", number)
+        .execute(&mut **tx)
+        .await?;
+    let erased = query!("DELETE FROM audit_log WHERE number = ?", number)
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+    query!("DELETE FROM audit_erasures WHERE number = ?", number)
+        .execute(&mut **tx)
+        .await?;
+    Ok(erased)
+}
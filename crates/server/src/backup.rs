@@ -0,0 +1,99 @@
+//! Snapshots of the database, taken on a schedule and on demand.
+//!
+//! Set `BACKUP_DIR` to turn scheduled backups on. `BACKUP_INTERVAL_HOURS` (default 24) sets how
+//! often they're taken and `BACKUP_KEEP` (default 7) how many are kept. Snapshots are complete,
+//! standalone SQLite files written with `VACUUM INTO`, so restoring is a matter of stopping the
+//! server and copying one over the database file.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use axum::{http::StatusCode, Extension};
+use sqlx::{query_scalar, Pool, Sqlite};
+use tracing::*;
+
+use crate::{config, tenant::Tenants};
+
+const PREFIX: &str = "decisionbot-";
+const SUFFIX: &str = ".sqlite3";
+
+pub struct Config {
+    pub dir: PathBuf,
+    pub interval_secs: i64,
+    pub keep: usize,
+}
+
+impl Config {
+    /// Backup settings, or None if backups aren't configured
+    pub fn configured() -> Option<Self> {
+        let config = config::get();
+        Some(Self {
+            dir: config.backup_dir.clone()?,
+            interval_secs: config.backup_interval_hours * 60 * 60,
+            keep: config.backup_keep,
+        })
+    }
+}
+
+/// Writes a snapshot of the database into `dir`, named by the time it was taken
+pub async fn snapshot(pool: &Pool<Sqlite>, dir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("While creating {}", dir.display()))?;
+    let taken_at = query_scalar!(r#"SELECT strftime('%Y%m%dT%H%M%SZ', 'now') as "t!: String""#)
+        .fetch_one(pool)
+        .await?;
+    let path = dir.join(format!("{PREFIX}{taken_at}{SUFFIX}"));
+    sqlx::query("VACUUM INTO ?")
+        .bind(path.to_string_lossy())
+        .execute(pool)
+        .await
+        .with_context(|| format!("While writing {}", path.display()))?;
+    info!("Backed up the database to {}", path.display());
+    Ok(path)
+}
+
+/// Deletes all but the newest `keep` snapshots in `dir`. Returns how many were deleted.
+pub fn prune(dir: &Path, keep: usize) -> Result<usize> {
+    let mut snapshots = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(PREFIX) && name.ends_with(SUFFIX))
+        })
+        .collect::<Vec<_>>();
+    // Names sort by time taken
+    snapshots.sort();
+    let expired = snapshots.len().saturating_sub(keep);
+    for path in &snapshots[..expired] {
+        fs::remove_file(path)?;
+        debug!("Deleted old backup {}", path.display());
+    }
+    Ok(expired)
+}
+
+/// Backs up every tenant right away, e.g. before a risky operation.
+/// Needs an admin token (see [crate::api_tokens]) and `BACKUP_DIR` set.
+pub async fn trigger_backup(Extension(tenants): Extension<Tenants>) -> (StatusCode, String) {
+    let Some(config) = Config::configured() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "BACKUP_DIR is not set".to_string(),
+        );
+    };
+    let mut paths = Vec::new();
+    for tenant in tenants.all() {
+        match snapshot(&tenant.pool, &tenant.backup_dir(&config.dir)).await {
+            Ok(path) => paths.push(path.display().to_string()),
+            Err(error) => {
+                error!("Error taking a backup: {error:?}");
+                return (StatusCode::INTERNAL_SERVER_ERROR, error.to_string());
+            }
+        }
+    }
+    (StatusCode::OK, paths.join("\n"))
+}
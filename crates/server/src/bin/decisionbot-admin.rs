@@ -0,0 +1,236 @@
+//! Operator tasks that are awkward over SMS. Reads the same settings as the server (`.env`,
+//! `decisionbot.toml` and the environment), so it uses the same database and can open data
+//! sealed with `DATA_KEY`.
+//!
+//! ```text
+//! decisionbot-admin users                  List everyone signed up
+//! decisionbot-admin log [NUMBER] [LIMIT]   Show the newest audit log entries (default 50)
+//! decisionbot-admin purge                  Run the server's cleanup job now
+//! decisionbot-admin announce MESSAGE...    Text every active user (sent by the server's job worker)
+//! decisionbot-admin token issue NAME [SCOPE]  Create a token for the HTTP API (read or admin)
+//! decisionbot-admin token revoke NAME      Stop a token from working
+//! decisionbot-admin tokens                 List tokens and when they were last used
+//! decisionbot-admin spend [DAYS]           Show estimated Twilio spend by day (default 30)
+//! ```
+
+use std::{env, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use dotenv::dotenv;
+use sqlx::{query, Pool, Sqlite, SqlitePool};
+use tracing_subscriber::EnvFilter;
+
+// Shared with the server rather than duplicated, since sealing and hashing have to match exactly
+#[allow(dead_code)]
+#[path = "../api_tokens.rs"]
+mod api_tokens;
+#[allow(dead_code)]
+#[path = "../config.rs"]
+mod config;
+#[allow(dead_code)]
+#[path = "../pii.rs"]
+mod pii;
+#[allow(dead_code)]
+#[path = "../util.rs"]
+mod util;
+
+use util::E164;
+
+const USAGE: &str = "Usage: decisionbot-admin <users | log [NUMBER] [LIMIT] | purge | \
+    announce MESSAGE... | token issue NAME [read|admin] | token revoke NAME | tokens | \
+    spend [DAYS]>";
+
+/// Queued the same way the server's own jobs are. These must match `Job`'s serialized form,
+/// which the server's tests pin down.
+const CLEANUP_PAYLOAD: &str = r#"{"type":"cleanup"}"#;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv().ok();
+    let config = config::load(config::Role::Admin)?;
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .init();
+    pii::init()?;
+    let pool = SqlitePool::connect(
+        config
+            .database_url
+            .as_deref()
+            .context("DATABASE_URL is not set")?,
+    )
+    .await?;
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["users"] => users(&pool).await,
+        ["log", rest @ ..] => log(&pool, rest).await,
+        ["purge"] => purge(&pool).await,
+        ["announce", message @ ..] if !message.is_empty() => {
+            announce(&pool, &message.join(" ")).await
+        }
+        ["token", "issue", name] => issue_token(&pool, name, "admin").await,
+        ["token", "issue", name, scope] => issue_token(&pool, name, scope).await,
+        ["token", "revoke", name] => revoke_token(&pool, name).await,
+        ["tokens"] => tokens(&pool).await,
+        ["spend"] => spend(&pool, "30").await,
+        ["spend", days] => spend(&pool, days).await,
+        _ => bail!("{USAGE}"),
+    }
+}
+
+async fn users(pool: &Pool<Sqlite>) -> Result<()> {
+    let users = query!(
+        r#"SELECT number, name, lang, timezone, date(created_at, 'unixepoch') as "joined!: String"
+         FROM users ORDER BY created_at, number"#
+    )
+    .fetch_all(pool)
+    .await?;
+    for user in &users {
+        println!(
+            "{}\t{}\t{}\t{}\tjoined {}",
+            pii::open(&user.number)?,
+            pii::open(&user.name)?,
+            user.lang,
+            user.timezone.as_deref().unwrap_or("UTC"),
+            user.joined
+        );
+    }
+    println!("{} user(s)", users.len());
+    Ok(())
+}
+
+/// Newest entries, printed oldest first so they read top to bottom
+async fn log(pool: &Pool<Sqlite>, args: &[&str]) -> Result<()> {
+    let (number, limit) = match args {
+        [] => (None, None),
+        [number, rest @ ..] if rest.len() <= 1 && E164::from_str(number).is_ok() => {
+            (Some(E164::from_str(number)?), rest.first().copied())
+        }
+        [limit] => (None, Some(*limit)),
+        _ => bail!("{USAGE}"),
+    };
+    let limit: i64 = limit.map_or(Ok(50), str::parse).context("Invalid LIMIT")?;
+    let number = number.map(|number| pii::seal(number.as_str()));
+    let mut entries = query!(
+        r#"SELECT datetime(created_at, 'unixepoch') as "at!: String", number, kind, detail
+         FROM audit_log WHERE ?1 IS NULL OR number = ?1 ORDER BY id DESC LIMIT ?2"#,
+        number,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+    entries.reverse();
+    for entry in entries {
+        println!(
+            "{}\t{}\t{}\t{}",
+            entry.at,
+            pii::open(&entry.number)?,
+            entry.kind,
+            pii::open(&entry.detail)?
+        );
+    }
+    Ok(())
+}
+
+/// Moves the pending cleanup job up to now rather than queueing another,
+/// which would then keep rescheduling itself alongside the first
+async fn purge(pool: &Pool<Sqlite>) -> Result<()> {
+    let moved = query!(
+        "UPDATE jobs SET run_at = unixepoch() WHERE payload = ? AND status = 'pending'",
+        CLEANUP_PAYLOAD
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    if moved == 0 {
+        query!("INSERT INTO jobs (payload) VALUES (?)", CLEANUP_PAYLOAD)
+            .execute(pool)
+            .await?;
+    }
+    println!("Cleanup queued; a running server will do it within a few seconds");
+    Ok(())
+}
+
+async fn announce(pool: &Pool<Sqlite>, message: &str) -> Result<()> {
+    let payload = serde_json::json!({ "type": "announcement", "body": message }).to_string();
+    query!("INSERT INTO jobs (payload) VALUES (?)", payload)
+        .execute(pool)
+        .await?;
+    let count = query!(r#"SELECT COUNT(*) as "count!: i64" FROM users"#)
+        .fetch_one(pool)
+        .await?
+        .count;
+    println!("Announcement queued for {count} user(s)");
+    Ok(())
+}
+
+async fn issue_token(pool: &Pool<Sqlite>, name: &str, scope: &str) -> Result<()> {
+    let token = api_tokens::issue(pool, name, scope.parse()?).await?;
+    println!("{token}");
+    eprintln!("Save this token now; it can't be shown again");
+    Ok(())
+}
+
+async fn revoke_token(pool: &Pool<Sqlite>, name: &str) -> Result<()> {
+    if !api_tokens::revoke(pool, name).await? {
+        bail!("No working token named \"{name}\"");
+    }
+    println!("Revoked \"{name}\"");
+    Ok(())
+}
+
+async fn tokens(pool: &Pool<Sqlite>) -> Result<()> {
+    let tokens = query!(
+        r#"SELECT name, scope, date(created_at, 'unixepoch') as "created!: String",
+            datetime(last_used_at, 'unixepoch') as last_used,
+            date(revoked_at, 'unixepoch') as revoked
+         FROM api_tokens ORDER BY created_at, name"#
+    )
+    .fetch_all(pool)
+    .await?;
+    for token in tokens {
+        println!(
+            "{}\t{}\tcreated {}\tlast used {}{}",
+            token.name,
+            token.scope,
+            token.created,
+            token.last_used.as_deref().unwrap_or("never"),
+            token
+                .revoked
+                .map(|date| format!("\trevoked {date}"))
+                .unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Texts sent and their estimated cost each day (UTC), oldest first
+async fn spend(pool: &Pool<Sqlite>, days: &str) -> Result<()> {
+    let days: i64 = days.parse().context("Invalid DAYS")?;
+    let since = format!("-{} days", days - 1);
+    let rows = query!(
+        r#"SELECT date(created_at, 'unixepoch') as "day!: String",
+            COUNT(*) as "texts!: i64", SUM(segments) as "segments!: i64", SUM(price) as "price!: f64"
+         FROM message_log
+         WHERE status = 'sent' AND created_at >= unixepoch('now', 'start of day', ?)
+         GROUP BY 1 ORDER BY 1"#,
+        since
+    )
+    .fetch_all(pool)
+    .await?;
+    let total: f64 = rows.iter().map(|row| row.price).sum();
+    for row in rows {
+        println!(
+            "{}	{} text(s)	{} segment(s)	${:.2}",
+            row.day, row.texts, row.segments, row.price
+        );
+    }
+    println!("${total:.2} in all");
+    Ok(())
+}
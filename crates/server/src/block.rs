@@ -0,0 +1,192 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use sqlx::{query, query_as, Pool, Sqlite};
+use tracing::*;
+
+use crate::{
+    audit::{self, Kind},
+    command::Command,
+    i18n::{t, Lang},
+    pii,
+    util::E164,
+    Contact,
+};
+
+/// Whether the operator has cut this number off.
+/// Checked at the webhook before any other processing.
+pub async fn on_blocklist(pool: &Pool<Sqlite>, number: &str) -> Result<bool> {
+    Ok(
+        query!("SELECT number FROM blocklist WHERE number = ?", number)
+            .fetch_optional(pool)
+            .await?
+            .is_some(),
+    )
+}
+
+/// Whether `blocker` has blocked `blocked` (both sealed), so nothing from one should reach
+/// the other
+pub async fn has_blocked(pool: &Pool<Sqlite>, blocker: &str, blocked: &str) -> Result<bool> {
+    Ok(query!(
+        "SELECT blocker_number FROM blocks WHERE blocker_number = ? AND blocked_number = ?",
+        blocker,
+        blocked
+    )
+    .fetch_optional(pool)
+    .await?
+    .is_some())
+}
+
+/// Who a `block`/`unblock` argument refers to
+enum Target {
+    /// `number` is sealed, ready for the blocks table; `name` is for showing the user
+    Found {
+        number: String,
+        name: String,
+    },
+    NotFound,
+    Ambiguous(Vec<String>),
+}
+
+/// Accepts either a phone number or (part of) the name of one of the user's contacts
+async fn resolve(pool: &Pool<Sqlite>, from: &str, target: &str) -> Result<Target> {
+    if let Ok(number) = E164::from_str(target) {
+        let sealed = pii::seal(number.as_str());
+        let name = match query!(
+            "SELECT contact_name FROM contacts WHERE submitter_number = ? AND contact_user_number = ?",
+            from,
+            sealed
+        )
+        .fetch_optional(pool)
+        .await?
+        {
+            Some(row) => pii::open(&row.contact_name)?,
+            None => number.to_string(),
+        };
+        return Ok(Target::Found {
+            number: sealed,
+            name,
+        });
+    }
+
+    let matches = Contact::search(
+        Contact::open_all(
+            query_as!(
+                Contact,
+                "SELECT id as \"id!\", contact_name, contact_user_number FROM contacts
+                 WHERE submitter_number = ? AND deleted_at IS NULL",
+                from
+            )
+            .fetch_all(pool)
+            .await?,
+        )?,
+        &[target],
+    );
+    Ok(match matches.as_slice() {
+        [] => Target::NotFound,
+        [contact] => Target::Found {
+            number: pii::seal(&contact.contact_user_number),
+            name: contact.contact_name.clone(),
+        },
+        _ => Target::Ambiguous(matches.into_iter().map(|c| c.contact_name).collect()),
+    })
+}
+
+pub async fn handle_block(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    target: &str,
+) -> Result<String> {
+    if target.is_empty() {
+        return list_blocks(pool, from, lang).await;
+    }
+    Ok(match resolve(pool, from, target).await? {
+        Target::Found { number, name } => {
+            query!(
+                "INSERT OR IGNORE INTO blocks (blocker_number, blocked_number) VALUES (?, ?)",
+                from,
+                number
+            )
+            .execute(pool)
+            .await?;
+            audit::record(pool, from, Kind::Mutation, format!("blocked {number}")).await?;
+            // Each block doubles as an abuse report for the operator to review
+            let blockers = query!(
+                "SELECT COUNT(*) as count FROM blocks WHERE blocked_number = ?",
+                number
+            )
+            .fetch_one(pool)
+            .await?
+            .count;
+            warn!("{from} blocked {number}, who has now been blocked by {blockers} user(s)");
+            t!(lang, "blocked", name = name, command = Command::unblock)
+        }
+        Target::NotFound => t!(lang, "no_contacts_matching", names = target),
+        Target::Ambiguous(names) => t!(lang, "block_ambiguous", names = names.join(", ")),
+    })
+}
+
+pub async fn handle_unblock(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    target: &str,
+) -> Result<String> {
+    if target.is_empty() {
+        return Ok(Command::unblock.hint(lang));
+    }
+    Ok(match resolve(pool, from, target).await? {
+        Target::Found { number, name } => {
+            let removed = query!(
+                "DELETE FROM blocks WHERE blocker_number = ? AND blocked_number = ?",
+                from,
+                number
+            )
+            .execute(pool)
+            .await?
+            .rows_affected();
+            if removed > 0 {
+                audit::record(pool, from, Kind::Mutation, format!("unblocked {number}")).await?;
+            }
+            if removed == 0 {
+                t!(lang, "not_blocked", name = name)
+            } else {
+                t!(lang, "unblocked", name = name)
+            }
+        }
+        Target::NotFound => t!(lang, "no_contacts_matching", names = target),
+        Target::Ambiguous(names) => t!(lang, "block_ambiguous", names = names.join(", ")),
+    })
+}
+
+async fn list_blocks(pool: &Pool<Sqlite>, from: &str, lang: Lang) -> Result<String> {
+    let blocked = query!(
+        "SELECT b.blocked_number, c.contact_name as \"contact_name?\"
+         FROM blocks b
+         LEFT JOIN contacts c
+            ON c.submitter_number = b.blocker_number AND c.contact_user_number = b.blocked_number
+         WHERE b.blocker_number = ?
+         ORDER BY b.created_at",
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+    if blocked.is_empty() {
+        return Ok(format!(
+            "{}\n{}",
+            t!(lang, "no_blocks"),
+            Command::block.hint(lang)
+        ));
+    }
+    let mut response = t!(lang, "your_blocks");
+    for row in blocked {
+        let number = pii::open(&row.blocked_number)?;
+        match row.contact_name {
+            Some(name) => response.push_str(&format!("• {} ({})\n", pii::open(&name)?, number)),
+            None => response.push_str(&format!("• {}\n", number)),
+        }
+    }
+    response.push_str(&Command::unblock.hint(lang));
+    Ok(response)
+}
@@ -0,0 +1,124 @@
+use anyhow::Result;
+use lru::LruCache;
+use sqlx::{query_as, Pool, Sqlite};
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tracing::*;
+
+use crate::{i18n::Lang, User};
+
+/// How long a user row is trusted before being read again
+const TTL: Duration = Duration::from_secs(5 * 60);
+/// Most users held at once. Past this, whoever was seen longest ago is dropped.
+const MAX_ENTRIES: usize = 10_000;
+
+/// Users dropped to make room since the server started, across every tenant, for `stats`
+static EVICTIONS: AtomicU64 = AtomicU64::new(0);
+
+pub fn evictions() -> u64 {
+    EVICTIONS.load(Ordering::Relaxed)
+}
+
+/// Recently seen users, keyed by (sealed) number, so each message from someone who's texting
+/// back and forth doesn't re-read their row.
+/// Only users that exist are cached: a number can become a user from elsewhere (e.g. when
+/// someone adds it as a contact), but an existing user only changes or goes away through their
+/// own commands, which call [UserCache::invalidate].
+#[derive(Clone)]
+pub struct UserCache {
+    users: Arc<Mutex<LruCache<String, (Instant, User)>>>,
+}
+
+impl Default for UserCache {
+    fn default() -> Self {
+        Self::with_capacity(MAX_ENTRIES)
+    }
+}
+
+impl UserCache {
+    fn with_capacity(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            users: Arc::new(Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    /// Looks up a user, from the cache if it's fresh
+    pub async fn get(&self, pool: &Pool<Sqlite>, number: &str) -> Result<Option<User>> {
+        if let Some((cached_at, user)) = self.users.lock().unwrap().get(number) {
+            if cached_at.elapsed() < TTL {
+                return Ok(Some(user.clone()));
+            }
+        }
+        let user = query_as!(
+            User,
+            "SELECT number, name, lang, timezone FROM users WHERE number = ?",
+            number
+        )
+        .fetch_optional(pool)
+        .await?;
+        match &user {
+            Some(user) => self.remember(number, user),
+            None => {
+                self.users.lock().unwrap().pop(number);
+            }
+        }
+        Ok(user)
+    }
+
+    fn remember(&self, number: &str, user: &User) {
+        let pushed = self
+            .users
+            .lock()
+            .unwrap()
+            .push(number.to_string(), (Instant::now(), user.clone()));
+        if pushed.is_some_and(|(evicted, _)| evicted != number) {
+            EVICTIONS.fetch_add(1, Ordering::Relaxed);
+            debug!("User cache full; dropped the least recently seen user");
+        }
+    }
+
+    /// The user's language, or the default for numbers that aren't users
+    pub async fn lang(&self, pool: &Pool<Sqlite>, number: &str) -> Result<Lang> {
+        match self.get(pool, number).await? {
+            Some(user) => user.lang.parse(),
+            None => Ok(Lang::default()),
+        }
+    }
+
+    /// Forgets a user after their row changes
+    pub fn invalidate(&self, number: &str) {
+        self.users.lock().unwrap().pop(number);
+    }
+}
+
+#[test]
+fn evicts_least_recently_seen() {
+    let user = |number: &str| User {
+        number: number.to_string(),
+        name: "Someone".to_string(),
+        lang: "en".to_string(),
+        timezone: None,
+    };
+    let cache = UserCache::with_capacity(2);
+    let before = evictions();
+    for number in [
+        "+15550000001",
+        "+15550000002",
+        "+15550000001",
+        "+15550000003",
+    ] {
+        cache.remember(number, &user(number));
+    }
+    let users = cache.users.lock().unwrap();
+    assert!(users.contains("+15550000001"));
+    assert!(!users.contains("+15550000002"));
+    assert!(users.contains("+15550000003"));
+    assert!(evictions() > before);
+}
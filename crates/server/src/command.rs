@@ -1,100 +1,293 @@
 use std::fmt::Display;
 
-use enum_iterator::Sequence;
+use enum_iterator::{all, Sequence};
 use serde::{Deserialize, Serialize};
 
+use crate::i18n::{t, Lang};
+
 // variants must be all lowercase for serde_json to deserialize them
 #[allow(non_camel_case_types)]
-#[derive(Deserialize, Serialize, Sequence, Debug)]
+#[derive(Deserialize, Serialize, Sequence, Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum Command {
     h,
     name,
+    me,
     info,
     stop,
+    movenumber,
+    pin,
+    #[serde(alias = "join")]
+    start,
     contacts,
+    import,
     delete,
     confirm,
     group,
+    // Not "cancel", which Twilio takes as opting out and never passes on
+    #[serde(alias = "nvm")]
+    nevermind,
+    pending,
+    language,
+    block,
+    unblock,
+    export,
+    trash,
+    restore,
+    remind,
+    sendlater,
+    timezone,
+    decide,
+    event,
+    rsvp,
+    rotation,
+    again,
 }
 
 impl TryFrom<&str> for Command {
     type Error = serde_json::Error;
     fn try_from(value: &str) -> std::prelude::v1::Result<Self, Self::Error> {
-        serde_json::from_str(&format!("\"{}\"", value.to_lowercase()))
+        serde_json::from_str(&format!("\"{}\"", normalize(value)))
     }
 }
 
+/// Phones auto-capitalize the first word and autocorrect likes to tack on punctuation,
+/// so "Contacts." or "Name:" should still match a command word.
+fn normalize(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
 impl Display for Command {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", format!("{:?}", self).split("::").last().unwrap())
     }
 }
 
+/// Groups commands into pages so the help output stays readable on a phone
+#[derive(Sequence, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Category {
+    Contacts,
+    Account,
+    Messages,
+    Decisions,
+}
+
+impl Category {
+    pub fn name(&self, lang: Lang) -> String {
+        match self {
+            Self::Contacts => t!(lang, "category_contacts"),
+            Self::Messages => t!(lang, "category_messages"),
+            Self::Account => t!(lang, "category_account"),
+            Self::Decisions => t!(lang, "category_decisions"),
+        }
+    }
+
+    pub fn commands(&self) -> Vec<Command> {
+        all::<Command>()
+            .filter(|c| c.category() == Some(*self))
+            .collect()
+    }
+}
+
+impl TryFrom<&str> for Category {
+    type Error = ();
+    /// Accepts either a category name (in any language) or its 1-based position in the help index
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value = normalize(value);
+        if let Ok(index) = value.parse::<usize>() {
+            return all::<Category>()
+                .nth(index.checked_sub(1).ok_or(())?)
+                .ok_or(());
+        }
+        all::<Category>()
+            .find(|c| all::<Lang>().any(|lang| c.name(lang) == value))
+            .ok_or(())
+    }
+}
+
 struct ParameterDoc {
     example: String,
     description: String,
 }
 
 impl Command {
-    pub fn description(&self) -> String {
+    /// Commands without a category (help itself, and replies to an in-progress action)
+    /// are left out of the category pages
+    pub fn category(&self) -> Option<Category> {
         match self {
-            Self::h => "show a list of available commands",
-            Self::info => "see information about a command",
-            Self::name => "set your preferred name",
-            Self::stop => "stop receiving messages and remove yourself from the database",
-            Self::contacts => "see a list of your groups and contacts",
-            Self::delete => "delete a contact by name",
-            Self::confirm => "confirm pending action(s)",
-            Self::group => "create a new group from your contacts",
+            Self::contacts
+            | Self::import
+            | Self::delete
+            | Self::trash
+            | Self::restore
+            | Self::group
+            | Self::block
+            | Self::unblock
+            | Self::pending => Some(Category::Contacts),
+            Self::remind | Self::sendlater => Some(Category::Messages),
+            Self::decide | Self::event | Self::rsvp | Self::rotation => Some(Category::Decisions),
+            Self::name
+            | Self::me
+            | Self::language
+            | Self::timezone
+            | Self::export
+            | Self::again
+            | Self::movenumber
+            | Self::pin
+            | Self::stop => Some(Category::Account),
+            Self::h | Self::info | Self::confirm | Self::nevermind | Self::start => None,
         }
-        .to_string()
     }
 
-    fn parameter_doc(&self) -> Option<ParameterDoc> {
+    pub fn description(&self, lang: Lang) -> String {
+        match self {
+            Self::h => t!(lang, "command_h"),
+            Self::info => t!(lang, "command_info"),
+            Self::name => t!(lang, "command_name"),
+            Self::me => t!(lang, "command_me"),
+            Self::stop => t!(lang, "command_stop"),
+            Self::start => t!(lang, "command_start"),
+            Self::movenumber => t!(lang, "command_movenumber"),
+            Self::pin => t!(lang, "command_pin"),
+            Self::contacts => t!(lang, "command_contacts"),
+            Self::import => t!(lang, "command_import"),
+            Self::delete => t!(lang, "command_delete"),
+            Self::confirm => t!(lang, "command_confirm"),
+            Self::group => t!(lang, "command_group"),
+            Self::nevermind => t!(lang, "command_nevermind"),
+            Self::pending => t!(lang, "command_pending"),
+            Self::language => t!(lang, "command_language"),
+            Self::block => t!(lang, "command_block"),
+            Self::unblock => t!(lang, "command_unblock"),
+            Self::export => t!(lang, "command_export"),
+            Self::trash => t!(lang, "command_trash"),
+            Self::restore => t!(lang, "command_restore"),
+            Self::remind => t!(lang, "command_remind"),
+            Self::sendlater => t!(lang, "command_sendlater"),
+            Self::timezone => t!(lang, "command_timezone"),
+            Self::decide => t!(lang, "command_decide"),
+            Self::event => t!(lang, "command_event"),
+            Self::rsvp => t!(lang, "command_rsvp"),
+            Self::rotation => t!(lang, "command_rotation"),
+            Self::again => t!(lang, "command_again"),
+        }
+    }
+
+    fn parameter_doc(&self, lang: Lang) -> Option<ParameterDoc> {
         match self {
             Self::h => None,
             Self::info => Some(ParameterDoc {
                 example: Command::name.to_string(),
-                description: "a command".to_string(),
+                description: t!(lang, "param_info"),
             }),
             Self::name => Some(ParameterDoc {
                 example: "John S.".to_string(),
-                description: "your name".to_string(),
+                description: t!(lang, "param_name"),
             }),
             Self::delete => Some(ParameterDoc {
                 example: "John".to_string(),
-                description: "contact name to delete".to_string(),
+                description: t!(lang, "param_delete"),
             }),
             Self::confirm => Some(ParameterDoc {
                 example: "2,3".to_string(),
-                description: "number(s) from a list of pending actions".to_string(),
+                description: t!(lang, "param_confirm"),
+            }),
+            Self::stop => Some(ParameterDoc {
+                example: "purge".to_string(),
+                description: t!(lang, "param_stop"),
+            }),
+            Self::start => None,
+            Self::movenumber => Some(ParameterDoc {
+                example: "(555) 555-0123".to_string(),
+                description: t!(lang, "param_movenumber"),
+            }),
+            Self::pin => Some(ParameterDoc {
+                example: "1234".to_string(),
+                description: t!(lang, "param_pin"),
+            }),
+            Self::contacts => Some(ParameterDoc {
+                example: "recent".to_string(),
+                description: t!(lang, "param_contacts"),
+            }),
+            Self::import => Some(ParameterDoc {
+                example: "replace".to_string(),
+                description: t!(lang, "param_import"),
             }),
-            Self::stop => None,
-            Self::contacts => None,
             Self::group => Some(ParameterDoc {
                 example: "John, Alice".to_string(),
-                description: "comma-separated list of contact name fragments".to_string(),
+                description: t!(lang, "param_group"),
+            }),
+            Self::nevermind | Self::pending | Self::again | Self::me => None,
+            Self::export => None,
+            Self::trash => None,
+            Self::restore => Some(ParameterDoc {
+                example: "1,3".to_string(),
+                description: t!(lang, "param_restore"),
+            }),
+            Self::remind => Some(ParameterDoc {
+                example: "family tomorrow 9am: vote on dinner".to_string(),
+                description: t!(lang, "param_remind"),
+            }),
+            Self::sendlater => Some(ParameterDoc {
+                example: "family 8am: don't forget to vote".to_string(),
+                description: t!(lang, "param_sendlater"),
+            }),
+            Self::decide => Some(ParameterDoc {
+                example: "pizza x2, tacos, sushi".to_string(),
+                description: t!(lang, "param_decide"),
+            }),
+            Self::event => Some(ParameterDoc {
+                example: "family friday 7pm: dinner at my place".to_string(),
+                description: t!(lang, "param_event"),
+            }),
+            Self::rsvp => Some(ParameterDoc {
+                example: "yes".to_string(),
+                description: t!(lang, "param_rsvp"),
+            }),
+            Self::rotation => Some(ParameterDoc {
+                example: "create dishes: me, Alex, Jo".to_string(),
+                description: t!(lang, "param_rotation"),
+            }),
+            Self::timezone => Some(ParameterDoc {
+                example: "America/New_York".to_string(),
+                description: t!(lang, "param_timezone"),
+            }),
+            Self::language => Some(ParameterDoc {
+                example: Lang::Es.code().to_string(),
+                description: t!(lang, "param_language", langs = Lang::list()),
+            }),
+            Self::block | Self::unblock => Some(ParameterDoc {
+                example: "John".to_string(),
+                description: t!(lang, "param_block"),
             }),
         }
     }
-    pub fn usage(&self) -> String {
-        if let Some(ParameterDoc { description, .. }) = self.parameter_doc() {
-            format!("Reply \"{self} X\", where X is {description}")
+    pub fn usage(&self, lang: Lang) -> String {
+        if let Some(ParameterDoc { description, .. }) = self.parameter_doc(lang) {
+            t!(
+                lang,
+                "usage_with_param",
+                command = self,
+                param = description
+            )
         } else {
-            format!("Reply \"{self}\"")
+            t!(lang, "usage", command = self)
         }
     }
-    pub fn example(&self) -> String {
-        self.parameter_doc()
-            .map(|ParameterDoc { example, .. }| format!("\nExample: \"{self} {example}\""))
+    pub fn example(&self, lang: Lang) -> String {
+        self.parameter_doc(lang)
+            .map(|ParameterDoc { example, .. }| {
+                t!(lang, "example", command = self, example = example)
+            })
             .unwrap_or_default()
     }
-    pub fn hint(&self) -> String {
-        format!(
-            "{}, to {}.{}",
-            self.usage(),
-            self.description(),
-            self.example()
+    pub fn hint(&self, lang: Lang) -> String {
+        t!(
+            lang,
+            "hint",
+            usage = self.usage(lang),
+            description = self.description(lang),
+            example = self.example(lang)
         )
     }
 }
@@ -107,3 +300,42 @@ fn command() {
         command_text
     );
 }
+
+#[test]
+fn command_autocorrect_artifacts() {
+    for command_text in [
+        "Name",
+        "NAME",
+        "name.",
+        "Name:",
+        "contacts!",
+        "Contacts?",
+        "\"h\"",
+        "H…",
+    ] {
+        assert!(
+            Command::try_from(command_text).is_ok(),
+            "\"{command_text}\" should be recognized"
+        );
+    }
+    assert!(Command::try_from("...").is_err());
+    assert!(Command::try_from("nam").is_err());
+}
+
+#[test]
+fn category() {
+    assert_eq!(Category::try_from("contacts"), Ok(Category::Contacts));
+    assert_eq!(Category::try_from("Account."), Ok(Category::Account));
+    assert_eq!(Category::try_from("contactos"), Ok(Category::Contacts));
+    assert_eq!(Category::try_from("1"), Ok(Category::Contacts));
+    assert_eq!(Category::try_from("2"), Ok(Category::Account));
+    assert_eq!(Category::try_from("3"), Ok(Category::Messages));
+    assert_eq!(Category::try_from("4"), Ok(Category::Decisions));
+    assert_eq!(Category::try_from("decisiones"), Ok(Category::Decisions));
+    assert!(Category::try_from("0").is_err());
+    assert!(Category::try_from("99").is_err());
+    assert!(Category::try_from("nope").is_err());
+    for category in all::<Category>() {
+        assert!(!category.commands().is_empty());
+    }
+}
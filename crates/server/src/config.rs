@@ -0,0 +1,465 @@
+//! The server's settings, read from a TOML file with environment variables (including `.env`)
+//! taking precedence.
+//!
+//! The file is `decisionbot.toml` in the working directory, or whatever `CONFIG_FILE` names.
+//! Each setting's key is its environment variable in lowercase, e.g. `callback_port = 8080` in
+//! the file and `CALLBACK_PORT=8080` in the environment; see `.env.template` for the full list.
+//! Everything is checked at startup, and every problem is reported at once.
+
+use std::{collections::HashMap, env, fmt::Display, fs, io, path::PathBuf, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::OnceCell;
+use phonenumber::country;
+
+use crate::util;
+
+const DEFAULT_FILE: &str = "decisionbot.toml";
+
+/// Every setting there is
+const KEYS: &[&str] = &[
+    "twilio_account_sid",
+    "twilio_api_key_sid",
+    "twilio_api_key_secret",
+    "server_number",
+    "client_number",
+    "callback_ip",
+    "callback_port",
+    "public_url",
+    "database_url",
+    "tenants",
+    "migrate_on_startup",
+    "data_key",
+    "messages_file",
+    "simulate",
+    "send_concurrency",
+    "send_per_second",
+    "backup_dir",
+    "backup_interval_hours",
+    "backup_keep",
+    "admin_token",
+    "error_webhook_url",
+    "otel_exporter_otlp_endpoint",
+    "terms_version",
+    "terms_url",
+    "phone_region",
+    "shutdown_timeout_secs",
+    "tls_cert_path",
+    "tls_key_path",
+    "quota_imports_per_hour",
+    "quota_scheduled_per_day",
+    "quota_recipients",
+    "segment_price",
+    "monthly_budget",
+    "line_type_lookup",
+];
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub twilio_account_sid: Option<String>,
+    pub twilio_api_key_sid: Option<String>,
+    pub twilio_api_key_secret: Option<String>,
+    /// The bot's number, when there's only one
+    pub server_number: Option<String>,
+    /// The operator's number, which gets startup and shutdown notices
+    pub client_number: Option<String>,
+    pub callback_ip: Option<String>,
+    pub callback_port: Option<u16>,
+    /// Where the server can be reached from outside, for links in messages
+    pub public_url: Option<String>,
+    pub database_url: Option<String>,
+    /// Several bot numbers, each with its own database (see [crate::tenant])
+    pub tenants: Option<String>,
+    pub migrate_on_startup: bool,
+    /// base64-encoded 32-byte key for encrypting personal data at rest
+    pub data_key: Option<String>,
+    /// JSON file replacing any of the bot's messages (see [crate::i18n::load_overrides])
+    pub messages_file: Option<String>,
+    pub simulate: bool,
+    pub send_concurrency: Option<usize>,
+    pub send_per_second: Option<f64>,
+    pub backup_dir: Option<PathBuf>,
+    pub backup_interval_hours: i64,
+    pub backup_keep: usize,
+    pub admin_token: Option<String>,
+    pub error_webhook_url: Option<String>,
+    pub otel_exporter_otlp_endpoint: Option<String>,
+    pub terms_version: Option<String>,
+    pub terms_url: Option<String>,
+    /// Where numbers written without a country code are from
+    pub phone_region: country::Id,
+    pub shutdown_timeout_secs: Option<u64>,
+    /// PEM files for serving HTTPS directly (see [crate::tls])
+    pub tls_cert_path: Option<PathBuf>,
+    pub tls_key_path: Option<PathBuf>,
+    /// Per-user limits (see [crate::quota]), with 0 meaning no limit
+    pub quota_imports_per_hour: i64,
+    pub quota_scheduled_per_day: i64,
+    pub quota_recipients: i64,
+    /// Estimated dollars per outbound segment, for tracking spend (see [crate::cost])
+    pub segment_price: f64,
+    /// Estimated monthly spend in dollars past which the operator gets a text
+    pub monthly_budget: Option<f64>,
+    /// Whether to ask Twilio what kind of line each new contact's number is (see [crate::lookup])
+    pub line_type_lookup: bool,
+}
+
+/// What the settings are for, which decides which of them are required
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Server,
+    /// The `decisionbot-admin` tool, which only needs the database
+    Admin,
+}
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+#[cfg(test)]
+thread_local! {
+    /// Lets a test change settings without touching the process-wide ones other tests share
+    static TEST_CONFIG: std::cell::Cell<Option<&'static Config>> = const { std::cell::Cell::new(None) };
+}
+
+/// Uses the given settings for the rest of the current test
+#[cfg(test)]
+pub fn use_test_config(config: Config) {
+    let config = Box::leak(Box::new(config));
+    TEST_CONFIG.with(|c| c.set(Some(config)));
+}
+
+/// The settings, as loaded by [load]. Before then (as in tests), every setting has its default.
+pub fn get() -> &'static Config {
+    #[cfg(test)]
+    if let Some(config) = TEST_CONFIG.with(|c| c.get()) {
+        return config;
+    }
+    CONFIG.get_or_init(Config::default)
+}
+
+/// Reads and checks the settings. Call once at startup, before anything uses them.
+pub fn load(role: Role) -> Result<&'static Config> {
+    let mut problems = Vec::new();
+    let mut raw = HashMap::new();
+
+    let (path, required) = match env::var("CONFIG_FILE") {
+        Ok(path) => (path, true),
+        Err(_) => (DEFAULT_FILE.to_string(), false),
+    };
+    match fs::read_to_string(&path) {
+        Ok(text) => read_file(&text, &path, &mut raw, &mut problems)?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound && !required => {}
+        Err(error) => return Err(error).with_context(|| format!("While reading {path}")),
+    }
+    for key in KEYS {
+        if let Ok(value) = env::var(key.to_uppercase()) {
+            raw.insert(*key, value);
+        }
+    }
+
+    let mut config = Config::parse(&raw, &mut problems);
+    if role == Role::Server {
+        config.simulate |= env::args().any(|arg| arg == "--simulate");
+    }
+    config.check(role, &mut problems);
+    if !problems.is_empty() {
+        bail!("Invalid configuration:\n- {}", problems.join("\n- "));
+    }
+    if CONFIG.set(config).is_err() {
+        bail!("Configuration was already loaded");
+    }
+    Ok(get())
+}
+
+/// Adds the settings in a config file to `raw`
+fn read_file(
+    text: &str,
+    path: &str,
+    raw: &mut HashMap<&'static str, String>,
+    problems: &mut Vec<String>,
+) -> Result<()> {
+    let table: toml::Table = text.parse().with_context(|| format!("In {path}"))?;
+    for (key, value) in table {
+        let Some(key) = KEYS.iter().find(|known| **known == key) else {
+            problems.push(format!("{path} has an unknown setting \"{key}\""));
+            continue;
+        };
+        let value = match value {
+            toml::Value::String(value) => value,
+            toml::Value::Integer(value) => value.to_string(),
+            toml::Value::Float(value) => value.to_string(),
+            toml::Value::Boolean(value) => value.to_string(),
+            _ => {
+                problems.push(format!("{key} in {path} must be a single value"));
+                continue;
+            }
+        };
+        raw.insert(key, value);
+    }
+    Ok(())
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::parse(&HashMap::new(), &mut Vec::new())
+    }
+}
+
+impl Config {
+    fn parse(raw: &HashMap<&str, String>, problems: &mut Vec<String>) -> Self {
+        let mut settings = Settings { raw, problems };
+        let phone_region = settings
+            .text("phone_region")
+            .and_then(|region| {
+                let parsed = region.to_uppercase().parse().ok();
+                if parsed.is_none() {
+                    settings.problems.push(format!(
+                        "PHONE_REGION \"{region}\" isn't a two-letter country code"
+                    ));
+                }
+                parsed
+            })
+            .unwrap_or(country::Id::US);
+        Self {
+            twilio_account_sid: settings.text("twilio_account_sid"),
+            twilio_api_key_sid: settings.text("twilio_api_key_sid"),
+            twilio_api_key_secret: settings.text("twilio_api_key_secret"),
+            server_number: settings.text("server_number"),
+            client_number: settings.text("client_number"),
+            callback_ip: settings.text("callback_ip"),
+            callback_port: settings.parsed("callback_port"),
+            public_url: settings.text("public_url"),
+            database_url: settings.text("database_url"),
+            tenants: settings.text("tenants"),
+            migrate_on_startup: settings.flag("migrate_on_startup").unwrap_or(true),
+            data_key: settings.text("data_key"),
+            messages_file: settings.text("messages_file"),
+            simulate: settings.flag("simulate").unwrap_or(false),
+            send_concurrency: settings.parsed("send_concurrency"),
+            send_per_second: settings.parsed("send_per_second"),
+            backup_dir: settings.text("backup_dir").map(PathBuf::from),
+            backup_interval_hours: settings.parsed("backup_interval_hours").unwrap_or(24),
+            backup_keep: settings.parsed("backup_keep").unwrap_or(7),
+            admin_token: settings.text("admin_token"),
+            error_webhook_url: settings.text("error_webhook_url"),
+            otel_exporter_otlp_endpoint: settings.text("otel_exporter_otlp_endpoint"),
+            terms_version: settings.text("terms_version"),
+            terms_url: settings.text("terms_url"),
+            phone_region,
+            shutdown_timeout_secs: settings.parsed("shutdown_timeout_secs"),
+            tls_cert_path: settings.text("tls_cert_path").map(PathBuf::from),
+            tls_key_path: settings.text("tls_key_path").map(PathBuf::from),
+            quota_imports_per_hour: settings.parsed("quota_imports_per_hour").unwrap_or(10),
+            quota_scheduled_per_day: settings.parsed("quota_scheduled_per_day").unwrap_or(50),
+            quota_recipients: settings.parsed("quota_recipients").unwrap_or(100),
+            // Twilio's price for a US long code
+            segment_price: settings.parsed("segment_price").unwrap_or(0.0083),
+            monthly_budget: settings.parsed("monthly_budget"),
+            line_type_lookup: settings.flag("line_type_lookup").unwrap_or(false),
+        }
+    }
+
+    /// Finds settings that are missing or don't fit together
+    fn check(&self, role: Role, problems: &mut Vec<String>) {
+        let mut missing = |name: &str, value: bool| {
+            if !value {
+                problems.push(format!("{name} is required"));
+            }
+        };
+        if role == Role::Admin {
+            missing("DATABASE_URL", self.database_url.is_some());
+        } else if self.tenants.is_none() {
+            missing("SERVER_NUMBER (or TENANTS)", self.server_number.is_some());
+            missing("DATABASE_URL (or TENANTS)", self.database_url.is_some());
+        }
+        if role == Role::Server {
+            missing("CALLBACK_IP", self.callback_ip.is_some());
+            missing("CALLBACK_PORT", self.callback_port.is_some());
+            if !self.simulate {
+                missing("TWILIO_ACCOUNT_SID", self.twilio_account_sid.is_some());
+                missing("TWILIO_API_KEY_SID", self.twilio_api_key_sid.is_some());
+                missing(
+                    "TWILIO_API_KEY_SECRET",
+                    self.twilio_api_key_secret.is_some(),
+                );
+                missing("CLIENT_NUMBER", self.client_number.is_some());
+            }
+        }
+
+        for (name, number) in [
+            ("SERVER_NUMBER", &self.server_number),
+            ("CLIENT_NUMBER", &self.client_number),
+        ] {
+            if let Some(number) = number {
+                if let Err(error) = util::parse(number, self.phone_region) {
+                    problems.push(format!("{name} \"{number}\" isn't a phone number: {error}"));
+                }
+            }
+        }
+        for (name, url) in [
+            ("PUBLIC_URL", &self.public_url),
+            ("ERROR_WEBHOOK_URL", &self.error_webhook_url),
+            (
+                "OTEL_EXPORTER_OTLP_ENDPOINT",
+                &self.otel_exporter_otlp_endpoint,
+            ),
+            ("TERMS_URL", &self.terms_url),
+        ] {
+            if let Some(url) = url {
+                if let Err(error) = reqwest::Url::parse(url) {
+                    problems.push(format!("{name} \"{url}\" isn't a URL: {error}"));
+                }
+            }
+        }
+        if self.terms_version.is_some() != self.terms_url.is_some() {
+            problems.push("TERMS_VERSION and TERMS_URL must be set together".to_string());
+        }
+        if self.tls_cert_path.is_some() != self.tls_key_path.is_some() {
+            problems.push("TLS_CERT_PATH and TLS_KEY_PATH must be set together".to_string());
+        }
+        for (name, path) in [
+            ("TLS_CERT_PATH", &self.tls_cert_path),
+            ("TLS_KEY_PATH", &self.tls_key_path),
+        ] {
+            if let Some(path) = path.as_ref().filter(|path| !path.is_file()) {
+                problems.push(format!("{name} \"{}\" doesn't exist", path.display()));
+            }
+        }
+        if self.backup_interval_hours <= 0 {
+            problems.push("BACKUP_INTERVAL_HOURS must be at least 1".to_string());
+        }
+        if self.send_concurrency == Some(0) {
+            problems.push("SEND_CONCURRENCY must be at least 1".to_string());
+        }
+        // Written this way round so NaN fails too
+        if self
+            .send_per_second
+            .is_some_and(|rate| !(rate > 0.0 && rate.is_finite()))
+        {
+            problems.push("SEND_PER_SECOND must be a number more than 0".to_string());
+        }
+        if self.segment_price < 0.0 {
+            problems.push("SEGMENT_PRICE can't be negative".to_string());
+        }
+        if self.monthly_budget.is_some_and(|budget| budget <= 0.0) {
+            problems.push("MONTHLY_BUDGET must be more than 0".to_string());
+        }
+        for (name, limit) in [
+            ("QUOTA_IMPORTS_PER_HOUR", self.quota_imports_per_hour),
+            ("QUOTA_SCHEDULED_PER_DAY", self.quota_scheduled_per_day),
+            ("QUOTA_RECIPIENTS", self.quota_recipients),
+        ] {
+            if limit < 0 {
+                problems.push(format!("{name} can't be negative"));
+            }
+        }
+    }
+}
+
+/// Reads typed values out of the raw text of the settings, noting any that don't parse
+struct Settings<'a> {
+    raw: &'a HashMap<&'a str, String>,
+    problems: &'a mut Vec<String>,
+}
+
+impl Settings<'_> {
+    /// Empty values count as unset, as they do in `.env`
+    fn text(&self, key: &str) -> Option<String> {
+        self.raw
+            .get(key)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn parsed<T: FromStr>(&mut self, key: &str) -> Option<T>
+    where
+        T::Err: Display,
+    {
+        let value = self.text(key)?;
+        match value.parse() {
+            Ok(parsed) => Some(parsed),
+            Err(error) => {
+                self.problems.push(format!(
+                    "{} \"{value}\" is invalid: {error}",
+                    key.to_uppercase()
+                ));
+                None
+            }
+        }
+    }
+
+    fn flag(&mut self, key: &str) -> Option<bool> {
+        let value = self.text(key)?;
+        match value.to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Some(true),
+            "0" | "false" | "no" | "off" => Some(false),
+            _ => {
+                self.problems.push(format!(
+                    "{} \"{value}\" should be true or false",
+                    key.to_uppercase()
+                ));
+                None
+            }
+        }
+    }
+}
+
+#[test]
+fn config_file() {
+    let (mut raw, mut problems) = (HashMap::new(), Vec::new());
+    read_file(
+        "server_number = \"+15550001111\"\ncallback_port = 8080\nsimulate = true\ncolour = \"blue\"",
+        "test.toml",
+        &mut raw,
+        &mut problems,
+    )
+    .unwrap();
+    assert_eq!(problems, ["test.toml has an unknown setting \"colour\""]);
+    // As from the environment, which wins over the file
+    raw.insert("callback_port", "9090".to_string());
+    let config = Config::parse(&raw, &mut problems);
+    assert_eq!(config.server_number.as_deref(), Some("+15550001111"));
+    assert_eq!(config.callback_port, Some(9090));
+    assert!(config.simulate);
+    assert!(config.migrate_on_startup);
+    assert_eq!(config.backup_keep, 7);
+    assert!(read_file("tenants = [", "test.toml", &mut raw, &mut problems).is_err());
+}
+
+#[test]
+fn config_problems() {
+    let raw = HashMap::from([
+        ("callback_port", "http".to_string()),
+        ("client_number", "12".to_string()),
+        ("public_url", "bot.example.com".to_string()),
+        ("terms_version", "2024-12".to_string()),
+        ("simulate", "maybe".to_string()),
+        ("tls_cert_path", "/nonexistent/cert.pem".to_string()),
+    ]);
+    let mut problems = Vec::new();
+    Config::parse(&raw, &mut problems).check(Role::Server, &mut problems);
+    assert_eq!(
+        problems,
+        [
+            "CALLBACK_PORT \"http\" is invalid: invalid digit found in string",
+            "SIMULATE \"maybe\" should be true or false",
+            "SERVER_NUMBER (or TENANTS) is required",
+            "DATABASE_URL (or TENANTS) is required",
+            "CALLBACK_IP is required",
+            "CALLBACK_PORT is required",
+            "TWILIO_ACCOUNT_SID is required",
+            "TWILIO_API_KEY_SID is required",
+            "TWILIO_API_KEY_SECRET is required",
+            "CLIENT_NUMBER \"12\" isn't a phone number: Phone number must be 10 digits (or 11 digits starting with 1)",
+            "PUBLIC_URL \"bot.example.com\" isn't a URL: relative URL without a base",
+            "TERMS_VERSION and TERMS_URL must be set together",
+            "TLS_CERT_PATH and TLS_KEY_PATH must be set together",
+            "TLS_CERT_PATH \"/nonexistent/cert.pem\" doesn't exist",
+        ]
+    );
+
+    // The admin tool only needs the database
+    let raw = HashMap::from([("database_url", "sqlite:test.sqlite3".to_string())]);
+    let mut problems = Vec::new();
+    Config::parse(&raw, &mut problems).check(Role::Admin, &mut problems);
+    assert!(problems.is_empty());
+}
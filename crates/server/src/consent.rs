@@ -0,0 +1,90 @@
+//! Double opt-in for messages from other people. Being in someone's contacts shouldn't mean
+//! getting texts from a bot you never signed up for, so the first time anyone sends something
+//! to someone who hasn't agreed to it, they're asked instead, and nothing is delivered to them
+//! until they reply YES. People who sign up themselves have agreed by doing so.
+
+use anyhow::{bail, Result};
+use sqlx::{query, Pool, Sqlite};
+
+use crate::{
+    audit::{self, Kind},
+    i18n::{t, Lang},
+    onboarding::answer,
+};
+
+/// Where someone stands on getting messages from other people
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Consent {
+    /// Never asked
+    Unknown,
+    /// Asked, but hasn't replied
+    Requested,
+    Granted,
+    Declined,
+}
+
+pub async fn status(pool: &Pool<Sqlite>, number: &str) -> Result<Consent> {
+    let consent = query!("SELECT consent FROM users WHERE number = ?", number)
+        .fetch_optional(pool)
+        .await?
+        .and_then(|row| row.consent);
+    Ok(match consent.as_deref() {
+        None => Consent::Unknown,
+        Some("requested") => Consent::Requested,
+        Some("granted") => Consent::Granted,
+        Some("declined") => Consent::Declined,
+        Some(other) => bail!("Unknown consent state: {other}"),
+    })
+}
+
+/// Notes that someone has been asked, so they're only asked once
+pub async fn mark_requested(pool: &Pool<Sqlite>, number: &str) -> Result<()> {
+    query!(
+        "UPDATE users SET consent = 'requested' WHERE number = ? AND consent IS NULL",
+        number
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Takes a YES or NO from someone who's been asked, returning the reply to it.
+/// Someone who said no can still change their mind with a YES later.
+/// Anything else, or from anyone else, is left for the usual handling.
+pub async fn handle_reply(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    body: &str,
+) -> Result<Option<String>> {
+    let Some(granted) = answer(body) else {
+        return Ok(None);
+    };
+    let answered = if granted {
+        query!(
+            "UPDATE users SET consent = 'granted'
+             WHERE number = ? AND consent IN ('requested', 'declined')",
+            from
+        )
+        .execute(pool)
+        .await?
+    } else {
+        query!(
+            "UPDATE users SET consent = 'declined' WHERE number = ? AND consent = 'requested'",
+            from
+        )
+        .execute(pool)
+        .await?
+    }
+    .rows_affected();
+    if answered == 0 {
+        return Ok(None);
+    }
+    Ok(Some(if granted {
+        audit::record(pool, from, Kind::Mutation, "agreed to messages").await?;
+        t!(lang, "consent_granted")
+    } else {
+        audit::record(pool, from, Kind::Mutation, "declined messages").await?;
+        t!(lang, "consent_declined")
+    }))
+}
@@ -1,25 +1,58 @@
-use std::str::FromStr;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
 
 use anyhow::{bail, Result};
+use chrono_tz::Tz;
 use ical::parser::vcard::component::VcardContact;
-use sqlx::{query, Pool, Sqlite};
+use sqlx::{query, query_as, Pool, Sqlite};
 
-use crate::{util::E164, ImportResult};
+use crate::{
+    audit::{self, Kind},
+    command::Command,
+    i18n::{self, t, Lang},
+    lookup, pii,
+    session::{self, SessionState},
+    timezone,
+    util::E164,
+    vcard, Contact, ImportResult,
+};
+
+/// Most cards read from one import, so one huge file can't tie the server up
+const MAX_CARDS: usize = 1_000;
+/// Most numbers kept from one card, which is more than any real person has
+const MAX_NUMBERS_PER_CARD: usize = 10;
 
 pub async fn process_contact_submission(
     pool: &Pool<Sqlite>,
     from: &str,
-    media_url: &Option<String>,
+    data: &[u8],
+    replace: bool,
 ) -> anyhow::Result<String> {
-    let vcard_data = reqwest::get(media_url.as_ref().unwrap())
-        .await?
-        .text()
-        .await?;
-    let reader = ical::VcardParser::new(vcard_data.as_bytes());
-    let mut stats = ImportStats::default();
+    let vcard_data = vcard::decode(data);
+    let mut reader = ical::VcardParser::new(vcard_data.as_bytes());
+    let lang = i18n::user_lang(pool, from).await?;
+    // Contacts added from here on are new in this import
+    let last_id = query!(
+        r#"SELECT COALESCE(MAX(id), 0) as "id!: i64" FROM contacts WHERE submitter_number = ?"#,
+        from
+    )
+    .fetch_one(pool)
+    .await?
+    .id;
+    let mut stats = ImportStats {
+        last_id,
+        ..Default::default()
+    };
 
-    for vcard in reader {
-        match process_vcard(pool, &from, vcard).await {
+    // Every number in the export, to find the contacts that aren't
+    let mut exported = HashSet::new();
+    for vcard in merge_by_name(reader.by_ref().take(MAX_CARDS)) {
+        if let Ok(card) = &vcard {
+            exported.extend(card_numbers(card).into_iter().map(|(number, _)| number));
+        }
+        match process_vcard(pool, from, vcard).await {
             Ok(ImportResult::Added) => stats.added += 1,
             Ok(ImportResult::Updated) => stats.updated += 1,
             Ok(ImportResult::Unchanged) => stats.skipped += 1,
@@ -27,31 +60,245 @@ pub async fn process_contact_submission(
             Err(e) => stats.add_error(&e.to_string()),
         }
     }
-    stats.format_report(pool, &from).await
+    let mut report = stats.format_report(pool, from, lang).await?;
+    let truncated = reader.next().is_some();
+    if truncated {
+        report.push_str(&t!(lang, "import_truncated", max = MAX_CARDS));
+    }
+    if replace {
+        report.push_str(&if truncated {
+            t!(lang, "import_replace_incomplete")
+        } else if stats.deferred > 0 {
+            t!(lang, "import_replace_after_picking")
+        } else {
+            propose_removals(pool, from, lang, &exported).await?
+        });
+    }
+    Ok(report)
 }
-pub async fn process_vcard(
+
+/// Offers to delete the contacts whose numbers aren't in `exported`, the way `delete` would
+async fn propose_removals(
     pool: &Pool<Sqlite>,
     from: &str,
-    vcard: Result<VcardContact, ical::parser::ParserError>,
-) -> Result<ImportResult> {
-    let user_exists = query!("SELECT * FROM users WHERE number = ?", from)
-        .fetch_optional(pool)
-        .await?
-        .is_some();
-    if !user_exists {
-        bail!("Please set your name first using the 'name' command before adding contacts");
+    lang: Lang,
+    exported: &HashSet<String>,
+) -> Result<String> {
+    let missing = Contact::open_all(
+        query_as!(
+            Contact,
+            r#"SELECT id as "id!", contact_name, contact_user_number
+             FROM contacts
+             WHERE submitter_number = ? AND deleted_at IS NULL
+             ORDER BY id"#,
+            from
+        )
+        .fetch_all(pool)
+        .await?,
+    )?
+    .into_iter()
+    .filter(|contact| !exported.contains(&contact.contact_user_number))
+    .collect::<Vec<_>>();
+    if missing.is_empty() {
+        return Ok(t!(lang, "import_replace_complete"));
     }
 
-    let card = vcard?;
+    let mut tx = pool.begin().await?;
+    session::start(&mut tx, from, SessionState::Deletion).await?;
+    for contact in &missing {
+        query!(
+            "INSERT INTO pending_deletions (session_submitter, group_id, contact_id)
+             VALUES (?, NULL, ?)",
+            from,
+            contact.id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
 
-    let name = card
-        .properties
+    let list = missing
         .iter()
-        .find(|p| p.name == "FN")
-        .and_then(|p| p.value.as_ref())
-        .ok_or_else(|| anyhow::anyhow!("No name provided"))?;
+        .enumerate()
+        .map(|(i, contact)| {
+            let area_code = E164::from_str(&contact.contact_user_number)
+                .map(|e| e.short_form())
+                .unwrap_or_else(|_| "???".to_string());
+            format!("{}. {} ({area_code})", i + 1, contact.contact_name)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(t!(
+        lang,
+        "import_replace_missing",
+        list = list,
+        command = Command::nevermind
+    ))
+}
+
+/// Folds cards for the same person, like separate work and personal cards, into the first of
+/// them, so all their numbers are picked from together
+fn merge_by_name(
+    cards: impl Iterator<Item = Result<VcardContact, ical::parser::ParserError>>,
+) -> Vec<Result<VcardContact, ical::parser::ParserError>> {
+    let mut merged: Vec<Result<VcardContact, ical::parser::ParserError>> = Vec::new();
+    let mut by_name = std::collections::HashMap::<String, usize>::new();
+    for card in cards {
+        let Some(key) = card.as_ref().ok().and_then(card_name).map(|name| {
+            name.split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase()
+        }) else {
+            merged.push(card);
+            continue;
+        };
+        match by_name.get(&key) {
+            Some(&i) => {
+                if let (Some(Ok(first)), Ok(card)) = (merged.get_mut(i), card) {
+                    first
+                        .properties
+                        .extend(card.properties.into_iter().filter(|p| p.name == "TEL"));
+                }
+            }
+            None => {
+                by_name.insert(key, merged.len());
+                merged.push(card);
+            }
+        }
+    }
+    merged
+}
+
+/// The name a card is filed under: its FN, or failing that (as in many vCard 2.1 exports) the
+/// given and family names from N
+fn card_name(card: &VcardContact) -> Option<String> {
+    let value = |name: &str| {
+        card.properties
+            .iter()
+            .find(|p| p.name == name)
+            .and_then(|p| p.value.as_deref())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    if let Some(name) = value("FN") {
+        return Some(name.to_string());
+    }
+    // N is family;given;additional;prefixes;suffixes
+    let parts = value("N")?.split(';').collect::<Vec<_>>();
+    let name = [parts.get(1), parts.first()]
+        .into_iter()
+        .flatten()
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!name.is_empty()).then_some(name)
+}
 
-    // Collect all TEL properties with their types/descriptions
+/// Where a contact works and what they do there, from a card's ORG and TITLE
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Details {
+    pub organization: Option<String>,
+    pub title: Option<String>,
+}
+
+impl Details {
+    fn from_card(card: &VcardContact) -> Self {
+        let value = |name: &str, separator: &str| {
+            card.properties
+                .iter()
+                .find(|p| p.name == name)
+                .and_then(|p| p.value.as_deref())
+                .map(|value| {
+                    value
+                        .split(';')
+                        .map(str::trim)
+                        .filter(|part| !part.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(separator)
+                })
+                .filter(|value| !value.is_empty())
+        };
+        Self {
+            // ORG is the organization, then any departments within it
+            organization: value("ORG", ", "),
+            title: value("TITLE", "; "),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.organization.is_none() && self.title.is_none()
+    }
+
+    /// E.g. "Acme, PM", or None if there's nothing to say
+    fn describe(&self) -> Option<String> {
+        let parts = [&self.organization, &self.title]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+
+    fn sealed(&self) -> (Option<String>, Option<String>) {
+        (
+            self.organization.as_deref().map(pii::seal),
+            self.title.as_deref().map(pii::seal),
+        )
+    }
+
+    fn open(organization: Option<String>, title: Option<String>) -> Result<Self> {
+        Ok(Self {
+            organization: organization.as_deref().map(pii::open).transpose()?,
+            title: title.as_deref().map(pii::open).transpose()?,
+        })
+    }
+}
+
+/// Records a contact's details, given their number in plaintext
+pub async fn set_details(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    number: &str,
+    details: &Details,
+) -> Result<()> {
+    let number = pii::seal(number);
+    let (organization, title) = details.sealed();
+    query!(
+        "UPDATE contacts SET organization = ?, title = ?
+         WHERE submitter_number = ? AND contact_user_number = ?",
+        organization,
+        title,
+        from,
+        number
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// The details of each of `from`'s contacts that has any, like "Acme, PM", by contact ID
+pub async fn described(pool: &Pool<Sqlite>, from: &str) -> Result<HashMap<i64, String>> {
+    let mut described = HashMap::new();
+    for row in query!(
+        r#"SELECT id as "id!", organization, title FROM contacts
+         WHERE submitter_number = ? AND deleted_at IS NULL
+            AND (organization IS NOT NULL OR title IS NOT NULL)"#,
+        from
+    )
+    .fetch_all(pool)
+    .await?
+    {
+        if let Some(description) = Details::open(row.organization, row.title)?.describe() {
+            described.insert(row.id, description);
+        }
+    }
+    Ok(described)
+}
+
+/// The numbers on a card in E.164, each with its type (and extension) if it has one
+fn card_numbers(card: &VcardContact) -> Vec<(String, Option<String>)> {
     let mut numbers = Vec::new();
     for prop in card.properties.iter().filter(|p| p.name == "TEL") {
         if let Some(raw_number) = &prop.value {
@@ -63,38 +310,90 @@ pub async fn process_vcard(
                         .and_then(|(_, values)| values.first())
                         .map(|v| v.to_string())
                 });
-                numbers.push((normalized.to_string(), description));
+                // Texts can't reach an extension, but it still tells the numbers apart
+                let description = match (description, normalized.extension()) {
+                    (Some(description), Some(extension)) => {
+                        Some(format!("{description} x{extension}"))
+                    }
+                    (None, Some(extension)) => Some(format!("x{extension}")),
+                    (description, None) => description,
+                };
+                // The same number on two merged cards is only offered once
+                if !numbers
+                    .iter()
+                    .any(|(number, _)| *number == normalized.to_string())
+                {
+                    numbers.push((normalized.to_string(), description));
+                }
             }
         }
     }
+    numbers
+}
+
+pub async fn process_vcard(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    vcard: Result<VcardContact, ical::parser::ParserError>,
+) -> Result<ImportResult> {
+    let Some(user) = query!("SELECT lang FROM users WHERE number = ?", from)
+        .fetch_optional(pool)
+        .await?
+    else {
+        bail!(t!(Lang::default(), "import_needs_name"));
+    };
+    let lang: Lang = user.lang.parse()?;
+
+    let card = vcard?;
 
+    let name = card_name(&card).ok_or_else(|| anyhow::anyhow!(t!(lang, "import_no_name")))?;
+    let name = name.as_str();
+    let details = Details::from_card(&card);
+
+    let mut numbers = card_numbers(&card);
     if numbers.is_empty() {
-        bail!("No valid phone numbers provided");
+        bail!(t!(lang, "import_no_numbers"));
     }
+    numbers.truncate(MAX_NUMBERS_PER_CARD);
 
     // Check existing contacts
     let existing_contacts = query!(
-        "SELECT contact_user_number, contact_name FROM contacts WHERE submitter_number = ?",
+        "SELECT contact_user_number, contact_name FROM contacts
+         WHERE submitter_number = ? AND deleted_at IS NULL",
         from
     )
     .fetch_all(pool)
     .await?;
 
     // If any number matches an existing contact, update that contact's name and return
+    // (sealing is deterministic, so sealed values can be compared directly)
+    let sealed_name = pii::seal(name);
     for (num, _) in &numbers {
+        let sealed_num = pii::seal(num);
         if let Some(existing) = existing_contacts
             .iter()
-            .find(|contact| &contact.contact_user_number == num)
+            .find(|contact| contact.contact_user_number == sealed_num)
         {
-            if existing.contact_name != *name {
+            // A newer card may say where they work now
+            if !details.is_empty() {
+                set_details(pool, from, num, &details).await?;
+            }
+            if existing.contact_name != sealed_name {
                 query!(
                     "UPDATE contacts SET contact_name = ? WHERE submitter_number = ? AND contact_user_number = ?",
-                    name,
+                    sealed_name,
                     from,
-                    num
+                    sealed_num
                 )
                 .execute(pool)
                 .await?;
+                audit::record(
+                    pool,
+                    from,
+                    Kind::Mutation,
+                    format!("renamed contact {num} to \"{name}\""),
+                )
+                .await?;
                 return Ok(ImportResult::Updated);
             }
             return Ok(ImportResult::Unchanged);
@@ -105,32 +404,32 @@ pub async fn process_vcard(
         // Store numbers in deferred_contacts table
         let mut tx = pool.begin().await?;
 
+        // Keep picks from earlier cards in this import, but start over if the user was doing something else
+        session::resume_or_start(&mut tx, from, SessionState::DeferredContacts).await?;
+
         // First clear any existing deferred contacts for this submitter and contact name
         query!(
             "DELETE FROM deferred_contacts WHERE submitter_number = ? AND contact_name = ?",
             from,
-            name
-        )
-        .execute(&mut *tx)
-        .await?;
-
-        // Set the pending action type to deferred_contacts
-        query!(
-            "INSERT OR REPLACE INTO pending_actions (submitter_number, action_type) VALUES (?, 'deferred_contacts')",
-            from
+            sealed_name
         )
         .execute(&mut *tx)
         .await?;
 
         // Insert all numbers as deferred contacts
+        let (organization, title) = details.sealed();
         for (number, description) in numbers {
+            let number = pii::seal(&number);
             query!(
-                "INSERT INTO deferred_contacts (submitter_number, contact_name, phone_number, phone_description) 
-                 VALUES (?, ?, ?, ?)",
+                "INSERT INTO deferred_contacts
+                    (submitter_number, contact_name, phone_number, phone_description, organization, title) 
+                 VALUES (?, ?, ?, ?, ?, ?)",
                 from,
-                name,
+                sealed_name,
                 number,
-                description
+                description,
+                organization,
+                title
             )
             .execute(&mut *tx)
             .await?;
@@ -142,43 +441,142 @@ pub async fn process_vcard(
         // Single number case - proceed with insertion
         let (number, _) = numbers.into_iter().next().unwrap();
         add_contact(pool, from, name, &number).await?;
+        if !details.is_empty() {
+            set_details(pool, from, &number, &details).await?;
+        }
         Ok(ImportResult::Added)
     }
 }
 
+/// Adds a contact for `from` (sealed, like all sender numbers), given its name and number in plaintext
 pub async fn add_contact(pool: &Pool<Sqlite>, from: &str, name: &str, number: &str) -> Result<()> {
+    let sealed_name = pii::seal(name);
+    let sealed_number = pii::seal(number);
     let mut tx = pool.begin().await?;
 
     // Create user if needed
-    let contact_user = query!("SELECT * FROM users WHERE number = ?", number)
+    let contact_user = query!("SELECT * FROM users WHERE number = ?", sealed_number)
         .fetch_optional(&mut *tx)
         .await?;
 
     if contact_user.is_none() {
         query!(
             "INSERT INTO users (number, name) VALUES (?, ?)",
-            number,
-            name
+            sealed_number,
+            sealed_name
         )
         .execute(&mut *tx)
         .await?;
+        audit::record(
+            &mut *tx,
+            from,
+            Kind::Mutation,
+            format!("created user {number} for a new contact"),
+        )
+        .await?;
     }
 
-    // Insert contact
+    // Insert contact, taking the place of any deleted one with the same number still in the trash
     query!(
         "INSERT INTO contacts (submitter_number, contact_name, contact_user_number) 
-         VALUES (?, ?, ?)",
+         VALUES (?, ?, ?)
+         ON CONFLICT(submitter_number, contact_user_number)
+         DO UPDATE SET contact_name = excluded.contact_name, deleted_at = NULL",
         from,
-        name,
-        number
+        sealed_name,
+        sealed_number
     )
     .execute(&mut *tx)
     .await?;
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!("added contact \"{name}\" ({number})"),
+    )
+    .await?;
 
+    lookup::request(&mut tx, &sealed_number).await?;
     tx.commit().await?;
     Ok(())
 }
 
+/// How many contacts `contacts recent` lists
+const RECENT_LIMIT: i64 = 10;
+
+/// A contact's short name that stays the same for as long as it exists, e.g. "c14", for naming
+/// it in commands without the risk of a list having changed in between
+pub fn handle(id: i64) -> String {
+    format!("c{id}")
+}
+
+/// The contact id a handle like "c14" stands for
+pub fn parse_handle(text: &str) -> Option<i64> {
+    let digits = text.strip_prefix(['c', 'C'])?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// The contacts most recently added or changed, newest first, e.g. to check what an import did
+pub async fn recent_contacts(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    zone: Tz,
+) -> Result<String> {
+    let contacts = query!(
+        r#"SELECT id as "id!", contact_name, contact_user_number,
+            created_at = updated_at as "added!: bool",
+            updated_at as "updated_at!: i64"
+         FROM contacts
+         WHERE submitter_number = ? AND deleted_at IS NULL
+         ORDER BY updated_at DESC, id DESC
+         LIMIT ?"#,
+        from,
+        RECENT_LIMIT
+    )
+    .fetch_all(pool)
+    .await?;
+    if contacts.is_empty() {
+        return Ok(t!(lang, "no_groups_or_contacts"));
+    }
+    let viewer = E164::from_str(&pii::open(from)?).ok();
+    let mut response = t!(lang, "recent_contacts");
+    for (i, contact) in contacts.iter().enumerate() {
+        let number = E164::from_str(&pii::open(&contact.contact_user_number)?)
+            .map(|e| e.display_for(viewer.as_ref()))
+            .unwrap_or_else(|_| "???".to_string());
+        let name = format!(
+            "{} ({})",
+            pii::open(&contact.contact_name)?,
+            handle(contact.id)
+        );
+        let date = timezone::display_date(contact.updated_at, zone);
+        response.push_str(&if contact.added {
+            t!(
+                lang,
+                "recent_added_line",
+                index = i + 1,
+                name = name,
+                number = number,
+                date = date
+            )
+        } else {
+            t!(
+                lang,
+                "recent_updated_line",
+                index = i + 1,
+                name = name,
+                number = number,
+                date = date
+            )
+        });
+    }
+    Ok(response)
+}
+
 // Update ImportStats to include deferred count
 #[derive(Default)]
 struct ImportStats {
@@ -188,6 +586,8 @@ struct ImportStats {
     failed: usize,
     deferred: usize,
     errors: std::collections::HashMap<String, usize>,
+    /// The newest of the user's contacts from before the import
+    last_id: i64,
 }
 
 impl ImportStats {
@@ -196,60 +596,59 @@ impl ImportStats {
         self.failed += 1;
     }
 
-    async fn format_report(&self, pool: &Pool<Sqlite>, from: &str) -> Result<String> {
-        let mut report = format!(
-            "Processed contacts: {} added, {} updated, {} unchanged, {} deferred, {} failed",
-            self.added, self.updated, self.skipped, self.deferred, self.failed
+    async fn format_report(&self, pool: &Pool<Sqlite>, from: &str, lang: Lang) -> Result<String> {
+        let mut report = t!(
+            lang,
+            "import_report",
+            added = self.added,
+            updated = self.updated,
+            unchanged = self.skipped,
+            deferred = self.deferred,
+            failed = self.failed
         );
 
         if !self.errors.is_empty() {
-            report.push_str("\nErrors encountered:");
+            report.push_str(&t!(lang, "import_errors"));
             for (error, count) in &self.errors {
                 report.push_str(&format!("\n- {} × {}", count, error));
             }
         }
 
+        let cant_text = query!(
+            r#"SELECT c.contact_name FROM contacts c
+             JOIN users u ON u.number = c.contact_user_number
+             WHERE c.submitter_number = ? AND c.id > ? AND c.deleted_at IS NULL
+                AND u.line_type IN ('landline', 'voip')
+             ORDER BY c.id"#,
+            from,
+            self.last_id
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|contact| pii::open(&contact.contact_name))
+        .collect::<Result<Vec<_>>>()?;
+        if !cant_text.is_empty() {
+            report.push_str(&t!(lang, "import_cant_text", names = cant_text.join(", ")));
+        }
+
         if self.deferred > 0 {
-            // Get all unique contact names for this submitter
-            let contacts = query!(
-                "SELECT DISTINCT contact_name FROM deferred_contacts WHERE submitter_number = ? ORDER BY contact_name",
-                from
-            )
-            .fetch_all(pool)
-            .await?;
+            let names = deferred_names(pool, from).await?;
+
+            if !names.is_empty() {
+                report.push_str(&t!(lang, "import_deferred"));
+
+                for (i, name) in names.iter().enumerate() {
+                    report.push_str(&format!("\n{}. {}", i + 1, name));
 
-            if !contacts.is_empty() {
-                report.push_str(
-                    "\n\nThe following contacts have multiple numbers. \
-                    Reply with \"confirm NA, MB, ...\" \
-                    where N and M are from the list of contacts below \
-                    and A and B are the letters for the desired phone numbers for each.\n",
-                );
-
-                for (i, contact) in contacts.iter().enumerate() {
-                    report.push_str(&format!("\n{}. {}", i + 1, contact.contact_name));
-
-                    // Get all numbers for this contact
-                    let numbers = query!(
-                        "SELECT phone_number, phone_description FROM deferred_contacts 
-                         WHERE submitter_number = ? AND contact_name = ? 
-                         ORDER BY id",
-                        from,
-                        contact.contact_name
-                    )
-                    .fetch_all(pool)
-                    .await?;
-
-                    for (j, number) in numbers.iter().enumerate() {
+                    for (j, (number, description)) in deferred_numbers(pool, from, name)
+                        .await?
+                        .into_iter()
+                        .enumerate()
+                    {
                         let letter = (b'a' + j as u8) as char;
-                        let desc = number
-                            .phone_description
-                            .as_deref()
-                            .unwrap_or("no description");
-                        report.push_str(&format!(
-                            "\n   {}. {} ({})",
-                            letter, number.phone_number, desc
-                        ));
+                        let desc = description.unwrap_or_else(|| t!(lang, "no_description"));
+                        report.push_str(&format!("\n   {}. {} ({})", letter, number, desc));
                     }
                 }
             }
@@ -257,3 +656,57 @@ impl ImportStats {
         Ok(report)
     }
 }
+
+/// Names of the contacts waiting for the user to pick a number, in the order they're listed
+pub async fn deferred_names(pool: &Pool<Sqlite>, from: &str) -> Result<Vec<String>> {
+    let mut names = query!(
+        "SELECT DISTINCT contact_name FROM deferred_contacts WHERE submitter_number = ?",
+        from
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| pii::open(&row.contact_name))
+    .collect::<Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+/// The numbers to choose from for one deferred contact, with their descriptions, in lettered order
+pub async fn deferred_numbers(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    name: &str,
+) -> Result<Vec<(String, Option<String>)>> {
+    let sealed_name = pii::seal(name);
+    query!(
+        "SELECT phone_number, phone_description FROM deferred_contacts 
+         WHERE submitter_number = ? AND contact_name = ? 
+         ORDER BY id",
+        from,
+        sealed_name
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| Ok((pii::open(&row.phone_number)?, row.phone_description)))
+    .collect()
+}
+
+/// The details from the card a deferred contact came from
+pub async fn deferred_details(pool: &Pool<Sqlite>, from: &str, name: &str) -> Result<Details> {
+    let sealed_name = pii::seal(name);
+    match query!(
+        "SELECT organization, title FROM deferred_contacts
+         WHERE submitter_number = ? AND contact_name = ?
+         LIMIT 1",
+        from,
+        sealed_name
+    )
+    .fetch_optional(pool)
+    .await?
+    {
+        Some(row) => Details::open(row.organization, row.title),
+        None => Ok(Details::default()),
+    }
+}
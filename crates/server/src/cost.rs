@@ -0,0 +1,78 @@
+//! Rough estimates of what the bot's texts cost, from how many segments each one takes and
+//! `SEGMENT_PRICE`. The operator sees them in `stats` and `decisionbot-admin spend`, and gets a
+//! text when the month's estimate passes `MONTHLY_BUDGET`. Twilio's invoice has the real numbers.
+//! With several bot numbers, each one is held to the budget separately.
+
+use anyhow::Result;
+use sqlx::{query, Pool, Sqlite};
+use tracing::*;
+
+use crate::{
+    config,
+    outbound::{self, Outgoing},
+};
+
+/// How often the spend is checked against the budget
+pub const BUDGET_CHECK_INTERVAL_SECS: i64 = 60 * 60;
+
+/// What sending that many segments is estimated to cost, in dollars
+pub fn price(segments: i64) -> f64 {
+    segments as f64 * config::get().segment_price
+}
+
+/// Estimated dollars spent today and this month, in UTC
+#[derive(Debug, Default, PartialEq)]
+pub struct Spend {
+    pub today: f64,
+    pub month: f64,
+}
+
+pub async fn spend(pool: &Pool<Sqlite>) -> Result<Spend> {
+    let row = query!(
+        r#"SELECT
+            COALESCE(SUM(price) FILTER (WHERE created_at >= unixepoch('now', 'start of day')), 0)
+                as "today!: f64",
+            COALESCE(SUM(price), 0) as "month!: f64"
+         FROM message_log WHERE created_at >= unixepoch('now', 'start of month')"#
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(Spend {
+        today: row.today,
+        month: row.month,
+    })
+}
+
+/// Texts the operator from `from` if this month's spend has passed the budget,
+/// unless they've already been told this month
+pub async fn check_budget(pool: &Pool<Sqlite>, from: &str) -> Result<()> {
+    let config = config::get();
+    let (Some(budget), Some(operator)) = (config.monthly_budget, config.client_number.clone())
+    else {
+        return Ok(());
+    };
+    let spent = spend(pool).await?.month;
+    if spent < budget {
+        return Ok(());
+    }
+    let first =
+        query!("INSERT OR IGNORE INTO budget_alerts (month) VALUES (strftime('%Y-%m', 'now'))")
+            .execute(pool)
+            .await?
+            .rows_affected()
+            > 0;
+    if !first {
+        return Ok(());
+    }
+    warn!("Estimated spend this month is ${spent:.2}, past the ${budget:.2} budget");
+    let alert = Outgoing {
+        to: operator,
+        body: format!(
+            "Estimated Twilio spend this month is ${spent:.2}, past the ${budget:.2} budget."
+        ),
+    };
+    for (_, result) in outbound::send_all(pool, from, vec![alert]).await? {
+        result?;
+    }
+    Ok(())
+}
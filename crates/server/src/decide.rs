@@ -0,0 +1,216 @@
+//! Quick random choices with `decide`, for when a vote would be overkill.
+//!
+//! `decide pizza, tacos, sushi` picks one of the options, and any option can be given more
+//! weight with a multiplier, e.g. `decide pizza x2, tacos`. `decide among family` picks someone
+//! from a group, or from the contacts named, e.g. `decide among Alice, Bob`. A bare `decide`
+//! shows the last few picks.
+
+use anyhow::Result;
+use chrono_tz::Tz;
+use rand::{distributions::WeightedIndex, prelude::Distribution, thread_rng};
+use sqlx::{query, query_as, Pool, Sqlite};
+
+use crate::{
+    audit::{self, Kind},
+    command::Command,
+    i18n::{t, Lang},
+    pii,
+    search::fold,
+    timezone, Contact,
+};
+
+/// How many past picks a bare `decide` shows
+const RECENT: i64 = 5;
+
+/// Words introducing the people to pick from
+const AMONG_WORDS: &[&str] = &["among", "entre"];
+
+#[derive(Debug, PartialEq, Eq)]
+struct Choice {
+    name: String,
+    weight: u32,
+}
+
+/// `decide` lists recent picks, `decide among <who>` picks a person,
+/// and `decide <options>` picks one of the options
+pub async fn handle_decide(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    zone: Tz,
+    args: &str,
+) -> Result<String> {
+    let args = args.trim();
+    if args.is_empty() {
+        return recent(pool, from, lang, zone).await;
+    }
+    let among = args
+        .split_once(char::is_whitespace)
+        .filter(|(word, _)| AMONG_WORDS.contains(&fold(word).as_str()))
+        .map(|(_, who)| who.trim());
+    let choices = match among {
+        Some(who) => {
+            let choices = people(pool, from, who).await?;
+            if choices.is_empty() {
+                return Ok(t!(lang, "decide_nobody", who = who));
+            }
+            choices
+        }
+        None => parse_choices(args),
+    };
+    if choices.len() < 2 {
+        return Ok(Command::decide.hint(lang));
+    }
+
+    let weights = WeightedIndex::new(choices.iter().map(|c| c.weight))?;
+    let choice = &choices[weights.sample(&mut thread_rng())].name;
+    let options = choices
+        .iter()
+        .map(|c| match c.weight {
+            1 => c.name.clone(),
+            weight => format!("{} x{weight}", c.name),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let (sealed_options, sealed_choice) = (pii::seal(&options), pii::seal(choice));
+    let mut tx = pool.begin().await?;
+    query!(
+        "INSERT INTO picks (creator_number, options, choice) VALUES (?, ?, ?)",
+        from,
+        sealed_options,
+        sealed_choice
+    )
+    .execute(&mut *tx)
+    .await?;
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!("picked \"{choice}\" from {options}"),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(t!(
+        lang,
+        "decide_picked",
+        choice = choice,
+        count = choices.len()
+    ))
+}
+
+/// Options separated by commas, each optionally followed by a weight like "x3"
+fn parse_choices(args: &str) -> Vec<Choice> {
+    args.split(',')
+        .map(str::trim)
+        .filter(|option| !option.is_empty())
+        .map(|option| {
+            let weight = option
+                .rsplit_once(char::is_whitespace)
+                .and_then(|(name, last)| {
+                    last.strip_prefix(['x', 'X', '×', '*'])
+                        .and_then(|weight| weight.parse().ok())
+                        .filter(|weight| *weight > 0)
+                        .map(|weight| (name.trim(), weight))
+                });
+            let (name, weight) = weight.unwrap_or((option, 1));
+            Choice {
+                name: name.to_string(),
+                weight,
+            }
+        })
+        .collect()
+}
+
+/// Everyone in the sender's group named `who`, or else the contacts matching it,
+/// by the names the sender knows them by
+async fn people(pool: &Pool<Sqlite>, from: &str, who: &str) -> Result<Vec<Choice>> {
+    let members = query!(
+        r#"SELECT COALESCE(c.contact_name, u.name) as "name!: String"
+         FROM groups g
+         JOIN group_members gm ON gm.group_id = g.id
+         JOIN users u ON u.number = gm.member_number
+         LEFT JOIN contacts c ON c.submitter_number = g.creator_number
+            AND c.contact_user_number = gm.member_number AND c.deleted_at IS NULL
+         WHERE g.creator_number = ? AND g.name = ? COLLATE NOCASE"#,
+        from,
+        who
+    )
+    .fetch_all(pool)
+    .await?;
+    let names = if members.is_empty() {
+        let fragments: Vec<_> = who.split(',').map(str::trim).collect();
+        Contact::search(
+            Contact::open_all(
+                query_as!(
+                    Contact,
+                    "SELECT id as \"id!\", contact_name, contact_user_number FROM contacts
+                     WHERE submitter_number = ? AND deleted_at IS NULL",
+                    from
+                )
+                .fetch_all(pool)
+                .await?,
+            )?,
+            &fragments,
+        )
+        .into_iter()
+        .map(|c| c.contact_name)
+        .collect()
+    } else {
+        let mut names = members
+            .iter()
+            .map(|m| pii::open(&m.name))
+            .collect::<Result<Vec<_>>>()?;
+        names.sort();
+        names
+    };
+    Ok(names
+        .into_iter()
+        .map(|name| Choice { name, weight: 1 })
+        .collect())
+}
+
+async fn recent(pool: &Pool<Sqlite>, from: &str, lang: Lang, zone: Tz) -> Result<String> {
+    let picks = query!(
+        "SELECT options, choice, created_at FROM picks
+         WHERE creator_number = ? ORDER BY id DESC LIMIT ?",
+        from,
+        RECENT
+    )
+    .fetch_all(pool)
+    .await?;
+    if picks.is_empty() {
+        return Ok(t!(lang, "no_picks", hint = Command::decide.hint(lang)));
+    }
+    let mut response = t!(lang, "your_picks");
+    for (i, pick) in picks.iter().enumerate() {
+        response.push_str(&t!(
+            lang,
+            "pick_line",
+            index = i + 1,
+            date = timezone::display_date(pick.created_at, zone),
+            choice = pii::open(&pick.choice)?,
+            options = pii::open(&pick.options)?
+        ));
+    }
+    Ok(response.trim_end().to_string())
+}
+
+#[test]
+fn choices() {
+    let choice = |name: &str, weight| Choice {
+        name: name.to_string(),
+        weight,
+    };
+    assert_eq!(
+        parse_choices("pizza x2, Thai food, ,sushi ×3, Room X0"),
+        [
+            choice("pizza", 2),
+            choice("Thai food", 1),
+            choice("sushi", 3),
+            choice("Room X0", 1),
+        ]
+    );
+    assert_eq!(parse_choices("tacos"), [choice("tacos", 1)]);
+}
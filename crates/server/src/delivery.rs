@@ -0,0 +1,261 @@
+//! Telling users when a text sent on their behalf, like a reminder to their group or an event
+//! invitation, never arrived, so they can reach that person another way.
+//!
+//! Twilio only knows whether a text was delivered some time after taking it, and reports that to
+//! `/status` when `PUBLIC_URL` is set. Each such text is remembered by its SID until then.
+//!
+//! A number that fails for good [GIVE_UP_AFTER] times in a row, like a landline or one that's
+//! been disconnected, stops getting texts from others, and its contacts show it, until a text
+//! gets through or it texts the bot itself.
+
+use anyhow::Result;
+use axum::{http::StatusCode, Extension, Form};
+use sqlx::{query, Pool, Sqlite};
+use tracing::*;
+
+use crate::{
+    i18n::{t, user_lang, Lang},
+    optout,
+    outbound::{self, Outgoing},
+    pii,
+    tenant::Tenants,
+};
+
+/// How long texts are remembered, well past when Twilio gives up on delivering them
+pub const RETENTION_SECS: i64 = 3 * 24 * 60 * 60;
+
+/// Permanent failures in a row before a number stops getting texts
+pub const GIVE_UP_AFTER: i64 = 3;
+
+/// Twilio error codes that mean the number will never get texts, as opposed to being off or
+/// filtered for now: unknown or invalid numbers, landlines, and numbers that aren't mobile
+const PERMANENT_ERRORS: [&str; 4] = ["30005", "30006", "21211", "21614"];
+
+/// Whether texts to `number` (sealed) have stopped, after failing for good too often, or because
+/// they've opted out (see [optout]) or paused their account
+pub async fn undeliverable(pool: &Pool<Sqlite>, number: &str) -> Result<bool> {
+    let failing = query!(
+        "SELECT 1 as found FROM users
+         WHERE number = ? AND (undeliverable_at IS NOT NULL OR deactivated_at IS NOT NULL)",
+        number
+    )
+    .fetch_optional(pool)
+    .await?
+    .is_some();
+    Ok(failing || optout::opted_out(pool, number).await?)
+}
+
+/// Starts sending to `number` (sealed) again, since it's just texted the bot
+pub async fn heard_from(pool: &Pool<Sqlite>, number: &str) -> Result<()> {
+    query!(
+        "UPDATE users SET delivery_failures = 0, undeliverable_at = NULL
+         WHERE number = ? AND delivery_failures > 0",
+        number
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Remembers the texts in `results` that went out to someone other than `creator`
+pub async fn track(
+    pool: &Pool<Sqlite>,
+    creator: &str,
+    results: &[(Outgoing, Result<String>)],
+) -> Result<()> {
+    for (message, result) in results {
+        let Ok(sid) = result else {
+            continue;
+        };
+        let recipient = pii::seal(&message.to);
+        if recipient == creator {
+            continue;
+        }
+        query!(
+            "INSERT INTO tracked_messages (sid, creator_number, recipient_number) VALUES (?, ?, ?)",
+            sid,
+            creator,
+            recipient
+        )
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+// field names must be exact (including case) to match API
+#[allow(non_snake_case)]
+#[derive(serde::Deserialize, Default, Debug)]
+pub struct StatusCallback {
+    pub MessageSid: String,
+    pub MessageStatus: String,
+    pub ErrorCode: Option<String>,
+    /// The bot number it was sent from, which picks the tenant
+    pub From: Option<String>,
+    pub To: Option<String>,
+}
+
+/// Where Twilio reports what happened to each text
+pub async fn handle_status(
+    Extension(tenants): Extension<Tenants>,
+    Form(status): Form<StatusCallback>,
+) -> StatusCode {
+    let tenant = tenants.get(status.From.as_deref());
+    if status.MessageStatus == "delivered" {
+        return match delivered(&tenant.pool, &status.MessageSid).await {
+            Ok(()) => StatusCode::NO_CONTENT,
+            Err(error) => {
+                error!(
+                    "Error handling the delivery of {}: {error:?}",
+                    status.MessageSid
+                );
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+    }
+    if !matches!(status.MessageStatus.as_str(), "undelivered" | "failed") {
+        return StatusCode::NO_CONTENT;
+    }
+    let result = async {
+        // Twilio knows they've opted out even if the bot missed it
+        if let (Some(optout::OPTED_OUT_ERROR), Some(to)) = (status.ErrorCode.as_deref(), &status.To)
+        {
+            optout::opt_out(&tenant.pool, &pii::seal(to)).await?;
+        }
+        let Some(notice) = notice(
+            &tenant.pool,
+            &status.MessageSid,
+            status.ErrorCode.as_deref(),
+        )
+        .await?
+        else {
+            return Ok(());
+        };
+        for (_, result) in outbound::send_all(&tenant.pool, &tenant.number, vec![notice]).await? {
+            result?;
+        }
+        anyhow::Ok(())
+    };
+    match result.await {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(error) => {
+            error!(
+                "Error handling the status of {}: {error:?}",
+                status.MessageSid
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Forgets a tracked text that arrived, and any failures before it to the same number
+pub async fn delivered(pool: &Pool<Sqlite>, sid: &str) -> Result<()> {
+    let Some(message) = query!(
+        "DELETE FROM tracked_messages WHERE sid = ? RETURNING recipient_number",
+        sid
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(());
+    };
+    query!(
+        "UPDATE users SET delivery_failures = 0, undeliverable_at = NULL WHERE number = ?",
+        message.recipient_number
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// What to tell the creator of a tracked text that couldn't be delivered, once.
+/// None if it wasn't tracked, or they've already been told.
+/// Counts the failure against the number if it's permanent, and stops texting it after
+/// [GIVE_UP_AFTER] in a row, saying so.
+pub async fn notice(
+    pool: &Pool<Sqlite>,
+    sid: &str,
+    error_code: Option<&str>,
+) -> Result<Option<Outgoing>> {
+    let Some(message) = query!(
+        "DELETE FROM tracked_messages WHERE sid = ? RETURNING creator_number, recipient_number",
+        sid
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let contact = query!(
+        "SELECT contact_name FROM contacts
+         WHERE submitter_number = ? AND contact_user_number = ? AND deleted_at IS NULL",
+        message.creator_number,
+        message.recipient_number
+    )
+    .fetch_optional(pool)
+    .await?;
+    let name = match contact {
+        Some(contact) => pii::open(&contact.contact_name)?,
+        None => pii::open(&message.recipient_number)?,
+    };
+    let lang = user_lang(pool, &message.creator_number).await?;
+    info!(
+        "Telling the sender a text to {} wasn't delivered",
+        pii::redact(&pii::open(&message.recipient_number)?)
+    );
+    let mut body = t!(
+        lang,
+        "delivery_failed",
+        name = name,
+        reason = reason(lang, error_code)
+    );
+    if error_code.is_some_and(|code| PERMANENT_ERRORS.contains(&code)) {
+        let given_up = query!(
+            "UPDATE users SET delivery_failures = delivery_failures + 1,
+                undeliverable_at = CASE
+                    WHEN undeliverable_at IS NULL AND delivery_failures + 1 >= ? THEN unixepoch()
+                    ELSE undeliverable_at
+                END
+             WHERE number = ?
+             RETURNING delivery_failures, undeliverable_at",
+            GIVE_UP_AFTER,
+            message.recipient_number
+        )
+        .fetch_optional(pool)
+        .await?
+        // Only the failure that crossed the line gives up, so this is said once
+        .is_some_and(|user| {
+            user.delivery_failures == GIVE_UP_AFTER && user.undeliverable_at.is_some()
+        });
+        if given_up {
+            warn!(
+                "Giving up on texting {} after {GIVE_UP_AFTER} permanent failures",
+                pii::redact(&pii::open(&message.recipient_number)?)
+            );
+            body.push(' ');
+            body.push_str(&t!(
+                lang,
+                "delivery_given_up",
+                name = name,
+                count = GIVE_UP_AFTER
+            ));
+        }
+    }
+    Ok(Some(Outgoing {
+        to: pii::open(&message.creator_number)?,
+        body,
+    }))
+}
+
+/// Why a text wasn't delivered, from Twilio's error code
+fn reason(lang: Lang, error_code: Option<&str>) -> String {
+    match error_code {
+        Some("30003") => t!(lang, "delivery_unreachable"),
+        Some("30004") => t!(lang, "delivery_blocked"),
+        Some("30005") => t!(lang, "delivery_unknown_number"),
+        Some("30006") => t!(lang, "delivery_landline"),
+        Some("30007") => t!(lang, "delivery_filtered"),
+        Some(optout::OPTED_OUT_ERROR) => t!(lang, "delivery_opted_out"),
+        _ => t!(lang, "delivery_unknown_reason"),
+    }
+}
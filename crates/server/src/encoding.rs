@@ -0,0 +1,88 @@
+//! How long a text is as the phone network sees it. Texts written entirely in the GSM-7 alphabet
+//! fit 160 characters in a segment, but a single emoji or letter outside it, like in a contact's
+//! name, sends the whole text as UCS-2, which fits only 70.
+
+/// Characters that take one septet in GSM-7
+const GSM7_BASIC: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?\
+    ¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+
+/// Characters that take two septets in GSM-7, an escape and the character
+const GSM7_EXTENDED: &str = "\x0c^{}\\[~]|€";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gsm7,
+    Ucs2,
+}
+
+impl Encoding {
+    /// The encoding Twilio will send `body` in
+    pub fn of(body: &str) -> Self {
+        if body
+            .chars()
+            .all(|c| GSM7_BASIC.contains(c) || GSM7_EXTENDED.contains(c))
+        {
+            Encoding::Gsm7
+        } else {
+            Encoding::Ucs2
+        }
+    }
+
+    /// How much of a segment `c` takes up, in septets or UTF-16 code units
+    pub fn width(self, c: char) -> usize {
+        match self {
+            Encoding::Gsm7 if GSM7_EXTENDED.contains(c) => 2,
+            Encoding::Gsm7 => 1,
+            Encoding::Ucs2 => c.len_utf16(),
+        }
+    }
+
+    pub fn length(self, body: &str) -> usize {
+        body.chars().map(|c| self.width(c)).sum()
+    }
+
+    /// How much fits in a text sent as one segment
+    fn single(self) -> usize {
+        match self {
+            Encoding::Gsm7 => 160,
+            Encoding::Ucs2 => 70,
+        }
+    }
+
+    /// How much fits in each segment of a longer text, after the header that joins them back up
+    pub fn per_segment(self) -> usize {
+        match self {
+            Encoding::Gsm7 => 153,
+            Encoding::Ucs2 => 67,
+        }
+    }
+}
+
+/// How many segments Twilio splits a text into, which is what it charges by
+pub fn segments(body: &str) -> i64 {
+    let encoding = Encoding::of(body);
+    let length = encoding.length(body);
+    if length <= encoding.single() {
+        1
+    } else {
+        length.div_ceil(encoding.per_segment()) as i64
+    }
+}
+
+#[test]
+fn segment_counts() {
+    assert_eq!(segments(""), 1);
+    assert_eq!(segments(&"a".repeat(160)), 1);
+    assert_eq!(segments(&"a".repeat(161)), 2);
+    assert_eq!(segments(&"a".repeat(306)), 2);
+    assert_eq!(segments(&"a".repeat(307)), 3);
+    // Accents in the GSM-7 alphabet don't change anything, but the ones outside it do
+    assert_eq!(segments(&"é".repeat(160)), 1);
+    assert_eq!(segments(&"á".repeat(70)), 1);
+    assert_eq!(segments(&"á".repeat(71)), 2);
+    assert_eq!(segments(&format!("{}😀", "a".repeat(68))), 1);
+    assert_eq!(segments(&format!("{}😀", "a".repeat(69))), 2);
+    // Each of these takes two septets
+    assert_eq!(segments(&"€".repeat(80)), 1);
+    assert_eq!(segments(&"€".repeat(81)), 2);
+}
@@ -0,0 +1,580 @@
+//! Events announced to a group with `event`, and who's coming.
+//!
+//! `event family friday 7pm: dinner at Sam's` invites everyone in the group, who answer with
+//! `rsvp yes`, `rsvp no` or `rsvp maybe`. A bare `event` lists upcoming events with their
+//! headcounts, and `event N` shows the organizer who's coming. Anyone who hasn't answered is
+//! reminded once, a day before the event (or halfway there, for events sooner than that).
+//!
+//! Invitations and reminders go out through the job runner ([Job::EventInvitations] and
+//! [Job::EventReminder]), with the same blocking and opt-in rules as reminders.
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use sqlx::{query, query_as, Pool, Sqlite};
+use tracing::*;
+
+use crate::{
+    audit::{self, Kind},
+    block,
+    command::Command,
+    consent::{self, Consent},
+    delivery,
+    i18n::{t, Lang},
+    jobs::{self, Job},
+    outbound::{self, Outgoing},
+    pii,
+    quota::{self, Quota},
+    search::fold,
+    timezone, when,
+};
+
+/// How long before an event anyone who hasn't answered is reminded
+const REMINDER_LEAD_SECS: i64 = 24 * 60 * 60;
+
+/// An answer to an invitation, as stored in `rsvps.response`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Response {
+    Yes,
+    No,
+    Maybe,
+}
+
+impl Response {
+    fn parse(word: &str) -> Option<Self> {
+        match fold(word).as_str() {
+            "yes" | "y" | "si" | "s" => Some(Self::Yes),
+            "no" | "n" => Some(Self::No),
+            "maybe" | "quizas" | "quiza" | "talvez" => Some(Self::Maybe),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Yes => "yes",
+            Self::No => "no",
+            Self::Maybe => "maybe",
+        }
+    }
+
+    /// How an answer (or the lack of one) is shown
+    fn describe(response: Option<&str>, lang: Lang) -> String {
+        match response {
+            Some("yes") => t!(lang, "rsvp_yes"),
+            Some("no") => t!(lang, "rsvp_no"),
+            Some("maybe") => t!(lang, "rsvp_maybe"),
+            _ => t!(lang, "rsvp_none"),
+        }
+    }
+}
+
+/// An upcoming event the sender organized or was invited to
+struct Listed {
+    id: i64,
+    title: String,
+    starts_at: i64,
+    group_name: String,
+    organizer_number: String,
+    organizer_name: String,
+    invited: bool,
+    response: Option<String>,
+    yes: i64,
+    no: i64,
+    maybe: i64,
+    pending: i64,
+}
+
+impl Listed {
+    fn time(&self, zone: Tz) -> String {
+        timezone::display(
+            DateTime::from_timestamp(self.starts_at, 0).unwrap_or_default(),
+            zone,
+        )
+    }
+}
+
+/// `event` lists upcoming events, `event N` shows one, and `event <group> <when>: <title>`
+/// announces one
+pub async fn handle_event(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    zone: Tz,
+    args: &str,
+) -> Result<String> {
+    let args = args.trim();
+    if args.is_empty() {
+        return list(pool, from, lang, zone).await;
+    }
+    if let Ok(selection) = args.parse::<usize>() {
+        return details(pool, from, lang, zone, selection).await;
+    }
+
+    let Some((head, title)) = when::split_message(args) else {
+        return Ok(Command::event.hint(lang));
+    };
+    let Some((group, time)) = head.trim().split_once(char::is_whitespace) else {
+        return Ok(Command::event.hint(lang));
+    };
+    if title.is_empty() {
+        return Ok(Command::event.hint(lang));
+    }
+    let now = Utc::now().with_timezone(&zone);
+    let Some(starts_at) = when::parse(time, &now) else {
+        return Ok(t!(lang, "remind_bad_time", time = time.trim()));
+    };
+    if starts_at <= now {
+        return Ok(t!(
+            lang,
+            "remind_past",
+            time = timezone::display(starts_at.to_utc(), zone)
+        ));
+    }
+    let Some(group) = query!(
+        "SELECT id, name FROM groups WHERE creator_number = ? AND name = ? COLLATE NOCASE",
+        from,
+        group
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(t!(lang, "event_unknown_group", group = group));
+    };
+
+    if let Some(reply) = quota::check(pool, from, lang, Quota::Scheduled).await? {
+        return Ok(reply);
+    }
+    if let Some(reply) = quota::check_recipients(pool, lang, group.id, &group.name).await? {
+        return Ok(reply);
+    }
+
+    let sealed_title = pii::seal(title);
+    let timestamp = starts_at.timestamp();
+    let lead = timestamp - now.timestamp();
+    let mut tx = pool.begin().await?;
+    let id = query!(
+        "INSERT INTO events (organizer_number, group_id, group_name, title, starts_at)
+         VALUES (?, ?, ?, ?, ?)",
+        from,
+        group.id,
+        group.name,
+        sealed_title,
+        timestamp
+    )
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+    jobs::enqueue(&mut *tx, &Job::EventInvitations { id }).await?;
+    quota::record(&mut *tx, from, Quota::Scheduled).await?;
+    jobs::enqueue_in(
+        &mut *tx,
+        &Job::EventReminder { id },
+        (lead / 2).max(lead - REMINDER_LEAD_SECS),
+    )
+    .await?;
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!(
+            "announced event {id} to {} at {timestamp}: \"{title}\"",
+            group.name
+        ),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(t!(
+        lang,
+        "event_scheduled",
+        title = title,
+        group = group.name,
+        time = timezone::display(starts_at.to_utc(), zone),
+        command = Command::event
+    ))
+}
+
+/// The sender's upcoming events, soonest first, in the order they're numbered
+async fn upcoming(pool: &Pool<Sqlite>, from: &str) -> Result<Vec<Listed>> {
+    query_as!(
+        Listed,
+        r#"SELECT e.id as "id!", e.title, e.starts_at, e.group_name, e.organizer_number,
+            u.name as organizer_name, r.number IS NOT NULL as "invited!: bool", r.response,
+            (SELECT COUNT(*) FROM rsvps WHERE event_id = e.id AND response = 'yes') as "yes!: i64",
+            (SELECT COUNT(*) FROM rsvps WHERE event_id = e.id AND response = 'no') as "no!: i64",
+            (SELECT COUNT(*) FROM rsvps WHERE event_id = e.id AND response = 'maybe') as "maybe!: i64",
+            (SELECT COUNT(*) FROM rsvps WHERE event_id = e.id AND response IS NULL) as "pending!: i64"
+         FROM events e
+         JOIN users u ON u.number = e.organizer_number
+         LEFT JOIN rsvps r ON r.event_id = e.id AND r.number = ?1
+         WHERE e.starts_at > unixepoch() AND (e.organizer_number = ?1 OR r.number IS NOT NULL)
+         ORDER BY e.starts_at, e.id"#,
+        from
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|event| {
+        Ok(Listed {
+            title: pii::open(&event.title)?,
+            organizer_name: pii::open(&event.organizer_name)?,
+            ..event
+        })
+    })
+    .collect()
+}
+
+async fn list(pool: &Pool<Sqlite>, from: &str, lang: Lang, zone: Tz) -> Result<String> {
+    let events = upcoming(pool, from).await?;
+    if events.is_empty() {
+        return Ok(t!(lang, "no_events", hint = Command::event.hint(lang)));
+    }
+    let mut response = t!(lang, "your_events");
+    for (i, event) in events.iter().enumerate() {
+        response.push_str(&if event.organizer_number == from {
+            t!(
+                lang,
+                "event_line_organizer",
+                index = i + 1,
+                title = event.title,
+                time = event.time(zone),
+                group = event.group_name,
+                yes = event.yes,
+                maybe = event.maybe,
+                no = event.no,
+                pending = event.pending
+            )
+        } else {
+            t!(
+                lang,
+                "event_line_invited",
+                index = i + 1,
+                title = event.title,
+                time = event.time(zone),
+                name = event.organizer_name,
+                response = Response::describe(event.response.as_deref(), lang)
+            )
+        });
+    }
+    response.push_str(&t!(
+        lang,
+        "event_instructions",
+        command = Command::event,
+        rsvp = Command::rsvp
+    ));
+    Ok(response)
+}
+
+/// Who's coming, for the organizer, or the headcount and the sender's answer, for a guest
+async fn details(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    zone: Tz,
+    selection: usize,
+) -> Result<String> {
+    let events = upcoming(pool, from).await?;
+    let Some(event) = selection.checked_sub(1).and_then(|i| events.get(i)) else {
+        return Ok(t!(lang, "invalid_selection", selection = selection));
+    };
+    if event.organizer_number != from {
+        return Ok(t!(
+            lang,
+            "event_invitation_status",
+            title = event.title,
+            time = event.time(zone),
+            name = event.organizer_name,
+            yes = event.yes,
+            maybe = event.maybe,
+            response = Response::describe(event.response.as_deref(), lang)
+        ));
+    }
+
+    // By the names the organizer has them saved under
+    let guests = query!(
+        r#"SELECT r.response, COALESCE(c.contact_name, u.name) as "name!: String"
+         FROM rsvps r
+         JOIN users u ON u.number = r.number
+         LEFT JOIN contacts c ON c.submitter_number = ? AND c.contact_user_number = r.number
+            AND c.deleted_at IS NULL
+         WHERE r.event_id = ?"#,
+        from,
+        event.id
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut names = guests
+        .iter()
+        .map(|guest| Ok((guest.response.as_deref(), pii::open(&guest.name)?)))
+        .collect::<Result<Vec<_>>>()?;
+    names.sort();
+    let with = |response: Option<&str>| {
+        let matching: Vec<_> = names
+            .iter()
+            .filter(|(r, _)| *r == response)
+            .map(|(_, name)| name.as_str())
+            .collect();
+        if matching.is_empty() {
+            "-".to_string()
+        } else {
+            matching.join(", ")
+        }
+    };
+    Ok(t!(
+        lang,
+        "event_guests",
+        title = event.title,
+        time = event.time(zone),
+        yes = with(Some("yes")),
+        maybe = with(Some("maybe")),
+        no = with(Some("no")),
+        pending = with(None)
+    ))
+}
+
+/// `rsvp yes|no|maybe` answers the sender's only invitation, and `rsvp N yes|no|maybe`
+/// the Nth event in their list
+pub async fn handle_rsvp(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    args: &str,
+) -> Result<String> {
+    let mut selection = None;
+    let mut response = None;
+    for word in args.split_whitespace() {
+        match (word.parse::<usize>(), Response::parse(word)) {
+            (Ok(number), _) if selection.is_none() => selection = Some(number),
+            (_, Some(answer)) if response.is_none() => response = Some(answer),
+            _ => return Ok(Command::rsvp.hint(lang)),
+        }
+    }
+    let Some(response) = response else {
+        return Ok(Command::rsvp.hint(lang));
+    };
+
+    let events = upcoming(pool, from).await?;
+    let event = match selection {
+        Some(selection) => match selection.checked_sub(1).and_then(|i| events.get(i)) {
+            Some(event) if event.invited => event,
+            Some(_) => return Ok(t!(lang, "rsvp_not_invited", index = selection)),
+            None => return Ok(t!(lang, "invalid_selection", selection = selection)),
+        },
+        None => {
+            let mut invitations = events.iter().filter(|event| event.invited);
+            match (invitations.next(), invitations.next()) {
+                (Some(event), None) => event,
+                (None, _) => return Ok(t!(lang, "rsvp_no_invitations")),
+                (Some(_), Some(_)) => {
+                    return Ok(t!(
+                        lang,
+                        "rsvp_which",
+                        command = Command::event,
+                        rsvp = Command::rsvp
+                    ))
+                }
+            }
+        }
+    };
+
+    let answer = response.as_str();
+    let mut tx = pool.begin().await?;
+    query!(
+        "UPDATE rsvps SET response = ?, updated_at = unixepoch() WHERE event_id = ? AND number = ?",
+        answer,
+        event.id,
+        from
+    )
+    .execute(&mut *tx)
+    .await?;
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!("answered {answer} to event {}", event.id),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(t!(
+        lang,
+        "rsvp_recorded",
+        response = Response::describe(Some(answer), lang),
+        title = event.title
+    ))
+}
+
+/// A recipient's language and time zone, for showing them the event
+async fn recipient_settings(pool: &Pool<Sqlite>, number: &str) -> Result<(Lang, Tz)> {
+    let user = query!("SELECT lang, timezone FROM users WHERE number = ?", number)
+        .fetch_one(pool)
+        .await?;
+    Ok((
+        user.lang.parse()?,
+        timezone::from_stored(user.timezone.as_deref()),
+    ))
+}
+
+/// Sends an event's invitations to its group from the bot number `sender`
+pub async fn invite(pool: &Pool<Sqlite>, sender: &str, id: i64) -> Result<()> {
+    send(pool, sender, id, invitations(pool, id).await?).await
+}
+
+/// Reminds everyone who hasn't answered an event's invitation, from the bot number `sender`
+pub async fn remind(pool: &Pool<Sqlite>, sender: &str, id: i64) -> Result<()> {
+    send(pool, sender, id, reminders(pool, id).await?).await
+}
+
+/// An event's invitations, each in its recipient's language and time zone, marking everyone
+/// invited as waiting for an answer. Members who've blocked the organizer are left out, as are
+/// those who haven't agreed to messages from others; anyone not yet asked gets asked instead
+/// (see [consent]). Empty if the group's been deleted.
+pub async fn invitations(pool: &Pool<Sqlite>, id: i64) -> Result<Vec<Outgoing>> {
+    let Some(event) = query!(
+        "SELECT e.organizer_number, e.group_id, e.title, e.starts_at, u.name
+         FROM events e JOIN users u ON u.number = e.organizer_number WHERE e.id = ?",
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(Vec::new());
+    };
+    let Some(group_id) = event.group_id else {
+        return Ok(Vec::new());
+    };
+    let members = query!(
+        "SELECT member_number FROM group_members WHERE group_id = ?",
+        group_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let (name, title) = (pii::open(&event.name)?, pii::open(&event.title)?);
+    let starts_at = DateTime::from_timestamp(event.starts_at, 0).unwrap_or_default();
+
+    let mut messages = Vec::new();
+    for member in members.into_iter().map(|row| row.member_number) {
+        if member == event.organizer_number
+            || block::has_blocked(pool, &member, &event.organizer_number).await?
+            || delivery::undeliverable(pool, &member).await?
+        {
+            continue;
+        }
+        let (lang, zone) = recipient_settings(pool, &member).await?;
+        let body = match consent::status(pool, &member).await? {
+            Consent::Granted => {
+                query!(
+                    "INSERT OR IGNORE INTO rsvps (event_id, number) VALUES (?, ?)",
+                    id,
+                    member
+                )
+                .execute(pool)
+                .await?;
+                t!(
+                    lang,
+                    "event_invitation",
+                    name = name,
+                    title = title,
+                    time = timezone::display(starts_at, zone),
+                    rsvp = Command::rsvp
+                )
+            }
+            Consent::Unknown => {
+                consent::mark_requested(pool, &member).await?;
+                t!(lang, "consent_request", name = name)
+            }
+            Consent::Requested | Consent::Declined => continue,
+        };
+        messages.push(Outgoing {
+            to: pii::open(&member)?,
+            body,
+        });
+    }
+    Ok(messages)
+}
+
+/// Reminders for everyone who hasn't answered an event's invitation, leaving out anyone who's
+/// since blocked the organizer. Empty if the event has started.
+pub async fn reminders(pool: &Pool<Sqlite>, id: i64) -> Result<Vec<Outgoing>> {
+    let Some(event) = query!(
+        "SELECT e.organizer_number, e.title, e.starts_at, u.name
+         FROM events e JOIN users u ON u.number = e.organizer_number
+         WHERE e.id = ? AND e.starts_at > unixepoch()",
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(Vec::new());
+    };
+    let pending = query!(
+        "SELECT number FROM rsvps WHERE event_id = ? AND response IS NULL",
+        id
+    )
+    .fetch_all(pool)
+    .await?;
+    let (name, title) = (pii::open(&event.name)?, pii::open(&event.title)?);
+    let starts_at = DateTime::from_timestamp(event.starts_at, 0).unwrap_or_default();
+
+    let mut messages = Vec::new();
+    for guest in pending.into_iter().map(|row| row.number) {
+        if block::has_blocked(pool, &guest, &event.organizer_number).await?
+            || delivery::undeliverable(pool, &guest).await?
+        {
+            continue;
+        }
+        let (lang, zone) = recipient_settings(pool, &guest).await?;
+        messages.push(Outgoing {
+            to: pii::open(&guest)?,
+            body: t!(
+                lang,
+                "event_reminder",
+                name = name,
+                title = title,
+                time = timezone::display(starts_at, zone),
+                rsvp = Command::rsvp
+            ),
+        });
+    }
+    Ok(messages)
+}
+
+/// Sends an event's texts. Fails only if none got through, since retrying after a partial
+/// failure would repeat them to everyone they did reach.
+async fn send(pool: &Pool<Sqlite>, sender: &str, id: i64, messages: Vec<Outgoing>) -> Result<()> {
+    if messages.is_empty() {
+        return Ok(());
+    }
+    let count = messages.len();
+    let results = outbound::send_all(pool, sender, messages).await?;
+    let organizer = query!("SELECT organizer_number FROM events WHERE id = ?", id)
+        .fetch_one(pool)
+        .await?
+        .organizer_number;
+    // They've gone out either way, so this isn't worth failing over
+    if let Err(error) = delivery::track(pool, &organizer, &results).await {
+        warn!("Couldn't track event {id}'s texts: {error:?}");
+    }
+    let failed = results
+        .into_iter()
+        .filter_map(|(message, result)| result.err().map(|error| (message, error)))
+        .inspect(|(message, error)| {
+            warn!(
+                "Couldn't send event {id} to {}: {error:?}",
+                pii::redact(&message.to)
+            )
+        })
+        .count();
+    if failed == count {
+        bail!("Couldn't send event {id} to anyone");
+    }
+    Ok(())
+}
+
+#[test]
+fn responses() {
+    assert_eq!(Response::parse("Yes"), Some(Response::Yes));
+    assert_eq!(Response::parse("sí"), Some(Response::Yes));
+    assert_eq!(Response::parse("Quizás"), Some(Response::Maybe));
+    assert_eq!(Response::parse("nope"), None);
+}
@@ -0,0 +1,289 @@
+use anyhow::{Context, Result};
+use axum::{
+    extract::Path,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Extension,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use serde_json::json;
+use sqlx::{query, Pool, Sqlite};
+use tracing::*;
+
+use crate::{
+    config,
+    i18n::{t, Lang},
+    pii,
+    tenant::Tenants,
+};
+
+/// How long an export link stays valid
+const EXPORT_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// Assembles everything stored about `from` and replies with a link to download it
+pub async fn handle_export(pool: &Pool<Sqlite>, from: &str, lang: Lang) -> Result<String> {
+    let body = pii::seal(&serde_json::to_string_pretty(&collect(pool, from).await?)?);
+    let token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    let public_url = config::get()
+        .public_url
+        .as_deref()
+        .context("PUBLIC_URL is needed for export links")?;
+
+    query!(
+        "INSERT INTO exports (token, number, body, expires_at) VALUES (?, ?, ?, unixepoch() + ?)",
+        token,
+        from,
+        body,
+        EXPORT_TTL_SECS
+    )
+    .execute(pool)
+    .await?;
+
+    info!("Created data export");
+    Ok(t!(
+        lang,
+        "export_ready",
+        link = format!("{}/export/{token}", public_url.trim_end_matches('/')),
+        hours = EXPORT_TTL_SECS / 60 / 60
+    ))
+}
+
+async fn collect(pool: &Pool<Sqlite>, from: &str) -> Result<serde_json::Value> {
+    let open_all = |values: Vec<String>| -> Result<Vec<String>> {
+        values.iter().map(|v| pii::open(v)).collect()
+    };
+    let user = query!(
+        "SELECT number, name, lang, timezone, consent, terms_version FROM users WHERE number = ?",
+        from
+    )
+    .fetch_one(pool)
+    .await?;
+    let contacts = query!(
+        "SELECT contact_name, contact_user_number, organization, title, deleted_at FROM contacts
+         WHERE submitter_number = ? ORDER BY contact_name",
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+    let groups = query!(
+        "SELECT g.name, GROUP_CONCAT(gm.member_number) as members
+         FROM groups g LEFT JOIN group_members gm ON gm.group_id = g.id
+         WHERE g.creator_number = ?
+         GROUP BY g.id ORDER BY g.name",
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+    // Other people's data is left out: only the names the user is saved under, not who saved them
+    let saved_as = query!(
+        "SELECT contact_name FROM contacts WHERE contact_user_number = ? ORDER BY contact_name",
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+    let member_of = query!(
+        "SELECT g.name FROM group_members gm JOIN groups g ON g.id = gm.group_id
+         WHERE gm.member_number = ? ORDER BY g.name",
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+    let blocks = query!(
+        "SELECT blocked_number FROM blocks WHERE blocker_number = ? ORDER BY created_at",
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+    let reminders = query!(
+        "SELECT target, body, send_at, status FROM scheduled_messages
+         WHERE creator_number = ? ORDER BY send_at, id",
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+    let later_messages = query!(
+        "SELECT target, body, time, status FROM later_messages
+         WHERE creator_number = ? ORDER BY id",
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+    let picks = query!(
+        "SELECT options, choice, created_at FROM picks WHERE creator_number = ? ORDER BY id",
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+    let rotations = query!(
+        r#"SELECT r.name, r.turn, r.interval_days,
+            (SELECT GROUP_CONCAT(name, char(10)) FROM
+                (SELECT name FROM rotation_members WHERE rotation_id = r.id ORDER BY position)
+            ) as "members: String"
+         FROM rotations r WHERE r.creator_number = ? ORDER BY r.name"#,
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+    let events = query!(
+        "SELECT group_name, title, starts_at, created_at FROM events
+         WHERE organizer_number = ? ORDER BY id",
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+    let rsvps = query!(
+        "SELECT e.title, e.starts_at, r.response FROM rsvps r JOIN events e ON e.id = r.event_id
+         WHERE r.number = ? ORDER BY e.id",
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+    let history = query!(
+        "SELECT created_at, kind, detail FROM audit_log WHERE number = ? ORDER BY id",
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(json!({
+        "user": {
+            "number": pii::open(&user.number)?,
+            "name": pii::open(&user.name)?,
+            "language": user.lang,
+            "timezone": user.timezone,
+            "consent": user.consent,
+            "terms_version": user.terms_version,
+        },
+        "contacts": contacts
+            .into_iter()
+            .map(|c| Ok(json!({
+                "name": pii::open(&c.contact_name)?,
+                "number": pii::open(&c.contact_user_number)?,
+                "organization": c.organization.as_deref().map(pii::open).transpose()?,
+                "title": c.title.as_deref().map(pii::open).transpose()?,
+                "deleted_at": c.deleted_at,
+            })))
+            .collect::<Result<Vec<_>>>()?,
+        "groups": groups
+            .into_iter()
+            .map(|g| Ok(json!({
+                "name": g.name,
+                "members": open_all(g.members
+                    .map(|m| m.split(',').map(str::to_string).collect())
+                    .unwrap_or_default())?,
+            })))
+            .collect::<Result<Vec<_>>>()?,
+        "saved_by_others_as": open_all(saved_as.into_iter().map(|c| c.contact_name).collect())?,
+        "member_of_groups": member_of.into_iter().map(|g| g.name).collect::<Vec<_>>(),
+        "blocked_numbers": open_all(blocks.into_iter().map(|b| b.blocked_number).collect())?,
+        "reminders": reminders
+            .into_iter()
+            .map(|r| Ok(json!({
+                "for": pii::open(&r.target)?,
+                "message": pii::open(&r.body)?,
+                "send_at": r.send_at,
+                "status": r.status,
+            })))
+            .collect::<Result<Vec<_>>>()?,
+        "later_messages": later_messages
+            .into_iter()
+            .map(|m| Ok(json!({
+                "for": pii::open(&m.target)?,
+                "message": pii::open(&m.body)?,
+                "time": m.time,
+                "status": m.status,
+            })))
+            .collect::<Result<Vec<_>>>()?,
+        "picks": picks
+            .into_iter()
+            .map(|p| Ok(json!({
+                "options": pii::open(&p.options)?,
+                "choice": pii::open(&p.choice)?,
+                "at": p.created_at,
+            })))
+            .collect::<Result<Vec<_>>>()?,
+        "rotations": rotations
+            .into_iter()
+            .map(|r| Ok(json!({
+                "name": r.name,
+                "members": open_all(r.members
+                    .map(|m| m.split('\n').map(str::to_string).collect())
+                    .unwrap_or_default())?,
+                "turn": r.turn,
+                "interval_days": r.interval_days,
+            })))
+            .collect::<Result<Vec<_>>>()?,
+        "events": events
+            .into_iter()
+            .map(|e| Ok(json!({
+                "group": e.group_name,
+                "title": pii::open(&e.title)?,
+                "starts_at": e.starts_at,
+                "created_at": e.created_at,
+            })))
+            .collect::<Result<Vec<_>>>()?,
+        "rsvps": rsvps
+            .into_iter()
+            .map(|r| Ok(json!({
+                "title": pii::open(&r.title)?,
+                "starts_at": r.starts_at,
+                "response": r.response,
+            })))
+            .collect::<Result<Vec<_>>>()?,
+        "history": history
+            .into_iter()
+            .map(|h| Ok(json!({
+                "at": h.created_at,
+                "kind": h.kind,
+                "detail": pii::open(&h.detail)?,
+            })))
+            .collect::<Result<Vec<_>>>()?,
+    }))
+}
+
+/// Looks up an export that hasn't expired, in whichever tenant's database has it
+async fn find(tenants: &Tenants, token: &str) -> Result<Option<String>> {
+    for tenant in tenants.all() {
+        if let Some(export) = query!(
+            "SELECT body FROM exports WHERE token = ? AND expires_at > unixepoch()",
+            token
+        )
+        .fetch_optional(&tenant.pool)
+        .await?
+        {
+            return Ok(Some(pii::open(&export.body)?));
+        }
+    }
+    Ok(None)
+}
+
+/// Serves a previously generated export until its link expires
+pub async fn serve_export(
+    Extension(tenants): Extension<Tenants>,
+    Path(token): Path<String>,
+) -> impl IntoResponse {
+    match find(&tenants, &token).await {
+        Ok(Some(body)) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "application/json")],
+            body,
+        ),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            [(header::CONTENT_TYPE, "text/plain")],
+            "Export not found or expired".to_string(),
+        ),
+        Err(error) => {
+            error!("Error serving export: {error:?}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                [(header::CONTENT_TYPE, "text/plain")],
+                "Internal Server Error!".to_string(),
+            )
+        }
+    }
+}
@@ -1,55 +1,131 @@
 use std::str::FromStr;
 
-use crate::{cleanup_expired_pending_actions, command::Command, util::E164};
+use crate::{
+    command::{Category, Command},
+    contacts::{deferred_names, deferred_numbers},
+    i18n::{t, Lang},
+    session::{self, SessionState},
+    timezone,
+    util::E164,
+    Contact,
+};
 use anyhow::Result;
+use chrono::DateTime;
+use chrono_tz::Tz;
 use enum_iterator::all;
-use sqlx::{query, Pool, Sqlite};
-
-pub async fn handle_help(pool: &Pool<Sqlite>, from: &str) -> Result<String> {
-    cleanup_expired_pending_actions(pool).await?;
-
-    let mut response = format!(
-        "General commands:\n{}\n",
-        all::<Command>()
-            .filter(|c| match c {
-                Command::confirm => false,
-                _ => true,
-            })
-            .map(|c| format!("- {c}"))
-            .collect::<Vec<_>>()
-            .join("\n")
-    );
-    response.push_str(&format!("\n{}", Command::info.hint()));
+use sqlx::{query_as, Pool, Sqlite};
+
+pub async fn handle_help(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    topic: Option<&str>,
+) -> Result<String> {
+    if let Some(topic) = topic {
+        return Ok(match Category::try_from(topic) {
+            Ok(category) => category_page(category, lang),
+            Err(()) => t!(
+                lang,
+                "help_unknown_category",
+                topic = topic,
+                index = index(lang)
+            ),
+        });
+    }
 
-    if let Some(pending_prompt) = get_pending_action_prompt(pool, from).await? {
-        response.push_str(&pending_prompt);
+    let mut response = index(lang);
+    if let Some(session_prompt) = get_session_prompt(pool, from, lang).await? {
+        response.push_str(&session_prompt);
+        response.push_str(&format!("\n\n{}", Command::nevermind.hint(lang)));
     }
 
     Ok(response)
 }
 
-async fn get_pending_action_prompt(pool: &Pool<Sqlite>, from: &str) -> Result<Option<String>> {
-    let pending = query!(
-        "SELECT action_type FROM pending_actions WHERE submitter_number = ?",
-        from
+/// `pending`: what the user is in the middle of, and when it will be dropped if they don't finish
+pub async fn handle_pending(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    zone: Tz,
+) -> Result<String> {
+    let Some((state, expires_at)) = session::current_with_expiry(pool, from).await? else {
+        return Ok(t!(lang, "nothing_pending"));
+    };
+    let mut response = t!(
+        lang,
+        "pending_session",
+        session = state.description(lang),
+        time = timezone::display(
+            DateTime::from_timestamp(expires_at, 0).unwrap_or_default(),
+            zone
+        )
+    );
+    if let Some(session_prompt) = get_session_prompt(pool, from, lang).await? {
+        response.push_str(&session_prompt);
+    }
+    response.push_str(&t!(lang, "pending_cancel", command = Command::nevermind));
+    Ok(response)
+}
+
+/// The compact overview: one line per category
+fn index(lang: Lang) -> String {
+    let categories = all::<Category>()
+        .enumerate()
+        .map(|(i, category)| {
+            let commands = category
+                .commands()
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{}. {} ({commands})", i + 1, category.name(lang))
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    t!(
+        lang,
+        "help_index",
+        categories = categories,
+        command = Command::h,
+        info_hint = Command::info.hint(lang)
+    )
+}
+
+/// The detail page for one category
+fn category_page(category: Category, lang: Lang) -> String {
+    let commands = category
+        .commands()
+        .iter()
+        .map(|c| format!("- {c}: {}", c.description(lang)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    t!(
+        lang,
+        "help_category_page",
+        category = category.name(lang),
+        commands = commands
     )
-    .fetch_optional(pool)
-    .await?;
-
-    match pending {
-        Some(row) => {
-            let prompt = match row.action_type.as_str() {
-                "deletion" => {
-                    let contacts = query!(
-                        "SELECT c.contact_name, c.contact_user_number 
-                         FROM pending_deletions pd
-                         JOIN contacts c ON c.id = pd.contact_id 
-                         WHERE pd.pending_action_submitter = ?
-                         ORDER BY c.contact_name",
-                        from
-                    )
-                    .fetch_all(pool)
-                    .await?;
+}
+
+async fn get_session_prompt(pool: &Pool<Sqlite>, from: &str, lang: Lang) -> Result<Option<String>> {
+    match session::current(pool, from).await? {
+        Some(state) => {
+            let prompt = match state {
+                SessionState::Deletion => {
+                    let contacts = Contact::open_in_order(
+                        query_as!(
+                            Contact,
+                            "SELECT c.id as \"id!\", c.contact_name, c.contact_user_number 
+                             FROM pending_deletions pd
+                             JOIN contacts c ON c.id = pd.contact_id 
+                             WHERE pd.session_submitter = ?
+                             ORDER BY pd.id",
+                            from
+                        )
+                        .fetch_all(pool)
+                        .await?,
+                    )?;
 
                     if contacts.is_empty() {
                         return Ok(None);
@@ -60,77 +136,56 @@ async fn get_pending_action_prompt(pool: &Pool<Sqlite>, from: &str) -> Result<Op
                         .enumerate()
                         .map(|(i, c)| {
                             let area_code = E164::from_str(&c.contact_user_number)
-                                .map(|e| e.area_code().to_string())
+                                .map(|e| e.short_form())
                                 .unwrap_or_else(|_| "???".to_string());
                             format!("{}. {} ({})", i + 1, c.contact_name, area_code)
                         })
                         .collect::<Vec<_>>()
                         .join("\n");
 
-                    format!(
-                        "\n\nYou have pending contact deletions:\n{}\n\
-                        To delete contacts, reply \"confirm NUM1, NUM2, ...\"",
-                        list
-                    )
+                    t!(lang, "prompt_deletion", list = list)
                 }
-                "deferred_contacts" => {
-                    let contacts = query!(
-                        "SELECT DISTINCT contact_name FROM deferred_contacts 
-                         WHERE submitter_number = ? 
-                         ORDER BY contact_name",
-                        from
-                    )
-                    .fetch_all(pool)
-                    .await?;
+                SessionState::DeferredContacts => {
+                    let names = deferred_names(pool, from).await?;
 
-                    if contacts.is_empty() {
+                    if names.is_empty() {
                         return Ok(None);
                     }
 
-                    let mut response =
-                        "\n\nYou have contacts with multiple numbers pending:\n".to_string();
+                    let mut response = t!(lang, "prompt_deferred");
 
-                    for (i, contact) in contacts.iter().enumerate() {
-                        response.push_str(&format!("\n{}. {}", i + 1, contact.contact_name));
+                    for (i, name) in names.iter().enumerate() {
+                        response.push_str(&format!("\n{}. {}", i + 1, name));
 
-                        let numbers = query!(
-                            "SELECT phone_number, phone_description 
-                             FROM deferred_contacts 
-                             WHERE submitter_number = ? AND contact_name = ?
-                             ORDER BY id",
-                            from,
-                            contact.contact_name
-                        )
-                        .fetch_all(pool)
-                        .await?;
-
-                        for (j, number) in numbers.iter().enumerate() {
+                        for (j, (number, description)) in deferred_numbers(pool, from, name)
+                            .await?
+                            .into_iter()
+                            .enumerate()
+                        {
                             let letter = (b'a' + j as u8) as char;
-                            let desc = number
-                                .phone_description
-                                .as_deref()
-                                .unwrap_or("no description");
-                            response.push_str(&format!(
-                                "\n   {}. {} ({})",
-                                letter, number.phone_number, desc
-                            ));
+                            let desc = description.unwrap_or_else(|| t!(lang, "no_description"));
+                            response.push_str(&format!("\n   {}. {} ({})", letter, number, desc));
                         }
                     }
 
-                    response.push_str("\n\nReply with \"confirm NA, MB, ...\" where N and M are contact numbers and A and B are letter choices");
+                    response.push_str(&t!(lang, "prompt_deferred_instructions"));
                     response
                 }
-                "group" => {
-                    let contacts = query!(
-                        "SELECT c.contact_name, c.contact_user_number 
-                         FROM pending_group_members pgm
-                         JOIN contacts c ON c.id = pgm.contact_id 
-                         WHERE pgm.pending_action_submitter = ?
-                         ORDER BY c.contact_name",
-                        from
-                    )
-                    .fetch_all(pool)
-                    .await?;
+                SessionState::Onboarding => return Ok(None),
+                SessionState::Group => {
+                    let contacts = Contact::open_in_order(
+                        query_as!(
+                            Contact,
+                            "SELECT c.id as \"id!\", c.contact_name, c.contact_user_number 
+                             FROM pending_group_members pgm
+                             JOIN contacts c ON c.id = pgm.contact_id 
+                             WHERE pgm.session_submitter = ?
+                             ORDER BY pgm.id",
+                            from
+                        )
+                        .fetch_all(pool)
+                        .await?,
+                    )?;
 
                     if contacts.is_empty() {
                         return Ok(None);
@@ -141,20 +196,15 @@ async fn get_pending_action_prompt(pool: &Pool<Sqlite>, from: &str) -> Result<Op
                         .enumerate()
                         .map(|(i, c)| {
                             let area_code = E164::from_str(&c.contact_user_number)
-                                .map(|e| e.area_code().to_string())
+                                .map(|e| e.short_form())
                                 .unwrap_or_else(|_| "???".to_string());
                             format!("{}. {} ({})", i + 1, c.contact_name, area_code)
                         })
                         .collect::<Vec<_>>()
                         .join("\n");
 
-                    format!(
-                        "\n\nYou have a pending group creation:\n{}\n\
-                        To create a group with these contacts, reply \"confirm NUM1, NUM2, ...\"",
-                        list
-                    )
+                    t!(lang, "prompt_group", list = list)
                 }
-                _ => return Ok(None),
             };
             Ok(Some(prompt))
         }
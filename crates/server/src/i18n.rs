@@ -0,0 +1,1772 @@
+use anyhow::{bail, Context, Result};
+use enum_iterator::{all, Sequence};
+use once_cell::sync::OnceCell;
+use sqlx::{query, Pool, Sqlite};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::str::FromStr;
+use tracing::*;
+
+/// A language the bot can reply in.
+/// The discriminant is the column of that language's text in [CATALOG].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Sequence)]
+pub enum Lang {
+    #[default]
+    En = 0,
+    Es = 1,
+}
+
+impl Lang {
+    /// Code stored in the users table
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::Es => "es",
+        }
+    }
+
+    /// The language's name for itself
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::En => "English",
+            Self::Es => "Español",
+        }
+    }
+
+    pub fn list() -> String {
+        all::<Lang>()
+            .map(|l| format!("{} ({})", l.code(), l.name()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl FromStr for Lang {
+    type Err = anyhow::Error;
+
+    /// Accepts a code or a name, in any case, with or without accents
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_lowercase().as_str() {
+            "en" | "english" | "ingles" | "inglés" => Self::En,
+            "es" | "spanish" | "espanol" | "español" => Self::Es,
+            _ => bail!("Unknown language: {s}"),
+        })
+    }
+}
+
+/// Looks up the stored language for a number, falling back to the default for unknown numbers
+pub async fn user_lang(pool: &Pool<Sqlite>, number: &str) -> Result<Lang> {
+    let lang = query!("SELECT lang FROM users WHERE number = ?", number)
+        .fetch_optional(pool)
+        .await?
+        .map(|row| row.lang.parse())
+        .transpose()?;
+    Ok(lang.unwrap_or_default())
+}
+
+/// Replacement texts for catalog messages, by language and key
+type Overrides = HashMap<(usize, &'static str), String>;
+
+static OVERRIDES: OnceCell<Overrides> = OnceCell::new();
+
+/// Loads replacements for catalog messages from the JSON file named by `MESSAGES_FILE`, if set,
+/// so the bot's wording can be changed without recompiling. The file maps language codes to
+/// keys to texts, e.g. `{"en": {"welcome": "Hi from Acme, {name}! {hint}"}}`; anything left
+/// out keeps its built-in text. Call once at startup.
+pub fn load_overrides() -> Result<()> {
+    let Some(path) = &crate::config::get().messages_file else {
+        return Ok(());
+    };
+    let json = fs::read_to_string(path).with_context(|| format!("While reading {path}"))?;
+    let overrides = parse_overrides(&json).with_context(|| format!("In {path}"))?;
+    info!("Loaded {} message override(s) from {path}", overrides.len());
+    if OVERRIDES.set(overrides).is_err() {
+        bail!("Message overrides were already loaded");
+    }
+    Ok(())
+}
+
+/// Checks every override against the catalog, so mistakes show up at startup
+/// rather than in a reply
+fn parse_overrides(json: &str) -> Result<Overrides> {
+    let file: HashMap<String, HashMap<String, String>> = serde_json::from_str(json)?;
+    let mut overrides = Overrides::new();
+    for (code, texts) in file {
+        let lang: Lang = code.parse()?;
+        for (key, text) in texts {
+            let Some((key, builtin)) = CATALOG.iter().find(|(k, _)| *k == key) else {
+                bail!("Unknown message key: {key}");
+            };
+            let allowed = placeholders(builtin[0]);
+            if let Some(unknown) = placeholders(&text)
+                .into_iter()
+                .find(|name| !allowed.contains(name))
+            {
+                bail!("Message {key} can't use {{{unknown}}}; it has: {allowed:?}");
+            }
+            overrides.insert((lang as usize, key), text);
+        }
+    }
+    Ok(overrides)
+}
+
+/// The names of the `{placeholders}` in a text, sorted
+fn placeholders(text: &str) -> Vec<&str> {
+    let mut names: Vec<_> = text
+        .split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Fills in a catalog message. Prefer the [t] macro.
+pub fn translate(lang: Lang, key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let Some((key, texts)) = CATALOG.iter().find(|(k, _)| *k == key) else {
+        error!("Missing message catalog entry: {key}");
+        return key.to_string();
+    };
+    let mut text = OVERRIDES
+        .get()
+        .and_then(|overrides| overrides.get(&(lang as usize, *key)))
+        .map(String::as_str)
+        .unwrap_or(texts[lang as usize])
+        .to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{name}}}"), &value.to_string());
+    }
+    text
+}
+
+/// `t!(lang, "key", name = value, ...)` renders catalog message `key` in `lang`,
+/// replacing each `{name}` placeholder with `value`
+macro_rules! t {
+    ($lang:expr, $key:literal) => {
+        $crate::i18n::translate($lang, $key, &[])
+    };
+    ($lang:expr, $key:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::translate(
+            $lang,
+            $key,
+            &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+],
+        )
+    };
+}
+pub(crate) use t;
+
+/// Every user-facing message, as `(key, [english, spanish])`.
+/// Placeholders in braces must appear in every language's version.
+const CATALOG: &[(&str, [&str; 2])] = &[
+    // Commands
+    (
+        "command_h",
+        [
+            "show a list of available commands by category",
+            "ver la lista de comandos por categoría",
+        ],
+    ),
+    (
+        "command_info",
+        [
+            "see information about a command",
+            "ver información sobre un comando",
+        ],
+    ),
+    (
+        "command_name",
+        ["set your preferred name", "elegir tu nombre preferido"],
+    ),
+    (
+        "command_me",
+        [
+            "see what the bot knows about you",
+            "ver lo que el bot sabe de ti",
+        ],
+    ),
+    (
+        "me",
+        [
+            "Here's what I know about you:\nName: {name}\nNumber: {number}\nLanguage: {language}\nTime zone: {zone} (now {time})\nMessages from others: {consent}\n{contacts} contacts, {groups} groups, {blocked} blocked\n{scheduled} scheduled texts, {events} upcoming events, {rotations} rotations, {picks} decisions",
+            "Esto es lo que sé de ti:\nNombre: {name}\nNúmero: {number}\nIdioma: {language}\nZona horaria: {zone} (ahora {time})\nMensajes de otros: {consent}\n{contacts} contactos, {groups} grupos, {blocked} bloqueados\n{scheduled} mensajes programados, {events} eventos próximos, {rotations} turnos, {picks} decisiones",
+        ],
+    ),
+    (
+        "me_consent_unknown",
+        ["not asked yet", "aún no se ha preguntado"],
+    ),
+    (
+        "me_consent_requested",
+        ["asked, waiting for your reply", "preguntado, esperando tu respuesta"],
+    ),
+    (
+        "me_consent_granted",
+        ["allowed", "permitidos"],
+    ),
+    (
+        "me_consent_declined",
+        ["turned down", "rechazados"],
+    ),
+    (
+        "command_stop",
+        [
+            "stop receiving messages, keeping your contacts in case you come back",
+            "dejar de recibir mensajes, guardando tus contactos por si vuelves",
+        ],
+    ),
+    (
+        "command_pin",
+        [
+            "set a PIN that stop, export, movenumber and deleting several contacts at once ask for",
+            "elegir un PIN que stop, export, movenumber y borrar varios contactos a la vez piden",
+        ],
+    ),
+    (
+        "param_pin",
+        [
+            "a 4-digit PIN, or your current PIN and a new one to change it, or \"off\" and your PIN to remove it",
+            "un PIN de 4 dígitos, o tu PIN actual y uno nuevo para cambiarlo, o \"off\" y tu PIN para quitarlo",
+        ],
+    ),
+    (
+        "pin_set",
+        [
+            "Your PIN is set. I'll ask for it before stop, export, movenumber and deleting several contacts at once. Send \"{command} off\" and your PIN to remove it.",
+            "Tu PIN quedó guardado. Te lo pediré antes de stop, export, movenumber y de borrar varios contactos a la vez. Envía \"{command} off\" y tu PIN para quitarlo.",
+        ],
+    ),
+    (
+        "pin_on",
+        [
+            "You have a PIN. To change it, send \"{command}\" with your current PIN and the new one. To remove it, send \"{command} off\" and your PIN.",
+            "Tienes un PIN. Para cambiarlo, envía \"{command}\" con tu PIN actual y el nuevo. Para quitarlo, envía \"{command} off\" y tu PIN.",
+        ],
+    ),
+    (
+        "pin_off",
+        [
+            "You don't have a PIN.\n{hint}",
+            "No tienes un PIN.\n{hint}",
+        ],
+    ),
+    (
+        "pin_invalid",
+        [
+            "A PIN is {length} digits.",
+            "Un PIN tiene {length} dígitos.",
+        ],
+    ),
+    (
+        "pin_removed",
+        ["Your PIN is removed.", "Tu PIN fue quitado."],
+    ),
+    (
+        "pin_required",
+        [
+            "Reply with your PIN within {minutes} minutes to go ahead with \"{command}\".",
+            "Responde con tu PIN en menos de {minutes} minutos para continuar con \"{command}\".",
+        ],
+    ),
+    (
+        "pin_wrong",
+        [
+            "That's not your PIN. {left} tries left.",
+            "Ese no es tu PIN. Quedan {left} intentos.",
+        ],
+    ),
+    (
+        "pin_locked",
+        [
+            "Too many wrong PINs. Try again in {minutes} minutes.",
+            "Demasiados PIN incorrectos. Inténtalo de nuevo en {minutes} minutos.",
+        ],
+    ),
+    (
+        "command_movenumber",
+        [
+            "move your account to a new phone number",
+            "mover tu cuenta a un número de teléfono nuevo",
+        ],
+    ),
+    (
+        "param_movenumber",
+        [
+            "your new number, then the code texted to it",
+            "tu número nuevo, y luego el código que se le envía",
+        ],
+    ),
+    (
+        "move_code_sent",
+        [
+            "I've texted a code to {number}. Once it arrives, reply here with \"{command} CODE\" within {minutes} minutes to move your account there.",
+            "Envié un código a {number}. Cuando llegue, responde aquí con \"{command} CÓDIGO\" en menos de {minutes} minutos para mover tu cuenta allí.",
+        ],
+    ),
+    (
+        "move_code",
+        [
+            "Your code to move {name}'s account to this number is {code}. If you didn't ask for this, ignore it.",
+            "Tu código para mover la cuenta de {name} a este número es {code}. Si no lo pediste, ignóralo.",
+        ],
+    ),
+    (
+        "move_done",
+        [
+            "Your account now lives at {number}. Text from there from now on.",
+            "Tu cuenta ahora está en {number}. A partir de ahora escribe desde allí.",
+        ],
+    ),
+    (
+        "move_wrong_code",
+        [
+            "That's not the code. {left} tries left.",
+            "Ese no es el código. Quedan {left} intentos.",
+        ],
+    ),
+    (
+        "move_too_many_attempts",
+        [
+            "Too many wrong codes. Send \"{command}\" with your new number to start over.",
+            "Demasiados códigos incorrectos. Envía \"{command}\" con tu número nuevo para empezar de nuevo.",
+        ],
+    ),
+    (
+        "move_code_expired",
+        [
+            "That code has expired. Send \"{command}\" with your new number to get another.",
+            "Ese código expiró. Envía \"{command}\" con tu número nuevo para recibir otro.",
+        ],
+    ),
+    (
+        "move_not_started",
+        [
+            "You're not moving to a new number. Send \"{command}\" with your new number to start.",
+            "No estás cambiando de número. Envía \"{command}\" con tu número nuevo para empezar.",
+        ],
+    ),
+    (
+        "move_cancelled",
+        [
+            "Cancelled moving to a new number.",
+            "Se canceló el cambio de número.",
+        ],
+    ),
+    (
+        "move_same_number",
+        [
+            "That's the number you're texting from.",
+            "Ese es el número desde el que escribes.",
+        ],
+    ),
+    (
+        "move_taken",
+        [
+            "{number} already has its own account. To move there, send \"stop purge\" from it first.",
+            "{number} ya tiene su propia cuenta. Para mudarte allí, envía primero \"stop purge\" desde ese número.",
+        ],
+    ),
+    (
+        "move_opted_out",
+        [
+            "{number} has opted out of texts from this number. Text START from it first.",
+            "{number} dejó de recibir mensajes de este número. Envía START desde allí primero.",
+        ],
+    ),
+    (
+        "command_start",
+        [
+            "come back after \"stop\", with your contacts and groups as you left them",
+            "volver después de \"stop\", con tus contactos y grupos como los dejaste",
+        ],
+    ),
+    (
+        "param_stop",
+        [
+            "\"purge\" to also delete your account and everything in it",
+            "\"purge\" para además borrar tu cuenta y todo lo que contiene",
+        ],
+    ),
+    (
+        "command_import",
+        [
+            "add contacts by sending contact cards",
+            "agregar contactos enviando tarjetas de contacto",
+        ],
+    ),
+    (
+        "command_again",
+        [
+            "get the bot's last reply again",
+            "recibir otra vez la última respuesta del bot",
+        ],
+    ),
+    (
+        "command_contacts",
+        [
+            "see a list of your groups and contacts",
+            "ver la lista de tus grupos y contactos",
+        ],
+    ),
+    (
+        "command_delete",
+        ["delete a contact by name", "borrar un contacto por nombre"],
+    ),
+    (
+        "command_confirm",
+        [
+            "confirm pending action(s)",
+            "confirmar acción(es) pendiente(s)",
+        ],
+    ),
+    (
+        "command_group",
+        [
+            "create a new group from your contacts",
+            "crear un grupo nuevo con tus contactos",
+        ],
+    ),
+    (
+        "command_nevermind",
+        [
+            "abandon the action you're in the middle of",
+            "abandonar la acción que tienes a medias",
+        ],
+    ),
+    (
+        "command_pending",
+        [
+            "see what you're in the middle of and when it expires",
+            "ver lo que tienes a medias y cuándo caduca",
+        ],
+    ),
+    (
+        "command_language",
+        [
+            "choose the language the bot replies in",
+            "elegir el idioma en el que responde el bot",
+        ],
+    ),
+    ("param_info", ["a command", "un comando"]),
+    ("param_name", ["your name", "tu nombre"]),
+    (
+        "param_delete",
+        [
+            "contact name to delete, or its handle from \"contacts\", like c14",
+            "el nombre del contacto a borrar, o su código de \"contacts\", como c14",
+        ],
+    ),
+    (
+        "param_confirm",
+        [
+            "number(s) from a list of pending actions",
+            "número(s) de una lista de acciones pendientes",
+        ],
+    ),
+    (
+        "param_group",
+        [
+            "comma-separated list of contact name fragments or handles like c14",
+            "una lista de partes de nombres de contactos o códigos como c14, separadas por comas",
+        ],
+    ),
+    (
+        "command_block",
+        [
+            "stop receiving decisions from someone",
+            "dejar de recibir decisiones de alguien",
+        ],
+    ),
+    (
+        "command_unblock",
+        ["undo a block", "deshacer un bloqueo"],
+    ),
+    (
+        "command_export",
+        [
+            "get a copy of everything stored about you",
+            "obtener una copia de todo lo que guardamos sobre ti",
+        ],
+    ),
+    (
+        "param_import",
+        [
+            "\"replace\" (optional) with a full export, to also remove contacts missing from it",
+            "\"replace\" (opcional) con una exportación completa, para quitar también los contactos que no estén en ella",
+        ],
+    ),
+    (
+        "param_contacts",
+        [
+            "\"recent\" (optional) to list the newest additions and changes first",
+            "\"recent\" (opcional) para ver primero lo último que se agregó o cambió",
+        ],
+    ),
+    (
+        "command_trash",
+        [
+            "list recently deleted contacts",
+            "ver los contactos borrados recientemente",
+        ],
+    ),
+    (
+        "command_restore",
+        ["bring back deleted contacts", "recuperar contactos borrados"],
+    ),
+    (
+        "param_restore",
+        [
+            "number(s) from the trash list",
+            "número(s) de la lista de la papelera",
+        ],
+    ),
+    (
+        "command_remind",
+        [
+            "schedule a text to yourself, a contact or a group, or list and cancel scheduled ones",
+            "programar un mensaje para ti, un contacto o un grupo, o ver y cancelar los programados",
+        ],
+    ),
+    (
+        "command_decide",
+        [
+            "have me pick one of several options, or someone from a group, at random",
+            "que elija al azar una de varias opciones, o a alguien de un grupo",
+        ],
+    ),
+    (
+        "command_event",
+        [
+            "invite a group to an event and see who's coming",
+            "invitar a un grupo a un evento y ver quién viene",
+        ],
+    ),
+    (
+        "command_rsvp",
+        [
+            "answer an invitation to an event",
+            "responder a una invitación a un evento",
+        ],
+    ),
+    (
+        "command_rotation",
+        [
+            "keep track of whose turn it is, for chores and the like",
+            "llevar la cuenta de a quién le toca, para tareas y demás",
+        ],
+    ),
+    (
+        "command_sendlater",
+        [
+            "write a text now to send at a time in each recipient's own time zone, or list and cancel them",
+            "escribir un mensaje ahora para enviarlo a una hora en la zona horaria de cada destinatario, o verlos y cancelarlos",
+        ],
+    ),
+    (
+        "param_remind",
+        [
+            "who (\"me\", a contact or a group), when (\"tomorrow 9am\", \"friday\", \"in 2 hours\"), a colon and the message; leave it out to list your reminders",
+            "a quién (\"yo\", un contacto o un grupo), cuándo (\"mañana 9am\", \"viernes\", \"en 2 horas\"), dos puntos y el mensaje; déjalo vacío para ver tus recordatorios",
+        ],
+    ),
+    (
+        "param_decide",
+        [
+            "options separated by commas, with \"x2\" after any that should be twice as likely, or \"among\" and a group or contacts; leave it out to see recent picks",
+            "opciones separadas por comas, con \"x2\" tras las que deban salir el doble, o \"entre\" y un grupo o contactos; déjalo vacío para ver las elecciones recientes",
+        ],
+    ),
+    (
+        "param_event",
+        [
+            "a group, when (\"friday 7pm\", \"tomorrow\"), a colon and what it is; a number from the list to see who's coming; leave it out to list upcoming events",
+            "un grupo, cuándo (\"viernes 7pm\", \"mañana\"), dos puntos y de qué se trata; un número de la lista para ver quién viene; déjalo vacío para ver los próximos eventos",
+        ],
+    ),
+    (
+        "param_rsvp",
+        [
+            "yes, no or maybe, after the event's number if you're invited to more than one",
+            "sí, no o quizás, tras el número del evento si tienes más de una invitación",
+        ],
+    ),
+    (
+        "param_rotation",
+        [
+            "\"create\", a name, a colon and who takes turns; \"next\" and a name to move it on; \"every\", a name and a number of days to move it on by itself; a name to see it; leave it out to list them",
+            "\"crear\", un nombre, dos puntos y quiénes se turnan; \"siguiente\" y un nombre para pasar el turno; \"cada\", un nombre y un número de días para que pase solo; un nombre para verlo; déjalo vacío para ver todos",
+        ],
+    ),
+    (
+        "command_timezone",
+        [
+            "see or set your time zone, used for every time you send or see",
+            "ver o elegir tu zona horaria, que se usa en todas las horas que envías o ves",
+        ],
+    ),
+    (
+        "param_timezone",
+        [
+            "a time zone, like \"America/New_York\", \"PST\" or \"Madrid\"",
+            "una zona horaria, como \"America/New_York\", \"PST\" o \"Madrid\"",
+        ],
+    ),
+    (
+        "param_language",
+        ["a language code ({langs})", "un código de idioma ({langs})"],
+    ),
+    (
+        "param_block",
+        [
+            "a contact's name or phone number",
+            "el nombre o número de teléfono de un contacto",
+        ],
+    ),
+    (
+        "usage_with_param",
+        [
+            "Reply \"{command} X\", where X is {param}",
+            "Responde \"{command} X\", donde X es {param}",
+        ],
+    ),
+    ("usage", ["Reply \"{command}\"", "Responde \"{command}\""]),
+    (
+        "example",
+        ["\nExample: \"{command} {example}\"", "\nEjemplo: \"{command} {example}\""],
+    ),
+    (
+        "hint",
+        ["{usage}, to {description}.{example}", "{usage}, para {description}.{example}"],
+    ),
+    // Help
+    ("category_contacts", ["contacts", "contactos"]),
+    ("category_account", ["account", "cuenta"]),
+    ("category_messages", ["messages", "mensajes"]),
+    ("category_decisions", ["decisions", "decisiones"]),
+    (
+        "help_index",
+        [
+            "Command categories:\n{categories}\n\nReply \"{command} X\", where X is a category name or number, to see its commands.\n{info_hint}",
+            "Categorías de comandos:\n{categories}\n\nResponde \"{command} X\", donde X es el nombre o número de una categoría, para ver sus comandos.\n{info_hint}",
+        ],
+    ),
+    (
+        "help_category_page",
+        ["Commands for {category}:\n{commands}", "Comandos de {category}:\n{commands}"],
+    ),
+    (
+        "help_unknown_category",
+        [
+            "Category \"{topic}\" not recognized.\n\n{index}",
+            "No reconocemos la categoría \"{topic}\".\n\n{index}",
+        ],
+    ),
+    (
+        "prompt_deletion",
+        [
+            "\n\nYou have pending contact deletions:\n{list}\nTo delete contacts, reply \"confirm NUM1, NUM2, ...\"",
+            "\n\nTienes contactos pendientes de borrar:\n{list}\nPara borrarlos, responde \"confirm NUM1, NUM2, ...\"",
+        ],
+    ),
+    (
+        "prompt_deferred",
+        [
+            "\n\nYou have contacts with multiple numbers pending:\n",
+            "\n\nTienes contactos con varios números pendientes:\n",
+        ],
+    ),
+    (
+        "prompt_deferred_instructions",
+        [
+            "\n\nReply with \"confirm NA, MB, ...\" where N and M are contact numbers and A and B are letter choices",
+            "\n\nResponde \"confirm NA, MB, ...\" donde N y M son números de contacto y A y B son las letras elegidas",
+        ],
+    ),
+    (
+        "prompt_group",
+        [
+            "\n\nYou have a pending group creation:\n{list}\nTo create a group with these contacts, reply \"confirm NUM1, NUM2, ...\"",
+            "\n\nTienes un grupo pendiente de crear:\n{list}\nPara crear un grupo con estos contactos, responde \"confirm NUM1, NUM2, ...\"",
+        ],
+    ),
+    ("no_description", ["no description", "sin descripción"]),
+    // Sessions
+    ("session_deletion", ["deletion", "borrado"]),
+    (
+        "session_deferred_contacts",
+        ["contact import", "importación de contactos"],
+    ),
+    ("session_group", ["group creation", "creación de grupo"]),
+    ("session_onboarding", ["sign-up", "registro"]),
+    (
+        "session_cancelled",
+        ["Cancelled your pending {session}.", "Se canceló tu {session} pendiente."],
+    ),
+    (
+        "expired_deletion",
+        [
+            "Your pending {session} expired after {minutes} minutes, so nothing was deleted. Send \"{command} NAME\" to start again.",
+            "Tu {session} pendiente caducó tras {minutes} minutos, así que no se borró nada. Envía \"{command} NOMBRE\" para empezar de nuevo.",
+        ],
+    ),
+    (
+        "expired_deferred_contacts",
+        [
+            "Your pending {session} expired after {minutes} minutes, so the contacts with several numbers weren't added. Resend the contact cards to try again.",
+            "Tu {session} pendiente caducó tras {minutes} minutos, así que no se agregaron los contactos con varios números. Vuelve a enviar las tarjetas de contacto para intentarlo de nuevo.",
+        ],
+    ),
+    (
+        "expired_group",
+        [
+            "Your pending {session} expired after {minutes} minutes, so no group was made. Send \"{command} NAMES\" to start again.",
+            "Tu {session} pendiente caducó tras {minutes} minutos, así que no se creó el grupo. Envía \"{command} NOMBRES\" para empezar de nuevo.",
+        ],
+    ),
+    (
+        "pending_session",
+        [
+            "In progress: {session} (expires {time})",
+            "En curso: {session} (caduca {time})",
+        ],
+    ),
+    (
+        "pending_cancel",
+        [
+            "\n\nReply \"{command}\" to drop it.",
+            "\n\nResponde \"{command}\" para descartarlo.",
+        ],
+    ),
+    (
+        "nothing_pending",
+        [
+            "You don't have anything in progress.",
+            "No tienes nada en curso.",
+        ],
+    ),
+    (
+        "nothing_to_cancel",
+        [
+            "You don't have anything in progress to cancel.",
+            "No tienes nada en curso que cancelar.",
+        ],
+    ),
+    // Onboarding and account
+    (
+        "greeting",
+        [
+            "Greetings! This is Decision Bot (https://github.com/samcarey/decisionbot).\nWhat name should your friends see? Just reply with it.",
+            "¡Hola! Este es Decision Bot (https://github.com/samcarey/decisionbot).\n¿Qué nombre deben ver tus amigos? Solo responde con él.",
+        ],
+    ),
+    (
+        "onboarding_confirm",
+        [
+            "Reply YES to use \"{name}\", or send a different name.",
+            "Responde SÍ para usar \"{name}\", o envía otro nombre.",
+        ],
+    ),
+    (
+        "onboarding_confirm_terms",
+        [
+            "Reply YES to use \"{name}\" and accept the terms of service ({url}), or send a different name.",
+            "Responde SÍ para usar \"{name}\" y aceptar los términos del servicio ({url}), o envía otro nombre.",
+        ],
+    ),
+    (
+        "terms_changed",
+        [
+            "The terms of service have changed: {url}\nReply YES to accept them and keep using Decision Bot, or \"stop\" to leave.",
+            "Los términos del servicio han cambiado: {url}\nResponde SÍ para aceptarlos y seguir usando Decision Bot, o \"stop\" para salir.",
+        ],
+    ),
+    (
+        "terms_accepted",
+        [
+            "Thanks for accepting the terms!",
+            "¡Gracias por aceptar los términos!",
+        ],
+    ),
+    (
+        "onboarding_ask_again",
+        [
+            "OK, what name should your friends see?",
+            "De acuerdo, ¿qué nombre deben ver tus amigos?",
+        ],
+    ),
+    (
+        "welcome",
+        [
+            "Hello, {name}! Here's a quick tour:\n- Send a contact card to add contacts\n- \"group\" makes a group out of them\n- \"remind\" schedules a text\n- \"language\" and \"timezone\" set how I talk to you\n{hint}",
+            "¡Hola, {name}! Un recorrido rápido:\n- Envía una tarjeta de contacto para añadir contactos\n- \"group\" crea un grupo con ellos\n- \"remind\" programa un mensaje\n- \"language\" y \"timezone\" ajustan cómo te hablo\n{hint}",
+        ],
+    ),
+    (
+        "name_too_long",
+        [
+            "That name is {length} characters long.\nPlease shorten it to {max} characters or less.",
+            "Ese nombre tiene {length} caracteres.\nAcórtalo a {max} caracteres o menos.",
+        ],
+    ),
+    (
+        "name_updated",
+        [
+            "Your name has been updated to \"{name}\"",
+            "Tu nombre se cambió a \"{name}\"",
+        ],
+    ),
+    (
+        "unsubscribed",
+        [
+            "You've been unsubscribed. Goodbye!",
+            "Se canceló tu suscripción. ¡Adiós!",
+        ],
+    ),
+    (
+        "account_kept",
+        [
+            "Your contacts and groups are kept in case you come back: text {start} to pick up where you left off, or \"{stop} purge\" to delete everything.",
+            "Tus contactos y grupos se guardan por si vuelves: envía {start} para seguir donde lo dejaste, o \"{stop} purge\" para borrarlo todo.",
+        ],
+    ),
+    (
+        "account_paused",
+        [
+            "Your account is paused since you texted STOP. Text {start} to come back, or \"{stop} purge\" to delete everything.",
+            "Tu cuenta está en pausa desde que enviaste STOP. Envía {start} para volver, o \"{stop} purge\" para borrarlo todo.",
+        ],
+    ),
+    (
+        "welcome_back",
+        [
+            "Welcome back! Your {contacts} contact(s) and {groups} group(s) are as you left them.",
+            "¡Bienvenido de nuevo! Tus {contacts} contacto(s) y {groups} grupo(s) están como los dejaste.",
+        ],
+    ),
+    (
+        "already_active",
+        [
+            "You're already subscribed.",
+            "Ya estás suscrito.",
+        ],
+    ),
+    (
+        "account_removed",
+        [
+            "Removed {contacts} contact(s), {groups} group(s), {listings} listing(s) in other people's contacts and {memberships} group membership(s).",
+            "Se eliminaron {contacts} contacto(s), {groups} grupo(s), {listings} registro(s) en los contactos de otras personas y {memberships} membresía(s) de grupos.",
+        ],
+    ),
+    (
+        "language_current",
+        [
+            "Your language is {language}. Available languages: {langs}\n{hint}",
+            "Tu idioma es {language}. Idiomas disponibles: {langs}\n{hint}",
+        ],
+    ),
+    (
+        "language_updated",
+        [
+            "Your language has been set to {language}.",
+            "Tu idioma ahora es {language}.",
+        ],
+    ),
+    (
+        "language_unknown",
+        [
+            "We don't support \"{language}\" yet. Available languages: {langs}",
+            "Todavía no tenemos \"{language}\". Idiomas disponibles: {langs}",
+        ],
+    ),
+    (
+        "blocked",
+        [
+            "Blocked {name}. You won't receive their decisions anymore. Reply \"{command} {name}\" to undo.",
+            "Bloqueaste a {name}. Ya no recibirás sus decisiones. Responde \"{command} {name}\" para deshacerlo.",
+        ],
+    ),
+    ("unblocked", ["Unblocked {name}.", "Desbloqueaste a {name}."]),
+    (
+        "not_blocked",
+        ["{name} isn't blocked.", "{name} no está bloqueado."],
+    ),
+    (
+        "block_ambiguous",
+        [
+            "More than one contact matches: {names}. Please be more specific.",
+            "Más de un contacto coincide: {names}. Por favor sé más específico.",
+        ],
+    ),
+    (
+        "no_blocks",
+        ["You haven't blocked anyone.", "No has bloqueado a nadie."],
+    ),
+    ("your_blocks", ["You've blocked:\n", "Bloqueaste a:\n"]),
+    (
+        "export_ready",
+        [
+            "Your data export is ready: {link}\nThe link expires in {hours} hours.",
+            "Tu exportación de datos está lista: {link}\nEl enlace caduca en {hours} horas.",
+        ],
+    ),
+    (
+        "rate_limited",
+        [
+            "You're sending messages too quickly. Please wait a minute and try again.",
+            "Estás enviando mensajes demasiado rápido. Espera un minuto e inténtalo de nuevo.",
+        ],
+    ),
+    (
+        "webhook_rejected",
+        [
+            "Sorry, that message couldn't be read.",
+            "Lo siento, no se pudo leer ese mensaje.",
+        ],
+    ),
+    (
+        "unknown_command",
+        [
+            "We didn't recognize that command word: \"{word}\".\n{hint}",
+            "No reconocemos esa palabra de comando: \"{word}\".\n{hint}",
+        ],
+    ),
+    (
+        "command_not_recognized",
+        [
+            "Command \"{command}\" not recognized",
+            "No reconocemos el comando \"{command}\"",
+        ],
+    ),
+    // Contacts and groups
+    (
+        "no_groups_or_contacts",
+        [
+            "You don't have any groups or contacts.",
+            "No tienes grupos ni contactos.",
+        ],
+    ),
+    ("your_groups", ["Your groups:\n", "Tus grupos:\n"]),
+    ("your_contacts", ["Your contacts:\n", "Tus contactos:\n"]),
+    (
+        "line_notice",
+        [
+            "Heads up: {name}'s number ({kind}) may not get texts.",
+            "Aviso: puede que el número de {name} ({kind}) no reciba mensajes.",
+        ],
+    ),
+    ("line_landline", ["landline", "fijo"]),
+    ("line_voip", ["VoIP", "VoIP"]),
+    (
+        "line_undeliverable",
+        ["texts to it keep failing", "los mensajes no le llegan"],
+    ),
+    (
+        "recent_contacts",
+        [
+            "Your most recently added or changed contacts:\n",
+            "Tus contactos agregados o cambiados más recientemente:\n",
+        ],
+    ),
+    (
+        "recent_added_line",
+        [
+            "{index}. {name}: {number}, added {date}\n",
+            "{index}. {name}: {number}, agregado el {date}\n",
+        ],
+    ),
+    (
+        "recent_updated_line",
+        [
+            "{index}. {name}: {number}, changed {date}\n",
+            "{index}. {name}: {number}, cambiado el {date}\n",
+        ],
+    ),
+    (
+        "group_line",
+        ["{index}. {name} ({count} members)\n", "{index}. {name} ({count} miembros)\n"],
+    ),
+    (
+        "group_bullet",
+        ["• {name} ({count} members)\n", "• {name} ({count} miembros)\n"],
+    ),
+    (
+        "group_needs_names",
+        [
+            "Please provide at least one name to search for.",
+            "Indica al menos un nombre para buscar.",
+        ],
+    ),
+    (
+        "no_contacts_matching",
+        [
+            "No contacts found matching: {names}",
+            "No se encontraron contactos que coincidan con: {names}",
+        ],
+    ),
+    (
+        "group_found",
+        [
+            "Found these contacts:\n{list}\n\nTo create a group with these contacts, reply \"confirm NUM1, NUM2, ...\"",
+            "Encontramos estos contactos:\n{list}\n\nPara crear un grupo con estos contactos, responde \"confirm NUM1, NUM2, ...\"",
+        ],
+    ),
+    (
+        "nothing_matching",
+        [
+            "No groups or contacts found matching \"{name}\"",
+            "No se encontraron grupos ni contactos que coincidan con \"{name}\"",
+        ],
+    ),
+    ("found_groups", ["Found these groups:\n", "Encontramos estos grupos:\n"]),
+    (
+        "found_contacts",
+        ["Found these contacts:\n", "Encontramos estos contactos:\n"],
+    ),
+    (
+        "delete_instructions",
+        [
+            "\nTo delete items, reply \"confirm NUM1, NUM2, ...\", where NUM1, NUM2, etc. are numbers from the lists above.",
+            "\nPara borrar elementos, responde \"confirm NUM1, NUM2, ...\", donde NUM1, NUM2, etc. son números de las listas de arriba.",
+        ],
+    ),
+    (
+        "nothing_to_confirm",
+        [
+            "No pending actions to confirm.",
+            "No hay acciones pendientes que confirmar.",
+        ],
+    ),
+    (
+        "invalid_selection_format",
+        [
+            "Invalid selection format: {selection}",
+            "Formato de selección no válido: {selection}",
+        ],
+    ),
+    (
+        "invalid_contact_number",
+        [
+            "Invalid contact number: {number}",
+            "Número de contacto no válido: {number}",
+        ],
+    ),
+    (
+        "contact_number_not_found",
+        [
+            "Contact number {number} not found",
+            "No se encontró el contacto número {number}",
+        ],
+    ),
+    (
+        "invalid_letter",
+        [
+            "Invalid letter selection: {letter}",
+            "Letra no válida: {letter}",
+        ],
+    ),
+    (
+        "add_failed",
+        [
+            "Failed to add {name} ({number}): {error}",
+            "No se pudo agregar a {name} ({number}): {error}",
+        ],
+    ),
+    (
+        "contacts_added",
+        [
+            "Successfully added {count} contact{s}:\n",
+            "Se agregaron {count} contacto{s}:\n",
+        ],
+    ),
+    ("failed_to_process", ["Failed to process:\n", "No se pudo procesar:\n"]),
+    (
+        "invalid_selection",
+        ["Invalid selection: {selection}", "Selección no válida: {selection}"],
+    ),
+    ("invalid_number", ["Invalid number: {number}", "Número no válido: {number}"]),
+    (
+        "no_valid_selections",
+        [
+            "No valid selections provided.",
+            "No se indicó ninguna selección válida.",
+        ],
+    ),
+    (
+        "groups_deleted",
+        ["Deleted {count} group{s}:\n", "Se borraron {count} grupo{s}:\n"],
+    ),
+    (
+        "contacts_deleted",
+        ["Deleted {count} contact{s}:\n", "Se borraron {count} contacto{s}:\n"],
+    ),
+    ("errors", ["Errors:\n", "Errores:\n"]),
+    (
+        "trash_hint",
+        [
+            "Deleted contacts can be restored for {days} days with \"{command}\".\n",
+            "Puedes recuperar los contactos borrados durante {days} días con \"{command}\".\n",
+        ],
+    ),
+    (
+        "trash_empty",
+        [
+            "You haven't deleted any contacts in the last {days} days.",
+            "No has borrado ningún contacto en los últimos {days} días.",
+        ],
+    ),
+    (
+        "your_trash",
+        [
+            "Contacts deleted in the last {days} days:\n",
+            "Contactos borrados en los últimos {days} días:\n",
+        ],
+    ),
+    (
+        "restore_instructions",
+        [
+            "Reply \"{command} N\" to bring one back, e.g. \"{command} 1\" or \"{command} 1,3\".",
+            "Responde \"{command} N\" para recuperar uno, p. ej. \"{command} 1\" o \"{command} 1,3\".",
+        ],
+    ),
+    (
+        "contacts_restored",
+        [
+            "Restored {count} contact{s}:\n",
+            "Se recuperaron {count} contacto{s}:\n",
+        ],
+    ),
+    (
+        "timezone_current",
+        [
+            "Your time zone is {zone}, where it's now {time}.\n{hint}",
+            "Tu zona horaria es {zone}, donde ahora son las {time}.\n{hint}",
+        ],
+    ),
+    (
+        "timezone_updated",
+        [
+            "Your time zone is now {zone}, where it's {time}.",
+            "Tu zona horaria ahora es {zone}, donde son las {time}.",
+        ],
+    ),
+    (
+        "timezone_unknown",
+        [
+            "Unknown time zone \"{zone}\". Try a name like \"America/Chicago\", a city like \"Madrid\", or an abbreviation like \"EST\".",
+            "Zona horaria desconocida \"{zone}\". Prueba un nombre como \"America/Chicago\", una ciudad como \"Madrid\" o una abreviatura como \"EST\".",
+        ],
+    ),
+    // Reminders
+    (
+        "remind_scheduled",
+        [
+            "Reminder for {who} scheduled for {time}.",
+            "Recordatorio para {who} programado para el {time}.",
+        ],
+    ),
+    (
+        "remind_bad_time",
+        [
+            "Couldn't understand the time \"{time}\". Try e.g. \"tomorrow 9am\", \"friday 6:30pm\" or \"in 2 hours\".",
+            "No entendí la hora \"{time}\". Prueba p. ej. \"mañana 9am\", \"viernes 6:30pm\" o \"en 2 horas\".",
+        ],
+    ),
+    (
+        "remind_past",
+        [
+            "{time} has already passed.",
+            "El {time} ya pasó.",
+        ],
+    ),
+    (
+        "remind_unknown_target",
+        [
+            "\"{who}\" isn't you, one of your groups or one of your contacts.",
+            "\"{who}\" no eres tú, ni uno de tus grupos, ni uno de tus contactos.",
+        ],
+    ),
+    (
+        "remind_ambiguous",
+        [
+            "\"{who}\" could be any of: {names}. Use more of the name.",
+            "\"{who}\" podría ser cualquiera de: {names}. Usa más del nombre.",
+        ],
+    ),
+    (
+        "param_sendlater",
+        [
+            "who (\"me\", a contact or a group), when in their time (\"8am\", \"friday 6pm\"), a colon and the message; leave it out to list your scheduled messages",
+            "a quién (\"yo\", un contacto o un grupo), cuándo en su hora (\"8am\", \"viernes 6pm\"), dos puntos y el mensaje; déjalo vacío para ver tus mensajes programados",
+        ],
+    ),
+    (
+        "later_scheduled",
+        [
+            "Message for {who} scheduled for {time} their time. The first one goes out {first}.",
+            "Mensaje para {who} programado para las {time} en su hora. El primero sale el {first}.",
+        ],
+    ),
+    (
+        "no_later_messages",
+        [
+            "You don't have any messages scheduled to send later.\n{hint}",
+            "No tienes mensajes programados para enviar más tarde.\n{hint}",
+        ],
+    ),
+    (
+        "your_later_messages",
+        ["Your scheduled messages:\n", "Tus mensajes programados:\n"],
+    ),
+    (
+        "later_line",
+        [
+            "{index}. {time} their time (next {next}), for {who}: {message}\n",
+            "{index}. {time} en su hora (próximo {next}), para {who}: {message}\n",
+        ],
+    ),
+    (
+        "later_cancelled",
+        [
+            "Cancelled the message for {who}: {message}",
+            "Se canceló el mensaje para {who}: {message}",
+        ],
+    ),
+    ("later_message", ["{name}: {message}", "{name}: {message}"]),
+    (
+        "no_reminders",
+        [
+            "You don't have any reminders scheduled.\n{hint}",
+            "No tienes recordatorios programados.\n{hint}",
+        ],
+    ),
+    (
+        "your_reminders",
+        ["Your reminders:\n", "Tus recordatorios:\n"],
+    ),
+    (
+        "reminder_line",
+        [
+            "{index}. {time}, for {who}: {message}\n",
+            "{index}. {time}, para {who}: {message}\n",
+        ],
+    ),
+    (
+        "remind_cancel_instructions",
+        [
+            "Reply \"{command} cancel N\" to cancel one.",
+            "Responde \"{command} cancel N\" para cancelar uno.",
+        ],
+    ),
+    (
+        "reminder_cancelled",
+        [
+            "Cancelled the reminder for {who}: {message}",
+            "Se canceló el recordatorio para {who}: {message}",
+        ],
+    ),
+    (
+        "reminder_message",
+        [
+            "Reminder from {name}: {message}",
+            "Recordatorio de {name}: {message}",
+        ],
+    ),
+    ("reminder_message_self", ["Reminder: {message}", "Recordatorio: {message}"]),
+    (
+        "consent_request",
+        [
+            "{name} wants to send you messages through Decision Bot (https://github.com/samcarey/decisionbot). Reply YES to get messages from your contacts here, or NO to not.",
+            "{name} quiere enviarte mensajes a través de Decision Bot (https://github.com/samcarey/decisionbot). Responde SÍ para recibir mensajes de tus contactos aquí, o NO si no quieres.",
+        ],
+    ),
+    (
+        "consent_granted",
+        [
+            "Thanks! You'll now get messages your contacts send you here.",
+            "¡Gracias! Ahora recibirás aquí los mensajes que te envíen tus contactos.",
+        ],
+    ),
+    (
+        "consent_declined",
+        [
+            "OK, you won't get messages from other people here. Reply YES any time to change that.",
+            "De acuerdo, no recibirás mensajes de otras personas aquí. Responde SÍ cuando quieras para cambiarlo.",
+        ],
+    ),
+    (
+        "group_created",
+        [
+            "Created group \"{name}\" with {count} members:\n",
+            "Se creó el grupo \"{name}\" con {count} miembros:\n",
+        ],
+    ),
+    // Decide
+    (
+        "decide_picked",
+        [
+            "I pick: {choice} (out of {count})",
+            "Elijo: {choice} (de {count})",
+        ],
+    ),
+    (
+        "decide_nobody",
+        [
+            "\"{who}\" isn't one of your groups or contacts.",
+            "\"{who}\" no es uno de tus grupos ni de tus contactos.",
+        ],
+    ),
+    (
+        "no_picks",
+        [
+            "You haven't had me decide anything yet.\n{hint}",
+            "Todavía no me has pedido decidir nada.\n{hint}",
+        ],
+    ),
+    ("your_picks", ["Your recent picks:\n", "Tus elecciones recientes:\n"]),
+    (
+        "pick_line",
+        [
+            "{index}. {date}: {choice} (from {options})\n",
+            "{index}. {date}: {choice} (de {options})\n",
+        ],
+    ),
+    // Events
+    (
+        "event_scheduled",
+        [
+            "Inviting {group} to \"{title}\" on {time}. Reply \"{command}\" to see who's coming.",
+            "Invitando a {group} a \"{title}\" el {time}. Responde \"{command}\" para ver quién viene.",
+        ],
+    ),
+    (
+        "event_unknown_group",
+        [
+            "\"{group}\" isn't one of your groups.",
+            "\"{group}\" no es uno de tus grupos.",
+        ],
+    ),
+    (
+        "event_invitation",
+        [
+            "{name} invites you to \"{title}\" on {time}. Reply \"{rsvp} yes\", \"{rsvp} no\" or \"{rsvp} maybe\".",
+            "{name} te invita a \"{title}\" el {time}. Responde \"{rsvp} sí\", \"{rsvp} no\" o \"{rsvp} quizás\".",
+        ],
+    ),
+    (
+        "event_reminder",
+        [
+            "{name} is still waiting to hear if you're coming to \"{title}\" on {time}. Reply \"{rsvp} yes\", \"{rsvp} no\" or \"{rsvp} maybe\".",
+            "{name} aún espera saber si vienes a \"{title}\" el {time}. Responde \"{rsvp} sí\", \"{rsvp} no\" o \"{rsvp} quizás\".",
+        ],
+    ),
+    (
+        "no_events",
+        [
+            "You don't have any upcoming events.\n{hint}",
+            "No tienes eventos próximos.\n{hint}",
+        ],
+    ),
+    ("your_events", ["Upcoming events:\n", "Próximos eventos:\n"]),
+    (
+        "event_line_organizer",
+        [
+            "{index}. {time} {title} ({group}): {yes} yes, {maybe} maybe, {no} no, {pending} waiting\n",
+            "{index}. {time} {title} ({group}): {yes} sí, {maybe} quizás, {no} no, {pending} sin responder\n",
+        ],
+    ),
+    (
+        "event_line_invited",
+        [
+            "{index}. {time} {title} (from {name}): {response}\n",
+            "{index}. {time} {title} (de {name}): {response}\n",
+        ],
+    ),
+    (
+        "event_instructions",
+        [
+            "Reply \"{command} N\" for details, or \"{rsvp} N yes\" to answer.",
+            "Responde \"{command} N\" para ver detalles, o \"{rsvp} N sí\" para responder.",
+        ],
+    ),
+    (
+        "event_guests",
+        [
+            "{title}, {time}\nYes: {yes}\nMaybe: {maybe}\nNo: {no}\nWaiting: {pending}",
+            "{title}, {time}\nSí: {yes}\nQuizás: {maybe}\nNo: {no}\nSin responder: {pending}",
+        ],
+    ),
+    (
+        "event_invitation_status",
+        [
+            "{title}, {time}, from {name}\n{yes} coming, {maybe} maybe. You said: {response}",
+            "{title}, {time}, de {name}\n{yes} vienen, {maybe} quizás. Tu respuesta: {response}",
+        ],
+    ),
+    ("rsvp_yes", ["yes", "sí"]),
+    ("rsvp_no", ["no", "no"]),
+    ("rsvp_maybe", ["maybe", "quizás"]),
+    ("rsvp_none", ["no answer yet", "sin responder"]),
+    (
+        "rsvp_recorded",
+        [
+            "Got it: {response} to \"{title}\".",
+            "Anotado: {response} a \"{title}\".",
+        ],
+    ),
+    (
+        "rsvp_no_invitations",
+        [
+            "You don't have any invitations to answer.",
+            "No tienes invitaciones que responder.",
+        ],
+    ),
+    (
+        "rsvp_which",
+        [
+            "You have more than one invitation. Reply \"{command}\" to list them, then \"{rsvp} N yes\".",
+            "Tienes más de una invitación. Responde \"{command}\" para verlas, y luego \"{rsvp} N sí\".",
+        ],
+    ),
+    (
+        "rsvp_not_invited",
+        [
+            "Event {index} is yours, not an invitation.",
+            "El evento {index} es tuyo, no una invitación.",
+        ],
+    ),
+    // Rotations
+    (
+        "rotation_created",
+        [
+            "Created rotation \"{name}\": {order}. It's {current}'s turn first. Reply \"{command} next {name}\" to move it on.",
+            "Se creó el turno \"{name}\": {order}. Empieza {current}. Responde \"{command} siguiente {name}\" para pasar el turno.",
+        ],
+    ),
+    (
+        "rotation_exists",
+        [
+            "You already have a rotation called \"{name}\".",
+            "Ya tienes un turno llamado \"{name}\".",
+        ],
+    ),
+    (
+        "rotation_unknown",
+        [
+            "\"{name}\" isn't one of your rotations.",
+            "\"{name}\" no es uno de tus turnos.",
+        ],
+    ),
+    (
+        "rotation_bad_interval",
+        [
+            "Couldn't understand \"{interval}\". Use a number of days, or \"off\".",
+            "No entendí \"{interval}\". Usa un número de días, o \"nunca\".",
+        ],
+    ),
+    (
+        "rotation_turn",
+        [
+            "{name}: it's {current}'s turn, then {next}.",
+            "{name}: le toca a {current}, y luego a {next}.",
+        ],
+    ),
+    ("rotation_order", ["\nOrder: {order}", "\nOrden: {order}"]),
+    (
+        "rotation_every",
+        [
+            "\nMoves on every {days} day(s).",
+            "\nPasa cada {days} día(s).",
+        ],
+    ),
+    (
+        "rotation_scheduled",
+        [
+            "\"{name}\" will move on by itself every {days} day(s).",
+            "\"{name}\" pasará solo cada {days} día(s).",
+        ],
+    ),
+    (
+        "rotation_unscheduled",
+        [
+            "\"{name}\" will only move on when you say so.",
+            "\"{name}\" solo pasará cuando tú lo digas.",
+        ],
+    ),
+    (
+        "rotation_deleted",
+        [
+            "Deleted rotation \"{name}\".",
+            "Se borró el turno \"{name}\".",
+        ],
+    ),
+    (
+        "no_rotations",
+        [
+            "You don't have any rotations.\n{hint}",
+            "No tienes turnos.\n{hint}",
+        ],
+    ),
+    ("your_rotations", ["Your rotations:\n", "Tus turnos:\n"]),
+    (
+        "rotation_line",
+        [
+            "{name}: {current}, then {next}\n",
+            "{name}: {current}, luego {next}\n",
+        ],
+    ),
+    (
+        "rotation_your_turn",
+        [
+            "{creator} says it's your turn: {name}. {next} is next.",
+            "{creator} dice que te toca: {name}. Después le toca a {next}.",
+        ],
+    ),
+    (
+        "rotation_your_turn_self",
+        [
+            "It's your turn: {name}. {next} is next.",
+            "Te toca: {name}. Después le toca a {next}.",
+        ],
+    ),
+    // Quotas
+    (
+        "quota_imports",
+        [
+            "You've imported contacts {limit} times in the last hour, which is the limit. Please try again later.",
+            "Has importado contactos {limit} veces en la última hora, que es el límite. Inténtalo de nuevo más tarde.",
+        ],
+    ),
+    (
+        "quota_scheduled",
+        [
+            "You've scheduled {limit} reminders and events in the last day, which is the limit. Please try again tomorrow.",
+            "Has programado {limit} recordatorios y eventos en el último día, que es el límite. Inténtalo de nuevo mañana.",
+        ],
+    ),
+    (
+        "quota_recipients",
+        [
+            "{group} has {count} members, but messages can only go to {limit} at once.",
+            "{group} tiene {count} miembros, pero los mensajes solo pueden ir a {limit} a la vez.",
+        ],
+    ),
+    // Delivery failures
+    (
+        "delivery_failed",
+        [
+            "Your message to {name} wasn't delivered: {reason}. You may want to reach them another way.",
+            "Tu mensaje a {name} no se entregó: {reason}. Quizá quieras contactarle de otra forma.",
+        ],
+    ),
+    (
+        "delivery_unreachable",
+        [
+            "their phone is off or out of service",
+            "su teléfono está apagado o sin servicio",
+        ],
+    ),
+    (
+        "delivery_blocked",
+        [
+            "their carrier blocked it",
+            "su operador lo bloqueó",
+        ],
+    ),
+    (
+        "delivery_unknown_number",
+        [
+            "the number isn't in service",
+            "el número no está en servicio",
+        ],
+    ),
+    (
+        "delivery_given_up",
+        [
+            "That's {count} times in a row, so {name} won't get any more texts from you here until the number is fixed.",
+            "Ya van {count} veces seguidas, así que {name} no recibirá más mensajes tuyos aquí hasta que se corrija el número.",
+        ],
+    ),
+    (
+        "delivery_landline",
+        [
+            "it's a landline or can't get texts",
+            "es un fijo o no puede recibir mensajes",
+        ],
+    ),
+    (
+        "delivery_filtered",
+        [
+            "their carrier filtered it as spam",
+            "su operador lo filtró como spam",
+        ],
+    ),
+    (
+        "delivery_opted_out",
+        [
+            "they've turned off texts from this number",
+            "desactivó los mensajes de este número",
+        ],
+    ),
+    (
+        "delivery_unknown_reason",
+        [
+            "the carrier didn't say why",
+            "el operador no dijo por qué",
+        ],
+    ),
+    // Contact import
+    (
+        "import_needs_name",
+        [
+            "Please set your name first using the 'name' command before adding contacts",
+            "Primero elige tu nombre con el comando 'name' antes de agregar contactos",
+        ],
+    ),
+    ("import_no_name", ["No name provided", "Falta el nombre"]),
+    (
+        "media_too_large",
+        [
+            "That attachment is too big. Please send files of {max} MB or less.",
+            "Ese archivo adjunto es demasiado grande. Envía archivos de {max} MB o menos.",
+        ],
+    ),
+    (
+        "import_no_numbers",
+        [
+            "No valid phone numbers provided",
+            "No hay números de teléfono válidos",
+        ],
+    ),
+    (
+        "import_report",
+        [
+            "Processed contacts: {added} added, {updated} updated, {unchanged} unchanged, {deferred} deferred, {failed} failed",
+            "Contactos procesados: {added} agregados, {updated} actualizados, {unchanged} sin cambios, {deferred} pendientes, {failed} con error",
+        ],
+    ),
+    (
+        "import_truncated",
+        [
+            "\n\nOnly the first {max} contacts were read. Please send the rest separately.",
+            "\n\nSolo se leyeron los primeros {max} contactos. Envía el resto por separado.",
+        ],
+    ),
+    (
+        "nothing_to_repeat",
+        [
+            "There's no recent reply to send again.",
+            "No hay ninguna respuesta reciente para enviar otra vez.",
+        ],
+    ),
+    (
+        "import_instructions",
+        [
+            "To import contacts, send their contact cards. To bring your contacts in line with your phone's, send a full export with the text \"{command} replace\": new people are added, names are updated, and you'll be asked before anyone missing from it is deleted.",
+            "Para importar contactos, envía sus tarjetas de contacto. Para igualar tus contactos con los de tu teléfono, envía una exportación completa con el texto \"{command} replace\": se agregan las personas nuevas, se actualizan los nombres y se te preguntará antes de borrar a quien no aparezca.",
+        ],
+    ),
+    (
+        "import_replace_missing",
+        [
+            "\n\nThese contacts aren't in the export:\n{list}\nTo delete them, reply \"confirm NUM1, NUM2, ...\" with their numbers from the list, or \"{command}\" to keep them.",
+            "\n\nEstos contactos no están en la exportación:\n{list}\nPara borrarlos, responde \"confirm NUM1, NUM2, ...\" con sus números de la lista, o \"{command}\" para conservarlos.",
+        ],
+    ),
+    (
+        "import_replace_complete",
+        [
+            "\n\nEveryone in your contacts is in the export.",
+            "\n\nTodos tus contactos están en la exportación.",
+        ],
+    ),
+    (
+        "import_replace_after_picking",
+        [
+            "\n\nOnce you've picked numbers, send the export again with \"import replace\" to review contacts missing from it.",
+            "\n\nCuando hayas elegido los números, envía la exportación otra vez con \"import replace\" para revisar los contactos que faltan.",
+        ],
+    ),
+    (
+        "import_replace_incomplete",
+        [
+            "\n\nSince the export wasn't read completely, no contacts are suggested for deletion.",
+            "\n\nComo la exportación no se leyó completa, no se sugiere borrar ningún contacto.",
+        ],
+    ),
+    (
+        "import_cant_text",
+        [
+            "\n\nThese numbers are landlines or VoIP, so they may not get texts: {names}",
+            "\n\nEstos números son fijos o VoIP, así que puede que no reciban mensajes: {names}",
+        ],
+    ),
+    (
+        "import_errors",
+        ["\nErrors encountered:", "\nErrores encontrados:"],
+    ),
+    (
+        "import_deferred",
+        [
+            "\n\nThe following contacts have multiple numbers. Reply with \"confirm NA, MB, ...\" where N and M are from the list of contacts below and A and B are the letters for the desired phone numbers for each.\n",
+            "\n\nLos siguientes contactos tienen varios números. Responde \"confirm NA, MB, ...\" donde N y M son de la lista de contactos de abajo y A y B son las letras de los números que quieres para cada uno.\n",
+        ],
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_is_complete() {
+        for (i, (key, texts)) in CATALOG.iter().enumerate() {
+            assert!(
+                CATALOG[..i].iter().all(|(k, _)| k != key),
+                "duplicate key {key}"
+            );
+            for text in texts {
+                assert!(!text.is_empty(), "{key} has an empty translation");
+                assert_eq!(
+                    placeholders(text),
+                    placeholders(texts[0]),
+                    "{key} placeholders differ between languages"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn translate_fills_placeholders() {
+        assert_eq!(
+            t!(Lang::En, "name_updated", name = "Sam"),
+            "Your name has been updated to \"Sam\""
+        );
+        assert_eq!(
+            t!(Lang::Es, "contacts_added", count = 2, s = "s"),
+            "Se agregaron 2 contactos:\n"
+        );
+    }
+
+    #[test]
+    fn overrides_are_checked() {
+        let overrides =
+            parse_overrides(r#"{"en": {"name_updated": "Hi {name}!"}, "es": {}}"#).unwrap();
+        assert_eq!(
+            overrides[&(Lang::En as usize, "name_updated")],
+            "Hi {name}!"
+        );
+        // Leaving out a placeholder is fine, making one up is not
+        assert!(parse_overrides(r#"{"en": {"name_updated": "Done"}}"#).is_ok());
+        assert!(parse_overrides(r#"{"en": {"name_updated": "Hi {nme}"}}"#).is_err());
+        assert!(parse_overrides(r#"{"en": {"no_such_key": "Hi"}}"#).is_err());
+        assert!(parse_overrides(r#"{"klingon": {}}"#).is_err());
+    }
+
+    #[test]
+    fn parse_lang() {
+        assert_eq!("ES".parse::<Lang>().unwrap(), Lang::Es);
+        assert_eq!("Español".parse::<Lang>().unwrap(), Lang::Es);
+        assert_eq!("english".parse::<Lang>().unwrap(), Lang::En);
+        assert!("klingon".parse::<Lang>().is_err());
+    }
+}
@@ -0,0 +1,340 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::{query, Pool, Sqlite, SqliteConnection, SqliteExecutor};
+use tracing::*;
+
+use crate::{
+    again, backup, config, cost, delivery, events, lookup, message_log,
+    outbound::{self, Outgoing},
+    pii, quota, remind, rotation, sendlater, session,
+    shutdown::Shutdown,
+    tenant::Tenant,
+    trash, verification,
+};
+
+/// How long a worker may hold a job before another worker assumes it died and retries it
+const LEASE_SECS: i64 = 5 * 60;
+/// How long the worker waits before checking again when nothing is due
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How often the cleanup job runs
+const CLEANUP_INTERVAL_SECS: i64 = 60 * 60;
+/// How long finished jobs are kept around for debugging
+const FINISHED_RETENTION_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Work to be done outside the webhook path.
+/// Stored as JSON, so variants can be added freely but existing ones shouldn't change shape
+/// while jobs of that kind may still be queued.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Job {
+    /// Purges expired sessions, export links, rate limit state, contacts long in the trash,
+    /// old message ids, old quota usage, texts no longer worth tracking, expired verification
+    /// codes, old message log entries and old finished jobs,
+    /// then schedules the next run
+    Cleanup,
+    /// Snapshots the database and prunes old snapshots, then schedules the next run.
+    /// Stops recurring if backups are no longer configured.
+    Backup,
+    /// Texts the operator if the month's estimated spend has passed the budget, then schedules
+    /// the next run. Stops recurring if there's no longer a budget.
+    BudgetCheck,
+    /// Ends sessions that have run out, telling their users. Queued for when the next one is due
+    /// to, and only ever queued once.
+    SessionExpiry,
+    /// Sends a reminder scheduled with `remind`, unless it was cancelled
+    Reminder { id: i64 },
+    /// Sends one recipient's copy of a message scheduled with `sendlater`, unless it was cancelled
+    LaterDelivery { id: i64 },
+    /// Invites an event's group to it
+    EventInvitations { id: i64 },
+    /// Reminds anyone who hasn't answered an event's invitation, unless it's already started
+    EventReminder { id: i64 },
+    /// Tells whoever has a rotation's turn that it's theirs, unless it's moved on since
+    RotationTurn { id: i64, turn: i64 },
+    /// Moves a rotation on by itself, unless it's been moved on or rescheduled since
+    RotationAdvance { id: i64, turn: i64, days: i64 },
+    /// Makes up a verification code and texts it, unless it's been replaced or has expired
+    VerificationCode { id: i64 },
+    /// Looks up the line type of a newly added number (sealed), unless it's known by then
+    LineTypeLookup { number: String },
+    /// Texts every user who's agreed to texts and still gets them the same message.
+    /// Queued by `decisionbot-admin announce`.
+    Announcement { body: String },
+}
+
+impl Job {
+    async fn run(&self, tenant: &Tenant) -> Result<()> {
+        let pool = &tenant.pool;
+        match self {
+            Job::Cleanup => {
+                // Catches sessions without an expiry job of their own, like onboarding ones.
+                // Anyone who couldn't be told is tried again next time.
+                if let Err(error) = session::expire(pool, &tenant.number).await {
+                    warn!("{error:?}");
+                }
+                query!("DELETE FROM exports WHERE expires_at <= unixepoch()")
+                    .execute(pool)
+                    .await?;
+                // Anyone not heard from in a day has long since refilled their bucket
+                query!("DELETE FROM rate_limits WHERE updated_at <= unixepoch() - 24 * 60 * 60")
+                    .execute(pool)
+                    .await?;
+                query!(
+                    "DELETE FROM contacts WHERE deleted_at <= unixepoch() - ?",
+                    trash::RETENTION_SECS
+                )
+                .execute(pool)
+                .await?;
+                // Twilio gives up retrying long before this
+                query!("DELETE FROM processed_messages WHERE received_at <= unixepoch() - 24 * 60 * 60")
+                    .execute(pool)
+                    .await?;
+                query!(
+                    "DELETE FROM quota_usage WHERE used_at <= unixepoch() - ?",
+                    quota::RETENTION_SECS
+                )
+                .execute(pool)
+                .await?;
+                query!(
+                    "DELETE FROM tracked_messages WHERE sent_at <= unixepoch() - ?",
+                    delivery::RETENTION_SECS
+                )
+                .execute(pool)
+                .await?;
+                query!(
+                    "DELETE FROM last_responses WHERE created_at <= unixepoch() - ?",
+                    again::RETENTION_SECS
+                )
+                .execute(pool)
+                .await?;
+                query!("DELETE FROM verification_codes WHERE expires_at <= unixepoch()")
+                    .execute(pool)
+                    .await?;
+                query!(
+                    "DELETE FROM message_log WHERE created_at <= unixepoch() - ?",
+                    message_log::RETENTION_SECS
+                )
+                .execute(pool)
+                .await?;
+                query!(
+                    "DELETE FROM jobs WHERE status != 'pending' AND finished_at <= unixepoch() - ?",
+                    FINISHED_RETENTION_SECS
+                )
+                .execute(pool)
+                .await?;
+                enqueue_in(pool, &Job::Cleanup, CLEANUP_INTERVAL_SECS).await
+            }
+            Job::Backup => {
+                let Some(config) = backup::Config::configured() else {
+                    return Ok(());
+                };
+                let dir = tenant.backup_dir(&config.dir);
+                backup::snapshot(pool, &dir).await?;
+                backup::prune(&dir, config.keep)?;
+                enqueue_in(pool, &Job::Backup, config.interval_secs).await
+            }
+            Job::BudgetCheck => {
+                if config::get().monthly_budget.is_none() {
+                    return Ok(());
+                }
+                cost::check_budget(pool, &tenant.number).await?;
+                enqueue_in(pool, &Job::BudgetCheck, cost::BUDGET_CHECK_INTERVAL_SECS).await
+            }
+            Job::SessionExpiry => session::expire(pool, &tenant.number).await,
+            Job::Reminder { id } => remind::deliver(pool, &tenant.number, *id).await,
+            Job::LaterDelivery { id } => sendlater::deliver(pool, &tenant.number, *id).await,
+            Job::VerificationCode { id } => verification::send(pool, &tenant.number, *id).await,
+            Job::LineTypeLookup { number } => lookup::run(pool, &tenant.number, number).await,
+            Job::EventInvitations { id } => events::invite(pool, &tenant.number, *id).await,
+            Job::EventReminder { id } => events::remind(pool, &tenant.number, *id).await,
+            Job::RotationTurn { id, turn } => {
+                rotation::notify(pool, &tenant.number, *id, *turn).await
+            }
+            Job::RotationAdvance { id, turn, days } => {
+                rotation::advance_scheduled(pool, *id, *turn, *days).await
+            }
+            Job::Announcement { body } => {
+                // Only people who've agreed to texts, which anyone signing up has, and still
+                // get them. Not contacts who've never been asked, or paused accounts.
+                let mut messages = Vec::new();
+                for user in query!("SELECT number FROM users WHERE consent = 'granted'")
+                    .fetch_all(pool)
+                    .await?
+                {
+                    if delivery::undeliverable(pool, &user.number).await? {
+                        continue;
+                    }
+                    messages.push(Outgoing {
+                        to: pii::open(&user.number)?,
+                        body: body.clone(),
+                    });
+                }
+                let count = messages.len();
+                let failed = outbound::send_all(pool, &tenant.number, messages)
+                    .await?
+                    .into_iter()
+                    .filter(|(_, result)| result.is_err())
+                    .count();
+                // Not retried, since that would repeat it to everyone it did reach
+                if failed > 0 {
+                    warn!("Announcement failed to reach {failed} of {count} user(s)");
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Queues a job to run as soon as a worker is free
+pub async fn enqueue(executor: impl SqliteExecutor<'_>, job: &Job) -> Result<()> {
+    enqueue_in(executor, job, 0).await
+}
+
+/// Queues a job to run after a delay.
+/// Pass the transaction making a related change, where there is one,
+/// so the job is only queued if the change commits.
+pub async fn enqueue_in(
+    executor: impl SqliteExecutor<'_>,
+    job: &Job,
+    delay_secs: i64,
+) -> Result<()> {
+    let payload = serde_json::to_string(job)?;
+    query!(
+        "INSERT INTO jobs (payload, run_at) VALUES (?, unixepoch() + ?)",
+        payload,
+        delay_secs
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Makes sure `job` is queued to run within `delay_secs`, moving up the one already queued, if
+/// any, rather than adding another. For jobs that do everything due by the time they run, like
+/// ending expired sessions. One that's running doesn't count, since it may be past that point.
+pub async fn enqueue_by(conn: &mut SqliteConnection, job: &Job, delay_secs: i64) -> Result<()> {
+    let payload = serde_json::to_string(job)?;
+    let queued = query!(
+        "UPDATE jobs SET run_at = MIN(run_at, unixepoch() + ?)
+         WHERE payload = ? AND status = 'pending'
+            AND (leased_until IS NULL OR leased_until <= unixepoch())",
+        delay_secs,
+        payload
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    if queued == 0 {
+        enqueue_in(&mut *conn, job, delay_secs).await?;
+    }
+    Ok(())
+}
+
+/// Queues a recurring job unless it's already queued, e.g. from before a restart
+pub async fn ensure_scheduled(pool: &Pool<Sqlite>, job: &Job) -> Result<()> {
+    let payload = serde_json::to_string(job)?;
+    let queued = query!(
+        "SELECT id FROM jobs WHERE payload = ? AND status = 'pending'",
+        payload
+    )
+    .fetch_optional(pool)
+    .await?;
+    if queued.is_none() {
+        enqueue(pool, job).await?;
+    }
+    Ok(())
+}
+
+/// Claims and runs the tenant's next due job, if any. Returns whether there was one.
+pub async fn run_next(tenant: &Tenant) -> Result<bool> {
+    let pool = &tenant.pool;
+    let Some(claimed) = query!(
+        "UPDATE jobs SET leased_until = unixepoch() + ?, attempts = attempts + 1
+         WHERE id = (
+            SELECT id FROM jobs
+            WHERE status = 'pending' AND run_at <= unixepoch()
+            AND (leased_until IS NULL OR leased_until <= unixepoch())
+            ORDER BY run_at, id
+            LIMIT 1
+         )
+         RETURNING id as \"id!\", payload as \"payload!\", attempts as \"attempts!\",
+            max_attempts as \"max_attempts!\"",
+        LEASE_SECS
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(false);
+    };
+
+    let result = match serde_json::from_str::<Job>(&claimed.payload) {
+        Ok(job) => {
+            debug!("Running job {}: {job:?}", claimed.id);
+            job.run(tenant).await
+        }
+        Err(error) => Err(error.into()),
+    };
+
+    match result {
+        Ok(()) => {
+            query!(
+                "UPDATE jobs SET status = 'done', leased_until = NULL, finished_at = unixepoch()
+                 WHERE id = ?",
+                claimed.id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Err(error) if claimed.attempts >= claimed.max_attempts => {
+            error!("Job {} failed for good: {error:?}", claimed.id);
+            let error = error.to_string();
+            query!(
+                "UPDATE jobs SET status = 'failed', leased_until = NULL, finished_at = unixepoch(),
+                 last_error = ? WHERE id = ?",
+                error,
+                claimed.id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Err(error) => {
+            warn!("Job {} failed, will retry: {error:?}", claimed.id);
+            // Back off exponentially: 1 minute, then 2, 4, ...
+            let backoff = 60 * 2_i64.pow(claimed.attempts.clamp(1, 10) as u32 - 1);
+            let error = error.to_string();
+            query!(
+                "UPDATE jobs SET leased_until = NULL, run_at = unixepoch() + ?, last_error = ?
+                 WHERE id = ?",
+                backoff,
+                error,
+                claimed.id
+            )
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(true)
+}
+
+/// Runs a tenant's jobs until shutdown, finishing the one in hand first.
+/// Spawn one per tenant per process.
+pub async fn worker(tenant: Tenant, shutdown: Shutdown) {
+    while !shutdown.is_requested() {
+        let idle = match run_next(&tenant).await {
+            Ok(ran) => !ran,
+            Err(error) => {
+                error!("Job worker error: {error:?}");
+                true
+            }
+        };
+        if idle {
+            tokio::select! {
+                () = tokio::time::sleep(POLL_INTERVAL) => {}
+                () = shutdown.clone().requested() => {}
+            }
+        }
+    }
+    debug!("Job worker for {} stopped", tenant.number);
+}
@@ -0,0 +1,206 @@
+//! Whether a number can get texts at all, from Twilio Lookup's line type intelligence, so users
+//! hear when a contact they add is a landline or VoIP number that may never see a poll.
+//!
+//! Off unless `LINE_TYPE_LOOKUP` is set, since Twilio charges for each lookup. Each number is
+//! looked up once, by a [Job::LineTypeLookup] queued when it's added rather than while the
+//! user waits, and the answer kept on its user row. Whoever has it as a contact hears if it
+//! may not get texts.
+
+use std::{str::FromStr, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use sqlx::{query, Pool, Sqlite};
+use tracing::*;
+
+use crate::{
+    config,
+    i18n::{t, Lang},
+    jobs::{self, Job},
+    outbound::{self, Outgoing},
+    pii, simulate,
+};
+
+/// How long a lookup may take before it's left for the job to retry
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .expect("the client's settings are valid")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineType {
+    Mobile,
+    Landline,
+    Voip,
+    Other,
+}
+
+impl LineType {
+    /// From Twilio's `line_type_intelligence.type`
+    fn from_twilio(kind: &str) -> Self {
+        match kind {
+            "mobile" => LineType::Mobile,
+            "landline" => LineType::Landline,
+            "fixedVoip" | "nonFixedVoip" => LineType::Voip,
+            _ => LineType::Other,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            LineType::Mobile => "mobile",
+            LineType::Landline => "landline",
+            LineType::Voip => "voip",
+            LineType::Other => "other",
+        }
+    }
+
+    /// A note to show next to the number, for lines that may not get texts
+    pub fn warning(&self, lang: Lang) -> Option<String> {
+        match self {
+            LineType::Landline => Some(t!(lang, "line_landline")),
+            LineType::Voip => Some(t!(lang, "line_voip")),
+            LineType::Mobile | LineType::Other => None,
+        }
+    }
+}
+
+impl FromStr for LineType {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mobile" => Ok(LineType::Mobile),
+            "landline" => Ok(LineType::Landline),
+            "voip" => Ok(LineType::Voip),
+            "other" => Ok(LineType::Other),
+            _ => bail!("Unknown line type \"{s}\""),
+        }
+    }
+}
+
+/// The line type of a user's number (sealed), if it's been looked up
+pub async fn known(
+    executor: impl sqlx::SqliteExecutor<'_>,
+    number: &str,
+) -> Result<Option<LineType>> {
+    query!("SELECT line_type FROM users WHERE number = ?", number)
+        .fetch_optional(executor)
+        .await?
+        .and_then(|user| user.line_type)
+        .map(|line_type| line_type.parse())
+        .transpose()
+}
+
+/// Queues a lookup of a user's number (sealed), if lookups are on and it isn't known yet.
+/// Meant to be part of the transaction adding the number.
+pub async fn request(tx: &mut sqlx::Transaction<'_, Sqlite>, number: &str) -> Result<()> {
+    if !config::get().line_type_lookup || simulate::enabled() {
+        return Ok(());
+    }
+    if known(&mut **tx, number).await?.is_some() {
+        return Ok(());
+    }
+    let job = Job::LineTypeLookup {
+        number: number.to_string(),
+    };
+    jobs::enqueue(&mut **tx, &job).await
+}
+
+/// Looks up a number (sealed), unless it's been looked up since the job was queued, and tells
+/// whoever has it as a contact, from the bot number `sender`, if it may not get texts
+pub async fn run(pool: &Pool<Sqlite>, sender: &str, number: &str) -> Result<()> {
+    if known(pool, number).await?.is_some() {
+        return Ok(());
+    }
+    let line_type = fetch(&pii::open(number)?).await?;
+    let kind = line_type.as_str();
+    query!(
+        "UPDATE users SET line_type = ? WHERE number = ?",
+        kind,
+        number
+    )
+    .execute(pool)
+    .await?;
+    if !matches!(line_type, LineType::Landline | LineType::Voip) {
+        return Ok(());
+    }
+
+    let contacts = query!(
+        "SELECT c.contact_name, c.submitter_number, u.lang FROM contacts c
+         JOIN users u ON u.number = c.submitter_number
+         WHERE c.contact_user_number = ? AND c.deleted_at IS NULL",
+        number
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut messages = Vec::new();
+    for contact in contacts {
+        let lang: Lang = contact.lang.parse()?;
+        let Some(kind) = line_type.warning(lang) else {
+            continue;
+        };
+        messages.push(Outgoing {
+            to: pii::open(&contact.submitter_number)?,
+            body: t!(
+                lang,
+                "line_notice",
+                name = pii::open(&contact.contact_name)?,
+                kind = kind
+            ),
+        });
+    }
+    // Not retried, since the line type is known now and a retry would stop short
+    for (message, result) in outbound::send_all(pool, sender, messages).await? {
+        if let Err(error) = result {
+            warn!(
+                "Couldn't tell {} about a contact's line type: {error:?}",
+                pii::redact(&message.to)
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn fetch(number: &str) -> Result<LineType> {
+    let config = config::get();
+    let response: serde_json::Value = CLIENT
+        .get(format!(
+            "https://lookups.twilio.com/v2/PhoneNumbers/{}",
+            number.replace('+', "%2B")
+        ))
+        .query(&[("Fields", "line_type_intelligence")])
+        .basic_auth(
+            config
+                .twilio_api_key_sid
+                .as_deref()
+                .context("TWILIO_API_KEY_SID is not set")?,
+            config.twilio_api_key_secret.as_deref(),
+        )
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(response["line_type_intelligence"]["type"]
+        .as_str()
+        .map_or(LineType::Other, LineType::from_twilio))
+}
+
+#[test]
+fn line_types() {
+    assert_eq!(LineType::from_twilio("mobile"), LineType::Mobile);
+    assert_eq!(LineType::from_twilio("nonFixedVoip"), LineType::Voip);
+    assert_eq!(LineType::from_twilio("tollFree"), LineType::Other);
+    for line_type in [
+        LineType::Mobile,
+        LineType::Landline,
+        LineType::Voip,
+        LineType::Other,
+    ] {
+        assert_eq!(line_type.as_str().parse::<LineType>().unwrap(), line_type);
+    }
+}
@@ -1,61 +1,198 @@
 use crate::command::Command;
 use anyhow::{bail, Context, Result};
+use audit::Kind;
 use axum::{
-    response::{Html, IntoResponse},
-    routing::post,
+    body::Body,
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
     Extension, Form, Router,
 };
+use cache::UserCache;
 use contacts::{add_contact, process_contact_submission};
 use dotenv::dotenv;
 use help::handle_help;
-use log::*;
-use openapi::apis::{
-    api20100401_message_api::{create_message, CreateMessageParams},
-    configuration::Configuration,
-};
+use i18n::{t, Lang};
+use lookup::LineType;
+use message_log::Entry;
+use outbound::Outgoing;
+use quota::Quota;
+use rate_limit::{Decision, RateLimiter};
+use replay::Claim;
+use session::SessionState;
 use sqlx::{query, query_as, Pool, Sqlite};
-use std::env;
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::str::FromStr;
+use tenant::{Tenant, Tenants};
+use tracing::*;
+use twiml::Twiml;
 use util::E164;
 
+mod account;
+mod again;
+mod api_tokens;
+mod audit;
+mod backup;
+mod block;
+mod cache;
 mod command;
+mod config;
+mod consent;
 mod contacts;
+mod cost;
+mod decide;
+mod delivery;
+mod encoding;
+mod events;
+mod export;
 mod help;
+mod i18n;
+mod jobs;
+mod lookup;
+mod media;
+mod message_log;
+mod movenumber;
+mod onboarding;
+mod optout;
+mod outbound;
+mod pii;
+mod pin;
+mod quota;
+mod rate_limit;
+mod remind;
+mod replay;
+mod report;
+mod rotation;
+mod search;
+mod sendlater;
+mod session;
+mod shutdown;
+mod simulate;
+mod stats;
+mod telemetry;
+mod tenant;
+mod terms;
 #[cfg(test)]
 mod test;
+mod timezone;
+mod tls;
+mod trash;
+mod twiml;
 mod util;
+mod vcard;
+mod verification;
+mod when;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    dotenv()?;
-    env_logger::init();
+    // Settings can come from .env, a config file, the environment, or a mix
+    dotenv().ok();
+    let config = config::load(config::Role::Server)?;
+    telemetry::init()?;
     info!("Starting up");
-    let twilio_config = Configuration {
-        basic_auth: Some((
-            env::var("TWILIO_API_KEY_SID")?,
-            Some(env::var("TWILIO_API_KEY_SECRET")?),
-        )),
-        ..Default::default()
-    };
-    send(
-        &twilio_config,
-        env::var("CLIENT_NUMBER")?,
-        "Server is starting up".to_string(),
-    )
-    .await?;
-    let pool = sqlx::SqlitePool::connect(&env::var("DATABASE_URL")?).await?;
-    query!("PRAGMA foreign_keys = ON").execute(&pool).await?; // SQLite has this off by default
-    let app = Router::new()
-        .route("/", post(handle_incoming_sms))
-        .layer(Extension(pool));
+    pii::init()?;
+    i18n::load_overrides()?;
+    // Brings the schema up to date, creating the database on a fresh deployment.
+    // Set MIGRATE_ON_STARTUP=false to manage it by hand instead, e.g. with `cargo make setup-db`.
+    let tenants = tenant::connect_all(config.migrate_on_startup).await?;
+    // Texts to the operator go out from the default number
+    let operator_tenant = tenants.get(None);
+    if simulate::enabled() {
+        warn!("Simulating: nothing will be sent through Twilio");
+    } else {
+        let startup = vec![Outgoing {
+            to: config
+                .client_number
+                .clone()
+                .context("CLIENT_NUMBER is not set")?,
+            body: "Server is starting up".to_string(),
+        }];
+        for (_, result) in
+            outbound::send_all(&operator_tenant.pool, &operator_tenant.number, startup).await?
+        {
+            result?;
+        }
+    }
+    let backups = backup::Config::configured().is_some();
+    let (stop, shutdown) = shutdown::channel();
+    let mut workers = Vec::new();
+    for tenant in tenants.all() {
+        jobs::ensure_scheduled(&tenant.pool, &jobs::Job::Cleanup).await?;
+        if backups {
+            jobs::ensure_scheduled(&tenant.pool, &jobs::Job::Backup).await?;
+        }
+        if config.monthly_budget.is_some() {
+            jobs::ensure_scheduled(&tenant.pool, &jobs::Job::BudgetCheck).await?;
+        }
+        workers.push(tokio::spawn(jobs::worker(tenant.clone(), shutdown.clone())));
+    }
+    let app = router(&tenants);
     let listener = tokio::net::TcpListener::bind(format!(
         "{}:{}",
-        env::var("CALLBACK_IP")?,
-        env::var("CALLBACK_PORT")?
+        config
+            .callback_ip
+            .as_deref()
+            .context("CALLBACK_IP is not set")?,
+        config.callback_port.context("CALLBACK_PORT is not set")?
     ))
     .await?;
     info!("Listening on {}", listener.local_addr()?);
-    axum::serve(listener, app).await?;
+    // Stops accepting connections once shutdown starts, then finishes with the open ones
+    let mut server = match tls::configured().await? {
+        Some(tls) => tokio::spawn(tls::serve(listener, app, tls, shutdown.clone())),
+        None => {
+            let server =
+                axum::serve(listener, app).with_graceful_shutdown(shutdown.clone().requested());
+            tokio::spawn(async move { server.await })
+        }
+    };
+    tokio::select! {
+        served = &mut server => {
+            telemetry::shutdown();
+            return Ok(served??);
+        }
+        () = shutdown::signal() => {}
+    }
+
+    info!("Shutting down");
+    stop.send_replace(true);
+    if !simulate::enabled() {
+        let notice = vec![Outgoing {
+            to: config
+                .client_number
+                .clone()
+                .context("CLIENT_NUMBER is not set")?,
+            body: "Server is shutting down".to_string(),
+        }];
+        for (_, result) in
+            outbound::send_all(&operator_tenant.pool, &operator_tenant.number, notice).await?
+        {
+            if let Err(error) = result {
+                warn!("Couldn't send the shutdown notice: {error:?}");
+            }
+        }
+    }
+    let drained = tokio::time::timeout(shutdown::timeout(), async {
+        if let Ok(Err(error)) = server.await {
+            error!("Server error while shutting down: {error:?}");
+        }
+        futures::future::join_all(workers).await;
+    })
+    .await;
+    if drained.is_err() {
+        warn!(
+            "Gave up waiting for in-flight work after {:?}",
+            shutdown::timeout()
+        );
+    }
+    for tenant in tenants.all() {
+        tenant.pool.close().await;
+    }
+    telemetry::shutdown();
+    info!("Shut down");
 
     Ok(())
 }
@@ -69,12 +206,20 @@ struct SmsMessage {
     NumMedia: Option<String>,
     MediaContentType0: Option<String>,
     MediaUrl0: Option<String>,
+    MessageSid: Option<String>,
+    /// The bot number it was sent to, which picks the tenant
+    To: Option<String>,
+    /// STOP, START or HELP when Twilio's Advanced Opt-Out took it as one of those
+    OptOutType: Option<String>,
 }
 
+#[derive(Clone)]
 struct User {
     number: String,
     #[allow(dead_code)]
     name: String,
+    lang: String,
+    timezone: Option<String>,
 }
 
 #[derive(Clone, sqlx::FromRow)]
@@ -84,6 +229,55 @@ struct Contact {
     contact_user_number: String,
 }
 
+impl Contact {
+    /// Opens contacts as read from the database, keeping their order
+    fn open_in_order(contacts: Vec<Contact>) -> Result<Vec<Contact>> {
+        contacts
+            .into_iter()
+            .map(|c| {
+                Ok(Contact {
+                    id: c.id,
+                    contact_name: pii::open(&c.contact_name)?,
+                    contact_user_number: pii::open(&c.contact_user_number)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Opens contacts as read from the database and sorts them by name,
+    /// which SQL can't do once names are sealed
+    fn open_all(contacts: Vec<Contact>) -> Result<Vec<Contact>> {
+        let mut contacts = Self::open_in_order(contacts)?;
+        contacts.sort_by(|a, b| a.contact_name.cmp(&b.contact_name));
+        Ok(contacts)
+    }
+
+    /// Keeps the contacts whose names match any of the fragments, or whose handles (see
+    /// [contacts::handle]) are among them, best matches first and otherwise in the order given
+    fn search(contacts: Vec<Contact>, fragments: &[&str]) -> Vec<Contact> {
+        let (handles, names): (Vec<_>, Vec<_>) = fragments
+            .iter()
+            .partition(|fragment| contacts::parse_handle(fragment).is_some());
+        let handles: Vec<_> = handles
+            .into_iter()
+            .filter_map(contacts::parse_handle)
+            .collect();
+        let mut matches: Vec<_> = contacts
+            .into_iter()
+            .filter_map(|c| {
+                if handles.contains(&c.id) {
+                    // Nothing matches better than naming exactly this one
+                    Some((u8::MAX, c))
+                } else {
+                    search::best_score(&c.contact_name, &names).map(|score| (score, c))
+                }
+            })
+            .collect();
+        matches.sort_by_key(|(score, _)| Reverse(*score));
+        matches.into_iter().map(|(_, c)| c).collect()
+    }
+}
+
 #[derive(Debug)]
 enum ImportResult {
     Added,
@@ -92,30 +286,187 @@ enum ImportResult {
     Deferred,
 }
 
+/// Twilio's webhook posts stay well under this, even with a full 1600 character body and media
+const MAX_WEBHOOK_BYTES: usize = 64 * 1024;
+
+fn router(tenants: &Tenants) -> Router {
+    let mut app = Router::new()
+        .route(
+            "/",
+            post(handle_incoming_sms).route_layer(middleware::from_fn(webhook_guard)),
+        )
+        .route("/export/:token", get(export::serve_export))
+        .route(
+            "/status",
+            post(delivery::handle_status).route_layer(middleware::from_fn(webhook_guard)),
+        )
+        .route(
+            "/admin/backup",
+            post(backup::trigger_backup).route_layer(middleware::from_fn_with_state(
+                (tenants.get(None).pool.clone(), api_tokens::Scope::Admin),
+                api_tokens::require,
+            )),
+        );
+    if simulate::enabled() {
+        app = app.route("/simulate", post(simulate::handle_simulate));
+    }
+    app.layer(Extension(tenants.clone()))
+}
+
+/// Turns away posts to the webhooks that are too big or aren't form encoded, with a TwiML reply,
+/// before they reach the handlers. Twilio never sends either.
+async fn webhook_guard(request: Request, next: Next) -> Response {
+    let reject = |status: StatusCode| {
+        let reply = Twiml::new().message(t!(Lang::default(), "webhook_rejected"));
+        (status, Html::<String>::from(reply)).into_response()
+    };
+    let form_encoded = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| {
+            mime.trim()
+                .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+        });
+    if !form_encoded {
+        warn!("Rejecting a webhook post that isn't form encoded");
+        return reject(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+    let (parts, body) = request.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, MAX_WEBHOOK_BYTES).await else {
+        warn!("Rejecting a webhook post over {MAX_WEBHOOK_BYTES} bytes");
+        return reject(StatusCode::PAYLOAD_TOO_LARGE);
+    };
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
 // Handler for incoming SMS messages
 async fn handle_incoming_sms(
-    Extension(pool): Extension<Pool<Sqlite>>,
+    Extension(tenants): Extension<Tenants>,
     Form(message): Form<SmsMessage>,
-) -> impl IntoResponse {
-    let response = match process_message(&pool, message).await {
+) -> Html<String> {
+    let tenant = tenants.get(message.To.as_deref());
+    // Everything logged while handling a message is tagged with who sent it and which message it was
+    let span = info_span!(
+        "message",
+        sender = %pii::redact(&message.From),
+        sid = message.MessageSid.as_deref().unwrap_or("none"),
+        command = field::Empty,
+    );
+    let response = respond(tenant, message).instrument(span).await;
+    response
+        .into_iter()
+        .fold(Twiml::new(), Twiml::message)
+        .into()
+}
+
+/// Handles a message end to end, returning the reply, if any
+async fn respond(tenant: &Tenant, message: SmsMessage) -> Option<String> {
+    let Tenant {
+        pool,
+        limiter,
+        users,
+        ..
+    } = tenant;
+    if !util::is_phone_number(&message.From) {
+        info!("Ignoring a message from a short code or alphanumeric sender");
+        return None;
+    }
+    let sid = message.MessageSid.clone();
+    if let Some(sid) = &sid {
+        match replay::claim(pool, sid).await {
+            Ok(Claim::First) => {}
+            Ok(Claim::Repeat(response)) => {
+                debug!("Already handled {sid}, replaying the response");
+                return response;
+            }
+            // Better to risk handling it twice than not at all
+            Err(error) => error!("Error checking for a repeated message: {error:?}"),
+        }
+    }
+
+    if let Err(error) = message_log::record(pool, Entry::Received).await {
+        warn!("Couldn't log a received message: {error:?}");
+    }
+    let plain_from = message.From.clone();
+    let command = message
+        .Body
+        .split_ascii_whitespace()
+        .next()
+        .and_then(|word| Command::try_from(word).ok());
+    let from = pii::seal(&message.From);
+    let opt_out_type = message.OptOutType.as_deref();
+    if let Err(error) = optout::record(pool, &from, opt_out_type, &message.Body).await {
+        error!("Error recording an opt-out: {error:?}");
+    }
+    let response = match screen(pool, limiter, &from).await {
+        Ok(Decision::Allow) => process_message(pool, users, message).await.map(Some),
+        Ok(Decision::Throttle) => users
+            .lang(pool, &from)
+            .await
+            .map(|lang| Some(t!(lang, "rate_limited"))),
+        Ok(Decision::Drop) => {
+            debug!("Dropping message");
+            Ok(None)
+        }
+        Err(error) => Err(error),
+    };
+    let response = match response {
         Ok(response) => response,
         Err(error) => {
             error!("Error: {error:?}");
-            "Internal Server Error!".to_string()
+            report::report(
+                &error,
+                report::Context {
+                    from: &plain_from,
+                    message_sid: sid.as_deref(),
+                    command,
+                },
+            );
+            Some("Internal Server Error!".to_string())
         }
     };
-    debug!("Sending response: {response}");
-    Html(format!(
-        r#"
-        <?xml version="1.0" encoding="UTF-8"?>
-        <Response>
-        <Message>{response}</Message>
-        </Response>
-        "#
-    ))
+    if let Some(sid) = &sid {
+        if let Err(error) = replay::finish(pool, sid, response.as_deref()).await {
+            error!("Error recording the response to {sid}: {error:?}");
+        }
+    }
+    if let Some(response) = response
+        .as_deref()
+        .filter(|_| command != Some(Command::again))
+    {
+        if let Err(error) = again::remember(pool, &from, response).await {
+            warn!("Couldn't keep the reply for sending again: {error:?}");
+        }
+    }
+    if let Some(response) = &response {
+        let segments = twiml::split(response)
+            .iter()
+            .map(|part| encoding::segments(part))
+            .sum();
+        if let Err(error) = message_log::record(pool, Entry::Sent { segments }).await {
+            warn!("Couldn't log a reply: {error:?}");
+        }
+    }
+    debug!("Sending response: {response:?}");
+    response
+}
+
+/// Decides whether a message gets processed at all, before touching anything else
+async fn screen(pool: &Pool<Sqlite>, limiter: &RateLimiter, from: &str) -> Result<Decision> {
+    if block::on_blocklist(pool, from).await? {
+        debug!("Sender is on the blocklist");
+        return Ok(Decision::Drop);
+    }
+    limiter.check(pool, from).await
 }
 
-async fn process_message(pool: &Pool<Sqlite>, message: SmsMessage) -> anyhow::Result<String> {
+async fn process_message(
+    pool: &Pool<Sqlite>,
+    users: &UserCache,
+    message: SmsMessage,
+) -> anyhow::Result<String> {
     trace!("Received {message:?}");
     let SmsMessage {
         Body: body,
@@ -123,222 +474,531 @@ async fn process_message(pool: &Pool<Sqlite>, message: SmsMessage) -> anyhow::Re
         NumMedia: media_count,
         MediaContentType0: media_type_0,
         MediaUrl0: media_url_0,
+        ..
     } = message;
-    debug!("Received from {from}: {body}");
-    if media_count == Some("1".to_string())
-        && media_type_0
-            .map(|t| ["text/vcard", "text/x-vcard"].contains(&t.as_str()))
-            .unwrap_or(false)
-    {
-        return process_contact_submission(pool, &from, &media_url_0).await;
+    debug!("Received: {body}");
+    let from = pii::seal(&from);
+    let logged_body = match &media_url_0 {
+        Some(url) => format!("{body} [media: {url}]"),
+        None => pin::redact(&body),
+    };
+    audit::record(pool, &from, Kind::Message, logged_body).await?;
+    // A number that can text the bot can be texted
+    delivery::heard_from(pool, &from).await?;
+    let vcard = match (media_count.as_deref(), &media_url_0) {
+        (Some("1"), Some(url)) => match vcard::attached(media_type_0.as_deref(), url).await {
+            Ok(vcard) => vcard,
+            Err(error) if error.is::<media::TooLarge>() => {
+                let lang = i18n::user_lang(pool, &from).await?;
+                return Ok(t!(
+                    lang,
+                    "media_too_large",
+                    max = media::MAX_BYTES / 1024 / 1024
+                ));
+            }
+            Err(error) => return Err(error),
+        },
+        _ => None,
+    };
+    if let Some(vcard) = vcard {
+        // "import replace" with the export also offers to delete contacts missing from it
+        let mut words = body.split_ascii_whitespace();
+        let replace = matches!(
+            words.next().map(Command::try_from),
+            Some(Ok(Command::import))
+        ) && words
+            .next()
+            .is_some_and(|word| word.eq_ignore_ascii_case("replace"));
+        // Only users' imports count, since nobody else's get anywhere
+        let Some(user) = users.get(pool, &from).await? else {
+            return process_contact_submission(pool, &from, &vcard, replace).await;
+        };
+        if let Some(reply) = quota::check(pool, &from, user.lang.parse()?, Quota::Imports).await? {
+            return Ok(reply);
+        }
+        quota::record(pool, &from, Quota::Imports).await?;
+        return process_contact_submission(pool, &from, &vcard, replace).await;
     }
 
+    // A PIN answers the command that asked for it, which then goes ahead as if just sent
+    let (body, unlocked) = match pin::unlock(pool, &from, &body).await? {
+        pin::Unlock::NotPin => (body, false),
+        pin::Unlock::Reply(reply) => return Ok(reply),
+        pin::Unlock::Run(waiting) => (waiting, true),
+    };
+
     let mut words = body.trim().split_ascii_whitespace();
     let command_word = words.next();
+    if command_word.is_some_and(|word| word.eq_ignore_ascii_case("stats"))
+        && stats::is_operator(&from)
+    {
+        return stats::handle_stats(pool).await;
+    }
     let command = command_word.map(Command::try_from);
+    if let Some(Ok(command)) = &command {
+        Span::current().record("command", field::display(command));
+        audit::record(pool, &from, Kind::Command, command).await?;
+    }
 
     let Some(User {
-        number, name: _, ..
-    }) = query_as!(User, "select * from users where number = ?", from)
-        .fetch_optional(pool)
-        .await?
+        number,
+        lang,
+        timezone: zone,
+        ..
+    }) = users.get(pool, &from).await?
     else {
-        return onboard_new_user(command, words, &from, pool).await;
+        return onboarding::handle(pool, &from, command, body.trim()).await;
     };
 
+    let lang: Lang = lang.parse()?;
+    let zone = timezone::from_stored(zone.as_deref());
+
+    // A paused account only answers to coming back or leaving for good
+    if !matches!(command, Some(Ok(Command::start | Command::stop)))
+        && account::is_deactivated(pool, &from).await?
+    {
+        return Ok(t!(
+            lang,
+            "account_paused",
+            start = Command::start,
+            stop = Command::stop
+        ));
+    }
+
+    // Ahead of the terms, so a YES to being asked about messages isn't taken as accepting them
+    if let Some(reply) = consent::handle_reply(pool, &from, lang, &body).await? {
+        return Ok(reply);
+    }
+    // Leaving is always allowed, terms or no terms
+    if !matches!(command, Some(Ok(Command::stop))) {
+        if let Some(reply) = terms::check(pool, &from, lang, &body).await? {
+            return Ok(reply);
+        }
+    }
+
     let Some(command) = command else {
-        return Ok(Command::h.hint());
+        return Ok(Command::h.hint(lang));
     };
 
     let Ok(command) = command else {
-        return Ok(format!(
-            "We didn't recognize that command word: \"{}\".\n{}",
-            command_word.unwrap(),
-            Command::h.hint()
+        return Ok(t!(
+            lang,
+            "unknown_command",
+            word = command_word.unwrap(),
+            hint = Command::h.hint(lang)
         ));
     };
 
+    if !unlocked {
+        if let Some(reply) = pin::guard(pool, &from, lang, command, &body).await? {
+            return Ok(reply);
+        }
+    }
+
     let response = match command {
         // I would use HELP for the help command, but Twilio intercepts and does not relay that
-        Command::h => handle_help(pool, &from).await?,
-        Command::name => match process_name(words) {
+        Command::h => handle_help(pool, &from, lang, words.next()).await?,
+        Command::name => match process_name(words, lang) {
             Ok(name) => {
-                query!("update users set name = ? where number = ?", name, from)
-                    .execute(pool)
-                    .await?;
-                format!("Your name has been updated to \"{name}\"")
+                let sealed_name = pii::seal(&name);
+                query!(
+                    "update users set name = ? where number = ?",
+                    sealed_name,
+                    from
+                )
+                .execute(pool)
+                .await?;
+                users.invalidate(&from);
+                audit::record(
+                    pool,
+                    &from,
+                    Kind::Mutation,
+                    format!("set name to \"{name}\""),
+                )
+                .await?;
+                t!(lang, "name_updated", name = name)
             }
             Err(hint) => hint.to_string(),
         },
+        Command::language => match words.next() {
+            None => t!(
+                lang,
+                "language_current",
+                language = lang.name(),
+                langs = Lang::list(),
+                hint = Command::language.hint(lang)
+            ),
+            Some(requested) => match requested.parse::<Lang>() {
+                Ok(new_lang) => {
+                    let code = new_lang.code();
+                    query!("update users set lang = ? where number = ?", code, from)
+                        .execute(pool)
+                        .await?;
+                    users.invalidate(&from);
+                    audit::record(
+                        pool,
+                        &from,
+                        Kind::Mutation,
+                        format!("set language to {code}"),
+                    )
+                    .await?;
+                    t!(new_lang, "language_updated", language = new_lang.name())
+                }
+                Err(_) => t!(
+                    lang,
+                    "language_unknown",
+                    language = requested,
+                    langs = Lang::list()
+                ),
+            },
+        },
+        Command::timezone => {
+            let requested = words.collect::<Vec<_>>().join(" ");
+            let response = timezone::handle_timezone(pool, &from, lang, zone, &requested).await?;
+            users.invalidate(&from);
+            response
+        }
         Command::stop => {
-            query!("delete from users where number = ?", number)
-                .execute(pool)
-                .await?;
-            // They won't actually see this when using Twilio
-            "You've been unsubscribed. Goodbye!".to_string()
+            // They won't actually see these when using Twilio
+            if words
+                .next()
+                .is_some_and(|word| word.eq_ignore_ascii_case("purge"))
+            {
+                let removed = account::delete_account(pool, &number).await?;
+                users.invalidate(&number);
+                info!("Deleted account: {removed:?}");
+                format!(
+                    "{}\n{}",
+                    t!(lang, "unsubscribed"),
+                    t!(
+                        lang,
+                        "account_removed",
+                        contacts = removed.contacts,
+                        groups = removed.groups,
+                        listings = removed.listings,
+                        memberships = removed.memberships
+                    )
+                )
+            } else {
+                account::deactivate(pool, &number).await?;
+                info!("Deactivated account");
+                format!(
+                    "{}\n{}",
+                    t!(lang, "unsubscribed"),
+                    t!(
+                        lang,
+                        "account_kept",
+                        start = Command::start,
+                        stop = Command::stop
+                    )
+                )
+            }
         }
+        Command::pin => {
+            let args = words.collect::<Vec<_>>().join(" ");
+            pin::handle_pin(pool, &from, lang, &args).await?
+        }
+        Command::movenumber => {
+            let args = words.collect::<Vec<_>>().join(" ");
+            let (response, moved_to) =
+                movenumber::handle_movenumber(pool, &from, lang, &args).await?;
+            users.invalidate(&from);
+            if let Some(new) = moved_to {
+                users.invalidate(&new);
+            }
+            response
+        }
+        Command::start => match account::reactivate(pool, &number).await? {
+            Some(kept) => t!(
+                lang,
+                "welcome_back",
+                contacts = kept.contacts,
+                groups = kept.groups
+            ),
+            None => t!(lang, "already_active"),
+        },
         Command::info => {
             let command_text = words.next();
             if let Some(command) = command_text.map(Command::try_from) {
                 if let Ok(command) = command {
-                    format!(
-                        "{}, to {}.{}",
-                        command.usage(),
-                        command.description(),
-                        command.example()
-                    )
+                    command.hint(lang)
                 } else {
-                    format!("Command \"{}\" not recognized", command_text.unwrap())
+                    t!(
+                        lang,
+                        "command_not_recognized",
+                        command = command_text.unwrap()
+                    )
                 }
             } else {
-                Command::info.hint()
+                Command::info.hint(lang)
             }
         }
-        Command::contacts => {
-            // First get the groups
-            let groups = query!(
-                "SELECT g.name, COUNT(gm.member_number) as member_count 
-                 FROM groups g 
-                 LEFT JOIN group_members gm ON g.id = gm.group_id
-                 WHERE g.creator_number = ?
-                 GROUP BY g.id, g.name
-                 ORDER BY g.name",
-                from
-            )
-            .fetch_all(pool)
-            .await?;
+        Command::me => account::handle_me(pool, &from, lang, zone).await?,
+        Command::again => again::last(pool, &from)
+            .await?
+            .unwrap_or_else(|| t!(lang, "nothing_to_repeat")),
+        Command::import => t!(lang, "import_instructions", command = Command::import),
+        Command::contacts => match words.next() {
+            Some(word) if word.eq_ignore_ascii_case("recent") => {
+                contacts::recent_contacts(pool, &from, lang, zone).await?
+            }
+            _ => {
+                // First get the groups
+                let groups = query!(
+                    "SELECT g.name, COUNT(gm.member_number) as member_count 
+                     FROM groups g 
+                     LEFT JOIN group_members gm ON g.id = gm.group_id
+                     WHERE g.creator_number = ?
+                     GROUP BY g.id, g.name
+                     ORDER BY g.name",
+                    from
+                )
+                .fetch_all(pool)
+                .await?;
 
-            // Then get the contacts
-            let contacts = query_as!(
-                Contact,
-                "SELECT id as \"id!\", contact_name, contact_user_number 
-                 FROM contacts 
-                 WHERE submitter_number = ? 
-                 ORDER BY contact_name",
-                from
-            )
-            .fetch_all(pool)
-            .await?;
+                // Then get the contacts
+                let contacts = Contact::open_all(
+                    query_as!(
+                        Contact,
+                        "SELECT id as \"id!\", contact_name, contact_user_number 
+                         FROM contacts 
+                         WHERE submitter_number = ? AND deleted_at IS NULL",
+                        from
+                    )
+                    .fetch_all(pool)
+                    .await?,
+                )?;
 
-            if groups.is_empty() && contacts.is_empty() {
-                "You don't have any groups or contacts.".to_string()
-            } else {
-                let mut response = String::new();
-
-                // Add groups section if there are any
-                if !groups.is_empty() {
-                    response.push_str("Your groups:\n");
-                    for (i, group) in groups.iter().enumerate() {
-                        response.push_str(&format!(
-                            "{}. {} ({} members)\n",
-                            i + 1,
-                            group.name,
-                            group.member_count
-                        ));
-                    }
-                }
+                if groups.is_empty() && contacts.is_empty() {
+                    t!(lang, "no_groups_or_contacts")
+                } else {
+                    let mut response = String::new();
 
-                // Add contacts section if there are any
-                if !contacts.is_empty() {
+                    // Add groups section if there are any
                     if !groups.is_empty() {
-                        response.push_str("\n"); // Add spacing between sections
+                        response.push_str(&t!(lang, "your_groups"));
+                        for (i, group) in groups.iter().enumerate() {
+                            response.push_str(&t!(
+                                lang,
+                                "group_line",
+                                index = i + 1,
+                                name = group.name,
+                                count = group.member_count
+                            ));
+                        }
                     }
-                    response.push_str("Your contacts:\n");
-                    let offset = groups.len(); // Start contact numbering after groups
-                    response.push_str(
-                        &contacts
-                            .iter()
-                            .enumerate()
-                            .map(|(i, c)| {
-                                format!(
-                                    "{}. {} ({})",
-                                    i + offset + 1,
-                                    c.contact_name,
-                                    &E164::from_str(&c.contact_user_number)
+
+                    // Add contacts section if there are any
+                    if !contacts.is_empty() {
+                        if !groups.is_empty() {
+                            response.push('\n'); // Add spacing between sections
+                        }
+                        response.push_str(&t!(lang, "your_contacts"));
+                        let viewer = E164::from_str(&pii::open(&from)?).ok();
+                        // Flag numbers that may not get texts, or no longer do
+                        let mut warnings = HashMap::new();
+                        for row in query!(
+                            r#"SELECT c.id as "id!", u.line_type, u.undeliverable_at FROM contacts c
+                             JOIN users u ON u.number = c.contact_user_number
+                             WHERE c.submitter_number = ? AND c.deleted_at IS NULL
+                                AND (u.line_type IS NOT NULL OR u.undeliverable_at IS NOT NULL)"#,
+                            from
+                        )
+                        .fetch_all(pool)
+                        .await?
+                        {
+                            let warning = match (row.undeliverable_at, row.line_type) {
+                                (Some(_), _) => Some(t!(lang, "line_undeliverable")),
+                                (None, Some(line_type)) => {
+                                    line_type.parse::<LineType>()?.warning(lang)
+                                }
+                                (None, None) => None,
+                            };
+                            if let Some(warning) = warning {
+                                warnings.insert(row.id, warning);
+                            }
+                        }
+                        let described = contacts::described(pool, &from).await?;
+                        let offset = groups.len(); // Start contact numbering after groups
+                        response.push_str(
+                            &contacts
+                                .iter()
+                                .enumerate()
+                                .map(|(i, c)| {
+                                    let mut number = E164::from_str(&c.contact_user_number)
                                         .expect("Should have been formatted upon db insertion")
-                                        .area_code()
-                                )
-                            })
-                            .collect::<Vec<_>>()
-                            .join("\n"),
-                    );
+                                        .display_for(viewer.as_ref());
+                                    if let Some(description) = described.get(&c.id) {
+                                        number.push_str(&format!(" — {description}"));
+                                    }
+                                    let handle = contacts::handle(c.id);
+                                    match warnings.get(&c.id) {
+                                        Some(warning) => format!(
+                                            "{}. {} ({handle}): {number} ({warning})",
+                                            i + offset + 1,
+                                            c.contact_name
+                                        ),
+                                        None => {
+                                            format!(
+                                                "{}. {} ({handle}): {number}",
+                                                i + offset + 1,
+                                                c.contact_name
+                                            )
+                                        }
+                                    }
+                                })
+                                .collect::<Vec<_>>()
+                                .join("\n"),
+                        );
+                    }
+                    response
                 }
-                response
             }
-        }
+        },
         Command::delete => {
             let name = words.collect::<Vec<_>>().join(" ");
             if name.is_empty() {
-                Command::delete.hint()
+                Command::delete.hint(lang)
             } else {
-                handle_delete(pool, &from, &name).await?
+                handle_delete(pool, &from, lang, &name).await?
             }
         }
         Command::confirm => {
             let nums = words.collect::<Vec<_>>().join(" ");
             if nums.is_empty() {
-                Command::confirm.hint()
+                Command::confirm.hint(lang)
+            } else {
+                handle_confirm(pool, &from, lang, &nums).await?
+            }
+        }
+        Command::block => {
+            let target = words.collect::<Vec<_>>().join(" ");
+            block::handle_block(pool, &from, lang, &target).await?
+        }
+        Command::unblock => {
+            let target = words.collect::<Vec<_>>().join(" ");
+            block::handle_unblock(pool, &from, lang, &target).await?
+        }
+        Command::export => export::handle_export(pool, &from, lang).await?,
+        Command::trash => trash::handle_trash(pool, &from, lang).await?,
+        Command::restore => {
+            let nums = words.collect::<Vec<_>>().join(" ");
+            if nums.is_empty() {
+                Command::restore.hint(lang)
             } else {
-                handle_confirm(pool, &from, &nums).await?
+                trash::handle_restore(pool, &from, lang, &nums).await?
             }
         }
+        Command::remind => {
+            remind::handle_remind(
+                pool,
+                &from,
+                lang,
+                zone,
+                &words.collect::<Vec<_>>().join(" "),
+            )
+            .await?
+        }
+        Command::sendlater => {
+            sendlater::handle_sendlater(
+                pool,
+                &from,
+                lang,
+                zone,
+                &words.collect::<Vec<_>>().join(" "),
+            )
+            .await?
+        }
+        Command::decide => {
+            decide::handle_decide(
+                pool,
+                &from,
+                lang,
+                zone,
+                &words.collect::<Vec<_>>().join(" "),
+            )
+            .await?
+        }
+        Command::event => {
+            events::handle_event(
+                pool,
+                &from,
+                lang,
+                zone,
+                &words.collect::<Vec<_>>().join(" "),
+            )
+            .await?
+        }
+        Command::rsvp => {
+            events::handle_rsvp(pool, &from, lang, &words.collect::<Vec<_>>().join(" ")).await?
+        }
+        Command::rotation => {
+            rotation::handle_rotation(pool, &from, lang, &words.collect::<Vec<_>>().join(" "))
+                .await?
+        }
         Command::group => {
             let names = words.collect::<Vec<_>>().join(" ");
             if names.is_empty() {
-                Command::group.hint()
+                Command::group.hint(lang)
             } else {
-                handle_group(pool, &from, &names).await?
+                handle_group(pool, &from, lang, &names).await?
             }
         }
+        Command::pending => help::handle_pending(pool, &from, lang, zone).await?,
+        Command::nevermind => match session::current(pool, &from).await? {
+            Some(state) => {
+                session::end(pool, &from).await?;
+                t!(lang, "session_cancelled", session = state.description(lang))
+            }
+            None => t!(lang, "nothing_to_cancel"),
+        },
     };
     Ok(response)
 }
 
-async fn handle_group(pool: &Pool<Sqlite>, from: &str, names: &str) -> anyhow::Result<String> {
+async fn handle_group(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    names: &str,
+) -> anyhow::Result<String> {
     let name_fragments: Vec<_> = names.split(',').map(str::trim).collect();
 
     if name_fragments.is_empty() {
-        return Ok("Please provide at least one name to search for.".to_string());
-    }
-
-    let mut contacts = Vec::new();
-    for fragment in &name_fragments {
-        let like = format!("%{}%", fragment.to_lowercase());
-        let mut matches = query_as!(
-            Contact,
-            "SELECT id as \"id!\", contact_name, contact_user_number 
-             FROM contacts 
-             WHERE submitter_number = ? 
-             AND LOWER(contact_name) LIKE ?
-             ORDER BY contact_name",
-            from,
-            like
-        )
-        .fetch_all(pool)
-        .await?;
-        contacts.append(&mut matches);
+        return Ok(t!(lang, "group_needs_names"));
     }
 
-    contacts.sort_by(|a, b| a.id.cmp(&b.id));
-    contacts.dedup_by(|a, b| a.id == b.id);
-    contacts.sort_by(|a, b| a.contact_name.cmp(&b.contact_name));
+    let contacts = Contact::search(
+        Contact::open_all(
+            query_as!(
+                Contact,
+                "SELECT id as \"id!\", contact_name, contact_user_number 
+                 FROM contacts 
+                 WHERE submitter_number = ? AND deleted_at IS NULL",
+                from
+            )
+            .fetch_all(pool)
+            .await?,
+        )?,
+        &name_fragments,
+    );
 
     if contacts.is_empty() {
-        return Ok(format!(
-            "No contacts found matching: {}",
-            name_fragments.join(", ")
+        return Ok(t!(
+            lang,
+            "no_contacts_matching",
+            names = name_fragments.join(", ")
         ));
     }
 
     let mut tx = pool.begin().await?;
 
-    // Set pending action type to group
-    set_pending_action(pool, from, "group", &mut tx).await?;
+    session::start(&mut tx, from, SessionState::Group).await?;
 
     // Store contacts for group creation
     for contact in &contacts {
         query!(
-            "INSERT INTO pending_group_members (pending_action_submitter, contact_id) 
+            "INSERT INTO pending_group_members (session_submitter, contact_id) 
              VALUES (?, ?)",
             from,
             contact.id
@@ -349,26 +1009,37 @@ async fn handle_group(pool: &Pool<Sqlite>, from: &str, names: &str) -> anyhow::R
 
     tx.commit().await?;
 
+    let described = contacts::described(pool, from).await?;
     let list = contacts
         .iter()
         .enumerate()
         .map(|(i, c)| {
             let area_code = E164::from_str(&c.contact_user_number)
-                .map(|e| e.area_code().to_string())
+                .map(|e| e.short_form())
                 .unwrap_or_else(|_| "???".to_string());
 
-            format!("{}. {} ({})", i + 1, c.contact_name, area_code)
+            match described.get(&c.id) {
+                Some(description) => format!(
+                    "{}. {} ({}) — {description}",
+                    i + 1,
+                    c.contact_name,
+                    area_code
+                ),
+                None => format!("{}. {} ({})", i + 1, c.contact_name, area_code),
+            }
         })
         .collect::<Vec<_>>()
         .join("\n");
 
-    Ok(format!(
-        "Found these contacts:\n{}\n\nTo create a group with these contacts, reply \"confirm NUM1, NUM2, ...\"",
-        list
-    ))
+    Ok(t!(lang, "group_found", list = list))
 }
 
-async fn handle_delete(pool: &Pool<Sqlite>, from: &str, name: &str) -> anyhow::Result<String> {
+async fn handle_delete(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    name: &str,
+) -> anyhow::Result<String> {
     let like = format!("%{}%", name.to_lowercase());
 
     // Find matching groups
@@ -395,33 +1066,34 @@ async fn handle_delete(pool: &Pool<Sqlite>, from: &str, name: &str) -> anyhow::R
     )
     .fetch_all(pool)
     .await?;
-    // Find matching contacts (rest of the code unchanged)
-    let contacts = query_as!(
-        Contact,
-        "SELECT id as \"id!\", contact_name, contact_user_number 
-         FROM contacts 
-         WHERE submitter_number = ? 
-         AND LOWER(contact_name) LIKE ?
-         ORDER BY contact_name",
-        from,
-        like
-    )
-    .fetch_all(pool)
-    .await?;
+    // Find matching contacts
+    let contacts = Contact::search(
+        Contact::open_all(
+            query_as!(
+                Contact,
+                "SELECT id as \"id!\", contact_name, contact_user_number 
+                 FROM contacts 
+                 WHERE submitter_number = ? AND deleted_at IS NULL",
+                from
+            )
+            .fetch_all(pool)
+            .await?,
+        )?,
+        &[name],
+    );
 
     if groups.is_empty() && contacts.is_empty() {
-        return Ok(format!("No groups or contacts found matching \"{}\"", name));
+        return Ok(t!(lang, "nothing_matching", name = name));
     }
 
     let mut tx = pool.begin().await?;
 
-    // Set pending action type to deletion
-    set_pending_action(pool, from, "deletion", &mut tx).await?;
+    session::start(&mut tx, from, SessionState::Deletion).await?;
 
     // Store groups for deletion
     for group in &groups {
         query!(
-            "INSERT INTO pending_deletions (pending_action_submitter, group_id, contact_id) 
+            "INSERT INTO pending_deletions (session_submitter, group_id, contact_id) 
              VALUES (?, ?, NULL)",
             from,
             group.id
@@ -433,7 +1105,7 @@ async fn handle_delete(pool: &Pool<Sqlite>, from: &str, name: &str) -> anyhow::R
     // Store contacts for deletion
     for contact in &contacts {
         query!(
-            "INSERT INTO pending_deletions (pending_action_submitter, group_id, contact_id) 
+            "INSERT INTO pending_deletions (session_submitter, group_id, contact_id) 
              VALUES (?, NULL, ?)",
             from,
             contact.id
@@ -448,13 +1120,14 @@ async fn handle_delete(pool: &Pool<Sqlite>, from: &str, name: &str) -> anyhow::R
 
     // List groups if any were found
     if !groups.is_empty() {
-        response.push_str("Found these groups:\n");
+        response.push_str(&t!(lang, "found_groups"));
         for (i, group) in groups.iter().enumerate() {
-            response.push_str(&format!(
-                "{}. {} ({} members)\n",
-                i + 1,
-                group.name,
-                group.member_count
+            response.push_str(&t!(
+                lang,
+                "group_line",
+                index = i + 1,
+                name = group.name,
+                count = group.member_count
             ));
         }
     }
@@ -462,28 +1135,30 @@ async fn handle_delete(pool: &Pool<Sqlite>, from: &str, name: &str) -> anyhow::R
     // List contacts if any were found, continuing the numbering
     if !contacts.is_empty() {
         if !groups.is_empty() {
-            response.push_str("\n");
+            response.push('\n');
         }
-        response.push_str("Found these contacts:\n");
+        response.push_str(&t!(lang, "found_contacts"));
+        let described = contacts::described(pool, from).await?;
         let offset = groups.len();
         for (i, c) in contacts.iter().enumerate() {
             let area_code = E164::from_str(&c.contact_user_number)
-                .map(|e| e.area_code().to_string())
+                .map(|e| e.short_form())
                 .unwrap_or_else(|_| "???".to_string());
 
             response.push_str(&format!(
-                "{}. {} ({})\n",
+                "{}. {} ({})",
                 i + offset + 1,
                 c.contact_name,
                 area_code
             ));
+            if let Some(description) = described.get(&c.id) {
+                response.push_str(&format!(" — {description}"));
+            }
+            response.push('\n');
         }
     }
 
-    response.push_str(
-        "\nTo delete items, reply \"confirm NUM1, NUM2, ...\", \
-        where NUM1, NUM2, etc. are numbers from the lists above.",
-    );
+    response.push_str(&t!(lang, "delete_instructions"));
 
     Ok(response)
 }
@@ -498,48 +1173,34 @@ struct GroupRecord {
 async fn handle_confirm(
     pool: &Pool<Sqlite>,
     from: &str,
+    lang: Lang,
     selections: &str,
 ) -> anyhow::Result<String> {
-    let pending_action = query!(
-        "SELECT action_type FROM pending_actions WHERE submitter_number = ?",
-        from
-    )
-    .fetch_optional(pool)
-    .await?;
-
-    let Some(action) = pending_action else {
-        return Ok("No pending actions to confirm.".to_string());
+    let Some(state) = session::current(pool, from).await? else {
+        return Ok(t!(lang, "nothing_to_confirm"));
     };
 
-    let action_type = action.action_type;
-
-    match action_type.as_str() {
-        "deferred_contacts" => {
+    match state {
+        SessionState::DeferredContacts => {
             let mut successful = Vec::new();
             let mut failed = Vec::new();
 
-            // Get all deferred contacts
-            let deferred_contacts = query!(
-                "SELECT DISTINCT contact_name FROM deferred_contacts WHERE submitter_number = ?",
-                from
-            )
-            .fetch_all(pool)
-            .await?;
+            // Get all deferred contacts, in the order they were listed
+            let deferred_contacts = contacts::deferred_names(pool, from).await?;
 
             // Process selections like "1a, 2b, 3a"
             for selection in selections.split(',').map(str::trim) {
                 // First validate basic format: must be digits followed by a single letter
                 if !selection
                     .chars()
-                    .rev()
-                    .next()
+                    .next_back()
                     .map(|c| c.is_ascii_lowercase())
                     .unwrap_or(false)
                     || !selection[..selection.len() - 1]
                         .chars()
                         .all(|c| c.is_ascii_digit())
                 {
-                    failed.push(format!("Invalid selection format: {}", selection));
+                    failed.push(t!(lang, "invalid_selection_format", selection = selection));
                     continue;
                 }
 
@@ -548,57 +1209,59 @@ async fn handle_confirm(
                 let contact_idx: usize = match num_str.parse::<usize>() {
                     Ok(n) if n > 0 => n - 1,
                     _ => {
-                        failed.push(format!("Invalid contact number: {}", num_str));
+                        failed.push(t!(lang, "invalid_contact_number", number = num_str));
                         continue;
                     }
                 };
 
                 // Get the contact name
-                let Some(contact_name) =
-                    deferred_contacts.get(contact_idx).map(|c| &c.contact_name)
-                else {
-                    failed.push(format!("Contact number {} not found", contact_idx + 1));
+                let Some(contact_name) = deferred_contacts.get(contact_idx) else {
+                    failed.push(t!(
+                        lang,
+                        "contact_number_not_found",
+                        number = contact_idx + 1
+                    ));
                     continue;
                 };
 
                 // Get all numbers for this contact to validate letter selection
-                let numbers = query!(
-                    "SELECT phone_number, phone_description FROM deferred_contacts 
-             WHERE submitter_number = ? AND contact_name = ?
-             ORDER BY id",
-                    from,
-                    contact_name
-                )
-                .fetch_all(pool)
-                .await?;
+                let numbers = contacts::deferred_numbers(pool, from, contact_name).await?;
 
                 let letter = letter.chars().next().unwrap();
                 let letter_idx = match letter {
                     'a'..='z' => {
                         let idx = (letter as u8 - b'a') as usize;
                         if idx >= numbers.len() {
-                            failed.push(format!("Invalid letter selection: {}", letter));
+                            failed.push(t!(lang, "invalid_letter", letter = letter));
                             continue;
                         }
                         idx
                     }
                     _ => {
-                        failed.push(format!("Invalid letter selection: {}", letter));
+                        failed.push(t!(lang, "invalid_letter", letter = letter));
                         continue;
                     }
                 };
 
                 // Get the selected number
-                let number = &numbers[letter_idx];
+                let (number, _) = &numbers[letter_idx];
 
                 // Insert the contact
-                if let Err(e) = add_contact(pool, from, contact_name, &number.phone_number).await {
-                    failed.push(format!(
-                        "Failed to add {} ({}): {}",
-                        contact_name, number.phone_number, e
+                let details = contacts::deferred_details(pool, from, contact_name).await?;
+                let added = async {
+                    add_contact(pool, from, contact_name, number).await?;
+                    contacts::set_details(pool, from, number, &details).await
+                };
+                if let Err(e) = added.await {
+                    failed.push(t!(
+                        lang,
+                        "add_failed",
+                        name = contact_name,
+                        number = number,
+                        error = e
                     ));
                 } else {
-                    successful.push(format!("{} ({})", contact_name, number.phone_number));
+                    successful.push(format!("{} ({})", contact_name, number));
                 }
             }
 
@@ -606,6 +1269,7 @@ async fn handle_confirm(
             let mut tx = pool.begin().await?;
             for contact in &successful {
                 if let Some(name) = contact.split(" (").next() {
+                    let name = pii::seal(name);
                     query!(
                         "DELETE FROM deferred_contacts WHERE submitter_number = ? AND contact_name = ?",
                         from,
@@ -616,7 +1280,7 @@ async fn handle_confirm(
                 }
             }
 
-            // Clean up pending action if all contacts are processed
+            // End the session if all contacts are processed
             let remaining = query!(
                 "SELECT COUNT(*) as count FROM deferred_contacts WHERE submitter_number = ?",
                 from
@@ -625,12 +1289,7 @@ async fn handle_confirm(
             .await?;
 
             if remaining.count == 0 {
-                query!(
-                    "DELETE FROM pending_actions WHERE submitter_number = ?",
-                    from
-                )
-                .execute(&mut *tx)
-                .await?;
+                session::end(&mut *tx, from).await?;
             }
 
             tx.commit().await?;
@@ -638,10 +1297,11 @@ async fn handle_confirm(
             // Format response
             let mut response = String::new();
             if !successful.is_empty() {
-                response.push_str(&format!(
-                    "Successfully added {} contact{}:\n",
-                    successful.len(),
-                    if successful.len() == 1 { "" } else { "s" }
+                response.push_str(&t!(
+                    lang,
+                    "contacts_added",
+                    count = successful.len(),
+                    s = if successful.len() == 1 { "" } else { "s" }
                 ));
                 for contact in successful {
                     response.push_str(&format!("• {}\n", contact));
@@ -650,9 +1310,9 @@ async fn handle_confirm(
 
             if !failed.is_empty() {
                 if !response.is_empty() {
-                    response.push_str("\n");
+                    response.push('\n');
                 }
-                response.push_str("Failed to process:\n");
+                response.push_str(&t!(lang, "failed_to_process"));
                 for error in failed {
                     response.push_str(&format!("• {}\n", error));
                 }
@@ -660,7 +1320,7 @@ async fn handle_confirm(
 
             Ok(response)
         }
-        "deletion" => {
+        SessionState::Deletion => {
             let mut invalid = Vec::new();
             let mut selected_groups = Vec::new();
             let mut selected_contacts = Vec::new();
@@ -682,23 +1342,26 @@ async fn handle_confirm(
                 FROM groups g
                 JOIN pending_deletions pd ON pd.group_id = g.id
                 LEFT JOIN member_counts mc ON mc.group_id = g.id
-                WHERE pd.pending_action_submitter = ?
+                WHERE pd.session_submitter = ?
                 ORDER BY g.name"#,
                 from
             )
             .fetch_all(pool)
             .await?;
-            let contacts = query_as!(
-                Contact,
-                "SELECT c.id as \"id!\", c.contact_name, c.contact_user_number 
-                 FROM contacts c
-                 JOIN pending_deletions pd ON pd.contact_id = c.id
-                 WHERE pd.pending_action_submitter = ?
-                 ORDER BY c.contact_name",
-                from
-            )
-            .fetch_all(pool)
-            .await?;
+            // In the order they were listed to the user
+            let contacts = Contact::open_in_order(
+                query_as!(
+                    Contact,
+                    "SELECT c.id as \"id!\", c.contact_name, c.contact_user_number 
+                     FROM contacts c
+                     JOIN pending_deletions pd ON pd.contact_id = c.id
+                     WHERE pd.session_submitter = ?
+                     ORDER BY pd.id",
+                    from
+                )
+                .fetch_all(pool)
+                .await?,
+            )?;
 
             // Process selections
             for num_str in selections.split(',').map(str::trim) {
@@ -714,15 +1377,15 @@ async fn handle_confirm(
                         } else if num < groups.len() + contacts.len() {
                             selected_contacts.push(contacts[num - groups.len()].clone());
                         } else {
-                            invalid.push(format!("Invalid selection: {}", num + 1));
+                            invalid.push(t!(lang, "invalid_selection", selection = num + 1));
                         }
                     }
-                    _ => invalid.push(format!("Invalid selection: {}", num_str)),
+                    _ => invalid.push(t!(lang, "invalid_selection", selection = num_str)),
                 }
             }
 
             if selected_groups.is_empty() && selected_contacts.is_empty() && invalid.is_empty() {
-                return Ok("No valid selections provided.".to_string());
+                return Ok(t!(lang, "no_valid_selections"));
             }
 
             let mut tx = pool.begin().await?;
@@ -732,22 +1395,37 @@ async fn handle_confirm(
                 query!("DELETE FROM groups WHERE id = ?", group.id)
                     .execute(&mut *tx)
                     .await?;
+                audit::record(
+                    &mut *tx,
+                    from,
+                    Kind::Mutation,
+                    format!("deleted group \"{}\"", group.name),
+                )
+                .await?;
             }
 
             // Delete selected contacts
             for contact in &selected_contacts {
-                query!("DELETE FROM contacts WHERE id = ?", contact.id)
-                    .execute(&mut *tx)
-                    .await?;
+                // Into the trash, from which it can be restored for a while
+                query!(
+                    "UPDATE contacts SET deleted_at = unixepoch() WHERE id = ? AND deleted_at IS NULL",
+                    contact.id
+                )
+                .execute(&mut *tx)
+                .await?;
+                audit::record(
+                    &mut *tx,
+                    from,
+                    Kind::Mutation,
+                    format!(
+                        "deleted contact \"{}\" ({})",
+                        contact.contact_name, contact.contact_user_number
+                    ),
+                )
+                .await?;
             }
 
-            // Clean up pending actions
-            query!(
-                "DELETE FROM pending_actions WHERE submitter_number = ?",
-                from
-            )
-            .execute(&mut *tx)
-            .await?;
+            session::end(&mut *tx, from).await?;
 
             tx.commit().await?;
 
@@ -755,26 +1433,30 @@ async fn handle_confirm(
             let mut response = String::new();
 
             if !selected_groups.is_empty() {
-                response.push_str(&format!(
-                    "Deleted {} group{}:\n",
-                    selected_groups.len(),
-                    if selected_groups.len() == 1 { "" } else { "s" }
+                response.push_str(&t!(
+                    lang,
+                    "groups_deleted",
+                    count = selected_groups.len(),
+                    s = if selected_groups.len() == 1 { "" } else { "s" }
                 ));
                 for group in selected_groups {
-                    response.push_str(&format!(
-                        "• {} ({} members)\n",
-                        group.name, group.member_count
+                    response.push_str(&t!(
+                        lang,
+                        "group_bullet",
+                        name = group.name,
+                        count = group.member_count
                     ));
                 }
             }
             if !selected_contacts.is_empty() {
                 if !response.is_empty() {
-                    response.push_str("\n");
+                    response.push('\n');
                 }
-                response.push_str(&format!(
-                    "Deleted {} contact{}:\n",
-                    selected_contacts.len(),
-                    if selected_contacts.len() == 1 {
+                response.push_str(&t!(
+                    lang,
+                    "contacts_deleted",
+                    count = selected_contacts.len(),
+                    s = if selected_contacts.len() == 1 {
                         ""
                     } else {
                         "s"
@@ -782,64 +1464,71 @@ async fn handle_confirm(
                 ));
                 for contact in selected_contacts {
                     let area_code = E164::from_str(&contact.contact_user_number)
-                        .map(|e| e.area_code().to_string())
+                        .map(|e| e.short_form())
                         .unwrap_or_else(|_| "???".to_string());
                     response.push_str(&format!("• {} ({})\n", contact.contact_name, area_code));
                 }
+                response.push_str(&t!(
+                    lang,
+                    "trash_hint",
+                    days = trash::RETENTION_SECS / (24 * 60 * 60),
+                    command = Command::trash
+                ));
             }
 
             if !invalid.is_empty() {
                 if !response.is_empty() {
-                    response.push_str("\n");
+                    response.push('\n');
                 }
-                response.push_str("Errors:\n");
+                response.push_str(&t!(lang, "errors"));
                 response.push_str(&invalid.join("\n"));
             }
 
             Ok(response)
         }
-        "group" => {
-            // Existing group creation logic remains the same
+        // Only people who aren't users yet are signing up
+        SessionState::Onboarding => Ok(t!(lang, "nothing_to_confirm")),
+        SessionState::Group => {
             let mut invalid = Vec::new();
             let mut selected_contacts = Vec::new();
 
+            // In the order they were listed to the user
+            let contacts = Contact::open_in_order(
+                query_as!(
+                    Contact,
+                    "SELECT c.id as \"id!\", c.contact_name, c.contact_user_number 
+                     FROM contacts c
+                     JOIN pending_group_members pgm ON pgm.contact_id = c.id
+                     WHERE pgm.session_submitter = ?
+                     ORDER BY pgm.id",
+                    from
+                )
+                .fetch_all(pool)
+                .await?,
+            )?;
+
             for num_str in selections.split(',').map(str::trim) {
                 match num_str.parse::<usize>() {
                     Ok(num) if num > 0 => {
-                        let offset = (num - 1) as i64;
-                        let query = query!(
-                            "SELECT c.id as \"id!\", c.contact_name, c.contact_user_number 
-                             FROM contacts c
-                             JOIN pending_group_members pgm ON pgm.contact_id = c.id
-                             WHERE pgm.pending_action_submitter = ?
-                             ORDER BY c.contact_name
-                             LIMIT 1 OFFSET ?",
-                            from,
-                            offset
-                        );
-                        if let Some(row) = query.fetch_optional(pool).await? {
-                            selected_contacts.push(Contact {
-                                id: row.id,
-                                contact_name: row.contact_name,
-                                contact_user_number: row.contact_user_number,
-                            });
+                        if let Some(contact) = contacts.get(num - 1) {
+                            selected_contacts.push(contact.clone());
                         } else {
-                            invalid.push(format!("Invalid selection: {}", num));
+                            invalid.push(t!(lang, "invalid_selection", selection = num));
                         }
                     }
-                    _ => invalid.push(format!("Invalid number: {}", num_str)),
+                    _ => invalid.push(t!(lang, "invalid_number", number = num_str)),
                 }
             }
 
-            create_group(pool, from, selected_contacts, invalid).await
+            create_group(pool, from, lang, selected_contacts, invalid).await
         }
-        _ => Ok("Invalid action type".to_string()),
     }
 }
 
 async fn create_group(
     pool: &Pool<Sqlite>,
     from: &str,
+    lang: Lang,
     contacts: Vec<Contact>,
     invalid: Vec<String>,
 ) -> anyhow::Result<String> {
@@ -882,130 +1571,67 @@ async fn create_group(
     .id;
 
     for contact in &contacts {
+        let member_number = pii::seal(&contact.contact_user_number);
         query!(
             "INSERT INTO group_members (group_id, member_number) VALUES (?, ?)",
             group_id,
-            contact.contact_user_number
+            member_number
         )
         .execute(&mut *tx)
         .await?;
     }
 
-    // Clean up pending actions
-    query!(
-        "DELETE FROM pending_actions WHERE submitter_number = ?",
-        from
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!(
+            "created group \"{group_name}\" with {} members",
+            contacts.len()
+        ),
     )
-    .execute(&mut *tx)
     .await?;
 
+    session::end(&mut *tx, from).await?;
+
     tx.commit().await?;
 
-    let mut response = format!(
-        "Created group \"{}\" with {} members:\n",
-        group_name,
-        contacts.len()
+    let mut response = t!(
+        lang,
+        "group_created",
+        name = group_name,
+        count = contacts.len()
     );
 
     for contact in contacts {
         let area_code = E164::from_str(&contact.contact_user_number)
-            .map(|e| e.area_code().to_string())
+            .map(|e| e.short_form())
             .unwrap_or_else(|_| "???".to_string());
         response.push_str(&format!("• {} ({})\n", contact.contact_name, area_code));
     }
 
     if !invalid.is_empty() {
-        response.push_str("\nErrors:\n");
+        response.push('\n');
+        response.push_str(&t!(lang, "errors"));
         response.push_str(&invalid.join("\n"));
     }
 
     Ok(response)
 }
 
-async fn onboard_new_user(
-    command: Option<Result<Command, serde_json::Error>>,
-    words: impl Iterator<Item = &str>,
-    from: &str,
-    pool: &Pool<Sqlite>,
-) -> anyhow::Result<String> {
-    let Some(Ok(Command::name)) = command else {
-        return Ok(format!(
-            "Greetings! This is Decision Bot (https://github.com/samcarey/decisionbot).\n\
-            To participate:\n{}",
-            Command::name.hint()
-        ));
-    };
-    Ok(match process_name(words) {
-        Ok(name) => {
-            query!("insert into users (number, name) values (?, ?)", from, name)
-                .execute(pool)
-                .await?;
-            format!("Hello, {name}! {}", Command::h.hint())
-        }
-        Err(hint) => hint.to_string(),
-    })
-}
-
-fn process_name<'a>(words: impl Iterator<Item = &'a str>) -> Result<String> {
+fn process_name<'a>(words: impl Iterator<Item = &'a str>, lang: Lang) -> Result<String> {
     let name = words.collect::<Vec<_>>().join(" ");
     if name.is_empty() {
-        bail!("{}", Command::name.usage());
+        bail!("{}", Command::name.usage(lang));
     }
     const MAX_NAME_LEN: usize = 20;
     if name.len() > MAX_NAME_LEN {
-        bail!(
-            "That name is {} characters long.\n\
-            Please shorten it to {MAX_NAME_LEN} characters or less.",
-            name.len()
-        );
+        bail!(t!(
+            lang,
+            "name_too_long",
+            length = name.len(),
+            max = MAX_NAME_LEN
+        ));
     }
     Ok(name)
 }
-
-async fn send(twilio_config: &Configuration, to: String, message: String) -> Result<()> {
-    let message_params = CreateMessageParams {
-        account_sid: env::var("TWILIO_ACCOUNT_SID")?,
-        to,
-        from: Some(env::var("SERVER_NUMBER")?),
-        body: Some(message),
-        ..Default::default()
-    };
-    let message = create_message(twilio_config, message_params)
-        .await
-        .context("While sending message")?;
-    trace!("Message sent with SID {}", message.sid.unwrap().unwrap());
-    Ok(())
-}
-
-async fn cleanup_expired_pending_actions(pool: &Pool<Sqlite>) -> Result<()> {
-    query!("DELETE FROM pending_actions WHERE created_at < unixepoch() - 300")
-        .execute(pool)
-        .await?;
-    Ok(())
-}
-
-async fn set_pending_action(
-    _pool: &Pool<Sqlite>, // Changed to _pool since it's unused
-    from: &str,
-    action_type: &str,
-    tx: &mut sqlx::Transaction<'_, Sqlite>,
-) -> Result<()> {
-    // Clear any existing pending action
-    query!(
-        "DELETE FROM pending_actions WHERE submitter_number = ?",
-        from
-    )
-    .execute(&mut **tx)
-    .await?;
-
-    // Create new pending action
-    query!(
-        "INSERT INTO pending_actions (submitter_number, action_type) VALUES (?, ?)",
-        from,
-        action_type
-    )
-    .execute(&mut **tx)
-    .await?;
-
-    Ok(())
-}
@@ -0,0 +1,110 @@
+//! Downloading attachments that come in with texts, like vCards. Twilio serves them from
+//! `MediaUrl0`, which needs the account's credentials when HTTP authentication on media is
+//! turned on. Downloads are capped in size and tried again when Twilio has a hiccup.
+
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use reqwest::{StatusCode, Url};
+use tracing::*;
+
+use crate::config;
+
+/// Largest attachment downloaded, the same as Twilio's limit on MMS
+pub const MAX_BYTES: usize = 5 * 1024 * 1024;
+
+/// Attempts at a download before giving up
+const ATTEMPTS: u32 = 3;
+
+/// How long to wait before the first retry, doubling after each one
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// An attachment was bigger than `MAX_BYTES`
+#[derive(Debug)]
+pub struct TooLarge;
+
+impl std::fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Attachment is over {MAX_BYTES} bytes")
+    }
+}
+
+impl std::error::Error for TooLarge {}
+
+/// The attachment at `url`
+pub async fn fetch(url: &str) -> Result<Vec<u8>> {
+    let url = Url::parse(url)?;
+    let mut delay = RETRY_DELAY;
+    for attempt in 1..=ATTEMPTS {
+        let reason = match fetch_once(&url).await? {
+            Fetch::Done(data) => return Ok(data),
+            Fetch::Retry(reason) => reason,
+        };
+        if attempt == ATTEMPTS {
+            bail!("Media download failed after {ATTEMPTS} attempts: {reason}");
+        }
+        warn!("Retrying a media download after {reason}");
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+    unreachable!("the last attempt returns")
+}
+
+enum Fetch {
+    Done(Vec<u8>),
+    /// Failed in a way that might not happen again
+    Retry(String),
+}
+
+async fn fetch_once(url: &Url) -> Result<Fetch> {
+    let mut request = reqwest::Client::new()
+        .get(url.clone())
+        .timeout(Duration::from_secs(30));
+    // Credentials only ever go to Twilio, and its redirects elsewhere drop them
+    if url.scheme() == "https"
+        && url
+            .host_str()
+            .is_some_and(|host| host == "twilio.com" || host.ends_with(".twilio.com"))
+    {
+        let config = config::get();
+        if let Some(key) = &config.twilio_api_key_sid {
+            request = request.basic_auth(key, config.twilio_api_key_secret.as_deref());
+        }
+    }
+    let mut response = match request.send().await {
+        Ok(response) => response,
+        Err(error) if error.is_connect() || error.is_timeout() => {
+            return Ok(Fetch::Retry(error.to_string()))
+        }
+        Err(error) => return Err(error.into()),
+    };
+    let status = response.status();
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        return Ok(Fetch::Retry(status.to_string()));
+    }
+    if let Err(error) = response.error_for_status_ref() {
+        return Err(error.into());
+    }
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_BYTES as u64)
+    {
+        return Err(TooLarge.into());
+    }
+    let mut data = Vec::new();
+    loop {
+        let chunk = match response.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(error) if error.is_timeout() || error.is_body() => {
+                return Ok(Fetch::Retry(error.to_string()))
+            }
+            Err(error) => return Err(error.into()),
+        };
+        if data.len() + chunk.len() > MAX_BYTES {
+            return Err(TooLarge.into());
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(Fetch::Done(data))
+}
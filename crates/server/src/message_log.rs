@@ -0,0 +1,76 @@
+//! Counts of the texts going in and out, and what the ones going out cost (see [crate::cost]),
+//! for the operator. Nothing about who sent them or what they said is kept here; that's what the
+//! audit log is for.
+
+use anyhow::Result;
+use sqlx::{query, Pool, Sqlite};
+
+use crate::cost;
+
+/// How long entries are kept
+pub const RETENTION_SECS: i64 = 90 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entry {
+    Received,
+    /// A text that went out, in so many segments
+    Sent {
+        segments: i64,
+    },
+    Failed,
+}
+
+impl Entry {
+    fn columns(&self) -> (&'static str, &'static str) {
+        match self {
+            Entry::Received => ("in", "received"),
+            Entry::Sent { .. } => ("out", "sent"),
+            Entry::Failed => ("out", "failed"),
+        }
+    }
+}
+
+pub async fn record(pool: &Pool<Sqlite>, entry: Entry) -> Result<()> {
+    let (direction, status) = entry.columns();
+    let segments = match entry {
+        Entry::Sent { segments } => segments,
+        Entry::Received | Entry::Failed => 0,
+    };
+    let price = cost::price(segments);
+    query!(
+        "INSERT INTO message_log (direction, status, segments, price) VALUES (?, ?, ?, ?)",
+        direction,
+        status,
+        segments,
+        price
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Texts received, sent and failed in the last `secs` seconds
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub received: i64,
+    pub sent: i64,
+    pub failed: i64,
+}
+
+pub async fn counts(pool: &Pool<Sqlite>, secs: i64) -> Result<Counts> {
+    let row = query!(
+        r#"SELECT
+            COUNT(*) FILTER (WHERE status = 'received') as "received!: i64",
+            COUNT(*) FILTER (WHERE status = 'sent') as "sent!: i64",
+            COUNT(*) FILTER (WHERE status = 'failed') as "failed!: i64"
+         FROM message_log WHERE created_at > unixepoch() - ?"#,
+        secs
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(Counts {
+        received: row.received,
+        sent: row.sent,
+        failed: row.failed,
+    })
+}
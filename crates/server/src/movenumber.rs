@@ -0,0 +1,274 @@
+//! Moving an account to a new phone number with `movenumber`.
+//!
+//! `movenumber <new number>` texts a code to the new number, and `movenumber <code>` from the
+//! old one, once it's arrived, moves everything over: the user's profile, contacts, groups,
+//! group memberships and decision history, along with their place in other people's contacts
+//! and groups. Until then nothing changes, so a mistyped number can't take anything over.
+//! The code is a [verification] one, so it expires and only allows a few wrong tries.
+
+use std::str::FromStr;
+
+use anyhow::Result;
+use sqlx::{query, Pool, Sqlite};
+
+use crate::{
+    audit::{self, Kind},
+    command::Command,
+    i18n::{t, Lang},
+    optout, pii,
+    util::E164,
+    verification::{self, Check, Purpose},
+};
+
+/// Columns holding a person's number in their own data. They follow the person to their new
+/// number, and anything in them at the new one means it's someone's account already.
+const OWNED_COLUMNS: [(&str, &str); 13] = [
+    ("contacts", "submitter_number"),
+    ("groups", "creator_number"),
+    ("blocks", "blocker_number"),
+    ("exports", "number"),
+    ("scheduled_messages", "creator_number"),
+    ("picks", "creator_number"),
+    ("events", "organizer_number"),
+    ("rotations", "creator_number"),
+    ("quota_usage", "number"),
+    ("tracked_messages", "creator_number"),
+    ("last_responses", "number"),
+    ("later_messages", "creator_number"),
+    ("pins", "number"),
+];
+
+/// Columns holding a person's number in other people's data. They follow the person too, but a
+/// number that's only in these (say, in a friend's contacts) is free to move to.
+const LISTED_COLUMNS: [(&str, &str); 7] = [
+    ("contacts", "contact_user_number"),
+    ("group_members", "member_number"),
+    ("blocks", "blocked_number"),
+    ("scheduled_messages", "recipient_number"),
+    ("rsvps", "number"),
+    ("rotation_members", "number"),
+    ("later_deliveries", "recipient_number"),
+];
+
+/// Columns for things under way, which the move calls off rather than carrying over: the
+/// session with the rows hanging off it, and verification codes. Anything in them at the new
+/// number means someone's using it already.
+const DROPPED_COLUMNS: [(&str, &str); 5] = [
+    ("pending_deletions", "session_submitter"),
+    ("pending_group_members", "session_submitter"),
+    ("deferred_contacts", "submitter_number"),
+    ("sessions", "submitter_number"),
+    ("verification_codes", "number"),
+];
+
+/// Columns about a phone rather than whoever's using it, which stay with the old number:
+/// rate limits, opt-outs, the blocklist, delivery tracking and the audit log. Listed only so
+/// the tests can check every column is accounted for.
+#[cfg(test)]
+const PHONE_COLUMNS: [(&str, &str); 6] = [
+    ("rate_limits", "number"),
+    ("opt_outs", "number"),
+    ("blocklist", "number"),
+    ("tracked_messages", "recipient_number"),
+    ("audit_log", "number"),
+    ("audit_erasures", "number"),
+];
+
+/// Every column holding a user's number, for checking none has been left out of the lists above
+#[cfg(test)]
+pub fn user_columns() -> impl Iterator<Item = (&'static str, &'static str)> {
+    OWNED_COLUMNS
+        .into_iter()
+        .chain(LISTED_COLUMNS)
+        .chain(DROPPED_COLUMNS)
+        .chain(PHONE_COLUMNS)
+        .chain([("users", "number")])
+}
+
+/// `movenumber <number>` starts a move, `movenumber <code>` finishes it,
+/// and `movenumber cancel` calls it off.
+/// Also returns the new number (sealed) once the account has moved there.
+pub async fn handle_movenumber(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    args: &str,
+) -> Result<(String, Option<String>)> {
+    let args = args.trim();
+    if args.is_empty() {
+        return Ok((Command::movenumber.hint(lang), None));
+    }
+    if args.eq_ignore_ascii_case("cancel") {
+        let response = if verification::cancel(pool, from, Purpose::MoveNumber).await? {
+            t!(lang, "move_cancelled")
+        } else {
+            t!(lang, "move_not_started", command = Command::movenumber)
+        };
+        return Ok((response, None));
+    }
+    if !verification::is_code(args) {
+        return Ok((start(pool, from, lang, args).await?, None));
+    }
+
+    let response = match verification::check(pool, from, Purpose::MoveNumber, args).await? {
+        Check::Valid { sent_to: new } => {
+            if has_account(pool, &new).await? {
+                t!(lang, "move_taken", number = display(&new)?)
+            } else {
+                move_account(pool, from, &new).await?;
+                let response = t!(lang, "move_done", number = display(&new)?);
+                return Ok((response, Some(new)));
+            }
+        }
+        Check::Wrong { left } => t!(lang, "move_wrong_code", left = left),
+        Check::TooManyAttempts => {
+            t!(
+                lang,
+                "move_too_many_attempts",
+                command = Command::movenumber
+            )
+        }
+        Check::Expired => t!(lang, "move_code_expired", command = Command::movenumber),
+        Check::NotStarted => t!(lang, "move_not_started", command = Command::movenumber),
+    };
+    Ok((response, None))
+}
+
+/// Texts a code to the new number, replacing any move already under way
+async fn start(pool: &Pool<Sqlite>, from: &str, lang: Lang, requested: &str) -> Result<String> {
+    let Ok(number) = E164::from_str(requested) else {
+        return Ok(t!(lang, "invalid_number", number = requested));
+    };
+    if number.extension().is_some() {
+        return Ok(t!(lang, "invalid_number", number = requested));
+    }
+    let display = number.display_for(Some(&number));
+    let new = pii::seal(number.as_str());
+    if new == from {
+        return Ok(t!(lang, "move_same_number"));
+    }
+    if has_account(pool, &new).await? {
+        return Ok(t!(lang, "move_taken", number = display));
+    }
+    if optout::opted_out(pool, &new).await? {
+        return Ok(t!(lang, "move_opted_out", number = display));
+    }
+
+    let mut tx = pool.begin().await?;
+    verification::start(&mut tx, from, Purpose::MoveNumber, &new).await?;
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!("started moving to {}", pii::redact(number.as_str())),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(t!(
+        lang,
+        "move_code_sent",
+        number = display,
+        command = Command::movenumber,
+        minutes = Purpose::MoveNumber.ttl_secs() / 60
+    ))
+}
+
+/// Whether `number` is someone's account already, with anything of their own there or a profile
+/// they've set up, so moving there would mix two people's accounts. A number that's only in
+/// other people's contacts and groups is free.
+async fn has_account(pool: &Pool<Sqlite>, number: &str) -> Result<bool> {
+    let set_up = query!(
+        "SELECT 1 as found FROM users WHERE number = ?
+            AND (terms_accepted_at IS NOT NULL OR timezone IS NOT NULL
+                OR deactivated_at IS NOT NULL)",
+        number
+    )
+    .fetch_optional(pool)
+    .await?;
+    if set_up.is_some() {
+        return Ok(true);
+    }
+    for (table, column) in OWNED_COLUMNS.into_iter().chain(DROPPED_COLUMNS) {
+        let found = sqlx::query(&format!("SELECT 1 FROM {table} WHERE {column} = ? LIMIT 1"))
+            .bind(number)
+            .fetch_optional(pool)
+            .await?;
+        if found.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn display(sealed: &str) -> Result<String> {
+    let number = pii::open(sealed)?;
+    Ok(E164::from_str(&number).map_or(number, |e164| e164.display_for(Some(&e164))))
+}
+
+/// Moves everything of `old`'s to `new` (both sealed), in one transaction.
+/// Whatever was under way at the old number is called off, then the new number takes over the
+/// old one's profile, every reference to the old number is pointed at it, and once nothing's
+/// left pointing at the old user it's removed.
+async fn move_account(pool: &Pool<Sqlite>, old: &str, new: &str) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for (table, column) in DROPPED_COLUMNS {
+        sqlx::query(&format!("DELETE FROM {table} WHERE {column} = ?"))
+            .bind(old)
+            .execute(&mut *tx)
+            .await?;
+    }
+    query!(
+        "INSERT INTO users (number, name) SELECT ?, name FROM users WHERE number = ?
+         ON CONFLICT (number) DO NOTHING",
+        new,
+        old
+    )
+    .execute(&mut *tx)
+    .await?;
+    query!(
+        "UPDATE users SET (name, lang, timezone, consent, terms_version, terms_accepted_at,
+            created_at, updated_at) = (SELECT name, lang, timezone, consent, terms_version,
+            terms_accepted_at, created_at, unixepoch() FROM users WHERE number = ?)
+         WHERE number = ?",
+        old,
+        new
+    )
+    .execute(&mut *tx)
+    .await?;
+    for (table, column) in OWNED_COLUMNS.into_iter().chain(LISTED_COLUMNS) {
+        // Anything they had under both numbers (say, in one person's contacts twice)
+        // keeps the new one
+        sqlx::query(&format!(
+            "UPDATE OR IGNORE {table} SET {column} = ? WHERE {column} = ?"
+        ))
+        .bind(new)
+        .bind(old)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(&format!("DELETE FROM {table} WHERE {column} = ?"))
+            .bind(old)
+            .execute(&mut *tx)
+            .await?;
+    }
+    // They're not their own contact, even if they'd saved the new number
+    query!(
+        "DELETE FROM contacts WHERE submitter_number = ?1 AND contact_user_number = ?1",
+        new
+    )
+    .execute(&mut *tx)
+    .await?;
+    query!("DELETE FROM users WHERE number = ?", old)
+        .execute(&mut *tx)
+        .await?;
+    let moved_to = pii::redact(&pii::open(new)?);
+    audit::record(
+        &mut *tx,
+        old,
+        Kind::Mutation,
+        format!("moved account to {moved_to}"),
+    )
+    .await?;
+    audit::record(&mut *tx, new, Kind::Mutation, "moved account here").await?;
+    tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,147 @@
+//! Signing up. Someone new is asked for their name, asked to confirm it (accepting the terms
+//! of service along with it, if there are any), and then shown around, so nobody has to guess
+//! at the `name` command. It's a session like any other flow, except
+//! that it runs before there's a user to attach it to.
+
+use anyhow::Result;
+use sqlx::{query, Pool, Sqlite};
+
+use crate::{
+    audit::{self, Kind},
+    command::Command,
+    i18n::{t, Lang},
+    pii, process_name,
+    search::fold,
+    session::{self, SessionState},
+    terms,
+};
+
+/// Replies to a message from a number that isn't a user yet
+pub async fn handle(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    command: Option<Result<Command, serde_json::Error>>,
+    body: &str,
+) -> Result<String> {
+    let lang = Lang::default();
+    let words = body.split_ascii_whitespace();
+    match command {
+        // Anyone who already knows the command can skip the questions,
+        // unless there are terms to accept
+        Some(Ok(Command::name)) => {
+            return Ok(match process_name(words.skip(1), lang) {
+                Ok(name) if terms::current().is_none() => register(pool, from, &name, lang).await?,
+                Ok(name) => propose(pool, from, &name, lang).await?,
+                Err(hint) => hint.to_string(),
+            })
+        }
+        Some(Ok(Command::h)) => return start(pool, from, lang).await,
+        _ => {}
+    }
+    let Some(session) = query!(
+        "SELECT pending_name FROM sessions
+         WHERE submitter_number = ? AND state = 'onboarding' AND expires_at > unixepoch()",
+        from
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return start(pool, from, lang).await;
+    };
+    match (session.pending_name, answer(body)) {
+        (Some(name), Some(true)) => register(pool, from, &pii::open(&name)?, lang).await,
+        (Some(_), Some(false)) => {
+            set_pending_name(pool, from, None).await?;
+            Ok(t!(lang, "onboarding_ask_again"))
+        }
+        _ => Ok(match process_name(words, lang) {
+            Ok(name) => propose(pool, from, &name, lang).await?,
+            Err(_) if body.is_empty() => t!(lang, "onboarding_ask_again"),
+            Err(hint) => hint.to_string(),
+        }),
+    }
+}
+
+/// Greets someone new and asks for their name
+async fn start(pool: &Pool<Sqlite>, from: &str, lang: Lang) -> Result<String> {
+    let mut tx = pool.begin().await?;
+    session::start(&mut tx, from, SessionState::Onboarding).await?;
+    tx.commit().await?;
+    Ok(t!(lang, "greeting"))
+}
+
+/// Asks someone to confirm their name, and accept the terms if there are any
+async fn propose(pool: &Pool<Sqlite>, from: &str, name: &str, lang: Lang) -> Result<String> {
+    let mut tx = pool.begin().await?;
+    session::resume_or_start(&mut tx, from, SessionState::Onboarding).await?;
+    tx.commit().await?;
+    set_pending_name(pool, from, Some(name)).await?;
+    Ok(match terms::current() {
+        Some(terms) => t!(
+            lang,
+            "onboarding_confirm_terms",
+            name = name,
+            url = terms.url
+        ),
+        None => t!(lang, "onboarding_confirm", name = name),
+    })
+}
+
+async fn set_pending_name(pool: &Pool<Sqlite>, from: &str, name: Option<&str>) -> Result<()> {
+    let sealed_name = name.map(pii::seal);
+    query!(
+        "UPDATE sessions SET pending_name = ? WHERE submitter_number = ?",
+        sealed_name,
+        from
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Whether a reply says yes or no, if it's either
+pub fn answer(body: &str) -> Option<bool> {
+    match fold(body.trim().trim_end_matches(['.', '!'])).as_str() {
+        "yes" | "y" | "yeah" | "yep" | "ok" | "okay" | "si" | "s" | "vale" => Some(true),
+        "no" | "n" | "nope" => Some(false),
+        _ => None,
+    }
+}
+
+async fn register(pool: &Pool<Sqlite>, from: &str, name: &str, lang: Lang) -> Result<String> {
+    let mut tx = pool.begin().await?;
+    session::end(&mut *tx, from).await?;
+    let sealed_name = pii::seal(name);
+    query!(
+        "INSERT INTO users (number, name, consent) VALUES (?, ?, 'granted')",
+        from,
+        sealed_name
+    )
+    .execute(&mut *tx)
+    .await?;
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!("registered as \"{name}\""),
+    )
+    .await?;
+    if let Some(terms) = terms::current() {
+        terms::accept(&mut tx, from, terms).await?;
+    }
+    tx.commit().await?;
+    Ok(t!(
+        lang,
+        "welcome",
+        name = name,
+        hint = Command::h.hint(lang)
+    ))
+}
+
+#[test]
+fn answers() {
+    assert_eq!(answer("Yes!"), Some(true));
+    assert_eq!(answer("Sí"), Some(true));
+    assert_eq!(answer("no"), Some(false));
+    assert_eq!(answer("Sam C"), None);
+}
@@ -0,0 +1,132 @@
+//! Keeping up with who's opted out of texts, the way Twilio sees it.
+//!
+//! Twilio stops delivering to anyone who texts STOP, or one of its other opt-out keywords, and
+//! starts again when they text START. With Advanced Opt-Out it says which it was in
+//! `OptOutType`; without it, a keyword has to be the whole text. A text Twilio turns away for
+//! this fails with error 21610, which the status callback reports too. All of these are kept in
+//! `opt_outs`, and [crate::outbound::send_all] won't send to anyone there.
+
+use anyhow::Result;
+use sqlx::{query, Pool, Sqlite};
+use tracing::*;
+
+use crate::pii;
+
+/// Twilio's default keywords for opting out, which only count as the whole text.
+/// Twilio's CANCEL is left out: when it really is an opt-out, Twilio says so with
+/// `OptOutType`, and otherwise it's someone answering the bot.
+const OPT_OUT_KEYWORDS: &[&str] = &[
+    "STOP",
+    "STOPALL",
+    "UNSUBSCRIBE",
+    "END",
+    "QUIT",
+    "REVOKE",
+    "OPTOUT",
+];
+
+/// Twilio's default keywords for opting back in
+const OPT_IN_KEYWORDS: &[&str] = &["START", "YES", "UNSTOP"];
+
+/// Twilio's error for a text to someone who's opted out
+pub const OPTED_OUT_ERROR: &str = "21610";
+
+/// A text was to someone who's opted out, so it wasn't sent
+#[derive(Debug)]
+pub struct OptedOut;
+
+impl std::fmt::Display for OptedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The recipient has opted out")
+    }
+}
+
+impl std::error::Error for OptedOut {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    OptOut,
+    OptIn,
+}
+
+/// What an incoming text does to its sender's opt-out, from Twilio's `OptOutType` if it sent
+/// one, or otherwise from whether the text is one of the default keywords
+fn change(opt_out_type: Option<&str>, body: &str) -> Option<Change> {
+    if let Some(opt_out_type) = opt_out_type {
+        return match opt_out_type.to_ascii_uppercase().as_str() {
+            "STOP" => Some(Change::OptOut),
+            "START" => Some(Change::OptIn),
+            _ => None,
+        };
+    }
+    let keyword = body.trim().to_ascii_uppercase();
+    if OPT_OUT_KEYWORDS.contains(&keyword.as_str()) {
+        Some(Change::OptOut)
+    } else if OPT_IN_KEYWORDS.contains(&keyword.as_str()) {
+        Some(Change::OptIn)
+    } else {
+        None
+    }
+}
+
+/// Notes an incoming text from `number` (sealed) that opts its sender out or back in
+pub async fn record(
+    pool: &Pool<Sqlite>,
+    number: &str,
+    opt_out_type: Option<&str>,
+    body: &str,
+) -> Result<()> {
+    match change(opt_out_type, body) {
+        Some(Change::OptOut) => opt_out(pool, number).await,
+        Some(Change::OptIn) => {
+            let opted_in = query!("DELETE FROM opt_outs WHERE number = ?", number)
+                .execute(pool)
+                .await?;
+            if opted_in.rows_affected() > 0 {
+                info!("A number opted back in");
+            }
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Stops texting `number` (sealed)
+pub async fn opt_out(pool: &Pool<Sqlite>, number: &str) -> Result<()> {
+    let opted_out = query!(
+        "INSERT INTO opt_outs (number) VALUES (?) ON CONFLICT DO NOTHING",
+        number
+    )
+    .execute(pool)
+    .await?;
+    if opted_out.rows_affected() > 0 {
+        info!("{} opted out", pii::redact(&pii::open(number)?));
+    }
+    Ok(())
+}
+
+/// Whether `number` (sealed) has opted out
+pub async fn opted_out(pool: &Pool<Sqlite>, number: &str) -> Result<bool> {
+    Ok(
+        query!("SELECT 1 as found FROM opt_outs WHERE number = ?", number)
+            .fetch_optional(pool)
+            .await?
+            .is_some(),
+    )
+}
+
+#[test]
+fn keywords() {
+    assert_eq!(change(None, "stop"), Some(Change::OptOut));
+    assert_eq!(change(None, " Unsubscribe\n"), Some(Change::OptOut));
+    assert_eq!(change(None, "start"), Some(Change::OptIn));
+    assert_eq!(change(None, "Yes"), Some(Change::OptIn));
+    // Only the whole text counts
+    assert_eq!(change(None, "stop by later"), None);
+    assert_eq!(change(None, "name Stop"), None);
+    assert_eq!(change(None, "cancel"), None);
+    // Twilio's word is final when it gives one, whatever the keywords are
+    assert_eq!(change(Some("STOP"), "arrêt"), Some(Change::OptOut));
+    assert_eq!(change(Some("START"), "stop"), Some(Change::OptIn));
+    assert_eq!(change(Some("HELP"), "stop"), None);
+}
@@ -0,0 +1,316 @@
+use std::{collections::BTreeMap, future::Future, sync::Mutex, time::Duration};
+
+use anyhow::{Context, Result};
+use futures::{stream, StreamExt};
+use openapi::apis::{
+    api20100401_message_api::{create_message, CreateMessageParams},
+    configuration::Configuration,
+};
+use sqlx::{Pool, Sqlite};
+use tokio::time::{sleep_until, Instant};
+use tracing::*;
+
+use crate::{
+    config, encoding,
+    message_log::{self, Entry},
+    optout::{self, OptedOut},
+    pii, simulate,
+};
+
+/// A message to send to one recipient
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outgoing {
+    pub to: String,
+    pub body: String,
+}
+
+/// When each of our numbers can next start a send. Every fan-out from a number shares its slots,
+/// so reminders and events going out at the same time don't add up past the number's limit.
+static NEXT_START: Mutex<BTreeMap<String, Instant>> = Mutex::new(BTreeMap::new());
+
+/// Limits for sending many messages at once
+#[derive(Debug, Clone, Copy)]
+pub struct FanOutConfig {
+    /// Most sends in flight at once
+    pub concurrency: usize,
+    /// Most sends started per second from each of our numbers
+    pub per_second: f64,
+}
+
+impl Default for FanOutConfig {
+    /// Twilio long codes are good for about one message per second
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            per_second: 1.0,
+        }
+    }
+}
+
+impl FanOutConfig {
+    /// Uses `SEND_CONCURRENCY` and `SEND_PER_SECOND`, falling back to the defaults
+    pub fn configured() -> Self {
+        let (config, default) = (config::get(), Self::default());
+        Self {
+            concurrency: config.send_concurrency.unwrap_or(default.concurrency),
+            per_second: config.send_per_second.unwrap_or(default.per_second),
+        }
+    }
+}
+
+/// Twilio credentials from the configuration
+pub fn twilio_config() -> Result<Configuration> {
+    let config = config::get();
+    Ok(Configuration {
+        basic_auth: Some((
+            config
+                .twilio_api_key_sid
+                .clone()
+                .context("TWILIO_API_KEY_SID is not set")?,
+            config.twilio_api_key_secret.clone(),
+        )),
+        ..Default::default()
+    })
+}
+
+/// Sends one text from one of our numbers, returning its SID. With `PUBLIC_URL` set, Twilio
+/// reports what happens to it afterwards to `/status` (see [crate::delivery]).
+#[instrument(skip_all)]
+pub async fn send(
+    twilio_config: &Configuration,
+    from: &str,
+    to: String,
+    message: String,
+) -> Result<String> {
+    let config = config::get();
+    let message_params = CreateMessageParams {
+        account_sid: config
+            .twilio_account_sid
+            .clone()
+            .context("TWILIO_ACCOUNT_SID is not set")?,
+        to,
+        from: Some(from.to_string()),
+        body: Some(message),
+        status_callback: config
+            .public_url
+            .as_ref()
+            .map(|url| format!("{}/status", url.trim_end_matches('/'))),
+        ..Default::default()
+    };
+    let message = create_message(twilio_config, message_params)
+        .await
+        .context("While sending message")?;
+    let sid = message
+        .sid
+        .flatten()
+        .context("Twilio didn't return a SID")?;
+    trace!("Message sent with SID {sid}");
+    Ok(sid)
+}
+
+/// Texts everyone through Twilio, within the limits set in the environment, and counts them in
+/// the message log. Returns each message with its SID, or why it couldn't be sent.
+/// Nothing is ever sent to anyone who's opted out; their messages fail with [OptedOut].
+/// When simulating, just logs them, with made-up SIDs.
+pub async fn send_all(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    messages: Vec<Outgoing>,
+) -> Result<Vec<(Outgoing, Result<String>)>> {
+    let mut blocked = Vec::new();
+    let mut allowed = Vec::new();
+    for message in messages {
+        if optout::opted_out(pool, &pii::seal(&message.to)).await? {
+            info!("Not texting {}, who opted out", pii::redact(&message.to));
+            blocked.push((message, Err(OptedOut.into())));
+        } else {
+            allowed.push(message);
+        }
+    }
+    let messages = allowed;
+    let mut results = if simulate::enabled() {
+        messages
+            .into_iter()
+            .map(|message| {
+                info!("Simulated text to {}: {}", message.to, message.body);
+                let sid = format!("SM{:032x}", rand::random::<u128>());
+                (message, Ok(sid))
+            })
+            .collect()
+    } else {
+        let twilio_config = twilio_config()?;
+        fan_out(&FanOutConfig::configured(), from, messages, |message| {
+            send(&twilio_config, from, message.to, message.body)
+        })
+        .await
+    };
+    for (message, result) in &results {
+        let entry = match result {
+            Ok(_) => Entry::Sent {
+                segments: encoding::segments(&message.body),
+            },
+            Err(_) => Entry::Failed,
+        };
+        // They've gone out either way, so this isn't worth failing over
+        if let Err(error) = message_log::record(pool, entry).await {
+            warn!("Couldn't log a sent message: {error:?}");
+        }
+    }
+    results.extend(blocked);
+    Ok(results)
+}
+
+/// Sends every message from `from` with `send`, keeping at most `concurrency` in flight
+/// and starting no more than `per_second` each second, counting other fan-outs from `from`.
+/// Returns each message with the outcome of sending it, in the order they finished.
+pub async fn fan_out<F, Fut, T>(
+    config: &FanOutConfig,
+    from: &str,
+    messages: Vec<Outgoing>,
+    send: F,
+) -> Vec<(Outgoing, Result<T>)>
+where
+    F: Fn(Outgoing) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let slots = stream::unfold(
+        (from.to_string(), config.per_second),
+        |(from, per_second)| async move {
+            pace(&from, per_second).await;
+            Some(((), (from, per_second)))
+        },
+    );
+
+    stream::iter(messages)
+        .zip(slots)
+        .map(|(message, ())| {
+            let sending = send(message.clone());
+            async move { (message, sending.await) }
+        })
+        .buffer_unordered(config.concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// Waits for the next slot to send from `from`, `1 / per_second` after the last one taken
+async fn pace(from: &str, per_second: f64) {
+    let start = {
+        let mut next_start = NEXT_START.lock().unwrap();
+        let now = Instant::now();
+        let start = next_start.get(from).map_or(now, |&next| next.max(now));
+        next_start.insert(
+            from.to_string(),
+            start + Duration::from_secs_f64(1.0 / per_second),
+        );
+        start
+    };
+    sleep_until(start).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+    use tokio::time::Instant;
+
+    #[tokio::test]
+    async fn fan_out_limits() {
+        let config = FanOutConfig {
+            concurrency: 2,
+            per_second: 20.0,
+        };
+        let messages: Vec<_> = (0..6)
+            .map(|i| Outgoing {
+                to: format!("+1555000000{i}"),
+                body: "hi".to_string(),
+            })
+            .collect();
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let most_in_flight = Arc::new(AtomicUsize::new(0));
+        let starts = Arc::new(Mutex::new(Vec::new()));
+        let results = fan_out(&config, "+15550000001", messages, |message| {
+            let (in_flight, most_in_flight, starts) =
+                (in_flight.clone(), most_in_flight.clone(), starts.clone());
+            async move {
+                starts.lock().unwrap().push(Instant::now());
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                most_in_flight.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(120)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                if message.to.ends_with('3') {
+                    bail!("undeliverable");
+                }
+                Ok(())
+            }
+        })
+        .await;
+
+        assert_eq!(results.len(), 6);
+        assert_eq!(results.iter().filter(|(_, r)| r.is_err()).count(), 1);
+        assert_eq!(most_in_flight.load(Ordering::SeqCst), 2);
+        let starts = starts.lock().unwrap();
+        for pair in starts.windows(2) {
+            // Allow a little slack for timer granularity
+            assert!(pair[1] - pair[0] >= Duration::from_millis(45));
+        }
+    }
+
+    #[tokio::test]
+    async fn fan_outs_share_each_number() {
+        let config = FanOutConfig {
+            concurrency: 4,
+            per_second: 20.0,
+        };
+        let messages = |count| {
+            (0..count)
+                .map(|i| Outgoing {
+                    to: format!("+1555000001{i}"),
+                    body: "hi".to_string(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let starts = Arc::new(Mutex::new(Vec::new()));
+        let record = |from: &'static str| {
+            let starts = starts.clone();
+            move |_| {
+                let starts = starts.clone();
+                async move {
+                    starts.lock().unwrap().push((from, Instant::now()));
+                    Ok(())
+                }
+            }
+        };
+
+        let began = Instant::now();
+        let (_, _, _) = tokio::join!(
+            fan_out(&config, "+15550000002", messages(3), record("+15550000002")),
+            fan_out(&config, "+15550000002", messages(3), record("+15550000002")),
+            fan_out(&config, "+15550000003", messages(1), record("+15550000003")),
+        );
+
+        let starts = starts.lock().unwrap();
+        let mut shared: Vec<_> = starts
+            .iter()
+            .filter(|(from, _)| *from == "+15550000002")
+            .map(|(_, start)| *start)
+            .collect();
+        shared.sort();
+        assert_eq!(shared.len(), 6);
+        // Each send waits for its own slot, however late the ones before it woke up.
+        // Allow a little slack for timer granularity.
+        for (i, start) in shared.iter().enumerate() {
+            assert!(*start - began >= Duration::from_millis((50 * i as u64).saturating_sub(5)));
+        }
+        // Another number doesn't wait its turn behind them
+        let (_, other) = starts
+            .iter()
+            .find(|(from, _)| *from == "+15550000003")
+            .unwrap();
+        assert!(*other < shared[1]);
+    }
+}
@@ -0,0 +1,303 @@
+//! Encryption at rest for phone numbers, names and anything else that identifies a person.
+//!
+//! Values are sealed with XChaCha20-Poly1305 under a key from the `DATA_KEY` environment variable.
+//! The nonce is derived from an HMAC of the plaintext, so sealing is deterministic:
+//! the same value always seals to the same text. That keeps equality lookups, primary keys and
+//! foreign keys working on sealed columns, at the cost of revealing which rows share a value.
+//! Anything that needs more than equality (substring search, sorting by name) happens in Rust
+//! after opening.
+//!
+//! By convention the sender's number is sealed once when a message arrives and passed around
+//! sealed, as an opaque id. Other values are sealed just before they're written or looked up,
+//! and opened just after they're read.
+//!
+//! Without a key, sealing is a no-op, and [open] passes through anything that isn't sealed,
+//! so a database can be switched over with [seal_existing].
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    XChaCha20Poly1305, XNonce,
+};
+use hmac::{Hmac, Mac};
+use once_cell::sync::OnceCell;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite};
+use tracing::*;
+
+/// Marks a sealed value, so it can't be confused with plaintext
+const PREFIX: &str = "enc:";
+const NONCE_LEN: usize = 24;
+
+static KEYS: OnceCell<Keys> = OnceCell::new();
+
+struct Keys {
+    cipher: XChaCha20Poly1305,
+    /// Keys the HMAC that derives each value's nonce
+    nonce_key: [u8; 32],
+    /// Keys the HMAC that short secrets like codes and PINs are stored as
+    secret_key: [u8; 32],
+}
+
+impl Keys {
+    /// Derives separate encryption and nonce keys from the configured key
+    fn new(key: &[u8]) -> Result<Self> {
+        if key.len() != 32 {
+            bail!("DATA_KEY must be 32 bytes, got {}", key.len());
+        }
+        let derive = |label: &[u8]| -> [u8; 32] {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("any key length works");
+            mac.update(label);
+            mac.finalize().into_bytes().into()
+        };
+        Ok(Self {
+            cipher: XChaCha20Poly1305::new(&derive(b"encryption").into()),
+            nonce_key: derive(b"nonce"),
+            secret_key: derive(b"secret"),
+        })
+    }
+
+    fn seal(&self, plaintext: &str) -> String {
+        let mut mac =
+            <Hmac<Sha256> as Mac>::new_from_slice(&self.nonce_key).expect("any key length works");
+        mac.update(plaintext.as_bytes());
+        let digest = mac.finalize().into_bytes();
+        let nonce = XNonce::from_slice(&digest[..NONCE_LEN]);
+        let ciphertext = self
+            .cipher
+            .encrypt(nonce, plaintext.as_bytes())
+            .expect("encryption can't fail for in-memory data");
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        format!("{PREFIX}{}", STANDARD_NO_PAD.encode(sealed))
+    }
+
+    fn open(&self, sealed: &str) -> Result<String> {
+        let bytes = STANDARD_NO_PAD.decode(sealed)?;
+        if bytes.len() < NONCE_LEN {
+            bail!("Sealed value is too short");
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Sealed value failed authentication"))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+/// Loads the key from `DATA_KEY` (base64-encoded, 32 bytes), if set.
+/// Call once at startup, before anything is sealed.
+pub fn init() -> Result<()> {
+    let Some(key) = &crate::config::get().data_key else {
+        warn!("DATA_KEY is not set; personal data will be stored unencrypted");
+        return Ok(());
+    };
+    let key = STANDARD_NO_PAD
+        .decode(key.trim().trim_end_matches('='))
+        .context("DATA_KEY must be base64")?;
+    if KEYS.set(Keys::new(&key)?).is_err() {
+        bail!("Encryption key was already loaded");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Lets a test encrypt without touching the process-wide key other tests share
+    static TEST_KEYS: std::cell::Cell<Option<&'static Keys>> = const { std::cell::Cell::new(None) };
+}
+
+/// Turns on encryption with a throwaway key for the rest of the current test
+#[cfg(test)]
+pub fn use_test_key() {
+    let keys = Box::leak(Box::new(Keys::new(&[7; 32]).unwrap()));
+    TEST_KEYS.with(|k| k.set(Some(keys)));
+}
+
+fn keys() -> Option<&'static Keys> {
+    #[cfg(test)]
+    if let Some(keys) = TEST_KEYS.with(|k| k.get()) {
+        return Some(keys);
+    }
+    KEYS.get()
+}
+
+/// Seals a value for storage. Deterministic, so sealed values can be compared and looked up.
+pub fn seal(plaintext: &str) -> String {
+    match keys() {
+        Some(keys) => keys.seal(plaintext),
+        None => plaintext.to_string(),
+    }
+}
+
+/// Recovers a sealed value. Values that were never sealed come back unchanged.
+pub fn open(stored: &str) -> Result<String> {
+    let Some(sealed) = stored.strip_prefix(PREFIX) else {
+        return Ok(stored.to_string());
+    };
+    keys()
+        .context("Found encrypted data but DATA_KEY is not set")?
+        .open(sealed)
+}
+
+/// Cuts a phone number down to its last four digits, for logs
+pub fn redact(number: &str) -> String {
+    let digits: Vec<char> = number.chars().collect();
+    let tail: String = digits[digits.len().saturating_sub(4)..].iter().collect();
+    format!("…{tail}")
+}
+
+/// A short, stable stand-in for a value, for telling reports about the same person apart
+/// without revealing who they are. Keyed when encryption is on, since there are few enough
+/// phone numbers to simply hash them all.
+pub fn fingerprint(value: &str) -> String {
+    let digest: Vec<u8> = match keys() {
+        Some(keys) => {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&keys.nonce_key)
+                .expect("any key length works");
+            mac.update(b"fingerprint:");
+            mac.update(value.as_bytes());
+            mac.finalize().into_bytes().to_vec()
+        }
+        None => Sha256::digest(value.as_bytes()).to_vec(),
+    };
+    digest[..6].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Hashes a short secret, like a one-time code or a PIN, for storing. Keyed when encryption is
+/// on, since there are few enough codes and PINs to simply hash them all; without a key,
+/// everything else is stored as plaintext anyway.
+pub fn hash_secret(value: &str) -> String {
+    let digest: Vec<u8> = match keys() {
+        Some(keys) => {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&keys.secret_key)
+                .expect("any key length works");
+            mac.update(value.as_bytes());
+            mac.finalize().into_bytes().to_vec()
+        }
+        None => Sha256::digest(value.as_bytes()).to_vec(),
+    };
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Every column holding personal data, as `(table, column)`.
+/// The audit log is left out: it's append-only, so entries from before encryption was
+/// turned on stay as they were written.
+const COLUMNS: &[(&str, &str)] = &[
+    ("users", "number"),
+    ("users", "name"),
+    ("contacts", "submitter_number"),
+    ("contacts", "contact_name"),
+    ("contacts", "contact_user_number"),
+    ("contacts", "organization"),
+    ("contacts", "title"),
+    ("deferred_contacts", "submitter_number"),
+    ("deferred_contacts", "contact_name"),
+    ("deferred_contacts", "phone_number"),
+    ("deferred_contacts", "organization"),
+    ("deferred_contacts", "title"),
+    ("groups", "creator_number"),
+    ("group_members", "member_number"),
+    ("sessions", "submitter_number"),
+    ("sessions", "pending_name"),
+    ("pending_deletions", "session_submitter"),
+    ("pending_group_members", "session_submitter"),
+    ("rate_limits", "number"),
+    ("blocks", "blocker_number"),
+    ("blocks", "blocked_number"),
+    ("blocklist", "number"),
+    ("exports", "number"),
+    ("exports", "body"),
+    ("processed_messages", "response"),
+    ("scheduled_messages", "creator_number"),
+    ("scheduled_messages", "recipient_number"),
+    ("scheduled_messages", "target"),
+    ("scheduled_messages", "body"),
+    ("later_messages", "creator_number"),
+    ("later_messages", "target"),
+    ("later_messages", "body"),
+    ("later_deliveries", "recipient_number"),
+    ("picks", "creator_number"),
+    ("picks", "options"),
+    ("picks", "choice"),
+    ("events", "organizer_number"),
+    ("events", "title"),
+    ("rsvps", "number"),
+    ("rotation_members", "name"),
+    ("rotation_members", "number"),
+    ("quota_usage", "number"),
+    ("tracked_messages", "creator_number"),
+    ("tracked_messages", "recipient_number"),
+    ("last_responses", "number"),
+    ("opt_outs", "number"),
+    ("last_responses", "response"),
+    ("verification_codes", "number"),
+    ("verification_codes", "send_to"),
+    ("pins", "number"),
+    ("pins", "pending"),
+];
+
+/// Seals any personal data still stored in plaintext, e.g. after a key is first configured.
+/// Runs in one transaction with foreign key checks deferred to the end, since numbers
+/// are keys that other tables point at.
+pub async fn seal_existing(pool: &Pool<Sqlite>) -> Result<()> {
+    if keys().is_none() {
+        return Ok(());
+    }
+    let mut tx = pool.begin().await?;
+    sqlx::query("PRAGMA defer_foreign_keys = ON")
+        .execute(&mut *tx)
+        .await?;
+    let pattern = format!("{PREFIX}%");
+    for (table, column) in COLUMNS {
+        let plaintexts: Vec<String> = sqlx::query_scalar(&format!(
+            "SELECT DISTINCT {column} FROM {table} WHERE {column} NOT LIKE ?"
+        ))
+        .bind(&pattern)
+        .fetch_all(&mut *tx)
+        .await?;
+        for plaintext in &plaintexts {
+            sqlx::query(&format!(
+                "UPDATE {table} SET {column} = ? WHERE {column} = ?"
+            ))
+            .bind(seal(plaintext))
+            .bind(plaintext)
+            .execute(&mut *tx)
+            .await?;
+        }
+        if !plaintexts.is_empty() {
+            info!("Sealed {} value(s) in {table}.{column}", plaintexts.len());
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_open() {
+        use_test_key();
+        let sealed = seal("+11234567890");
+        assert!(sealed.starts_with(PREFIX));
+        assert!(!sealed.contains("1234567890"));
+        assert_eq!(sealed, seal("+11234567890"));
+        assert_ne!(sealed, seal("+11234567891"));
+        assert_eq!(open(&sealed).unwrap(), "+11234567890");
+
+        // Plaintext written before encryption was turned on still reads back
+        assert_eq!(open("John Doe").unwrap(), "John Doe");
+
+        // Tampering is detected
+        let mut tampered = sealed.clone();
+        tampered.replace_range(10..11, if &sealed[10..11] == "A" { "B" } else { "A" });
+        assert!(open(&tampered).is_err());
+
+        assert_eq!(redact("+11234567890"), "…7890");
+        assert_eq!(redact("12"), "…12");
+    }
+}
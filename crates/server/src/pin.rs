@@ -0,0 +1,291 @@
+//! Optional PINs guarding destructive commands, so someone borrowing a phone for a minute
+//! can't delete or export an account.
+//!
+//! `pin 1234` sets one. From then on `stop`, `export`, moving to a new number and confirming
+//! more than one deletion at a time ask for it first, and go ahead once it's sent. After
+//! [MAX_FAILURES] wrong PINs in a row, those commands are locked for [LOCKOUT_SECS]. PINs are
+//! stored hashed, the same way as [verification] codes, so with `DATA_KEY` set a copy of the
+//! database can't be tried against every PIN offline, out of reach of the lockout.
+
+use anyhow::Result;
+use sqlx::{query, Pool, Sqlite};
+
+use crate::{
+    audit::{self, Kind},
+    command::Command,
+    i18n::{self, t, Lang},
+    pii,
+    session::{self, SessionState},
+    verification,
+};
+
+pub const LENGTH: usize = 4;
+/// Wrong PINs in a row before the lockout
+const MAX_FAILURES: i64 = 5;
+pub const LOCKOUT_SECS: i64 = 15 * 60;
+/// How long a command waits for its PIN
+const PENDING_SECS: i64 = 5 * 60;
+
+fn is_pin(text: &str) -> bool {
+    text.len() == LENGTH && text.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Why a PIN didn't work
+enum Refusal {
+    Wrong { left: i64 },
+    Locked { minutes: i64 },
+}
+
+impl Refusal {
+    fn reply(&self, lang: Lang) -> String {
+        match self {
+            Refusal::Wrong { left } => t!(lang, "pin_wrong", left = left),
+            Refusal::Locked { minutes } => t!(lang, "pin_locked", minutes = minutes),
+        }
+    }
+}
+
+/// Checks a PIN `from` sent, counting it against them if it's wrong. None if it's right.
+async fn attempt(pool: &Pool<Sqlite>, from: &str, pin: &str) -> Result<Option<Refusal>> {
+    let Some(stored) = query!(
+        r#"SELECT salt, pin_hash, failures,
+            COALESCE(locked_until - unixepoch(), 0) as "locked_for!: i64"
+         FROM pins WHERE number = ?"#,
+        from
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    if stored.locked_for > 0 {
+        return Ok(Some(Refusal::Locked {
+            minutes: (stored.locked_for + 59) / 60,
+        }));
+    }
+    if verification::matches(&stored.salt, pin, &stored.pin_hash) {
+        query!(
+            "UPDATE pins SET failures = 0, locked_until = NULL WHERE number = ?",
+            from
+        )
+        .execute(pool)
+        .await?;
+        return Ok(None);
+    }
+    let failures = stored.failures + 1;
+    if failures >= MAX_FAILURES {
+        let mut tx = pool.begin().await?;
+        query!(
+            "UPDATE pins SET failures = 0, locked_until = unixepoch() + ?,
+                pending = NULL, pending_at = NULL
+             WHERE number = ?",
+            LOCKOUT_SECS,
+            from
+        )
+        .execute(&mut *tx)
+        .await?;
+        audit::record(&mut *tx, from, Kind::Mutation, "locked out by wrong PINs").await?;
+        tx.commit().await?;
+        return Ok(Some(Refusal::Locked {
+            minutes: LOCKOUT_SECS / 60,
+        }));
+    }
+    query!(
+        "UPDATE pins SET failures = ? WHERE number = ?",
+        failures,
+        from
+    )
+    .execute(pool)
+    .await?;
+    Ok(Some(Refusal::Wrong {
+        left: MAX_FAILURES - failures,
+    }))
+}
+
+/// `pin` says whether one's set, `pin 1234` sets one, `pin OLD NEW` changes it,
+/// and `pin off OLD` removes it
+pub async fn handle_pin(pool: &Pool<Sqlite>, from: &str, lang: Lang, args: &str) -> Result<String> {
+    let has_pin = query!("SELECT 1 as found FROM pins WHERE number = ?", from)
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+    let words = args.split_whitespace().collect::<Vec<_>>();
+    match (has_pin, words.as_slice()) {
+        (true, []) => Ok(t!(lang, "pin_on", command = Command::pin)),
+        (false, []) => Ok(t!(lang, "pin_off", hint = Command::pin.hint(lang))),
+        (false, [new]) => set(pool, from, lang, new).await,
+        (true, [_]) => Ok(t!(lang, "pin_on", command = Command::pin)),
+        (true, [off, old]) if off.eq_ignore_ascii_case("off") => {
+            match attempt(pool, from, old).await? {
+                None => {
+                    let mut tx = pool.begin().await?;
+                    query!("DELETE FROM pins WHERE number = ?", from)
+                        .execute(&mut *tx)
+                        .await?;
+                    audit::record(&mut *tx, from, Kind::Mutation, "removed PIN").await?;
+                    tx.commit().await?;
+                    Ok(t!(lang, "pin_removed"))
+                }
+                Some(refusal) => Ok(refusal.reply(lang)),
+            }
+        }
+        (true, [old, new]) => match attempt(pool, from, old).await? {
+            None => set(pool, from, lang, new).await,
+            Some(refusal) => Ok(refusal.reply(lang)),
+        },
+        _ => Ok(Command::pin.hint(lang)),
+    }
+}
+
+async fn set(pool: &Pool<Sqlite>, from: &str, lang: Lang, pin: &str) -> Result<String> {
+    if !is_pin(pin) {
+        return Ok(t!(lang, "pin_invalid", length = LENGTH));
+    }
+    let salt = verification::salt();
+    let pin_hash = verification::hash(&salt, pin);
+    let mut tx = pool.begin().await?;
+    query!(
+        "INSERT INTO pins (number, salt, pin_hash) VALUES (?, ?, ?)
+         ON CONFLICT (number) DO UPDATE SET salt = excluded.salt, pin_hash = excluded.pin_hash",
+        from,
+        salt,
+        pin_hash
+    )
+    .execute(&mut *tx)
+    .await?;
+    audit::record(&mut *tx, from, Kind::Mutation, "set PIN").await?;
+    tx.commit().await?;
+    Ok(t!(lang, "pin_set", command = Command::pin))
+}
+
+/// Whether `command` (sent as `body`) is one the PIN guards
+async fn guards(pool: &Pool<Sqlite>, from: &str, command: Command, body: &str) -> Result<bool> {
+    let args = body
+        .trim()
+        .split_once(char::is_whitespace)
+        .map_or("", |(_, args)| args.trim());
+    Ok(match command {
+        Command::stop | Command::export => true,
+        // Starting or finishing a move, but not calling one off
+        Command::movenumber => !args.is_empty() && !args.eq_ignore_ascii_case("cancel"),
+        Command::confirm => {
+            args.split(',').filter(|s| !s.trim().is_empty()).count() > 1
+                && session::current(pool, from).await? == Some(SessionState::Deletion)
+        }
+        _ => false,
+    })
+}
+
+/// Holds `command` (sent as `body`) for the PIN, if it's one the PIN guards and they have one.
+/// Returns what to tell them instead of running it.
+pub async fn guard(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    command: Command,
+    body: &str,
+) -> Result<Option<String>> {
+    if !guards(pool, from, command, body).await? {
+        return Ok(None);
+    }
+    let Some(stored) = query!(
+        r#"SELECT COALESCE(locked_until - unixepoch(), 0) as "locked_for!: i64"
+         FROM pins WHERE number = ?"#,
+        from
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    if stored.locked_for > 0 {
+        let minutes = (stored.locked_for + 59) / 60;
+        return Ok(Some(Refusal::Locked { minutes }.reply(lang)));
+    }
+    let pending = pii::seal(body.trim());
+    query!(
+        "UPDATE pins SET pending = ?, pending_at = unixepoch() WHERE number = ?",
+        pending,
+        from
+    )
+    .execute(pool)
+    .await?;
+    Ok(Some(t!(
+        lang,
+        "pin_required",
+        command = command,
+        minutes = PENDING_SECS / 60
+    )))
+}
+
+/// A message as it can go in the audit log, with any PIN in it left out
+pub fn redact(body: &str) -> String {
+    let mut words = body.split_whitespace();
+    match words.next() {
+        Some(word) if is_pin(word) && words.next().is_none() => "[PIN]".to_string(),
+        Some(word)
+            if matches!(Command::try_from(word), Ok(Command::pin)) && words.next().is_some() =>
+        {
+            format!("{word} [PIN]")
+        }
+        _ => body.to_string(),
+    }
+}
+
+/// What to do with a message that might be a PIN
+pub enum Unlock {
+    /// It isn't one, or nothing's waiting for one
+    NotPin,
+    /// It's wrong, so say so
+    Reply(String),
+    /// It's right, so run the command that was waiting for it
+    Run(String),
+}
+
+/// Takes a PIN sent for a command waiting on it
+pub async fn unlock(pool: &Pool<Sqlite>, from: &str, body: &str) -> Result<Unlock> {
+    let body = body.trim();
+    if !is_pin(body) {
+        return Ok(Unlock::NotPin);
+    }
+    let Some(waiting) = query!(
+        r#"SELECT pending as "pending!" FROM pins
+         WHERE number = ? AND pending IS NOT NULL AND pending_at > unixepoch() - ?"#,
+        from,
+        PENDING_SECS
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(Unlock::NotPin);
+    };
+    match attempt(pool, from, body).await? {
+        None => {
+            query!(
+                "UPDATE pins SET pending = NULL, pending_at = NULL WHERE number = ?",
+                from
+            )
+            .execute(pool)
+            .await?;
+            Ok(Unlock::Run(pii::open(&waiting.pending)?))
+        }
+        Some(refusal) => {
+            let lang = i18n::user_lang(pool, from).await?;
+            Ok(Unlock::Reply(refusal.reply(lang)))
+        }
+    }
+}
+
+#[test]
+fn pins() {
+    assert!(is_pin("1234"));
+    assert!(is_pin("0000"));
+    assert!(!is_pin("123"));
+    assert!(!is_pin("12345"));
+    assert!(!is_pin("12a4"));
+
+    assert_eq!(redact("1234"), "[PIN]");
+    assert_eq!(redact("pin 1234 5678"), "pin [PIN]");
+    assert_eq!(redact("Pin"), "Pin");
+    assert_eq!(redact("call me at 1234"), "call me at 1234");
+}
@@ -0,0 +1,119 @@
+//! Per-user limits on what costs the most to send, so one enthusiastic user can't run up the
+//! Twilio bill for everyone: contact imports an hour, reminders and events scheduled a day, and
+//! how many group members a reminder or event can go to. Set in the configuration, where 0 turns
+//! a limit off.
+
+use anyhow::Result;
+use sqlx::{query, Pool, Sqlite, SqliteExecutor};
+
+use crate::{
+    config,
+    i18n::{t, Lang},
+};
+
+/// How long usage is kept, which must cover the longest window
+pub const RETENTION_SECS: i64 = 24 * 60 * 60;
+
+/// Something limited to so many uses per user in a window of time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quota {
+    Imports,
+    Scheduled,
+}
+
+impl Quota {
+    fn kind(&self) -> &'static str {
+        match self {
+            Quota::Imports => "import",
+            Quota::Scheduled => "scheduled",
+        }
+    }
+
+    fn window_secs(&self) -> i64 {
+        match self {
+            Quota::Imports => 60 * 60,
+            Quota::Scheduled => 24 * 60 * 60,
+        }
+    }
+
+    fn limit(&self) -> i64 {
+        let config = config::get();
+        match self {
+            Quota::Imports => config.quota_imports_per_hour,
+            Quota::Scheduled => config.quota_scheduled_per_day,
+        }
+    }
+}
+
+/// The reply to send instead of going ahead, if `from` has used up the quota
+pub async fn check(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    quota: Quota,
+) -> Result<Option<String>> {
+    let limit = quota.limit();
+    if limit == 0 {
+        return Ok(None);
+    }
+    let (kind, window) = (quota.kind(), quota.window_secs());
+    let used = query!(
+        r#"SELECT COUNT(*) as "count!: i64" FROM quota_usage
+         WHERE number = ? AND kind = ? AND used_at > unixepoch() - ?"#,
+        from,
+        kind,
+        window
+    )
+    .fetch_one(pool)
+    .await?
+    .count;
+    if used < limit {
+        return Ok(None);
+    }
+    Ok(Some(match quota {
+        Quota::Imports => t!(lang, "quota_imports", limit = limit),
+        Quota::Scheduled => t!(lang, "quota_scheduled", limit = limit),
+    }))
+}
+
+/// Counts a use against the quota. Pass the transaction making the change, where there is one.
+pub async fn record(executor: impl SqliteExecutor<'_>, from: &str, quota: Quota) -> Result<()> {
+    let kind = quota.kind();
+    query!(
+        "INSERT INTO quota_usage (number, kind) VALUES (?, ?)",
+        from,
+        kind
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// The reply to send instead of going ahead, if a group is too big to message all at once
+pub async fn check_recipients(
+    pool: &Pool<Sqlite>,
+    lang: Lang,
+    group_id: i64,
+    group: &str,
+) -> Result<Option<String>> {
+    let limit = config::get().quota_recipients;
+    if limit == 0 {
+        return Ok(None);
+    }
+    let count = query!(
+        r#"SELECT COUNT(*) as "count!: i64" FROM group_members WHERE group_id = ?"#,
+        group_id
+    )
+    .fetch_one(pool)
+    .await?
+    .count;
+    Ok((count > limit).then(|| {
+        t!(
+            lang,
+            "quota_recipients",
+            group = group,
+            count = count,
+            limit = limit
+        )
+    }))
+}
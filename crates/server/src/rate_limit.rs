@@ -0,0 +1,187 @@
+use anyhow::Result;
+use lru::LruCache;
+use sqlx::{query, Pool, Sqlite};
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::*;
+
+/// How many messages a sender can send back-to-back before being throttled
+pub const BURST: f64 = 10.0;
+/// Tokens regained per second (one message every 6 seconds, sustained)
+const REFILL_PER_SEC: f64 = 1.0 / 6.0;
+/// Most senders' buckets held in memory. Past this, the one heard from longest ago is dropped.
+/// A throttled sender's bucket is also in the database, so dropping it doesn't let them off.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Buckets dropped to make room since the server started, across every tenant, for `stats`
+static EVICTIONS: AtomicU64 = AtomicU64::new(0);
+
+pub fn evictions() -> u64 {
+    EVICTIONS.load(Ordering::Relaxed)
+}
+
+/// What to do with an incoming message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Handle it as usual
+    Allow,
+    /// Reply with a throttle notice instead of handling it
+    Throttle,
+    /// The sender has already been told they're throttled; ignore it entirely
+    Drop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bucket {
+    tokens: f64,
+    /// Unix time (seconds) the tokens were last brought up to date
+    updated_at: f64,
+    /// Whether the sender has been sent a throttle notice since they last had a token
+    notified: bool,
+}
+
+impl Bucket {
+    fn full(now: f64) -> Self {
+        Self {
+            tokens: BURST,
+            updated_at: now,
+            notified: false,
+        }
+    }
+
+    fn take(&mut self, now: f64) -> Decision {
+        let elapsed = (now - self.updated_at).max(0.0);
+        self.tokens = (self.tokens + elapsed * REFILL_PER_SEC).min(BURST);
+        self.updated_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.notified = false;
+            Decision::Allow
+        } else if self.notified {
+            Decision::Drop
+        } else {
+            self.notified = true;
+            Decision::Throttle
+        }
+    }
+}
+
+/// Token bucket rate limiter for inbound messages, keyed by sender.
+/// Buckets live in memory. While a sender is throttled their bucket is also kept in the database,
+/// so restarting the server doesn't hand an abusive sender a fresh burst.
+#[derive(Clone)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<LruCache<String, Bucket>>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        let capacity = NonZeroUsize::new(MAX_BUCKETS).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            buckets: Arc::new(Mutex::new(LruCache::new(capacity))),
+        }
+    }
+}
+
+impl RateLimiter {
+    pub async fn check(&self, pool: &Pool<Sqlite>, from: &str) -> Result<Decision> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+        let cached = self.buckets.lock().unwrap().contains(from);
+        let stored = if cached {
+            None
+        } else {
+            load(pool, from).await?
+        };
+
+        let (was_notified, bucket, decision) = {
+            let mut buckets = self.buckets.lock().unwrap();
+            let mut bucket = buckets
+                .get(from)
+                .copied()
+                .or(stored)
+                .unwrap_or_else(|| Bucket::full(now));
+            let was_notified = bucket.notified;
+            let decision = bucket.take(now);
+            if let Some((evicted, _)) = buckets.push(from.to_string(), bucket) {
+                if evicted != from {
+                    EVICTIONS.fetch_add(1, Ordering::Relaxed);
+                    debug!("Rate limiter full; dropped the least recently heard from sender");
+                }
+            }
+            (was_notified, bucket, decision)
+        };
+
+        match decision {
+            Decision::Throttle => store(pool, from, &bucket).await?,
+            Decision::Allow if was_notified => {
+                query!("DELETE FROM rate_limits WHERE number = ?", from)
+                    .execute(pool)
+                    .await?;
+            }
+            _ => {}
+        }
+        Ok(decision)
+    }
+}
+
+async fn load(pool: &Pool<Sqlite>, from: &str) -> Result<Option<Bucket>> {
+    Ok(query!(
+        "SELECT tokens, updated_at, notified FROM rate_limits WHERE number = ?",
+        from
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| Bucket {
+        tokens: row.tokens,
+        updated_at: row.updated_at,
+        notified: row.notified,
+    }))
+}
+
+async fn store(pool: &Pool<Sqlite>, from: &str, bucket: &Bucket) -> Result<()> {
+    query!(
+        "INSERT INTO rate_limits (number, tokens, updated_at, notified) VALUES (?, ?, ?, ?)
+         ON CONFLICT(number) DO UPDATE SET
+            tokens = excluded.tokens,
+            updated_at = excluded.updated_at,
+            notified = excluded.notified",
+        from,
+        bucket.tokens,
+        bucket.updated_at,
+        bucket.notified
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket() {
+        let mut bucket = Bucket::full(0.0);
+        for _ in 0..BURST as usize {
+            assert_eq!(bucket.take(0.0), Decision::Allow);
+        }
+        assert_eq!(bucket.take(0.0), Decision::Throttle);
+        assert_eq!(bucket.take(1.0), Decision::Drop);
+
+        // One token comes back every 1 / REFILL_PER_SEC seconds
+        let refilled = 1.0 + 1.0 / REFILL_PER_SEC;
+        assert_eq!(bucket.take(refilled), Decision::Allow);
+        assert_eq!(bucket.take(refilled), Decision::Throttle);
+
+        // Never refills past the burst size
+        let mut bucket = Bucket::full(0.0);
+        bucket.take(1_000_000.0);
+        assert_eq!(bucket.tokens, BURST - 1.0);
+    }
+}
@@ -0,0 +1,406 @@
+//! Messages scheduled for later with `remind`, to the sender, a contact or a group.
+//!
+//! Each one is a row in `scheduled_messages` plus a [Job::Reminder] due at the same time,
+//! which sends it unless it's been cancelled in the meantime.
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use sqlx::{query, query_as, Pool, Sqlite};
+use tracing::*;
+
+use crate::{
+    audit::{self, Kind},
+    block,
+    command::Command,
+    consent::{self, Consent},
+    delivery,
+    i18n::{t, user_lang, Lang},
+    jobs::{self, Job},
+    outbound::{self, Outgoing},
+    pii,
+    quota::{self, Quota},
+    timezone, when, Contact,
+};
+
+/// Words that mean the sender themself
+const SELF_WORDS: &[&str] = &["me", "yo"];
+
+/// Who a reminder goes to. Numbers are sealed.
+pub enum Target {
+    Number { number: String, label: String },
+    Group { id: i64, label: String },
+    NotFound,
+    Ambiguous(Vec<String>),
+}
+
+/// `remind` lists pending reminders, `remind cancel N` cancels one,
+/// and `remind <who> <when>: <message>` schedules one
+pub async fn handle_remind(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    zone: Tz,
+    args: &str,
+) -> Result<String> {
+    let args = args.trim();
+    if args.is_empty() {
+        return list(pool, from, lang, zone).await;
+    }
+    if let Some(selection) = args
+        .split_once(char::is_whitespace)
+        .filter(|(word, _)| word.eq_ignore_ascii_case("cancel"))
+        .map(|(_, selection)| selection.trim())
+    {
+        return cancel(pool, from, lang, selection).await;
+    }
+
+    let Some((head, body)) = when::split_message(args) else {
+        return Ok(Command::remind.hint(lang));
+    };
+    let Some((who, time)) = head.trim().split_once(char::is_whitespace) else {
+        return Ok(Command::remind.hint(lang));
+    };
+    if body.is_empty() {
+        return Ok(Command::remind.hint(lang));
+    }
+
+    let now = Utc::now().with_timezone(&zone);
+    let Some(send_at) = when::parse(time, &now) else {
+        return Ok(t!(lang, "remind_bad_time", time = time.trim()));
+    };
+    if send_at <= now {
+        return Ok(t!(
+            lang,
+            "remind_past",
+            time = timezone::display(send_at.to_utc(), zone)
+        ));
+    }
+
+    let target = resolve(pool, from, who).await?;
+    let (group_id, recipient_number, label) = match &target {
+        Target::Number { number, label } => (None, Some(number.as_str()), label.as_str()),
+        Target::Group { id, label } => (Some(*id), None, label.as_str()),
+        Target::NotFound => return Ok(t!(lang, "remind_unknown_target", who = who)),
+        Target::Ambiguous(names) => {
+            return Ok(t!(
+                lang,
+                "remind_ambiguous",
+                who = who,
+                names = names.join(", ")
+            ))
+        }
+    };
+
+    if let Some(reply) = quota::check(pool, from, lang, Quota::Scheduled).await? {
+        return Ok(reply);
+    }
+    if let Some(id) = group_id {
+        if let Some(reply) = quota::check_recipients(pool, lang, id, label).await? {
+            return Ok(reply);
+        }
+    }
+
+    let sealed_label = pii::seal(label);
+    let sealed_body = pii::seal(body);
+    let timestamp = send_at.timestamp();
+    let mut tx = pool.begin().await?;
+    let id = query!(
+        "INSERT INTO scheduled_messages
+         (creator_number, group_id, recipient_number, target, body, send_at)
+         VALUES (?, ?, ?, ?, ?, ?)",
+        from,
+        group_id,
+        recipient_number,
+        sealed_label,
+        sealed_body,
+        timestamp
+    )
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+    jobs::enqueue_in(&mut *tx, &Job::Reminder { id }, timestamp - now.timestamp()).await?;
+    quota::record(&mut *tx, from, Quota::Scheduled).await?;
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!("scheduled reminder {id} to {label} at {timestamp}: \"{body}\""),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(t!(
+        lang,
+        "remind_scheduled",
+        who = label,
+        time = timezone::display(send_at.to_utc(), zone)
+    ))
+}
+
+/// Finds who `who` names: the sender, one of their groups, or a single contact
+pub async fn resolve(pool: &Pool<Sqlite>, from: &str, who: &str) -> Result<Target> {
+    if SELF_WORDS.iter().any(|word| who.eq_ignore_ascii_case(word)) {
+        return Ok(Target::Number {
+            number: from.to_string(),
+            label: who.to_lowercase(),
+        });
+    }
+    if let Some(group) = query!(
+        "SELECT id, name FROM groups WHERE creator_number = ? AND name = ? COLLATE NOCASE",
+        from,
+        who
+    )
+    .fetch_optional(pool)
+    .await?
+    {
+        return Ok(Target::Group {
+            id: group.id,
+            label: group.name,
+        });
+    }
+    let matches = Contact::search(
+        Contact::open_all(
+            query_as!(
+                Contact,
+                "SELECT id as \"id!\", contact_name, contact_user_number FROM contacts
+                 WHERE submitter_number = ? AND deleted_at IS NULL",
+                from
+            )
+            .fetch_all(pool)
+            .await?,
+        )?,
+        &[who],
+    );
+    Ok(match matches.as_slice() {
+        [] => Target::NotFound,
+        [contact] => Target::Number {
+            number: pii::seal(&contact.contact_user_number),
+            label: contact.contact_name.clone(),
+        },
+        _ => Target::Ambiguous(matches.into_iter().map(|c| c.contact_name).collect()),
+    })
+}
+
+struct Pending {
+    id: i64,
+    target: String,
+    body: String,
+    send_at: i64,
+}
+
+/// The sender's pending reminders, soonest first, in the order they're numbered for `cancel`
+async fn pending(pool: &Pool<Sqlite>, from: &str) -> Result<Vec<Pending>> {
+    query_as!(
+        Pending,
+        "SELECT id as \"id!\", target, body, send_at FROM scheduled_messages
+         WHERE creator_number = ? AND status = 'pending'
+         ORDER BY send_at, id",
+        from
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|p| {
+        Ok(Pending {
+            target: pii::open(&p.target)?,
+            body: pii::open(&p.body)?,
+            ..p
+        })
+    })
+    .collect()
+}
+
+async fn list(pool: &Pool<Sqlite>, from: &str, lang: Lang, zone: Tz) -> Result<String> {
+    let reminders = pending(pool, from).await?;
+    if reminders.is_empty() {
+        return Ok(t!(lang, "no_reminders", hint = Command::remind.hint(lang)));
+    }
+    let mut response = t!(lang, "your_reminders");
+    for (i, reminder) in reminders.iter().enumerate() {
+        let time = DateTime::from_timestamp(reminder.send_at, 0).unwrap_or_default();
+        response.push_str(&t!(
+            lang,
+            "reminder_line",
+            index = i + 1,
+            time = timezone::display(time, zone),
+            who = reminder.target,
+            message = reminder.body
+        ));
+    }
+    response.push_str(&t!(
+        lang,
+        "remind_cancel_instructions",
+        command = Command::remind
+    ));
+    Ok(response)
+}
+
+async fn cancel(pool: &Pool<Sqlite>, from: &str, lang: Lang, selection: &str) -> Result<String> {
+    let reminders = pending(pool, from).await?;
+    let reminder = match selection.parse::<usize>() {
+        Ok(num) if num > 0 => match reminders.get(num - 1) {
+            Some(reminder) => reminder,
+            None => return Ok(t!(lang, "invalid_selection", selection = num)),
+        },
+        _ => return Ok(t!(lang, "invalid_number", number = selection)),
+    };
+    let mut tx = pool.begin().await?;
+    query!(
+        "UPDATE scheduled_messages SET status = 'cancelled' WHERE id = ?",
+        reminder.id
+    )
+    .execute(&mut *tx)
+    .await?;
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!("cancelled reminder {}", reminder.id),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(t!(
+        lang,
+        "reminder_cancelled",
+        who = reminder.target,
+        message = reminder.body
+    ))
+}
+
+/// The texts a pending reminder turns into, each in its recipient's language.
+/// Recipients who've blocked the creator are left out, as are those who haven't agreed to
+/// messages from others; anyone not yet asked gets asked instead (see [consent]).
+/// Empty if the reminder isn't pending.
+pub async fn messages(pool: &Pool<Sqlite>, id: i64) -> Result<Vec<Outgoing>> {
+    let Some(reminder) = query!(
+        "SELECT s.creator_number, s.group_id, s.recipient_number, s.body, u.name
+         FROM scheduled_messages s JOIN users u ON u.number = s.creator_number
+         WHERE s.id = ? AND s.status = 'pending'",
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(Vec::new());
+    };
+    let creator = reminder.creator_number;
+    let recipients = match (reminder.group_id, reminder.recipient_number) {
+        (Some(group_id), _) => query!(
+            "SELECT member_number FROM group_members WHERE group_id = ?",
+            group_id
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| row.member_number)
+        .collect(),
+        (None, Some(number)) => vec![number],
+        (None, None) => bail!("Reminder {id} has no recipient"),
+    };
+    let name = pii::open(&reminder.name)?;
+    let body = pii::open(&reminder.body)?;
+
+    let mut messages = Vec::new();
+    for recipient in recipients {
+        let text = |lang| {
+            if recipient == creator {
+                t!(lang, "reminder_message_self", message = body)
+            } else {
+                t!(lang, "reminder_message", name = name, message = body)
+            }
+        };
+        messages.extend(compose(pool, &creator, &name, &recipient, text).await?);
+    }
+    Ok(messages)
+}
+
+/// The text from `creator` (named `name`) to `recipient`, written by `text` in the recipient's
+/// language, if they're to get it: not if they've blocked the creator or haven't agreed to
+/// messages from others or texts to them keep failing (see [delivery]), and if they've not yet
+/// been asked, a request to agree instead.
+/// Numbers are sealed.
+pub async fn compose(
+    pool: &Pool<Sqlite>,
+    creator: &str,
+    name: &str,
+    recipient: &str,
+    text: impl FnOnce(Lang) -> String,
+) -> Result<Option<Outgoing>> {
+    if block::has_blocked(pool, recipient, creator).await?
+        || delivery::undeliverable(pool, recipient).await?
+    {
+        return Ok(None);
+    }
+    let lang = user_lang(pool, recipient).await?;
+    let body = if recipient == creator {
+        text(lang)
+    } else {
+        match consent::status(pool, recipient).await? {
+            Consent::Granted => text(lang),
+            // Asked in place of this text, which they don't get
+            Consent::Unknown => {
+                consent::mark_requested(pool, recipient).await?;
+                t!(lang, "consent_request", name = name)
+            }
+            Consent::Requested | Consent::Declined => return Ok(None),
+        }
+    };
+    Ok(Some(Outgoing {
+        to: pii::open(recipient)?,
+        body,
+    }))
+}
+
+/// Sends a reminder that's come due from the bot number `sender`, unless it's been cancelled
+pub async fn deliver(pool: &Pool<Sqlite>, sender: &str, id: i64) -> Result<()> {
+    let messages = messages(pool, id).await?;
+    let count = messages.len();
+    let failed = if messages.is_empty() {
+        0
+    } else {
+        send(pool, sender, id, messages).await?
+    };
+    // Retrying after a partial failure would repeat the reminder to everyone it did reach
+    if count > 0 && failed == count {
+        bail!("Couldn't send reminder {id} to anyone");
+    }
+    query!(
+        "UPDATE scheduled_messages SET status = 'sent' WHERE id = ? AND status = 'pending'",
+        id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Sends a reminder's texts, returning how many failed
+async fn send(
+    pool: &Pool<Sqlite>,
+    sender: &str,
+    id: i64,
+    messages: Vec<Outgoing>,
+) -> Result<usize> {
+    let results = outbound::send_all(pool, sender, messages).await?;
+    let creator = query!(
+        "SELECT creator_number FROM scheduled_messages WHERE id = ?",
+        id
+    )
+    .fetch_one(pool)
+    .await?
+    .creator_number;
+    // They've gone out either way, so this isn't worth failing over
+    if let Err(error) = delivery::track(pool, &creator, &results).await {
+        warn!("Couldn't track reminder {id}'s texts: {error:?}");
+    }
+    Ok(results
+        .into_iter()
+        .filter_map(|(message, result)| result.err().map(|error| (message, error)))
+        .inspect(|(message, error)| {
+            warn!(
+                "Couldn't send reminder {id} to {}: {error:?}",
+                pii::redact(&message.to)
+            )
+        })
+        .count())
+}
@@ -0,0 +1,48 @@
+//! Twilio retries a webhook when we're slow to answer, which would otherwise apply commands
+//! like `confirm` twice. Each message is claimed by its `MessageSid` before it's handled,
+//! and a repeat gets the reply the first delivery got.
+
+use anyhow::Result;
+use sqlx::{query, Pool, Sqlite};
+
+use crate::pii;
+
+pub enum Claim {
+    /// First time we've seen this message; handle it and then call [finish]
+    First,
+    /// Seen before. Holds the reply we gave, if we gave one and have finished handling it.
+    Repeat(Option<String>),
+}
+
+pub async fn claim(pool: &Pool<Sqlite>, sid: &str) -> Result<Claim> {
+    let inserted = query!(
+        "INSERT INTO processed_messages (sid) VALUES (?) ON CONFLICT DO NOTHING",
+        sid
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    if inserted == 1 {
+        return Ok(Claim::First);
+    }
+    let response = query!("SELECT response FROM processed_messages WHERE sid = ?", sid)
+        .fetch_one(pool)
+        .await?
+        .response
+        .map(|response| pii::open(&response))
+        .transpose()?;
+    Ok(Claim::Repeat(response))
+}
+
+/// Records the reply to a claimed message, for replaying to repeats
+pub async fn finish(pool: &Pool<Sqlite>, sid: &str, response: Option<&str>) -> Result<()> {
+    let response = response.map(pii::seal);
+    query!(
+        "UPDATE processed_messages SET response = ? WHERE sid = ?",
+        response,
+        sid
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//! Reports failed messages to an external error tracker, if `ERROR_WEBHOOK_URL` is set.
+//! Each report is POSTed as JSON; anything that accepts a JSON webhook (Slack workflows,
+//! Sentry's generic webhook integration, a small collector of your own) will do.
+
+use serde_json::{json, Value};
+use tracing::*;
+
+use crate::{command::Command, config, pii};
+
+/// What we know about the message that failed
+pub struct Context<'a> {
+    /// The sender's number, in plaintext. Only a fingerprint of it is sent.
+    pub from: &'a str,
+    pub message_sid: Option<&'a str>,
+    pub command: Option<Command>,
+}
+
+/// Sends a report in the background, so the reply to the user isn't held up
+pub fn report(error: &anyhow::Error, context: Context) {
+    let Some(url) = config::get().error_webhook_url.as_ref() else {
+        return;
+    };
+    let body = payload(error, &context);
+    tokio::spawn(async move {
+        let sent = reqwest::Client::new()
+            .post(url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(error) = sent {
+            warn!("Couldn't send error report: {error}");
+        }
+    });
+}
+
+fn payload(error: &anyhow::Error, context: &Context) -> Value {
+    json!({
+        "message": error.to_string(),
+        "chain": error.chain().map(|cause| cause.to_string()).collect::<Vec<_>>(),
+        "command": context.command.map(|command| command.to_string()),
+        "sender": pii::fingerprint(context.from),
+        "message_sid": context.message_sid,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context as _};
+
+    #[test]
+    fn payload_has_chain_and_context() {
+        let error = Err::<(), _>(anyhow!("database is locked"))
+            .context("While deleting contacts")
+            .unwrap_err();
+        let body = payload(
+            &error,
+            &Context {
+                from: "+11234567890",
+                message_sid: Some("SM123"),
+                command: Some(Command::confirm),
+            },
+        );
+        assert_eq!(body["message"], "While deleting contacts");
+        assert_eq!(body["chain"][1], "database is locked");
+        assert_eq!(body["command"], "confirm");
+        assert_eq!(body["message_sid"], "SM123");
+        assert_eq!(body["sender"].as_str().unwrap().len(), 12);
+        assert!(!body.to_string().contains("1234567890"));
+    }
+}
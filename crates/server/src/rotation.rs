@@ -0,0 +1,547 @@
+//! Taking turns with `rotation`, for the chores and the rounds a group shares.
+//!
+//! `rotation create dishes: me, Alex, Jo` sets up the order, `rotation next dishes` moves it on
+//! and `rotation every dishes 7` moves it on by itself once a week. Whoever's turn it is gets
+//! told, if they're the creator or one of the creator's contacts; anyone else in the order is
+//! just a name. A bare `rotation` lists them all with whose turn it is.
+
+use anyhow::Result;
+use sqlx::{query, query_as, Pool, Sqlite, Transaction};
+use tracing::*;
+
+use crate::{
+    audit::{self, Kind},
+    block,
+    command::Command,
+    consent::{self, Consent},
+    delivery,
+    i18n::{t, user_lang, Lang},
+    jobs::{self, Job},
+    outbound::{self, Outgoing},
+    pii,
+    search::fold,
+    Contact,
+};
+
+/// Words that mean the sender themself
+const SELF_WORDS: &[&str] = &["me", "yo"];
+const CREATE_WORDS: &[&str] = &["create", "crear"];
+const NEXT_WORDS: &[&str] = &["next", "siguiente"];
+const EVERY_WORDS: &[&str] = &["every", "cada"];
+const DELETE_WORDS: &[&str] = &["delete", "borrar"];
+/// Turns off moving on by itself
+const NEVER_WORDS: &[&str] = &["off", "never", "nunca"];
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// Someone in a rotation, with their number sealed
+struct Member {
+    name: String,
+    number: Option<String>,
+}
+
+struct Rotation {
+    id: i64,
+    name: String,
+    turn: i64,
+    interval_days: Option<i64>,
+}
+
+/// `rotation` lists the sender's rotations, and `rotation <action> <name> ...` manages one
+pub async fn handle_rotation(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    args: &str,
+) -> Result<String> {
+    let args = args.trim();
+    if args.is_empty() {
+        return list(pool, from, lang).await;
+    }
+    let (action, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let action = fold(action);
+    let rest = rest.trim();
+    if CREATE_WORDS.contains(&action.as_str()) {
+        return match rest.split_once(':') {
+            Some((name, members)) if !name.trim().is_empty() => {
+                create(pool, from, lang, name.trim(), members).await
+            }
+            _ => Ok(Command::rotation.hint(lang)),
+        };
+    }
+    if EVERY_WORDS.contains(&action.as_str()) {
+        let Some((name, interval)) = rest.rsplit_once(char::is_whitespace) else {
+            return Ok(Command::rotation.hint(lang));
+        };
+        let Some(days) = parse_interval(interval) else {
+            return Ok(t!(lang, "rotation_bad_interval", interval = interval));
+        };
+        let Some(rotation) = find(pool, from, name.trim()).await? else {
+            return Ok(t!(lang, "rotation_unknown", name = name.trim()));
+        };
+        return schedule(pool, from, lang, rotation, days).await;
+    }
+    let moving_on = NEXT_WORDS.contains(&action.as_str());
+    if !moving_on && !DELETE_WORDS.contains(&action.as_str()) {
+        return show(pool, from, lang, args).await;
+    }
+    if rest.is_empty() {
+        return Ok(Command::rotation.hint(lang));
+    }
+    let Some(rotation) = find(pool, from, rest).await? else {
+        return Ok(t!(lang, "rotation_unknown", name = rest));
+    };
+    if moving_on {
+        next(pool, from, lang, rotation).await
+    } else {
+        delete(pool, from, lang, rotation).await
+    }
+}
+
+/// How many days between turns, or Some(None) to stop moving on by itself
+fn parse_interval(text: &str) -> Option<Option<i64>> {
+    let text = fold(text);
+    if NEVER_WORDS.contains(&text.as_str()) {
+        return Some(None);
+    }
+    match text.as_str() {
+        "day" | "daily" | "dia" => Some(Some(1)),
+        "week" | "weekly" | "semana" => Some(Some(7)),
+        _ => text.parse().ok().filter(|days| *days > 0).map(Some),
+    }
+}
+
+async fn find(pool: &Pool<Sqlite>, from: &str, name: &str) -> Result<Option<Rotation>> {
+    Ok(query_as!(
+        Rotation,
+        "SELECT id as \"id!\", name, turn, interval_days FROM rotations
+         WHERE creator_number = ? AND name = ? COLLATE NOCASE",
+        from,
+        name
+    )
+    .fetch_optional(pool)
+    .await?)
+}
+
+/// A rotation's members in order, opened
+async fn members(pool: &Pool<Sqlite>, id: i64) -> Result<Vec<Member>> {
+    query_as!(
+        Member,
+        "SELECT name, number FROM rotation_members WHERE rotation_id = ? ORDER BY position",
+        id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|member| {
+        Ok(Member {
+            name: pii::open(&member.name)?,
+            number: member.number,
+        })
+    })
+    .collect()
+}
+
+/// Who has a given turn, and who's after them
+fn at(members: &[Member], turn: i64) -> (&Member, &Member) {
+    let count = members.len() as i64;
+    (
+        &members[turn.rem_euclid(count) as usize],
+        &members[(turn + 1).rem_euclid(count) as usize],
+    )
+}
+
+async fn create(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    name: &str,
+    names: &str,
+) -> Result<String> {
+    let names: Vec<_> = names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    if names.len() < 2 {
+        return Ok(Command::rotation.hint(lang));
+    }
+    if find(pool, from, name).await?.is_some() {
+        return Ok(t!(lang, "rotation_exists", name = name));
+    }
+
+    let contacts = Contact::open_all(
+        query_as!(
+            Contact,
+            "SELECT id as \"id!\", contact_name, contact_user_number FROM contacts
+             WHERE submitter_number = ? AND deleted_at IS NULL",
+            from
+        )
+        .fetch_all(pool)
+        .await?,
+    )?;
+    let own_name = pii::open(
+        &query!("SELECT name FROM users WHERE number = ?", from)
+            .fetch_one(pool)
+            .await?
+            .name,
+    )?;
+    // Whoever matches a contact is told when it's their turn, under the name they're saved as
+    let mut members = Vec::new();
+    for who in names {
+        if SELF_WORDS.iter().any(|word| who.eq_ignore_ascii_case(word)) {
+            members.push(Member {
+                name: own_name.clone(),
+                number: Some(from.to_string()),
+            });
+            continue;
+        }
+        let matches = Contact::search(contacts.clone(), &[who]);
+        members.push(match matches.as_slice() {
+            [] => Member {
+                name: who.to_string(),
+                number: None,
+            },
+            [contact] => Member {
+                name: contact.contact_name.clone(),
+                number: Some(pii::seal(&contact.contact_user_number)),
+            },
+            _ => {
+                return Ok(t!(
+                    lang,
+                    "remind_ambiguous",
+                    who = who,
+                    names = matches
+                        .into_iter()
+                        .map(|c| c.contact_name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            }
+        });
+    }
+
+    let order = members
+        .iter()
+        .map(|member| member.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut tx = pool.begin().await?;
+    let id = query!(
+        "INSERT INTO rotations (creator_number, name) VALUES (?, ?)",
+        from,
+        name
+    )
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+    for (position, member) in members.iter().enumerate() {
+        let (position, sealed_name) = (position as i64, pii::seal(&member.name));
+        query!(
+            "INSERT INTO rotation_members (rotation_id, position, name, number)
+             VALUES (?, ?, ?, ?)",
+            id,
+            position,
+            sealed_name,
+            member.number
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    jobs::enqueue(&mut *tx, &Job::RotationTurn { id, turn: 0 }).await?;
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!("created rotation {id} \"{name}\": {order}"),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(t!(
+        lang,
+        "rotation_created",
+        name = name,
+        order = order,
+        current = members[0].name,
+        command = Command::rotation
+    ))
+}
+
+/// Moves a rotation on by one turn, telling whoever's turn it is now,
+/// and schedules the next move if it moves on by itself
+async fn advance(tx: &mut Transaction<'_, Sqlite>, rotation: &Rotation) -> Result<i64> {
+    let turn = rotation.turn + 1;
+    query!(
+        "UPDATE rotations SET turn = ? WHERE id = ?",
+        turn,
+        rotation.id
+    )
+    .execute(&mut **tx)
+    .await?;
+    let id = rotation.id;
+    jobs::enqueue(&mut **tx, &Job::RotationTurn { id, turn }).await?;
+    if let Some(days) = rotation.interval_days {
+        jobs::enqueue_in(
+            &mut **tx,
+            &Job::RotationAdvance { id, turn, days },
+            days * DAY_SECS,
+        )
+        .await?;
+    }
+    Ok(turn)
+}
+
+async fn next(pool: &Pool<Sqlite>, from: &str, lang: Lang, rotation: Rotation) -> Result<String> {
+    let members = members(pool, rotation.id).await?;
+    let mut tx = pool.begin().await?;
+    let turn = advance(&mut tx, &rotation).await?;
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!("moved rotation {} on to turn {turn}", rotation.id),
+    )
+    .await?;
+    tx.commit().await?;
+    let (current, next) = at(&members, turn);
+    Ok(t!(
+        lang,
+        "rotation_turn",
+        name = rotation.name,
+        current = current.name,
+        next = next.name
+    ))
+}
+
+async fn schedule(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    rotation: Rotation,
+    days: Option<i64>,
+) -> Result<String> {
+    let mut tx = pool.begin().await?;
+    query!(
+        "UPDATE rotations SET interval_days = ? WHERE id = ?",
+        days,
+        rotation.id
+    )
+    .execute(&mut *tx)
+    .await?;
+    // Any move already queued on the old schedule sees the change and does nothing
+    if let Some(days) = days {
+        jobs::enqueue_in(
+            &mut *tx,
+            &Job::RotationAdvance {
+                id: rotation.id,
+                turn: rotation.turn,
+                days,
+            },
+            days * DAY_SECS,
+        )
+        .await?;
+    }
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!(
+            "set rotation {} to move on every {days:?} day(s)",
+            rotation.id
+        ),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(match days {
+        Some(days) => t!(
+            lang,
+            "rotation_scheduled",
+            name = rotation.name,
+            days = days
+        ),
+        None => t!(lang, "rotation_unscheduled", name = rotation.name),
+    })
+}
+
+/// Moves a rotation on by itself, unless it's been moved on or rescheduled since this was queued
+pub async fn advance_scheduled(pool: &Pool<Sqlite>, id: i64, turn: i64, days: i64) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let Some(rotation) = query_as!(
+        Rotation,
+        "SELECT id as \"id!\", name, turn, interval_days FROM rotations
+         WHERE id = ? AND turn = ? AND interval_days = ?",
+        id,
+        turn,
+        days
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(());
+    };
+    advance(&mut tx, &rotation).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn delete(pool: &Pool<Sqlite>, from: &str, lang: Lang, rotation: Rotation) -> Result<String> {
+    let mut tx = pool.begin().await?;
+    query!("DELETE FROM rotations WHERE id = ?", rotation.id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!("deleted rotation {}", rotation.id),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(t!(lang, "rotation_deleted", name = rotation.name))
+}
+
+async fn show(pool: &Pool<Sqlite>, from: &str, lang: Lang, name: &str) -> Result<String> {
+    let Some(rotation) = find(pool, from, name).await? else {
+        return Ok(t!(lang, "rotation_unknown", name = name));
+    };
+    let members = members(pool, rotation.id).await?;
+    let (current, next) = at(&members, rotation.turn);
+    let mut response = t!(
+        lang,
+        "rotation_turn",
+        name = rotation.name,
+        current = current.name,
+        next = next.name
+    );
+    response.push_str(&t!(
+        lang,
+        "rotation_order",
+        order = members
+            .iter()
+            .map(|member| member.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+    if let Some(days) = rotation.interval_days {
+        response.push_str(&t!(lang, "rotation_every", days = days));
+    }
+    Ok(response)
+}
+
+async fn list(pool: &Pool<Sqlite>, from: &str, lang: Lang) -> Result<String> {
+    let rotations = query_as!(
+        Rotation,
+        "SELECT id as \"id!\", name, turn, interval_days FROM rotations
+         WHERE creator_number = ? ORDER BY name",
+        from
+    )
+    .fetch_all(pool)
+    .await?;
+    if rotations.is_empty() {
+        return Ok(t!(
+            lang,
+            "no_rotations",
+            hint = Command::rotation.hint(lang)
+        ));
+    }
+    let mut response = t!(lang, "your_rotations");
+    for rotation in rotations {
+        let members = members(pool, rotation.id).await?;
+        let (current, next) = at(&members, rotation.turn);
+        response.push_str(&t!(
+            lang,
+            "rotation_line",
+            name = rotation.name,
+            current = current.name,
+            next = next.name
+        ));
+    }
+    Ok(response.trim_end().to_string())
+}
+
+/// The text telling whoever has a rotation's given turn that it's theirs, in their language.
+/// None if the rotation has moved on since, or they aren't a user, have blocked the creator or
+/// haven't agreed to messages from others; anyone not yet asked gets asked instead (see
+/// [consent]).
+pub async fn turn_message(pool: &Pool<Sqlite>, id: i64, turn: i64) -> Result<Option<Outgoing>> {
+    let Some(rotation) = query!(
+        "SELECT r.creator_number, r.name, u.name as creator_name
+         FROM rotations r JOIN users u ON u.number = r.creator_number
+         WHERE r.id = ? AND r.turn = ?",
+        id,
+        turn
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let members = members(pool, id).await?;
+    let (current, next) = at(&members, turn);
+    let Some(number) = &current.number else {
+        return Ok(None);
+    };
+    let creator = rotation.creator_number;
+    if block::has_blocked(pool, number, &creator).await?
+        || delivery::undeliverable(pool, number).await?
+    {
+        return Ok(None);
+    }
+    let lang = user_lang(pool, number).await?;
+    let creator_name = pii::open(&rotation.creator_name)?;
+    let body = if *number == creator {
+        t!(
+            lang,
+            "rotation_your_turn_self",
+            name = rotation.name,
+            next = next.name
+        )
+    } else {
+        match consent::status(pool, number).await? {
+            Consent::Granted => t!(
+                lang,
+                "rotation_your_turn",
+                creator = creator_name,
+                name = rotation.name,
+                next = next.name
+            ),
+            Consent::Unknown => {
+                consent::mark_requested(pool, number).await?;
+                t!(lang, "consent_request", name = creator_name)
+            }
+            Consent::Requested | Consent::Declined => return Ok(None),
+        }
+    };
+    Ok(Some(Outgoing {
+        to: pii::open(number)?,
+        body,
+    }))
+}
+
+/// Tells whoever has a rotation's given turn that it's theirs, from the bot number `sender`
+pub async fn notify(pool: &Pool<Sqlite>, sender: &str, id: i64, turn: i64) -> Result<()> {
+    let Some(message) = turn_message(pool, id, turn).await? else {
+        return Ok(());
+    };
+    for (message, result) in outbound::send_all(pool, sender, vec![message]).await? {
+        if let Err(error) = result {
+            warn!(
+                "Couldn't tell {} it's their turn in rotation {id}",
+                pii::redact(&message.to)
+            );
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn intervals() {
+    assert_eq!(parse_interval("7"), Some(Some(7)));
+    assert_eq!(parse_interval("Weekly"), Some(Some(7)));
+    assert_eq!(parse_interval("día"), Some(Some(1)));
+    assert_eq!(parse_interval("off"), Some(None));
+    assert_eq!(parse_interval("0"), None);
+    assert_eq!(parse_interval("soon"), None);
+}
@@ -0,0 +1,57 @@
+//! Matching contact names against what someone typed.
+//!
+//! Names are sealed at rest, so matching happens here, after they're opened, rather than in SQL.
+//! Case and accents are ignored, so "jose" finds "José", and better matches rank first.
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Lowercases and strips accents
+pub fn fold(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// How well `name` matches `query`, higher being better, or None if it doesn't match at all:
+/// 3 for the whole name, 2 if every word typed starts a word of the name, 1 for anywhere inside
+pub fn score(name: &str, query: &str) -> Option<u8> {
+    let name = fold(name);
+    let query = fold(query.trim());
+    if query.is_empty() {
+        return None;
+    }
+    if name == query {
+        return Some(3);
+    }
+    let words: Vec<_> = name.split_whitespace().collect();
+    if query
+        .split_whitespace()
+        .all(|typed| words.iter().any(|word| word.starts_with(typed)))
+    {
+        return Some(2);
+    }
+    name.contains(&query).then_some(1)
+}
+
+/// The best score of `name` against any of `queries`
+pub fn best_score(name: &str, queries: &[&str]) -> Option<u8> {
+    queries.iter().filter_map(|query| score(name, query)).max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scoring() {
+        assert_eq!(fold("José Ñúñez"), "jose nunez");
+        assert_eq!(score("José Núñez", "jose nunez"), Some(3));
+        assert_eq!(score("José Núñez", "NUN"), Some(2));
+        assert_eq!(score("Alice Smith", "smi ali"), Some(2));
+        assert_eq!(score("Alice Smith", "mit"), Some(1));
+        assert_eq!(score("Alice Smith", "bob"), None);
+        assert_eq!(score("Alice Smith", " "), None);
+        assert_eq!(best_score("Alice Smith", &["bob", "ali", "mit"]), Some(2));
+    }
+}
@@ -0,0 +1,327 @@
+//! Messages written now and sent later with `sendlater`, at a time in each recipient's own time
+//! zone, so `sendlater family 8am: don't forget to vote` reaches everyone at 8am where they are.
+//!
+//! Each one is a row in `later_messages`, with a row in `later_deliveries` and a
+//! [Job::LaterDelivery] for each recipient, due when the time comes for them. Recipients who
+//! haven't set a time zone get it at the creator's time.
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use sqlx::{query, Pool, Sqlite};
+use tracing::*;
+
+use crate::{
+    audit::{self, Kind},
+    command::Command,
+    delivery,
+    i18n::{t, Lang},
+    jobs::{self, Job},
+    outbound, pii,
+    quota::{self, Quota},
+    remind::{self, Target},
+    timezone, when,
+};
+
+/// `sendlater` lists pending messages, `sendlater cancel N` cancels one,
+/// and `sendlater <who> <when>: <message>` schedules one
+pub async fn handle_sendlater(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    zone: Tz,
+    args: &str,
+) -> Result<String> {
+    let args = args.trim();
+    if args.is_empty() {
+        return list(pool, from, lang, zone).await;
+    }
+    if let Some(selection) = args
+        .split_once(char::is_whitespace)
+        .filter(|(word, _)| word.eq_ignore_ascii_case("cancel"))
+        .map(|(_, selection)| selection.trim())
+    {
+        return cancel(pool, from, lang, selection).await;
+    }
+
+    let Some((head, body)) = when::split_message(args) else {
+        return Ok(Command::sendlater.hint(lang));
+    };
+    let Some((who, time)) = head.trim().split_once(char::is_whitespace) else {
+        return Ok(Command::sendlater.hint(lang));
+    };
+    let time = time.trim();
+    if body.is_empty() {
+        return Ok(Command::sendlater.hint(lang));
+    }
+
+    let now = Utc::now().with_timezone(&zone);
+    let Some(send_at) = when::parse(time, &now) else {
+        return Ok(t!(lang, "remind_bad_time", time = time));
+    };
+    if send_at <= now {
+        return Ok(t!(
+            lang,
+            "remind_past",
+            time = timezone::display(send_at.to_utc(), zone)
+        ));
+    }
+
+    let (recipients, label) = match remind::resolve(pool, from, who).await? {
+        Target::Number { number, label } => (vec![number], label),
+        Target::Group { id, label } => {
+            if let Some(reply) = quota::check_recipients(pool, lang, id, &label).await? {
+                return Ok(reply);
+            }
+            let members = query!(
+                "SELECT member_number FROM group_members WHERE group_id = ?",
+                id
+            )
+            .fetch_all(pool)
+            .await?;
+            (
+                members.into_iter().map(|m| m.member_number).collect(),
+                label,
+            )
+        }
+        Target::NotFound => return Ok(t!(lang, "remind_unknown_target", who = who)),
+        Target::Ambiguous(names) => {
+            return Ok(t!(
+                lang,
+                "remind_ambiguous",
+                who = who,
+                names = names.join(", ")
+            ))
+        }
+    };
+    if let Some(reply) = quota::check(pool, from, lang, Quota::Scheduled).await? {
+        return Ok(reply);
+    }
+
+    // When it's the time for each of them, or right away if it already is where they are
+    let mut deliveries = Vec::new();
+    for recipient in recipients {
+        let stored = query!("SELECT timezone FROM users WHERE number = ?", recipient)
+            .fetch_optional(pool)
+            .await?
+            .and_then(|user| user.timezone);
+        let their_zone = match stored {
+            Some(stored) => timezone::from_stored(Some(&stored)),
+            None => zone,
+        };
+        let their_now = now.with_timezone(&their_zone);
+        let their_time = when::parse(time, &their_now)
+            .map_or(send_at.timestamp(), |at| at.timestamp())
+            .max(now.timestamp());
+        deliveries.push((recipient, their_time));
+    }
+    let first = deliveries
+        .iter()
+        .map(|(_, at)| *at)
+        .min()
+        .unwrap_or(send_at.timestamp());
+
+    let sealed_label = pii::seal(&label);
+    let sealed_body = pii::seal(body);
+    let mut tx = pool.begin().await?;
+    let id = query!(
+        "INSERT INTO later_messages (creator_number, target, body, time) VALUES (?, ?, ?, ?)",
+        from,
+        sealed_label,
+        sealed_body,
+        time
+    )
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+    for (recipient, at) in &deliveries {
+        let delivery = query!(
+            "INSERT INTO later_deliveries (message_id, recipient_number, send_at) VALUES (?, ?, ?)",
+            id,
+            recipient,
+            at
+        )
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        let job = Job::LaterDelivery { id: delivery };
+        jobs::enqueue_in(&mut *tx, &job, at - now.timestamp()).await?;
+    }
+    quota::record(&mut *tx, from, Quota::Scheduled).await?;
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!("scheduled later message {id} to {label} at {time} their time: \"{body}\""),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(t!(
+        lang,
+        "later_scheduled",
+        who = label,
+        time = time,
+        first = timezone::display(DateTime::from_timestamp(first, 0).unwrap_or_default(), zone)
+    ))
+}
+
+struct Pending {
+    id: i64,
+    target: String,
+    body: String,
+    time: String,
+    next: i64,
+}
+
+/// The sender's pending messages, soonest first, in the order they're numbered for `cancel`
+async fn pending(pool: &Pool<Sqlite>, from: &str) -> Result<Vec<Pending>> {
+    query!(
+        r#"SELECT m.id as "id!", m.target as "target!", m.body as "body!", m.time as "time!",
+            MIN(d.send_at) as "next!: i64"
+         FROM later_messages m JOIN later_deliveries d ON d.message_id = m.id
+         WHERE m.creator_number = ? AND m.status = 'pending' AND d.status = 'pending'
+         GROUP BY m.id ORDER BY MIN(d.send_at), m.id"#,
+        from
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|p| {
+        Ok(Pending {
+            id: p.id,
+            target: pii::open(&p.target)?,
+            body: pii::open(&p.body)?,
+            time: p.time,
+            next: p.next,
+        })
+    })
+    .collect()
+}
+
+async fn list(pool: &Pool<Sqlite>, from: &str, lang: Lang, zone: Tz) -> Result<String> {
+    let messages = pending(pool, from).await?;
+    if messages.is_empty() {
+        return Ok(t!(
+            lang,
+            "no_later_messages",
+            hint = Command::sendlater.hint(lang)
+        ));
+    }
+    let mut response = t!(lang, "your_later_messages");
+    for (i, message) in messages.iter().enumerate() {
+        let next = DateTime::from_timestamp(message.next, 0).unwrap_or_default();
+        response.push_str(&t!(
+            lang,
+            "later_line",
+            index = i + 1,
+            time = message.time,
+            next = timezone::display(next, zone),
+            who = message.target,
+            message = message.body
+        ));
+    }
+    response.push_str(&t!(
+        lang,
+        "remind_cancel_instructions",
+        command = Command::sendlater
+    ));
+    Ok(response)
+}
+
+async fn cancel(pool: &Pool<Sqlite>, from: &str, lang: Lang, selection: &str) -> Result<String> {
+    let messages = pending(pool, from).await?;
+    let message = match selection.parse::<usize>() {
+        Ok(num) if num > 0 => match messages.get(num - 1) {
+            Some(message) => message,
+            None => return Ok(t!(lang, "invalid_selection", selection = num)),
+        },
+        _ => return Ok(t!(lang, "invalid_number", number = selection)),
+    };
+    let mut tx = pool.begin().await?;
+    query!(
+        "UPDATE later_messages SET status = 'cancelled' WHERE id = ?",
+        message.id
+    )
+    .execute(&mut *tx)
+    .await?;
+    query!(
+        "UPDATE later_deliveries SET status = 'cancelled'
+         WHERE message_id = ? AND status = 'pending'",
+        message.id
+    )
+    .execute(&mut *tx)
+    .await?;
+    audit::record(
+        &mut *tx,
+        from,
+        Kind::Mutation,
+        format!("cancelled later message {}", message.id),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(t!(
+        lang,
+        "later_cancelled",
+        who = message.target,
+        message = message.body
+    ))
+}
+
+/// Sends one recipient's copy of a later message that's come due, from the bot number
+/// `sender`, unless it's been cancelled. Marks the message sent once everyone's had theirs.
+pub async fn deliver(pool: &Pool<Sqlite>, sender: &str, id: i64) -> Result<()> {
+    let Some(later) = query!(
+        r#"SELECT d.message_id, d.recipient_number, m.creator_number, m.body, u.name
+         FROM later_deliveries d
+         JOIN later_messages m ON m.id = d.message_id
+         JOIN users u ON u.number = m.creator_number
+         WHERE d.id = ? AND d.status = 'pending' AND m.status = 'pending'"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(());
+    };
+    let name = pii::open(&later.name)?;
+    let body = pii::open(&later.body)?;
+    let creator = later.creator_number;
+    let recipient = later.recipient_number;
+    let text = |lang| {
+        if recipient == creator {
+            body.clone()
+        } else {
+            t!(lang, "later_message", name = name, message = body)
+        }
+    };
+    if let Some(message) = remind::compose(pool, &creator, &name, &recipient, text).await? {
+        let results = outbound::send_all(pool, sender, vec![message]).await?;
+        // It's gone out either way, so this isn't worth failing over
+        if let Err(error) = delivery::track(pool, &creator, &results).await {
+            warn!("Couldn't track later message {id}'s text: {error:?}");
+        }
+        if let Some((_, Err(error))) = results.first() {
+            bail!("Couldn't send later message {id}: {error:?}");
+        }
+    }
+
+    let mut tx = pool.begin().await?;
+    query!(
+        "UPDATE later_deliveries SET status = 'sent' WHERE id = ?",
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+    query!(
+        "UPDATE later_messages SET status = 'sent'
+         WHERE id = ? AND status = 'pending' AND NOT EXISTS
+            (SELECT 1 FROM later_deliveries WHERE message_id = ? AND status = 'pending')",
+        later.message_id,
+        later.message_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
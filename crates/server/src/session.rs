@@ -0,0 +1,288 @@
+use crate::{
+    command::Command,
+    i18n::{t, user_lang, Lang},
+    jobs::{self, Job},
+    optout,
+    outbound::{self, Outgoing},
+    pii,
+};
+use anyhow::{bail, Result};
+use sqlx::{query, Pool, Sqlite, SqliteExecutor};
+use std::str::FromStr;
+use tracing::*;
+
+/// A multi-message flow that a user is partway through.
+/// Each user has at most one active session; starting a new one replaces the old,
+/// and the rows hanging off the old one (pending deletions, deferred contacts, etc.)
+/// are removed along with it by the foreign key cascade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    /// Waiting for the user to pick which matched groups/contacts to delete
+    Deletion,
+    /// Waiting for the user to pick one number for each imported contact that had several
+    DeferredContacts,
+    /// Waiting for the user to pick which matched contacts go in a new group
+    Group,
+    /// Walking someone new through picking their name, before they're a user
+    Onboarding,
+}
+
+impl SessionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Deletion => "deletion",
+            Self::DeferredContacts => "deferred_contacts",
+            Self::Group => "group",
+            Self::Onboarding => "onboarding",
+        }
+    }
+
+    /// What the flow is called when talking to the user about it
+    pub fn description(&self, lang: Lang) -> String {
+        match self {
+            Self::Deletion => t!(lang, "session_deletion"),
+            Self::DeferredContacts => t!(lang, "session_deferred_contacts"),
+            Self::Group => t!(lang, "session_group"),
+            Self::Onboarding => t!(lang, "session_onboarding"),
+        }
+    }
+
+    /// What to tell the user when the flow is dropped for taking too long, if anything
+    fn expired_message(&self, lang: Lang) -> Option<String> {
+        let minutes = self.timeout_secs() / 60;
+        let session = self.description(lang);
+        match self {
+            Self::Deletion => Some(t!(
+                lang,
+                "expired_deletion",
+                session = session,
+                minutes = minutes,
+                command = Command::delete
+            )),
+            Self::DeferredContacts => Some(t!(
+                lang,
+                "expired_deferred_contacts",
+                session = session,
+                minutes = minutes
+            )),
+            Self::Group => Some(t!(
+                lang,
+                "expired_group",
+                session = session,
+                minutes = minutes,
+                command = Command::group
+            )),
+            // They may never have meant to sign up
+            Self::Onboarding => None,
+        }
+    }
+
+    /// How long the user has to finish the flow before it is dropped
+    fn timeout_secs(&self) -> i64 {
+        match self {
+            Self::Deletion | Self::DeferredContacts | Self::Group => 5 * 60,
+            // People signing up may well wander off and come back later
+            Self::Onboarding => 24 * 60 * 60,
+        }
+    }
+}
+
+impl FromStr for SessionState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "deletion" => Self::Deletion,
+            "deferred_contacts" => Self::DeferredContacts,
+            "group" => Self::Group,
+            "onboarding" => Self::Onboarding,
+            _ => bail!("Unknown session state: {s}"),
+        })
+    }
+}
+
+/// Returns the user's active session, ignoring any that have expired
+pub async fn current(pool: &Pool<Sqlite>, from: &str) -> Result<Option<SessionState>> {
+    query!(
+        "SELECT state FROM sessions WHERE submitter_number = ? AND expires_at > unixepoch()",
+        from
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| row.state.parse())
+    .transpose()
+}
+
+/// Like [current], along with when the session expires
+pub async fn current_with_expiry(
+    pool: &Pool<Sqlite>,
+    from: &str,
+) -> Result<Option<(SessionState, i64)>> {
+    query!(
+        "SELECT state, expires_at FROM sessions WHERE submitter_number = ? AND expires_at > unixepoch()",
+        from
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| Ok((row.state.parse()?, row.expires_at)))
+    .transpose()
+}
+
+/// Starts a fresh session, discarding whatever the user was previously in the middle of
+pub async fn start(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    from: &str,
+    state: SessionState,
+) -> Result<()> {
+    end(&mut **tx, from).await?;
+    let state_str = state.as_str();
+    let timeout = state.timeout_secs();
+    query!(
+        "INSERT INTO sessions (submitter_number, state, expires_at) VALUES (?, ?, unixepoch() + ?)",
+        from,
+        state_str,
+        timeout
+    )
+    .execute(&mut **tx)
+    .await?;
+    schedule_expiry(tx, state).await
+}
+
+/// Makes sure the job that tells the user if the session runs out is queued for when it does.
+/// One job ends every session that's run out by then, so there's only ever one queued.
+async fn schedule_expiry(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    state: SessionState,
+) -> Result<()> {
+    if state == SessionState::Onboarding {
+        return Ok(());
+    }
+    jobs::enqueue_by(&mut *tx, &Job::SessionExpiry, state.timeout_secs()).await
+}
+
+/// Queues the expiry job for the next session due to run out, since the one queued for it may
+/// have been moved up for another
+async fn schedule_next_expiry(pool: &Pool<Sqlite>) -> Result<()> {
+    let onboarding = SessionState::Onboarding.as_str();
+    let next = query!(
+        r#"SELECT MIN(expires_at) - unixepoch() as "delay: i64" FROM sessions
+         WHERE state != ? AND expires_at > unixepoch()"#,
+        onboarding
+    )
+    .fetch_one(pool)
+    .await?;
+    if let Some(delay) = next.delay {
+        jobs::enqueue_by(&mut *pool.acquire().await?, &Job::SessionExpiry, delay).await?;
+    }
+    Ok(())
+}
+
+/// Like [start], but if the user is already in an unexpired session of the same kind,
+/// keeps its data and just pushes back the expiry.
+/// Used by flows that accumulate state across messages, like a multi-card import.
+pub async fn resume_or_start(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    from: &str,
+    state: SessionState,
+) -> Result<()> {
+    let state_str = state.as_str();
+    let timeout = state.timeout_secs();
+    let resumed = query!(
+        "UPDATE sessions SET expires_at = unixepoch() + ?
+         WHERE submitter_number = ? AND state = ? AND expires_at > unixepoch()",
+        timeout,
+        from,
+        state_str
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+    if resumed == 0 {
+        start(tx, from, state).await
+    } else {
+        schedule_expiry(tx, state).await
+    }
+}
+
+/// Ends the user's session (if any) along with all of its pending data
+pub async fn end(executor: impl SqliteExecutor<'_>, from: &str) -> Result<()> {
+    query!("DELETE FROM sessions WHERE submitter_number = ?", from)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// The sessions that have run out, each with its user (sealed) and what to tell them, if anything
+async fn expired(pool: &Pool<Sqlite>) -> Result<Vec<(String, Option<Outgoing>)>> {
+    let expired =
+        query!("SELECT submitter_number, state FROM sessions WHERE expires_at <= unixepoch()")
+            .fetch_all(pool)
+            .await?;
+    let mut sessions = Vec::new();
+    for session in expired {
+        let state: SessionState = session.state.parse()?;
+        let lang = user_lang(pool, &session.submitter_number).await?;
+        let message = match state.expired_message(lang) {
+            Some(body) => Some(Outgoing {
+                to: pii::open(&session.submitter_number)?,
+                body,
+            }),
+            None => None,
+        };
+        sessions.push((session.submitter_number, message));
+    }
+    Ok(sessions)
+}
+
+/// What [expire] would tell users about their sessions that have run out
+#[cfg(test)]
+pub async fn expiry_notices(pool: &Pool<Sqlite>) -> Result<Vec<Outgoing>> {
+    Ok(expired(pool)
+        .await?
+        .into_iter()
+        .filter_map(|(_, message)| message)
+        .collect())
+}
+
+/// Ends the sessions that have run out, telling each user whose flow was dropped from the bot
+/// number `sender`. A session is only ended once its user has been told, so anyone who
+/// couldn't be is tried again when this is. The rows hanging off the sessions go too,
+/// by the foreign key cascade. Then queues the job again for the next session to run out.
+pub async fn expire(pool: &Pool<Sqlite>, sender: &str) -> Result<()> {
+    let mut ended = Vec::new();
+    let mut messages = Vec::new();
+    for (number, message) in expired(pool).await? {
+        match message {
+            Some(message) => messages.push(message),
+            None => ended.push(number),
+        }
+    }
+    let mut failed = 0;
+    for (message, result) in outbound::send_all(pool, sender, messages).await? {
+        match result {
+            // There's no telling someone who's opted out
+            Err(error) if !error.is::<optout::OptedOut>() => {
+                warn!(
+                    "Couldn't tell {} their session expired: {error:?}",
+                    pii::redact(&message.to)
+                );
+                failed += 1;
+            }
+            _ => ended.push(pii::seal(&message.to)),
+        }
+    }
+    for number in ended {
+        // Unless they've started something new since
+        query!(
+            "DELETE FROM sessions WHERE submitter_number = ? AND expires_at <= unixepoch()",
+            number
+        )
+        .execute(pool)
+        .await?;
+    }
+    schedule_next_expiry(pool).await?;
+    if failed > 0 {
+        bail!("Couldn't tell {failed} user(s) their session expired");
+    }
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//! Stopping cleanly on SIGTERM (what process managers send) or Ctrl-C.
+//!
+//! The server stops accepting webhooks, then gives the handlers already running and the job
+//! workers' current jobs up to `SHUTDOWN_TIMEOUT_SECS` (default 30) to finish before closing the
+//! databases. Queued jobs stay in the database for the next start to pick up.
+
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tracing::*;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Tells whoever holds a copy that the server is shutting down
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+/// Returns the sender that starts the shutdown and the [Shutdown] to hand out
+pub fn channel() -> (watch::Sender<bool>, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+    (sender, Shutdown(receiver))
+}
+
+impl Shutdown {
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Waits until shutdown starts (or forever, if it never does)
+    pub async fn requested(mut self) {
+        if self.0.wait_for(|requested| *requested).await.is_err() {
+            // The sender is gone without having asked, so it never will
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Waits for SIGTERM or Ctrl-C
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(error) = tokio::signal::ctrl_c().await {
+            error!("Couldn't listen for Ctrl-C: {error}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(error) => {
+                error!("Couldn't listen for SIGTERM: {error}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        () = ctrl_c => info!("Received Ctrl-C"),
+        () = terminate => info!("Received SIGTERM"),
+    }
+}
+
+/// How long to wait for in-flight work before giving up on it
+pub fn timeout() -> Duration {
+    crate::config::get()
+        .shutdown_timeout_secs
+        .map_or(DEFAULT_TIMEOUT, Duration::from_secs)
+}
@@ -0,0 +1,57 @@
+//! Running the bot locally without Twilio.
+//!
+//! Start the server with `--simulate` (or `SIMULATE=1`) and nothing is sent through Twilio:
+//! outgoing texts are logged instead, the startup text is skipped, and no Twilio credentials
+//! are needed. Messages can then be sent in as plain text, e.g.
+//! `curl -d 'contacts' "http://$CALLBACK_IP:$CALLBACK_PORT/simulate?from=5551234567"`,
+//! and the reply comes back as the response body.
+
+use std::str::FromStr;
+
+use axum::{extract::Query, http::StatusCode, Extension};
+use serde::Deserialize;
+use tracing::*;
+
+use crate::{config, pii, respond, tenant::Tenants, util::E164, SmsMessage};
+
+/// Whether outgoing texts are only logged
+pub fn enabled() -> bool {
+    config::get().simulate
+}
+
+#[derive(Deserialize)]
+pub struct Sender {
+    pub from: String,
+    /// Which bot number it's sent to, when there are several
+    pub to: Option<String>,
+}
+
+/// Handles a plain-text message as if it came through Twilio from `?from=`, returning the reply.
+/// Only routed when simulating.
+pub async fn handle_simulate(
+    Extension(tenants): Extension<Tenants>,
+    Query(Sender { from, to }): Query<Sender>,
+    body: String,
+) -> (StatusCode, String) {
+    // A "+" in a query string arrives as a space, so take the number in any format
+    let Ok(from) = E164::from_str(&from) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid phone number: {from}"),
+        );
+    };
+    let message = SmsMessage {
+        From: from.to_string(),
+        Body: body,
+        To: to,
+        ..Default::default()
+    };
+    let span = info_span!(
+        "simulated message",
+        sender = %pii::redact(&message.From),
+        command = field::Empty,
+    );
+    let tenant = tenants.get(message.To.as_deref());
+    let response = respond(tenant, message).instrument(span).await;
+    (StatusCode::OK, response.unwrap_or_default())
+}
@@ -0,0 +1,63 @@
+//! `stats` for the operator: texted from `CLIENT_NUMBER`, it replies with how the bot is doing,
+//! for checking in when away from a terminal. From anyone else it's just an unknown command.
+
+use std::str::FromStr;
+
+use anyhow::Result;
+use sqlx::{query, Pool, Sqlite};
+
+use crate::{cache, config, cost, message_log, pii, rate_limit, util::E164};
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// Whether a (sealed) number is the operator's
+pub fn is_operator(from: &str) -> bool {
+    config::get()
+        .client_number
+        .as_deref()
+        .and_then(|number| E164::from_str(number).ok())
+        .is_some_and(|number| pii::seal(number.as_str()) == from)
+}
+
+/// A summary of the database the message came in on
+pub async fn handle_stats(pool: &Pool<Sqlite>) -> Result<String> {
+    let counts = query!(
+        r#"SELECT
+            (SELECT COUNT(*) FROM users) as "users!: i64",
+            (SELECT COUNT(*) FROM users WHERE created_at > unixepoch() - ?1) as "new_users!: i64",
+            (SELECT COUNT(*) FROM scheduled_messages WHERE status = 'pending') as "reminders!: i64",
+            (SELECT COUNT(*) FROM events WHERE starts_at > unixepoch()) as "events!: i64",
+            (SELECT COUNT(*) FROM jobs WHERE status = 'failed' AND finished_at > unixepoch() - ?1)
+                as "failed_jobs!: i64",
+            (SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size())
+                as "bytes!: i64""#,
+        DAY_SECS
+    )
+    .fetch_one(pool)
+    .await?;
+    let messages = message_log::counts(pool, DAY_SECS).await?;
+    let spend = cost::spend(pool).await?;
+    Ok(format!(
+        "Users: {} ({} new today)\n\
+         Last 24h: {} in, {} out, {} failed to send\n\
+         Spend (est.): ${:.2} today, ${:.2} this month\n\
+         Pending reminders: {}\n\
+         Upcoming events: {}\n\
+         Failed jobs (24h): {}\n\
+         Database: {:.1} MB\n\
+         Dropped from memory: {} users, {} rate limits",
+        counts.users,
+        counts.new_users,
+        messages.received,
+        messages.sent,
+        messages.failed,
+        spend.today,
+        spend.month,
+        counts.reminders,
+        counts.events,
+        counts.failed_jobs,
+        counts.bytes as f64 / 1_000_000.0,
+        cache::evictions(),
+        rate_limit::evictions()
+    ))
+}
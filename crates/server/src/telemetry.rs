@@ -0,0 +1,57 @@
+//! Logging, and optionally exporting traces over OTLP (to Jaeger, Tempo, etc.).
+//!
+//! Export is turned on by setting `OTEL_EXPORTER_OTLP_ENDPOINT`, e.g. `http://localhost:4318`.
+//! The other standard `OTEL_*` variables (headers, timeout) are honored too.
+//! Spans cover each inbound message and each Twilio API call, and sqlx's query events are
+//! recorded on whichever span ran the query.
+
+use anyhow::Result;
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace::Config, Resource};
+use tracing::*;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+/// Sets up logging to stderr, filtered by `RUST_LOG`, plus trace export if configured.
+/// Call once, early in startup, from within the Tokio runtime.
+pub fn init() -> Result<()> {
+    let endpoint = &crate::config::get().otel_exporter_otlp_endpoint;
+    let otlp = match endpoint {
+        Some(endpoint) => {
+            let provider =
+                opentelemetry_otlp::new_pipeline()
+                    .tracing()
+                    .with_exporter(
+                        opentelemetry_otlp::new_exporter()
+                            .http()
+                            .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/'))),
+                    )
+                    .with_trace_config(Config::default().with_resource(Resource::new([
+                        KeyValue::new("service.name", "decisionbot"),
+                    ])))
+                    .install_batch(runtime::Tokio)?;
+            opentelemetry::global::set_tracer_provider(provider.clone());
+            // Filtered separately from the log, which is often quieter, but queries are wanted here
+            Some(
+                tracing_opentelemetry::layer()
+                    .with_tracer(provider.tracer("decisionbot"))
+                    .with_filter(EnvFilter::new("info,sqlx::query=debug")),
+            )
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(EnvFilter::from_default_env()))
+        .with(otlp)
+        .try_init()?;
+    if let Some(endpoint) = endpoint {
+        info!("Exporting traces to {endpoint}");
+    }
+    Ok(())
+}
+
+/// Sends any traces still buffered. Call before exiting.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
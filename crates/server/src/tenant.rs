@@ -0,0 +1,164 @@
+//! Serving several bot numbers from one server, e.g. for different friend groups or clients.
+//!
+//! Each tenant is a Twilio number with its own database, so users, contacts, groups and
+//! everything else are kept entirely apart: someone texting two of the numbers is two unrelated
+//! users. Incoming messages are routed by the number they were sent to (Twilio's `To`), and
+//! everything a tenant sends goes out from its number.
+//!
+//! Set `TENANTS` to comma-separated `number=database_url` pairs, e.g.
+//! `TENANTS=+15550001111=sqlite:family.sqlite3,+15550002222=sqlite:work.sqlite3`.
+//! Without it there's a single tenant: `SERVER_NUMBER` with `DATABASE_URL`.
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use sqlx::{query, sqlite::SqliteConnectOptions, Pool, Sqlite, SqlitePool};
+use tracing::*;
+
+use crate::{cache::UserCache, config, pii, rate_limit::RateLimiter};
+
+/// One bot number and everything that belongs to it
+#[derive(Clone)]
+pub struct Tenant {
+    /// The Twilio number messages come in on and go out from
+    pub number: String,
+    pub pool: Pool<Sqlite>,
+    pub limiter: RateLimiter,
+    pub users: UserCache,
+    /// Where under `BACKUP_DIR` this tenant's snapshots go, when there are several tenants
+    backup_subdir: Option<String>,
+}
+
+impl Tenant {
+    pub fn new(number: String, pool: Pool<Sqlite>) -> Self {
+        Self {
+            number,
+            pool,
+            limiter: RateLimiter::default(),
+            users: UserCache::default(),
+            backup_subdir: None,
+        }
+    }
+
+    /// The directory for this tenant's backups, given `BACKUP_DIR`
+    pub fn backup_dir(&self, base: &Path) -> PathBuf {
+        match &self.backup_subdir {
+            Some(subdir) => base.join(subdir),
+            None => base.to_path_buf(),
+        }
+    }
+}
+
+/// Every tenant, shared by all the routes
+#[derive(Clone)]
+pub struct Tenants(Arc<Vec<Tenant>>);
+
+impl Tenants {
+    /// The first tenant is the default, for messages to a number that isn't configured
+    pub fn new(tenants: Vec<Tenant>) -> Self {
+        assert!(!tenants.is_empty(), "There must be at least one tenant");
+        Self(Arc::new(tenants))
+    }
+
+    /// The tenant for the number a message was sent to
+    pub fn get(&self, to: Option<&str>) -> &Tenant {
+        match to.and_then(|to| self.0.iter().find(|tenant| tenant.number == to)) {
+            Some(tenant) => tenant,
+            None => {
+                if self.0.len() > 1 {
+                    warn!("No tenant for {to:?}; using {}", self.0[0].number);
+                }
+                &self.0[0]
+            }
+        }
+    }
+
+    pub fn all(&self) -> &[Tenant] {
+        &self.0
+    }
+}
+
+/// Each tenant's (number, database URL), from `TENANTS` or else `SERVER_NUMBER`/`DATABASE_URL`
+pub fn configured() -> Result<Vec<(String, String)>> {
+    let config = config::get();
+    let Some(tenants) = &config.tenants else {
+        return Ok(vec![(
+            config
+                .server_number
+                .clone()
+                .context("SERVER_NUMBER is not set")?,
+            config
+                .database_url
+                .clone()
+                .context("DATABASE_URL is not set")?,
+        )]);
+    };
+    parse_config(tenants).context("Invalid TENANTS")
+}
+
+fn parse_config(tenants: &str) -> Result<Vec<(String, String)>> {
+    let config = tenants
+        .split(',')
+        .map(|pair| match pair.trim().split_once('=') {
+            Some((number, url)) if !number.trim().is_empty() && !url.trim().is_empty() => {
+                Ok((number.trim().to_string(), url.trim().to_string()))
+            }
+            _ => bail!("Expected number=database_url, got \"{pair}\""),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (i, (number, _)) in config.iter().enumerate() {
+        if config[..i].iter().any(|(other, _)| other == number) {
+            bail!("{number} is listed more than once");
+        }
+    }
+    Ok(config)
+}
+
+/// Connects to a tenant's database, creating and migrating it unless `migrate` is false,
+/// and seals any personal data left in plaintext
+pub async fn connect(url: &str, migrate: bool) -> Result<Pool<Sqlite>> {
+    let options = SqliteConnectOptions::from_str(url)?.create_if_missing(migrate);
+    let pool = SqlitePool::connect_with(options).await?;
+    if migrate {
+        sqlx::migrate!()
+            .run(&pool)
+            .await
+            .with_context(|| format!("While migrating {url}"))?;
+    }
+    query!("PRAGMA foreign_keys = ON").execute(&pool).await?; // SQLite has this off by default
+    pii::seal_existing(&pool).await?;
+    Ok(pool)
+}
+
+/// Sets up every configured tenant
+pub async fn connect_all(migrate: bool) -> Result<Tenants> {
+    let config = configured()?;
+    let several = config.len() > 1;
+    let mut tenants = Vec::new();
+    for (number, url) in config {
+        let mut tenant = Tenant::new(number, connect(&url, migrate).await?);
+        if several {
+            tenant.backup_subdir = Some(tenant.number.trim_start_matches('+').to_string());
+        }
+        info!("Serving {} from {url}", tenant.number);
+        tenants.push(tenant);
+    }
+    Ok(Tenants::new(tenants))
+}
+
+#[test]
+fn tenants_config() {
+    assert_eq!(
+        parse_config("+15550001111=sqlite:a.sqlite3, +15550002222 = sqlite:b.sqlite3").unwrap(),
+        vec![
+            ("+15550001111".to_string(), "sqlite:a.sqlite3".to_string()),
+            ("+15550002222".to_string(), "sqlite:b.sqlite3".to_string()),
+        ]
+    );
+    assert!(parse_config("+15550001111").is_err());
+    assert!(parse_config("+15550001111=sqlite:a.sqlite3,+15550001111=sqlite:b.sqlite3").is_err());
+}
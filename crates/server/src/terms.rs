@@ -0,0 +1,103 @@
+//! Terms of service, which SMS carriers increasingly want to see users explicitly accept.
+//!
+//! Set `TERMS_VERSION` and `TERMS_URL` and signing up includes accepting the terms, with the
+//! version and time recorded on the user. Changing `TERMS_VERSION` asks everyone to accept
+//! again before they can carry on. Without them, there's no terms step at all.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use sqlx::{query, Pool, Sqlite};
+
+use crate::{
+    audit::{self, Kind},
+    config,
+    i18n::{t, Lang},
+    onboarding::answer,
+};
+
+#[derive(Debug)]
+pub struct Terms {
+    pub version: String,
+    pub url: String,
+}
+
+static TERMS: Lazy<Option<Terms>> = Lazy::new(|| {
+    Some(Terms {
+        version: config::get().terms_version.clone()?,
+        url: config::get().terms_url.clone()?,
+    })
+});
+
+#[cfg(test)]
+thread_local! {
+    /// Lets a test have terms without touching the process-wide ones other tests share
+    static TEST_TERMS: std::cell::Cell<Option<&'static Terms>> = const { std::cell::Cell::new(None) };
+}
+
+/// Turns on the given terms for the rest of the current test
+#[cfg(test)]
+pub fn use_test_terms(version: &str) {
+    let terms = Box::leak(Box::new(Terms {
+        version: version.to_string(),
+        url: "https://example.com/terms".to_string(),
+    }));
+    TEST_TERMS.with(|t| t.set(Some(terms)));
+}
+
+/// The terms users have to accept, if there are any
+pub fn current() -> Option<&'static Terms> {
+    #[cfg(test)]
+    if let Some(terms) = TEST_TERMS.with(|t| t.get()) {
+        return Some(terms);
+    }
+    TERMS.as_ref()
+}
+
+/// Records that a user accepted `terms` just now
+pub async fn accept(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    number: &str,
+    terms: &Terms,
+) -> Result<()> {
+    query!(
+        "UPDATE users SET terms_version = ?, terms_accepted_at = unixepoch() WHERE number = ?",
+        terms.version,
+        number
+    )
+    .execute(&mut **tx)
+    .await?;
+    audit::record(
+        &mut **tx,
+        number,
+        Kind::Mutation,
+        format!("accepted terms version {}", terms.version),
+    )
+    .await
+}
+
+/// For a user who hasn't accepted the current terms, the reply to their message: thanks if
+/// it's a YES, and otherwise a request to accept. None if they're up to date.
+pub async fn check(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    body: &str,
+) -> Result<Option<String>> {
+    let Some(terms) = current() else {
+        return Ok(None);
+    };
+    let accepted = query!("SELECT terms_version FROM users WHERE number = ?", from)
+        .fetch_optional(pool)
+        .await?
+        .and_then(|row| row.terms_version);
+    if accepted.as_deref() == Some(terms.version.as_str()) {
+        return Ok(None);
+    }
+    if answer(body) == Some(true) {
+        let mut tx = pool.begin().await?;
+        accept(&mut tx, from, terms).await?;
+        tx.commit().await?;
+        return Ok(Some(t!(lang, "terms_accepted")));
+    }
+    Ok(Some(t!(lang, "terms_changed", url = terms.url)))
+}
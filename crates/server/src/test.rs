@@ -1,3 +1,8 @@
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+};
 use contacts::process_vcard;
 
 use super::*;
@@ -7,9 +12,15 @@ async fn setup_db(pool: &Pool<Sqlite>) -> Result<()> {
     Ok(())
 }
 
+/// A lone tenant on the test database
+fn tenant(pool: &Pool<Sqlite>) -> Tenant {
+    Tenant::new("+15550000000".to_string(), pool.clone())
+}
+
 async fn send_message(pool: &Pool<Sqlite>, from: &str, body: &str) -> Result<String> {
     process_message(
         pool,
+        &UserCache::default(),
         SmsMessage {
             From: from.to_string(),
             Body: body.to_string(),
@@ -19,6 +30,96 @@ async fn send_message(pool: &Pool<Sqlite>, from: &str, body: &str) -> Result<Str
     .await
 }
 
+/// Plays whole conversations through `process_message` the way Twilio would deliver them,
+/// checking each reply along the way
+struct Simulator {
+    pool: Pool<Sqlite>,
+    users: UserCache,
+}
+
+impl Simulator {
+    async fn new(pool: &Pool<Sqlite>) -> Result<Self> {
+        setup_db(pool).await?;
+        Ok(Self {
+            pool: pool.clone(),
+            users: UserCache::default(),
+        })
+    }
+
+    async fn text(&self, from: &str, body: &str) -> Result<String> {
+        self.deliver(SmsMessage {
+            From: from.to_string(),
+            Body: body.to_string(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Texts `body` and checks the reply contains `expected`
+    async fn expect(&self, from: &str, body: &str, expected: &str) -> Result<String> {
+        let reply = self.text(from, body).await?;
+        assert!(
+            reply.contains(expected),
+            "{body:?} got {reply:?}, expected {expected:?}"
+        );
+        Ok(reply)
+    }
+
+    /// Sends `vcard` as an attachment, served over HTTP for the bot to fetch like Twilio's media
+    async fn send_vcard(&self, from: &str, vcard: &str) -> Result<String> {
+        self.send_media(from, "", Some("text/vcard"), "/contact.vcf", vcard)
+            .await
+    }
+
+    /// Sends `body` with an attachment that's served at `path` as `content`
+    async fn send_media(
+        &self,
+        from: &str,
+        body: &str,
+        content_type: Option<&str>,
+        path: &str,
+        content: &str,
+    ) -> Result<String> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}{path}", listener.local_addr()?);
+        let content = content.to_string();
+        let media = Router::new().route(path, get(move || async move { content }));
+        let server = tokio::spawn(async move { axum::serve(listener, media).await });
+        let reply = self
+            .deliver(SmsMessage {
+                From: from.to_string(),
+                Body: body.to_string(),
+                NumMedia: Some("1".to_string()),
+                MediaContentType0: content_type.map(str::to_string),
+                MediaUrl0: Some(url),
+                ..Default::default()
+            })
+            .await;
+        server.abort();
+        reply
+    }
+
+    async fn deliver(&self, message: SmsMessage) -> Result<String> {
+        process_message(&self.pool, &self.users, message).await
+    }
+
+    /// The names of `from`'s contacts, alphabetically
+    async fn contact_names(&self, from: &str) -> Result<Vec<String>> {
+        let from = pii::seal(from);
+        let mut names = query!(
+            "SELECT contact_name FROM contacts WHERE submitter_number = ? AND deleted_at IS NULL",
+            from
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| pii::open(&row.contact_name))
+        .collect::<Result<Vec<_>>>()?;
+        names.sort();
+        Ok(names)
+    }
+}
+
 #[sqlx::test]
 async fn test_new_user_registration(pool: Pool<Sqlite>) -> Result<()> {
     setup_db(&pool).await?;
@@ -46,15 +147,153 @@ async fn test_new_user_registration(pool: Pool<Sqlite>) -> Result<()> {
     assert!(response.contains("Hello, John Doe!"));
 
     // Verify user was created in database
-    let user = query_as!(User, "SELECT * FROM users WHERE number = ?", "+1234567890")
-        .fetch_one(&pool)
-        .await?;
+    let user = query_as!(
+        User,
+        "SELECT number, name, lang, timezone FROM users WHERE number = ?",
+        "+1234567890"
+    )
+    .fetch_one(&pool)
+    .await?;
     assert_eq!(user.name, "John Doe");
     assert_eq!(user.number, "+1234567890");
 
     Ok(())
 }
 
+#[sqlx::test]
+async fn test_onboarding(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    pii::use_test_key();
+    let number = "+1234567890";
+
+    assert!(send_message(&pool, number, "hello?")
+        .await?
+        .contains("What name should your friends see?"));
+    assert!(send_message(&pool, number, "Sam C")
+        .await?
+        .contains("Reply YES to use \"Sam C\""));
+
+    // Changing their mind
+    assert!(send_message(&pool, number, "no")
+        .await?
+        .contains("what name should your friends see?"));
+    assert!(
+        send_message(&pool, number, "This Name Is Way Too Long For The System")
+            .await?
+            .contains("Please shorten it")
+    );
+    assert!(send_message(&pool, number, "Sam Carey")
+        .await?
+        .contains("Reply YES to use \"Sam Carey\""));
+
+    // Not a user until they confirm, and the name waiting for confirmation is sealed
+    let pending = query!("SELECT pending_name FROM sessions")
+        .fetch_one(&pool)
+        .await?
+        .pending_name
+        .unwrap();
+    assert_ne!(pending, "Sam Carey");
+    assert_eq!(pii::open(&pending)?, "Sam Carey");
+    let users = query!(r#"SELECT COUNT(*) as "count!: i64" FROM users"#)
+        .fetch_one(&pool)
+        .await?
+        .count;
+    assert_eq!(users, 0);
+
+    let response = send_message(&pool, number, "yes").await?;
+    assert!(response.contains("Hello, Sam Carey! Here's a quick tour"));
+    let sealed_number = pii::seal(number);
+    let user = query!("SELECT name FROM users WHERE number = ?", sealed_number)
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(pii::open(&user.name)?, "Sam Carey");
+    assert!(query!("SELECT state FROM sessions")
+        .fetch_optional(&pool)
+        .await?
+        .is_none());
+
+    // Registered users aren't asked again
+    assert!(send_message(&pool, number, "yes")
+        .await?
+        .contains("Reply \"h\""));
+
+    // Deleting an account leaves other people's sign-ups alone,
+    // and signing up again afterwards starts over
+    send_message(&pool, "+19876543210", "hi").await?;
+    send_message(&pool, number, "stop purge").await?;
+    assert!(send_message(&pool, "+19876543210", "Alice")
+        .await?
+        .contains("Reply YES to use \"Alice\""));
+    assert!(send_message(&pool, number, "Sam")
+        .await?
+        .contains("Greetings!"));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_terms(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    terms::use_test_terms("1");
+    let number = "+1234567890";
+
+    // Signing up includes accepting the terms, even with the name command
+    send_message(&pool, number, "hi").await?;
+    let response = send_message(&pool, number, "Sam").await?;
+    assert!(response.contains("accept the terms of service (https://example.com/terms)"));
+    let response = send_message(&pool, "+19876543210", "name Alice").await?;
+    assert!(response.contains("Reply YES to use \"Alice\" and accept the terms"));
+    assert!(send_message(&pool, number, "yes")
+        .await?
+        .contains("Hello, Sam!"));
+    let user = query!(
+        "SELECT terms_version, terms_accepted_at FROM users WHERE number = ?",
+        number
+    )
+    .fetch_one(&pool)
+    .await?;
+    assert_eq!(user.terms_version.as_deref(), Some("1"));
+    assert!(user.terms_accepted_at.is_some());
+    assert!(send_message(&pool, number, "contacts")
+        .await?
+        .contains("You don't have any"));
+
+    // New terms have to be accepted before carrying on
+    terms::use_test_terms("2");
+    assert!(send_message(&pool, number, "contacts")
+        .await?
+        .contains("The terms of service have changed"));
+    assert!(send_message(&pool, number, "yes")
+        .await?
+        .contains("Thanks for accepting the terms!"));
+    assert!(send_message(&pool, number, "contacts")
+        .await?
+        .contains("You don't have any"));
+    let user = query!("SELECT terms_version FROM users WHERE number = ?", number)
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(user.terms_version.as_deref(), Some("2"));
+
+    // Someone asked about getting messages, who hasn't accepted the terms either, is answering
+    // the question they were asked
+    let alice = "+15555550100";
+    add_contact(&pool, number, "Alice Smith", alice).await?;
+    consent::mark_requested(&pool, alice).await?;
+    assert!(send_message(&pool, alice, "yes")
+        .await?
+        .contains("You'll now get messages your contacts send you here"));
+    assert_eq!(
+        consent::status(&pool, alice).await?,
+        consent::Consent::Granted
+    );
+    // The terms are still there to accept
+    assert!(send_message(&pool, alice, "yes")
+        .await?
+        .contains("Thanks for accepting the terms!"));
+
+    Ok(())
+}
+
 #[sqlx::test]
 async fn test_help_and_info_commands(pool: Pool<Sqlite>) -> Result<()> {
     setup_db(&pool).await?;
@@ -64,11 +303,25 @@ async fn test_help_and_info_commands(pool: Pool<Sqlite>) -> Result<()> {
 
     // Test help command
     let response = send_message(&pool, "+1234567890", "h").await?;
-    assert!(response.contains("General commands"));
+    assert!(response.contains("Command categories"));
     assert!(response.contains("name"));
     assert!(response.contains("info"));
     assert!(response.contains("contacts"));
 
+    // Test category pages by name and by number
+    let response = send_message(&pool, "+1234567890", "h contacts").await?;
+    assert!(response.contains("Commands for contacts"));
+    assert!(response.contains("delete: delete a contact by name"));
+    assert!(!response.contains("set your preferred name"));
+
+    let response = send_message(&pool, "+1234567890", "h 2").await?;
+    assert!(response.contains("Commands for account"));
+    assert!(response.contains("name: set your preferred name"));
+
+    let response = send_message(&pool, "+1234567890", "h nonsense").await?;
+    assert!(response.contains("Category \"nonsense\" not recognized"));
+    assert!(response.contains("Command categories"));
+
     // Test info command without parameter
     let response = send_message(&pool, "+1234567890", "info").await?;
     assert!(response.contains("Reply \"info X\""));
@@ -83,6 +336,32 @@ async fn test_help_and_info_commands(pool: Pool<Sqlite>) -> Result<()> {
 
     Ok(())
 }
+#[sqlx::test]
+async fn test_autocorrected_commands(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    // Auto-capitalized command word with a trailing colon during onboarding
+    let response = send_message(&pool, "+1234567890", "Name: John Doe").await?;
+    assert!(response.contains("Hello, John Doe!"));
+
+    // Trailing punctuation added by autocorrect
+    let response = send_message(&pool, "+1234567890", "Contacts.").await?;
+    assert!(response.contains("You don't have any"));
+
+    let response = send_message(&pool, "+1234567890", "H!").await?;
+    assert!(response.contains("Command categories"));
+
+    // Command words given as arguments are normalized too
+    let response = send_message(&pool, "+1234567890", "Info Name?").await?;
+    assert!(response.contains("set your preferred name"));
+
+    // Punctuation inside the argument is left alone
+    let response = send_message(&pool, "+1234567890", "Name John S.").await?;
+    assert!(response.contains("Your name has been updated to \"John S.\""));
+
+    Ok(())
+}
+
 #[sqlx::test]
 async fn test_contact_management(pool: Pool<Sqlite>) -> Result<()> {
     setup_db(&pool).await?;
@@ -141,6 +420,20 @@ async fn test_malformed_vcard(pool: Pool<Sqlite>) -> Result<()> {
     let response = send_message(&pool, "+1234567890", "contacts").await?;
     assert!(response.contains("You don't have any")); // Changed assertion
 
+    // Without FN or N there's nothing to call them
+    let nameless = "BEGIN:VCARD\nVERSION:2.1\nN:;;;;\nTEL:+19876543210\nEND:VCARD\n";
+    let mut reader = ical::VcardParser::new(nameless.as_bytes());
+    let result = process_vcard(&pool, "+1234567890", reader.next().unwrap()).await;
+    assert_eq!(result.unwrap_err().to_string(), "No name provided");
+
+    // vCard 2.1 exports often only have N, which is family;given;...
+    let structured = "BEGIN:VCARD\nVERSION:2.1\nN:Smith;Alice;;Dr.;\nTEL:+19876543210\nEND:VCARD\n";
+    let mut reader = ical::VcardParser::new(structured.as_bytes());
+    let result = process_vcard(&pool, "+1234567890", reader.next().unwrap()).await?;
+    assert!(matches!(result, ImportResult::Added));
+    let response = send_message(&pool, "+1234567890", "contacts").await?;
+    assert!(response.contains("Alice Smith"));
+
     Ok(())
 }
 
@@ -157,14 +450,18 @@ async fn test_user_deletion(pool: Pool<Sqlite>) -> Result<()> {
     let vcard = reader.next().unwrap();
     process_vcard(&pool, "+1234567890", vcard).await?;
 
-    // Test stop command - should cascade delete contacts due to foreign key constraint
-    let response = send_message(&pool, "+1234567890", "stop").await?;
+    // Test stop purge - should cascade delete contacts due to foreign key constraint
+    let response = send_message(&pool, "+1234567890", "stop purge").await?;
     assert!(response.contains("unsubscribed"));
 
     // Verify user was deleted
-    let user = query_as!(User, "SELECT * FROM users WHERE number = ?", "+1234567890")
-        .fetch_optional(&pool)
-        .await?;
+    let user = query_as!(
+        User,
+        "SELECT number, name, lang, timezone FROM users WHERE number = ?",
+        "+1234567890"
+    )
+    .fetch_optional(&pool)
+    .await?;
     assert!(user.is_none());
 
     // Verify contacts were deleted due to foreign key constraint
@@ -184,6 +481,37 @@ async fn test_user_deletion(pool: Pool<Sqlite>) -> Result<()> {
     Ok(())
 }
 
+#[sqlx::test]
+async fn test_user_deletion_references(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    send_message(&pool, "+19876543210", "name Alice Smith").await?;
+
+    // Alice has John as a contact and in a group
+    add_contact(&pool, "+19876543210", "John Doe", "+1234567890").await?;
+    send_message(&pool, "+19876543210", "group john").await?;
+    send_message(&pool, "+19876543210", "confirm 1").await?;
+
+    let response = send_message(&pool, "+1234567890", "stop purge").await?;
+    assert!(response.contains("1 listing(s) in other people's contacts"));
+    assert!(response.contains("1 group membership(s)"));
+
+    let user = query_as!(
+        User,
+        "SELECT number, name, lang, timezone FROM users WHERE number = ?",
+        "+1234567890"
+    )
+    .fetch_optional(&pool)
+    .await?;
+    assert!(user.is_none());
+    let response = send_message(&pool, "+19876543210", "contacts").await?;
+    assert!(!response.contains("John Doe"));
+    assert!(response.contains("(0 members)"));
+
+    Ok(())
+}
+
 #[sqlx::test]
 async fn test_contact_updates(pool: Pool<Sqlite>) -> Result<()> {
     setup_db(&pool).await?;
@@ -304,9 +632,9 @@ async fn test_multi_number_contact_selection(pool: Pool<Sqlite>) -> Result<()> {
     .await?;
     assert_eq!(deferred_count.count, 0);
 
-    // Verify pending action was cleaned up
+    // Verify the session was cleaned up
     let pending_count = query!(
-        "SELECT COUNT(*) as count FROM pending_actions WHERE submitter_number = ?",
+        "SELECT COUNT(*) as count FROM sessions WHERE submitter_number = ?",
         "+1234567890"
     )
     .fetch_one(&pool)
@@ -441,3 +769,2581 @@ async fn test_group_deletion(pool: Pool<Sqlite>) -> Result<()> {
 
     Ok(())
 }
+
+#[sqlx::test]
+async fn test_cancel_session(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+
+    let response = send_message(&pool, "+1234567890", "nevermind").await?;
+    assert!(response.contains("anything in progress"));
+
+    let vcard = "BEGIN:VCARD\nVERSION:3.0\nFN:Alice Smith\nTEL:+19876543210\nEND:VCARD\n";
+    let mut reader = ical::VcardParser::new(vcard.as_bytes());
+    process_vcard(&pool, "+1234567890", reader.next().unwrap()).await?;
+
+    send_message(&pool, "+1234567890", "delete Alice").await?;
+    let response = send_message(&pool, "+1234567890", "h").await?;
+    assert!(response.contains("pending contact deletions"));
+    assert!(response.contains("Reply \"nevermind\""));
+
+    let response = send_message(&pool, "+1234567890", "pending").await?;
+    assert!(response.starts_with("In progress: deletion (expires "));
+    assert!(response.contains("pending contact deletions:\n1. Alice Smith"));
+    assert!(response.ends_with("Reply \"nevermind\" to drop it."));
+
+    let response = send_message(&pool, "+1234567890", "nvm").await?;
+    assert!(response.contains("Cancelled your pending deletion"));
+    let response = send_message(&pool, "+1234567890", "pending").await?;
+    assert_eq!(response, "You don't have anything in progress.");
+
+    // Nothing is left to confirm and the contact survives
+    let response = send_message(&pool, "+1234567890", "confirm 1").await?;
+    assert!(response.contains("No pending actions"));
+    let response = send_message(&pool, "+1234567890", "contacts").await?;
+    assert!(response.contains("Alice Smith"));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_session_expiry(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    config::use_test_config(config::Config {
+        simulate: true,
+        ..Default::default()
+    });
+
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    let vcard = "BEGIN:VCARD\nVERSION:3.0\nFN:Alice Smith\n\
+        TEL;TYPE=CELL:+19876543210\nTEL;TYPE=WORK:+19876543211\nEND:VCARD\n";
+    let mut reader = ical::VcardParser::new(vcard.as_bytes());
+    let result = process_vcard(&pool, "+1234567890", reader.next().unwrap()).await?;
+    assert!(matches!(result, ImportResult::Deferred));
+
+    // Each card pushes the session back, but it still only takes one job to end it
+    for name in ["Bob Jones", "Carol White"] {
+        let vcard = format!(
+            "BEGIN:VCARD\nVERSION:3.0\nFN:{name}\n\
+            TEL;TYPE=CELL:+15555550100\nTEL;TYPE=WORK:+15555550101\nEND:VCARD\n"
+        );
+        let mut reader = ical::VcardParser::new(vcard.as_bytes());
+        process_vcard(&pool, "+1234567890", reader.next().unwrap()).await?;
+    }
+    let pending = query!(
+        r#"SELECT COUNT(*) as "count!: i64" FROM jobs WHERE payload = ? AND status = 'pending'"#,
+        r#"{"type":"session_expiry"}"#
+    )
+    .fetch_one(&pool)
+    .await?;
+    assert_eq!(pending.count, 1);
+
+    query!("UPDATE sessions SET expires_at = unixepoch() - 1")
+        .execute(&pool)
+        .await?;
+
+    let response = send_message(&pool, "+1234567890", "confirm 1a").await?;
+    assert!(response.contains("No pending actions"));
+
+    // The deferred picks go away with the session
+    session::expire(&pool, "+15550000000").await?;
+    let deferred_count = query!("SELECT COUNT(*) as count FROM deferred_contacts")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(deferred_count.count, 0);
+
+    // A session left to run out is ended by a job queued for when it's due, which tells the user
+    add_contact(&pool, "+1234567890", "Alice Smith", "+19876543210").await?;
+    send_message(&pool, "+1234567890", "delete Alice").await?;
+    let delay = query!(
+        r#"SELECT run_at - unixepoch() as "delay!: i64" FROM jobs WHERE payload = ?"#,
+        r#"{"type":"session_expiry"}"#
+    )
+    .fetch_all(&pool)
+    .await?;
+    assert_eq!(delay.last().unwrap().delay, 5 * 60);
+    assert!(session::expiry_notices(&pool).await?.is_empty());
+    query!("UPDATE sessions SET expires_at = unixepoch() - 1")
+        .execute(&pool)
+        .await?;
+    assert_eq!(
+        session::expiry_notices(&pool).await?,
+        vec![Outgoing {
+            to: "+1234567890".to_string(),
+            body: "Your pending deletion expired after 5 minutes, so nothing was deleted. \
+                   Send \"delete NAME\" to start again."
+                .to_string(),
+        }]
+    );
+    // Someone else texting in the meantime doesn't end it quietly
+    send_message(&pool, "+19876543210", "name Alice").await?;
+    assert_eq!(session::expiry_notices(&pool).await?.len(), 1);
+    let sent = |pool: Pool<Sqlite>| async move {
+        query!(r#"SELECT COUNT(*) as "count!: i64" FROM message_log WHERE status = 'sent'"#)
+            .fetch_one(&pool)
+            .await
+            .map(|row| row.count)
+    };
+    let before = sent(pool.clone()).await?;
+    query!(
+        "UPDATE jobs SET run_at = unixepoch() WHERE payload = ?",
+        r#"{"type":"session_expiry"}"#
+    )
+    .execute(&pool)
+    .await?;
+    while jobs::run_next(&tenant(&pool)).await? {}
+    assert_eq!(sent(pool.clone()).await?, before + 1);
+    assert!(session::expiry_notices(&pool).await?.is_empty());
+    assert_eq!(
+        session::current(&pool, &pii::seal("+1234567890")).await?,
+        None
+    );
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_language(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+
+    let response = send_message(&pool, "+1234567890", "language").await?;
+    assert!(response.contains("Your language is English"));
+
+    let response = send_message(&pool, "+1234567890", "language klingon").await?;
+    assert!(response.contains("We don't support \"klingon\""));
+
+    let response = send_message(&pool, "+1234567890", "language es").await?;
+    assert!(response.contains("Tu idioma ahora es"));
+
+    // Replies now come back in Spanish, and category names are accepted in either language
+    let response = send_message(&pool, "+1234567890", "h").await?;
+    assert!(response.contains("Categorías de comandos"));
+    let response = send_message(&pool, "+1234567890", "h contactos").await?;
+    assert!(response.contains("Comandos de contactos"));
+    let response = send_message(&pool, "+1234567890", "contacts").await?;
+    assert!(response.contains("No tienes"));
+
+    let response = send_message(&pool, "+1234567890", "language english").await?;
+    assert!(response.contains("Your language has been set to English"));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_user_cache(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    let users = UserCache::default();
+    let send = |body: &str| {
+        process_message(
+            &pool,
+            &users,
+            SmsMessage {
+                From: "+1234567890".to_string(),
+                Body: body.to_string(),
+                ..Default::default()
+            },
+        )
+    };
+
+    send("name John Doe").await?;
+    assert!(send("language").await?.contains("Your language is English"));
+
+    // Changes made through commands show up right away
+    send("language es").await?;
+    assert!(send("language").await?.contains("Tu idioma es"));
+
+    // Changes made behind the cache's back don't, until the entry expires
+    query!("UPDATE users SET lang = 'en'")
+        .execute(&pool)
+        .await?;
+    assert!(send("language").await?.contains("Tu idioma es"));
+    users.invalidate("+1234567890");
+    assert!(send("language").await?.contains("Your language is English"));
+
+    // A removed user isn't remembered
+    send("stop purge").await?;
+    assert!(send("h").await?.contains("Greetings!"));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_rate_limit(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    let limiter = RateLimiter::default();
+    for _ in 0..rate_limit::BURST as usize {
+        assert_eq!(limiter.check(&pool, "+1234567890").await?, Decision::Allow);
+    }
+    assert_eq!(
+        limiter.check(&pool, "+1234567890").await?,
+        Decision::Throttle
+    );
+    assert_eq!(limiter.check(&pool, "+1234567890").await?, Decision::Drop);
+
+    // Other senders are unaffected
+    assert_eq!(limiter.check(&pool, "+1987654321").await?, Decision::Allow);
+
+    // A restarted server picks the throttle back up from the database
+    let limiter = RateLimiter::default();
+    assert_eq!(limiter.check(&pool, "+1234567890").await?, Decision::Drop);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_block(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    add_contact(&pool, "+1234567890", "Alice Smith", "+19876543210").await?;
+    add_contact(&pool, "+1234567890", "Alice Jones", "+19876543211").await?;
+
+    let response = send_message(&pool, "+1234567890", "block").await?;
+    assert!(response.contains("You haven't blocked anyone"));
+
+    let response = send_message(&pool, "+1234567890", "block alice").await?;
+    assert!(response.contains("More than one contact matches"));
+
+    let response = send_message(&pool, "+1234567890", "block smith").await?;
+    assert!(response.contains("Blocked Alice Smith"));
+
+    // By number, whether or not they're a contact
+    let response = send_message(&pool, "+1234567890", "block 555-123-4567").await?;
+    assert!(response.contains("Blocked +15551234567"));
+
+    let response = send_message(&pool, "+1234567890", "block").await?;
+    assert!(response.contains("Alice Smith (+19876543210)"));
+    assert!(response.contains("+15551234567"));
+
+    let response = send_message(&pool, "+1234567890", "unblock Alice Smith").await?;
+    assert!(response.contains("Unblocked Alice Smith"));
+    let response = send_message(&pool, "+1234567890", "unblock Alice Smith").await?;
+    assert!(response.contains("isn't blocked"));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_blocklist(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    let limiter = RateLimiter::default();
+    assert_eq!(
+        screen(&pool, &limiter, "+1234567890").await?,
+        Decision::Allow
+    );
+    query!("INSERT INTO blocklist (number, reason) VALUES ('+1234567890', 'harassment')")
+        .execute(&pool)
+        .await?;
+    assert_eq!(
+        screen(&pool, &limiter, "+1234567890").await?,
+        Decision::Drop
+    );
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_export(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    config::use_test_config(config::Config {
+        public_url: Some("https://bot.example.com/".to_string()),
+        ..Default::default()
+    });
+
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    add_contact(&pool, "+1234567890", "Alice Smith", "+19876543210").await?;
+    add_contact(&pool, "+19876543210", "Johnny", "+1234567890").await?;
+
+    let response = send_message(&pool, "+1234567890", "export").await?;
+    assert!(response.contains("https://bot.example.com/export/"));
+
+    let export = query!("SELECT token, body FROM exports WHERE number = '+1234567890'")
+        .fetch_one(&pool)
+        .await?;
+    assert!(response.contains(&export.token));
+    let body: serde_json::Value = serde_json::from_str(&export.body)?;
+    assert_eq!(body["user"]["name"], "John Doe");
+    assert_eq!(body["contacts"][0]["number"], "+19876543210");
+    assert_eq!(body["saved_by_others_as"][0], "Johnny");
+
+    let served = export::serve_export(
+        Extension(Tenants::new(vec![tenant(&pool)])),
+        Path(export.token),
+    )
+    .await
+    .into_response();
+    assert_eq!(served.status(), StatusCode::OK);
+    let missing = export::serve_export(
+        Extension(Tenants::new(vec![tenant(&pool)])),
+        Path("nope".to_string()),
+    )
+    .await
+    .into_response();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_repeated_webhook(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    let tenants = Tenants::new(vec![tenant(&pool)]);
+    let deliver = |sid: &str, body: &str| {
+        handle_incoming_sms(
+            Extension(tenants.clone()),
+            Form(SmsMessage {
+                From: "+1234567890".to_string(),
+                Body: body.to_string(),
+                MessageSid: Some(sid.to_string()),
+                ..Default::default()
+            }),
+        )
+    };
+
+    let first = deliver("SM1", "name John Doe").await.0;
+    assert!(first.contains("Hello, John Doe!"));
+    let renamed = deliver("SM2", "name Jane Doe").await.0;
+    assert!(renamed.contains("updated to \"Jane Doe\""));
+
+    // Twilio retrying the first message gets the same reply without it being handled again
+    assert_eq!(deliver("SM1", "name John Doe").await.0, first);
+    let user = query_as!(
+        User,
+        "SELECT number, name, lang, timezone FROM users WHERE number = ?",
+        "+1234567890"
+    )
+    .fetch_one(&pool)
+    .await?;
+    assert_eq!(user.name, "Jane Doe");
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_non_phone_senders(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    let tenants = Tenants::new(vec![tenant(&pool)]);
+    for sender in ["12345", "ACME", "AMAZON1234"] {
+        let response = handle_incoming_sms(
+            Extension(tenants.clone()),
+            Form(SmsMessage {
+                From: sender.to_string(),
+                Body: "name Spam".to_string(),
+                MessageSid: Some(format!("SM{sender}")),
+                ..Default::default()
+            }),
+        )
+        .await
+        .0;
+        assert!(!response.contains("<Message>"), "{sender}: {response}");
+    }
+
+    // Nobody was signed up and nothing was logged
+    let users = query!("SELECT COUNT(*) as count FROM users")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(users.count, 0);
+    let logged = query!("SELECT COUNT(*) as count FROM message_log")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(logged.count, 0);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_audit_log(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    add_contact(&pool, "+1234567890", "Alice Smith", "+19876543210").await?;
+    send_message(&pool, "+1234567890", "delete alice").await?;
+    send_message(&pool, "+1234567890", "confirm 1").await?;
+
+    let entries =
+        query!("SELECT kind, detail FROM audit_log WHERE number = '+1234567890' ORDER BY id")
+            .fetch_all(&pool)
+            .await?
+            .into_iter()
+            .map(|e| format!("{}: {}", e.kind, e.detail))
+            .collect::<Vec<_>>();
+    assert_eq!(
+        entries,
+        [
+            "message: name John Doe",
+            "command: name",
+            "mutation: registered as \"John Doe\"",
+            "mutation: created user +19876543210 for a new contact",
+            "mutation: added contact \"Alice Smith\" (+19876543210)",
+            "message: delete alice",
+            "command: delete",
+            "message: confirm 1",
+            "command: confirm",
+            "mutation: deleted contact \"Alice Smith\" (+19876543210)",
+        ]
+    );
+
+    // Entries can't be rewritten
+    assert!(query!("UPDATE audit_log SET detail = 'nothing happened'")
+        .execute(&pool)
+        .await
+        .is_err());
+    assert!(query!("DELETE FROM audit_log")
+        .execute(&pool)
+        .await
+        .is_err());
+
+    // Except that deleting an account takes its history with it, leaving only a note that an
+    // account went
+    send_message(&pool, "+19876543210", "name Alice").await?;
+    send_message(&pool, "+1234567890", "stop purge").await?;
+    let entries = query!("SELECT number, detail FROM audit_log ORDER BY id")
+        .fetch_all(&pool)
+        .await?;
+    assert!(entries
+        .iter()
+        .all(|e| e.number != "+1234567890" && !e.detail.contains("John Doe")));
+    assert!(entries.iter().any(|e| e.number == "+19876543210"));
+    let last = entries.last().unwrap();
+    assert_eq!(last.number, pii::fingerprint("+1234567890"));
+    assert!(
+        last.detail.starts_with("deleted account"),
+        "{}",
+        last.detail
+    );
+    // And only then
+    assert!(query!("DELETE FROM audit_log")
+        .execute(&pool)
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+/// Raw contents of the columns holding personal data, for checking what is readable without the key
+async fn raw_values(pool: &Pool<Sqlite>) -> Result<Vec<String>> {
+    let mut values = Vec::new();
+    for sql in [
+        "SELECT number || ' ' || name FROM users",
+        "SELECT submitter_number || ' ' || contact_name || ' ' || contact_user_number FROM contacts",
+        "SELECT member_number FROM group_members",
+        "SELECT number || ' ' || detail FROM audit_log",
+    ] {
+        values.extend(sqlx::query_scalar::<_, String>(sql).fetch_all(pool).await?);
+    }
+    Ok(values)
+}
+
+#[sqlx::test]
+async fn test_encryption_at_rest(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    pii::use_test_key();
+
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    let vcard = "BEGIN:VCARD\nVERSION:3.0\nFN:Alice Smith\nTEL:+19876543210\nEND:VCARD\n\
+        BEGIN:VCARD\nVERSION:3.0\nFN:Bob Jones\nTEL;TYPE=CELL:+15555555555\nTEL;TYPE=WORK:+15555555556\nEND:VCARD\n";
+    for card in ical::VcardParser::new(vcard.as_bytes()) {
+        process_vcard(&pool, &pii::seal("+1234567890"), card).await?;
+    }
+
+    // Everything still works from the user's point of view
+    let response = send_message(&pool, "+1234567890", "h").await?;
+    assert!(response.contains("1. Bob Jones"));
+    assert!(response.contains("a. +15555555555 (CELL)"));
+    let response = send_message(&pool, "+1234567890", "confirm 1b").await?;
+    assert!(response.contains("Bob Jones (+15555555556)"));
+    let response = send_message(&pool, "+1234567890", "contacts").await?;
+    assert!(
+        response.contains("1. Alice Smith (c1): (987) 654-3210"),
+        "{response}"
+    );
+    assert!(
+        response.contains("2. Bob Jones (c2): (555) 555-5556"),
+        "{response}"
+    );
+    let response = send_message(&pool, "+1234567890", "group smith, bob").await?;
+    assert!(response.contains("1. Alice Smith"));
+    assert!(response.contains("2. Bob Jones"));
+    let response = send_message(&pool, "+1234567890", "confirm 2").await?;
+    assert!(response.contains("• Bob Jones (555)"));
+    let response = send_message(&pool, "+1234567890", "block alice").await?;
+    assert!(response.contains("Blocked Alice Smith"));
+    let response = send_message(&pool, "+1234567890", "block").await?;
+    assert!(response.contains("Alice Smith (+19876543210)"));
+
+    // But nothing identifying is readable in the database
+    let values = raw_values(&pool).await?;
+    assert!(!values.is_empty());
+    for value in values {
+        for plaintext in ["John", "Alice", "Bob", "1234567890", "9876543210", "555555"] {
+            assert!(!value.contains(plaintext), "{plaintext} leaked in {value}");
+        }
+    }
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_seal_existing(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    add_contact(&pool, "+1234567890", "Alice Smith", "+19876543210").await?;
+    send_message(&pool, "+1234567890", "group alice").await?;
+    send_message(&pool, "+1234567890", "confirm 1").await?;
+
+    // A key is configured on a database that was written without one
+    pii::use_test_key();
+    pii::seal_existing(&pool).await?;
+
+    let response = send_message(&pool, "+1234567890", "contacts").await?;
+    assert!(response.contains("group0 (1 members)"));
+    assert!(
+        response.contains("Alice Smith (c1): (987) 654-3210"),
+        "{response}"
+    );
+    for value in sqlx::query_scalar::<_, String>(
+        "SELECT number || ' ' || name FROM users
+         UNION ALL SELECT contact_name || ' ' || contact_user_number FROM contacts
+         UNION ALL SELECT member_number FROM group_members",
+    )
+    .fetch_all(&pool)
+    .await?
+    {
+        assert!(!value.contains("Alice") && !value.contains("1234567890"));
+    }
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_jobs(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    query!(
+        "INSERT INTO exports (token, number, body, expires_at)
+         VALUES ('old', '+1234567890', '{}', unixepoch() - 1)"
+    )
+    .execute(&pool)
+    .await?;
+
+    // Nothing queued yet
+    assert!(!jobs::run_next(&tenant(&pool)).await?);
+
+    jobs::ensure_scheduled(&pool, &jobs::Job::Cleanup).await?;
+    jobs::ensure_scheduled(&pool, &jobs::Job::Cleanup).await?;
+    assert!(jobs::run_next(&tenant(&pool)).await?);
+    let exports = query!("SELECT COUNT(*) as count FROM exports")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(exports.count, 0);
+
+    // The cleanup rescheduled itself for later, and wasn't queued twice
+    assert!(!jobs::run_next(&tenant(&pool)).await?);
+    let pending = query!("SELECT COUNT(*) as count FROM jobs WHERE status = 'pending'")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(pending.count, 1);
+
+    // Failures are retried later, until they run out of attempts
+    query!("INSERT INTO jobs (payload, max_attempts) VALUES ('{\"type\":\"unknown\"}', 2)")
+        .execute(&pool)
+        .await?;
+    assert!(jobs::run_next(&tenant(&pool)).await?);
+    let job = query!("SELECT status, attempts, last_error FROM jobs WHERE max_attempts = 2")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!((job.status.as_str(), job.attempts), ("pending", 1));
+    assert!(job.last_error.is_some());
+    assert!(!jobs::run_next(&tenant(&pool)).await?);
+
+    query!("UPDATE jobs SET run_at = unixepoch() WHERE max_attempts = 2")
+        .execute(&pool)
+        .await?;
+    assert!(jobs::run_next(&tenant(&pool)).await?);
+    let job = query!("SELECT status FROM jobs WHERE max_attempts = 2")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(job.status, "failed");
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_worker_shutdown(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    let (stop, shutdown) = shutdown::channel();
+    let worker = tokio::spawn(jobs::worker(tenant(&pool), shutdown));
+    // Idle, so it's waiting to poll again, which shutting down cuts short
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    stop.send_replace(true);
+    tokio::time::timeout(std::time::Duration::from_secs(1), worker).await??;
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_backup(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+
+    let dir = std::env::temp_dir().join(format!("decisionbot-test-{}", rand::random::<u64>()));
+    let path = backup::snapshot(&pool, &dir).await?;
+
+    // The snapshot is a working database in its own right
+    let restored = sqlx::SqlitePool::connect(&format!("sqlite:{}", path.display())).await?;
+    let user = query_as!(
+        User,
+        "SELECT number, name, lang, timezone FROM users WHERE number = ?",
+        "+1234567890"
+    )
+    .fetch_one(&restored)
+    .await?;
+    assert_eq!(user.name, "John Doe");
+    restored.close().await;
+
+    // Only the newest snapshots are kept
+    for name in ["decisionbot-20000101T000000Z.sqlite3", "unrelated.txt"] {
+        std::fs::write(dir.join(name), "")?;
+    }
+    assert_eq!(backup::prune(&dir, 1)?, 1);
+    assert!(path.exists());
+    assert!(dir.join("unrelated.txt").exists());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_trash(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    for (name, number) in [
+        ("Alice Smith", "+19876543210"),
+        ("Bob Jones", "+15555555555"),
+    ] {
+        let vcard = format!("BEGIN:VCARD\nVERSION:3.0\nFN:{name}\nTEL:{number}\nEND:VCARD\n");
+        let mut reader = ical::VcardParser::new(vcard.as_bytes());
+        process_vcard(&pool, "+1234567890", reader.next().unwrap()).await?;
+    }
+
+    let response = send_message(&pool, "+1234567890", "trash").await?;
+    assert!(response.contains("You haven't deleted any contacts"));
+
+    send_message(&pool, "+1234567890", "delete Alice").await?;
+    let response = send_message(&pool, "+1234567890", "confirm 1").await?;
+    assert!(response.contains("Deleted 1 contact"));
+    assert!(response.contains("can be restored for 30 days with \"trash\""));
+    let response = send_message(&pool, "+1234567890", "contacts").await?;
+    assert!(!response.contains("Alice Smith"));
+    assert!(send_message(&pool, "+1234567890", "delete Alice")
+        .await?
+        .contains("No groups or contacts found matching"));
+
+    let response = send_message(&pool, "+1234567890", "trash").await?;
+    assert!(response.contains("1. Alice Smith (987)"));
+    assert!(!response.contains("Bob Jones"));
+
+    let response = send_message(&pool, "+1234567890", "restore 1, 2").await?;
+    assert!(response.contains("Restored 1 contact"));
+    assert!(response.contains("Invalid selection: 2"));
+    let response = send_message(&pool, "+1234567890", "contacts").await?;
+    assert!(response.contains("Alice Smith"));
+
+    // Re-adding a contact that's in the trash brings it back under the new name
+    send_message(&pool, "+1234567890", "delete Alice").await?;
+    send_message(&pool, "+1234567890", "confirm 1").await?;
+    let vcard = "BEGIN:VCARD\nVERSION:3.0\nFN:Alice Cooper\nTEL:+19876543210\nEND:VCARD\n";
+    let mut reader = ical::VcardParser::new(vcard.as_bytes());
+    let result = process_vcard(&pool, "+1234567890", reader.next().unwrap()).await?;
+    assert!(matches!(result, ImportResult::Added));
+    let response = send_message(&pool, "+1234567890", "contacts").await?;
+    assert!(response.contains("Alice Cooper"));
+
+    // Contacts are purged once they've been in the trash long enough
+    send_message(&pool, "+1234567890", "delete Bob").await?;
+    send_message(&pool, "+1234567890", "confirm 1").await?;
+    query!("UPDATE contacts SET deleted_at = deleted_at - 31 * 24 * 60 * 60 WHERE deleted_at IS NOT NULL")
+        .execute(&pool)
+        .await?;
+    jobs::enqueue(&pool, &jobs::Job::Cleanup).await?;
+    assert!(jobs::run_next(&tenant(&pool)).await?);
+    let response = send_message(&pool, "+1234567890", "trash").await?;
+    assert!(response.contains("You haven't deleted any contacts"));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_recent_contacts(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    let import = |name: &str, number: &str| {
+        let vcard = format!("BEGIN:VCARD\nVERSION:3.0\nFN:{name}\nTEL:{number}\nEND:VCARD\n");
+        let mut reader = ical::VcardParser::new(vcard.as_bytes());
+        let card = reader.next().unwrap();
+        let pool = pool.clone();
+        async move { process_vcard(&pool, "+1234567890", card).await }
+    };
+
+    import("Alice Smith", "+19876543210").await?;
+    import("Bob Jones", "+15555555555").await?;
+    let user = query!("SELECT created_at, updated_at FROM users WHERE number = '+1234567890'")
+        .fetch_one(&pool)
+        .await?;
+    assert!(user.created_at.is_some() && user.created_at == user.updated_at);
+
+    // Pretend both were added yesterday, then rename Alice today
+    query!("UPDATE contacts SET created_at = created_at - 86400, updated_at = updated_at - 86400")
+        .execute(&pool)
+        .await?;
+    import("Alice Cooper", "+19876543210").await?;
+
+    let response = send_message(&pool, "+1234567890", "contacts recent").await?;
+    let alice = response
+        .find("1. Alice Cooper (c1): (987) 654-3210, changed")
+        .unwrap();
+    let bob = response
+        .find("2. Bob Jones (c2): (555) 555-5555, added")
+        .unwrap();
+    assert!(alice < bob);
+
+    // Without "recent" the list is alphabetical as before
+    let response = send_message(&pool, "+1234567890", "contacts").await?;
+    assert!(response.find("Alice Cooper").unwrap() < response.find("Bob Jones").unwrap());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_contact_search(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    for (name, number) in [
+        ("Joanna Lee", "+19876543210"),
+        ("Zoe Ann", "+15555555555"),
+        ("José Núñez", "+14155550100"),
+    ] {
+        let vcard = format!("BEGIN:VCARD\nVERSION:3.0\nFN:{name}\nTEL:{number}\nEND:VCARD\n");
+        let mut reader = ical::VcardParser::new(vcard.as_bytes());
+        process_vcard(&pool, "+1234567890", reader.next().unwrap()).await?;
+    }
+
+    // Accents and case don't matter
+    let response = send_message(&pool, "+1234567890", "delete JOSE nunez").await?;
+    assert!(response.contains("1. José Núñez"));
+    send_message(&pool, "+1234567890", "nevermind").await?;
+
+    // A name starting with what was typed ranks above one merely containing it,
+    // and confirming goes by the order shown
+    let response = send_message(&pool, "+1234567890", "delete ann").await?;
+    assert!(response.contains("1. Zoe Ann (555)"));
+    assert!(response.contains("2. Joanna Lee (987)"));
+    let response = send_message(&pool, "+1234567890", "h").await?;
+    assert!(response.contains("1. Zoe Ann (555)"));
+    let response = send_message(&pool, "+1234567890", "confirm 1").await?;
+    assert!(response.contains("Zoe Ann"));
+    assert!(!response.contains("Joanna Lee"));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_remind(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    add_contact(&pool, "+1234567890", "Alice Smith", "+19876543210").await?;
+    add_contact(&pool, "+1234567890", "Bob Wilson", "+19876543211").await?;
+    send_message(&pool, "+19876543210", "name Alice").await?;
+    send_message(&pool, "+19876543210", "language es").await?;
+    send_message(&pool, "+1234567890", "group Alice, Bob").await?;
+    send_message(&pool, "+1234567890", "confirm 1,2").await?;
+
+    let response = send_message(&pool, "+1234567890", "remind").await?;
+    assert!(response.contains("You don't have any reminders scheduled"));
+
+    let response = send_message(&pool, "+1234567890", "remind me someday: call mom").await?;
+    assert!(response.contains("Couldn't understand the time \"someday\""));
+    let response = send_message(&pool, "+1234567890", "remind me in 99999999999 days: x").await?;
+    assert!(
+        response.contains("Couldn't understand the time"),
+        "{response}"
+    );
+    let response = send_message(&pool, "+1234567890", "remind carol tomorrow: hi").await?;
+    assert!(response.contains("\"carol\" isn't you"));
+    let response = send_message(&pool, "+1234567890", "remind tomorrow 9am").await?;
+    assert!(response.contains("Reply \"remind X\""));
+
+    let response = send_message(
+        &pool,
+        "+1234567890",
+        "remind group0 tomorrow 9:30pm: vote on the dinner poll",
+    )
+    .await?;
+    assert!(response.contains("Reminder for group0 scheduled for"));
+    assert!(response.contains("21:30 UTC"));
+    send_message(
+        &pool,
+        "+1234567890",
+        "remind alice in 2 hours: bring chairs",
+    )
+    .await?;
+    send_message(&pool, "+1234567890", "remind me in 10 minutes: call mom").await?;
+
+    let response = send_message(&pool, "+1234567890", "remind").await?;
+    assert!(response.contains("1. "));
+    assert!(response.contains(", for me: call mom\n2. "));
+    assert!(response.contains(", for Alice Smith: bring chairs\n3. "));
+    assert!(response.contains(", for group0: vote on the dinner poll"));
+
+    let response = send_message(&pool, "+1234567890", "remind cancel 2").await?;
+    assert!(response.contains("Cancelled the reminder for Alice Smith: bring chairs"));
+    let response = send_message(&pool, "+1234567890", "remind cancel 3").await?;
+    assert!(response.contains("Invalid selection: 3"));
+
+    // Each reminder is sent by a job due at its time
+    let reminders = query!("SELECT id as \"id!\", status FROM scheduled_messages ORDER BY id")
+        .fetch_all(&pool)
+        .await?;
+    let due = query!(
+        "SELECT payload, run_at - unixepoch() as \"delay!: i64\" FROM jobs
+         WHERE payload LIKE '%reminder%' ORDER BY id"
+    )
+    .fetch_all(&pool)
+    .await?;
+    assert_eq!(due.len(), 3);
+    assert!((7190..=7200).contains(&due[1].delay));
+    assert_eq!(
+        serde_json::from_str::<jobs::Job>(&due[1].payload)?,
+        jobs::Job::Reminder {
+            id: reminders[1].id
+        }
+    );
+    assert_eq!(reminders[1].status, "cancelled");
+
+    // Contacts who haven't agreed to messages from others are asked instead, once
+    let messages = remind::messages(&pool, reminders[0].id).await?;
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0]
+        .body
+        .starts_with("John Doe quiere enviarte mensajes"));
+    assert!(messages[1]
+        .body
+        .starts_with("John Doe wants to send you messages"));
+    let response = send_message(&pool, "+19876543210", "Sí").await?;
+    assert!(response.contains("¡Gracias!"));
+    let response = send_message(&pool, "+19876543211", "no").await?;
+    assert!(response.contains("you won't get messages from other people"));
+
+    // In each recipient's language, leaving out anyone who's blocked the sender
+    let messages = remind::messages(&pool, reminders[0].id).await?;
+    assert_eq!(
+        messages,
+        vec![Outgoing {
+            to: "+19876543210".to_string(),
+            body: "Recordatorio de John Doe: vote on the dinner poll".to_string(),
+        }]
+    );
+    query!(
+        "INSERT INTO blocks (blocker_number, blocked_number) VALUES (?, ?)",
+        "+19876543210",
+        "+1234567890"
+    )
+    .execute(&pool)
+    .await?;
+    assert!(remind::messages(&pool, reminders[0].id).await?.is_empty());
+    // Until they change their mind
+    send_message(&pool, "+19876543211", "yes").await?;
+    assert_eq!(remind::messages(&pool, reminders[0].id).await?.len(), 1);
+    assert_eq!(
+        remind::messages(&pool, reminders[2].id).await?,
+        vec![Outgoing {
+            to: "+1234567890".to_string(),
+            body: "Reminder: call mom".to_string(),
+        }]
+    );
+    // Cancelled reminders aren't sent
+    assert!(remind::messages(&pool, reminders[1].id).await?.is_empty());
+    remind::deliver(&pool, "+15550000000", reminders[1].id).await?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_decide(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    add_contact(&pool, "+1234567890", "Alice Smith", "+19876543210").await?;
+    add_contact(&pool, "+1234567890", "Bob Wilson", "+19876543211").await?;
+    send_message(&pool, "+1234567890", "group Alice, Bob").await?;
+    send_message(&pool, "+1234567890", "confirm 1,2").await?;
+
+    let response = send_message(&pool, "+1234567890", "decide").await?;
+    assert!(response.contains("You haven't had me decide anything yet"));
+    let response = send_message(&pool, "+1234567890", "decide pizza").await?;
+    assert!(response.contains("Reply \"decide X\""));
+
+    let response = send_message(&pool, "+1234567890", "decide pizza x3, tacos").await?;
+    assert!(
+        response == "I pick: pizza (out of 2)" || response == "I pick: tacos (out of 2)",
+        "{response}"
+    );
+    let response = send_message(&pool, "+1234567890", "Decide among Group0").await?;
+    assert!(response.contains("Alice Smith") || response.contains("Bob Wilson"));
+    let response = send_message(&pool, "+1234567890", "decide among alice, bob").await?;
+    assert!(response.contains("(out of 2)"));
+    let response = send_message(&pool, "+1234567890", "decide among carol").await?;
+    assert!(response.contains("\"carol\" isn't one of your groups or contacts"));
+
+    let response = send_message(&pool, "+1234567890", "decide").await?;
+    assert!(response.starts_with("Your recent picks:\n1. "));
+    assert!(response.contains("(from Alice Smith, Bob Wilson)\n2. "));
+    assert!(response.contains("(from pizza x3, tacos)"));
+    assert!(!response.ends_with('\n'));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_events(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    add_contact(&pool, "+1234567890", "Alice Smith", "+19876543210").await?;
+    add_contact(&pool, "+1234567890", "Bob Wilson", "+19876543211").await?;
+    send_message(&pool, "+19876543210", "name Alice").await?;
+    send_message(&pool, "+19876543210", "language es").await?;
+    send_message(&pool, "+1234567890", "group Alice, Bob").await?;
+    send_message(&pool, "+1234567890", "confirm 1,2").await?;
+
+    let response = send_message(&pool, "+1234567890", "event").await?;
+    assert!(response.contains("You don't have any upcoming events"));
+    let response = send_message(&pool, "+1234567890", "event carol tomorrow: hi").await?;
+    assert!(response.contains("\"carol\" isn't one of your groups"));
+    let response = send_message(&pool, "+1234567890", "event group0 someday: hi").await?;
+    assert!(response.contains("Couldn't understand the time \"someday\""));
+
+    let response = send_message(
+        &pool,
+        "+1234567890",
+        "event group0 tomorrow 7pm: dinner at my place",
+    )
+    .await?;
+    assert!(response.contains("Inviting group0 to \"dinner at my place\" on "));
+    assert!(response.contains("19:00 UTC"));
+
+    // Invitations go out right away, and non-responders are reminded halfway there
+    let id = query!("SELECT id as \"id!\" FROM events")
+        .fetch_one(&pool)
+        .await?
+        .id;
+    let due = query!(
+        "SELECT payload, run_at - unixepoch() as \"delay!: i64\" FROM jobs
+         WHERE payload LIKE '%event%' ORDER BY id"
+    )
+    .fetch_all(&pool)
+    .await?;
+    assert_eq!(
+        serde_json::from_str::<jobs::Job>(&due[0].payload)?,
+        jobs::Job::EventInvitations { id }
+    );
+    assert_eq!(due[0].delay, 0);
+    assert_eq!(
+        serde_json::from_str::<jobs::Job>(&due[1].payload)?,
+        jobs::Job::EventReminder { id }
+    );
+    assert!(due[1].delay > 0 && due[1].delay <= 24 * 60 * 60);
+
+    // Members who haven't agreed to messages from others are asked instead
+    let messages = events::invitations(&pool, id).await?;
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0]
+        .body
+        .starts_with("John Doe quiere enviarte mensajes"));
+    send_message(&pool, "+19876543210", "Sí").await?;
+    send_message(&pool, "+19876543211", "no").await?;
+    let messages = events::invitations(&pool, id).await?;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].to, "+19876543210");
+    assert!(messages[0]
+        .body
+        .starts_with("John Doe te invita a \"dinner at my place\" el "));
+    assert!(events::reminders(&pool, id).await?[0]
+        .body
+        .starts_with("John Doe aún espera saber si vienes"));
+
+    let response = send_message(&pool, "+1234567890", "event").await?;
+    assert!(response.contains("dinner at my place (group0): 0 yes, 0 maybe, 0 no, 1 waiting"));
+    let response = send_message(&pool, "+19876543210", "event").await?;
+    assert!(response.contains("dinner at my place (de John Doe): sin responder"));
+
+    let response = send_message(&pool, "+19876543210", "rsvp").await?;
+    assert!(response.contains("rsvp"));
+    let response = send_message(&pool, "+19876543210", "rsvp 2 sí").await?;
+    assert!(response.contains("Selección no válida: 2"));
+    let response = send_message(&pool, "+19876543210", "rsvp quizás").await?;
+    assert_eq!(response, "Anotado: quizás a \"dinner at my place\".");
+    assert!(events::reminders(&pool, id).await?.is_empty());
+    let response = send_message(&pool, "+1234567890", "rsvp yes").await?;
+    assert!(response.contains("You don't have any invitations to answer"));
+    let response = send_message(&pool, "+1234567890", "rsvp 1 yes").await?;
+    assert!(response.contains("Event 1 is yours, not an invitation"));
+
+    // Who's coming, by the names the organizer knows them by
+    let response = send_message(&pool, "+1234567890", "event 1").await?;
+    assert!(response.contains("Yes: -\nMaybe: Alice Smith\nNo: -\nWaiting: -"));
+    let response = send_message(&pool, "+19876543210", "event 1").await?;
+    assert!(response.contains("0 vienen, 1 quizás. Tu respuesta: quizás"));
+
+    // Anyone who's blocked the organizer isn't invited
+    query!(
+        "INSERT INTO blocks (blocker_number, blocked_number) VALUES (?, ?)",
+        "+19876543210",
+        "+1234567890"
+    )
+    .execute(&pool)
+    .await?;
+    assert!(events::invitations(&pool, id).await?.is_empty());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_rotation(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    add_contact(&pool, "+1234567890", "Alice Smith", "+19876543210").await?;
+    send_message(&pool, "+19876543210", "name Alice").await?;
+    send_message(&pool, "+19876543210", "language es").await?;
+
+    let response = send_message(&pool, "+1234567890", "rotation").await?;
+    assert!(response.contains("You don't have any rotations"));
+    let response = send_message(&pool, "+1234567890", "rotation create dishes: me").await?;
+    assert!(response.contains("Reply \"rotation X\""));
+
+    let response = send_message(
+        &pool,
+        "+1234567890",
+        "rotation create dishes: me, alice, Jo",
+    )
+    .await?;
+    assert!(response.starts_with(
+        "Created rotation \"dishes\": John Doe, Alice Smith, Jo. It's John Doe's turn first."
+    ));
+    let response = send_message(&pool, "+1234567890", "rotation create Dishes: a, b").await?;
+    assert!(response.contains("You already have a rotation called \"Dishes\""));
+    let id = query!("SELECT id as \"id!\" FROM rotations")
+        .fetch_one(&pool)
+        .await?
+        .id;
+    assert_eq!(
+        rotation::turn_message(&pool, id, 0).await?,
+        Some(Outgoing {
+            to: "+1234567890".to_string(),
+            body: "It's your turn: dishes. Alice Smith is next.".to_string(),
+        })
+    );
+
+    let response = send_message(&pool, "+1234567890", "rotation next dishes").await?;
+    assert_eq!(response, "dishes: it's Alice Smith's turn, then Jo.");
+    // Only whoever has the turn now is told, after agreeing to messages from others
+    assert_eq!(rotation::turn_message(&pool, id, 0).await?, None);
+    assert!(rotation::turn_message(&pool, id, 1)
+        .await?
+        .unwrap()
+        .body
+        .starts_with("John Doe quiere enviarte mensajes"));
+    send_message(&pool, "+19876543210", "Sí").await?;
+    assert_eq!(
+        rotation::turn_message(&pool, id, 1).await?.unwrap().body,
+        "John Doe dice que te toca: dishes. Después le toca a Jo."
+    );
+    // Anyone who isn't a contact is just a name
+    send_message(&pool, "+1234567890", "rotation next dishes").await?;
+    assert_eq!(rotation::turn_message(&pool, id, 2).await?, None);
+
+    // Moving on by itself
+    let response = send_message(&pool, "+1234567890", "rotation every dishes soon").await?;
+    assert!(response.contains("Couldn't understand \"soon\""));
+    let response = send_message(&pool, "+1234567890", "rotation every dishes weekly").await?;
+    assert!(response.contains("\"dishes\" will move on by itself every 7 day(s)"));
+    let due = query!(
+        "SELECT payload, run_at - unixepoch() as \"delay!: i64\" FROM jobs
+         WHERE payload LIKE '%rotation_advance%'"
+    )
+    .fetch_one(&pool)
+    .await?;
+    assert_eq!(
+        serde_json::from_str::<jobs::Job>(&due.payload)?,
+        jobs::Job::RotationAdvance {
+            id,
+            turn: 2,
+            days: 7
+        }
+    );
+    assert!((7 * 24 * 60 * 60 - 10..=7 * 24 * 60 * 60).contains(&due.delay));
+    rotation::advance_scheduled(&pool, id, 2, 7).await?;
+    // Already moved on, so a second run does nothing
+    rotation::advance_scheduled(&pool, id, 2, 7).await?;
+    let response = send_message(&pool, "+1234567890", "rotation Dishes").await?;
+    assert_eq!(
+        response,
+        "dishes: it's John Doe's turn, then Alice Smith.\nOrder: John Doe, Alice Smith, Jo\nMoves on every 7 day(s)."
+    );
+    send_message(&pool, "+1234567890", "rotation every dishes off").await?;
+    rotation::advance_scheduled(&pool, id, 3, 7).await?;
+
+    let response = send_message(&pool, "+1234567890", "rotation").await?;
+    assert_eq!(
+        response,
+        "Your rotations:\ndishes: John Doe, then Alice Smith"
+    );
+    let response = send_message(&pool, "+1234567890", "rotation delete dishes").await?;
+    assert!(response.contains("Deleted rotation \"dishes\""));
+    let response = send_message(&pool, "+1234567890", "rotation next dishes").await?;
+    assert!(response.contains("\"dishes\" isn't one of your rotations"));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_timezone(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+
+    let response = send_message(&pool, "+1234567890", "timezone").await?;
+    assert!(response.contains("Your time zone is UTC"));
+    let response = send_message(&pool, "+1234567890", "timezone Narnia").await?;
+    assert!(response.contains("Unknown time zone \"Narnia\""));
+    let response = send_message(&pool, "+1234567890", "timezone pst").await?;
+    assert!(response.contains("Your time zone is now America/Los_Angeles"));
+    let response = send_message(&pool, "+1234567890", "timezone").await?;
+    assert!(response.contains("Your time zone is America/Los_Angeles"));
+
+    // Times are read and shown in the user's zone
+    let response = send_message(&pool, "+1234567890", "remind me tomorrow 9am: stretch").await?;
+    assert!(response.contains(" 09:00 P"));
+    let offset =
+        query!("SELECT (send_at % 86400) / 3600 as \"hour!: i64\" FROM scheduled_messages")
+            .fetch_one(&pool)
+            .await?;
+    // 9am Pacific is 16:00 or 17:00 UTC, depending on daylight saving time
+    assert!([16, 17].contains(&offset.hour));
+
+    send_message(&pool, "+1234567890", "timezone Asia/Tokyo").await?;
+    let response = send_message(&pool, "+1234567890", "remind").await?;
+    assert!(response.contains(":00 JST, for me: stretch"));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_simulate(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    let tenants = Tenants::new(vec![tenant(&pool)]);
+    let simulate = |from: &str, body: &str| {
+        simulate::handle_simulate(
+            Extension(tenants.clone()),
+            Query(simulate::Sender {
+                from: from.to_string(),
+                to: None,
+            }),
+            body.to_string(),
+        )
+    };
+
+    // Plain text back, not TwiML
+    let (status, response) = simulate(" 1234567890", "name John Doe").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(response.starts_with("Hello, John Doe!"));
+    let (_, response) = simulate("(123) 456-7890", "language").await;
+    assert!(response.contains("Your language is English"));
+
+    let (status, _) = simulate("12345", "name John Doe").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_api_tokens(pool: Pool<Sqlite>) -> Result<()> {
+    use api_tokens::Scope;
+    let bearer = |token: &str| {
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(
+            axum::http::header::AUTHORIZATION,
+            format!("Bearer {token}").parse().unwrap(),
+        );
+        headers
+    };
+
+    let read = api_tokens::issue(&pool, "dashboard", Scope::Read).await?;
+    let admin = api_tokens::issue(&pool, "scripts", Scope::Admin).await?;
+    assert!(read.starts_with("dbt_") && read != admin);
+    assert!(api_tokens::issue(&pool, "dashboard", Scope::Admin)
+        .await
+        .is_err());
+    // Only the hash is stored
+    let stored = query!("SELECT token_hash, last_used_at FROM api_tokens WHERE name = 'dashboard'")
+        .fetch_one(&pool)
+        .await?;
+    assert!(!stored.token_hash.contains(&read[4..]));
+    assert_eq!(stored.last_used_at, None);
+
+    let headers = axum::http::HeaderMap::new();
+    assert_eq!(api_tokens::scope(&pool, &headers).await?, None);
+    assert_eq!(api_tokens::scope(&pool, &bearer("dbt_nope")).await?, None);
+    assert_eq!(
+        api_tokens::scope(&pool, &bearer(&read)).await?,
+        Some(Scope::Read)
+    );
+    assert_eq!(
+        api_tokens::scope(&pool, &bearer(&admin)).await?,
+        Some(Scope::Admin)
+    );
+    assert!(Scope::Admin >= Scope::Read && Scope::Read < Scope::Admin);
+    assert!(
+        query!("SELECT last_used_at FROM api_tokens WHERE name = 'dashboard'")
+            .fetch_one(&pool)
+            .await?
+            .last_used_at
+            .is_some()
+    );
+
+    assert!(api_tokens::revoke(&pool, "dashboard").await?);
+    assert!(!api_tokens::revoke(&pool, "dashboard").await?);
+    assert_eq!(api_tokens::scope(&pool, &bearer(&read)).await?, None);
+
+    // ADMIN_TOKEN still works, as a token with every scope
+    config::use_test_config(config::Config {
+        admin_token: Some("secret".to_string()),
+        ..Default::default()
+    });
+    assert_eq!(
+        api_tokens::scope(&pool, &bearer("secret")).await?,
+        Some(Scope::Admin)
+    );
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_stats(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    config::use_test_config(config::Config {
+        client_number: Some("+19876543299".to_string()),
+        simulate: true,
+        ..Default::default()
+    });
+    let text = |from: &str, body: &str| SmsMessage {
+        From: from.to_string(),
+        Body: body.to_string(),
+        ..Default::default()
+    };
+    respond(&tenant(&pool), text("+1234567890", "name John Doe")).await;
+    outbound::send_all(
+        &pool,
+        "+15550000000",
+        vec![Outgoing {
+            to: "+1234567890".to_string(),
+            body: "Hi".to_string(),
+        }],
+    )
+    .await?;
+
+    // From anyone else it's just a word
+    let response = send_message(&pool, "+1234567890", "stats").await?;
+    assert!(!response.contains("Users:"));
+    let response = respond(&tenant(&pool), text("+19876543299", "Stats"))
+        .await
+        .unwrap();
+    assert!(response.starts_with(
+        "Users: 1 (1 new today)\nLast 24h: 2 in, 2 out, 0 failed to send\n\
+         Spend (est.): $0.02 today, $0.02 this month\nPending reminders: 0\n"
+    ));
+    assert!(response.contains("\nDatabase: "));
+    assert!(response.contains("\nDropped from memory: "));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_budget(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    config::use_test_config(config::Config {
+        client_number: Some("+19876543299".to_string()),
+        simulate: true,
+        segment_price: 0.01,
+        monthly_budget: Some(0.05),
+        ..Default::default()
+    });
+    let sent = || async {
+        query!(r#"SELECT COUNT(*) as "count!: i64" FROM message_log"#)
+            .fetch_one(&pool)
+            .await
+    };
+    let text = |body: String| Outgoing {
+        to: "+1234567890".to_string(),
+        body,
+    };
+
+    // Long texts cost more than one segment
+    outbound::send_all(&pool, "+15550000000", vec![text("a".repeat(400))]).await?;
+    assert!((cost::spend(&pool).await?.month - 0.03).abs() < 1e-9);
+    cost::check_budget(&pool, "+15550000000").await?;
+    assert_eq!(sent().await?.count, 1);
+
+    // Passing the budget texts the operator, only the first time that month
+    outbound::send_all(&pool, "+15550000000", vec![text("a".repeat(300))]).await?;
+    cost::check_budget(&pool, "+15550000000").await?;
+    assert_eq!(sent().await?.count, 3);
+    cost::check_budget(&pool, "+15550000000").await?;
+    assert_eq!(sent().await?.count, 3);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_delivery(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    config::use_test_config(config::Config {
+        simulate: true,
+        ..Default::default()
+    });
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    add_contact(&pool, "+1234567890", "Alice Smith", "+19876543210").await?;
+    send_message(
+        &pool,
+        "+1234567890",
+        "remind alice tomorrow 9am: bring chairs",
+    )
+    .await?;
+    send_message(&pool, "+1234567890", "remind me tomorrow 9am: call mom").await?;
+    for reminder in query!(r#"SELECT id as "id!" FROM scheduled_messages"#)
+        .fetch_all(&pool)
+        .await?
+    {
+        remind::deliver(&pool, "+15550000000", reminder.id).await?;
+    }
+
+    // Only the text to someone else is tracked
+    let tracked = query!(r#"SELECT sid as "sid!" FROM tracked_messages"#)
+        .fetch_all(&pool)
+        .await?;
+    assert_eq!(tracked.len(), 1);
+    let notice = delivery::notice(&pool, &tracked[0].sid, Some("30006"))
+        .await?
+        .unwrap();
+    assert_eq!(notice.to, "+1234567890");
+    assert_eq!(
+        notice.body,
+        "Your message to Alice Smith wasn't delivered: it's a landline or can't get texts. \
+         You may want to reach them another way."
+    );
+    // Twilio may report the same failure more than once
+    assert_eq!(delivery::notice(&pool, &tracked[0].sid, None).await?, None);
+    assert_eq!(delivery::notice(&pool, "SMunknown", None).await?, None);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_undeliverable(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    config::use_test_config(config::Config {
+        simulate: true,
+        ..Default::default()
+    });
+    let pool = &pool;
+    let me = "+1234567890";
+    send_message(pool, me, "name John Doe").await?;
+    add_contact(pool, me, "Alice Smith", "+19876543210").await?;
+    consent::mark_requested(pool, &pii::seal("+19876543210")).await?;
+    send_message(pool, "+19876543210", "yes").await?;
+    let remind = |count| async move {
+        for _ in 0..count {
+            send_message(pool, me, "remind alice tomorrow 9am: bring chairs").await?;
+        }
+        for reminder in
+            query!(r#"SELECT id as "id!" FROM scheduled_messages WHERE status = 'pending'"#)
+                .fetch_all(pool)
+                .await?
+        {
+            remind::deliver(pool, "+15550000000", reminder.id).await?;
+        }
+        anyhow::Ok(
+            query!(r#"SELECT sid as "sid!" FROM tracked_messages ORDER BY sent_at, sid"#)
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .map(|row| row.sid)
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    // Failures that may not last, or that a delivery comes between, don't add up
+    let sids = remind(3).await?;
+    assert_eq!(sids.len(), 3);
+    delivery::notice(pool, &sids[0], Some("30003")).await?;
+    delivery::notice(pool, &sids[1], Some("30006")).await?;
+    delivery::delivered(pool, &sids[2]).await?;
+    let sids = remind(2).await?;
+    for sid in &sids {
+        let notice = delivery::notice(pool, sid, Some("30006")).await?.unwrap();
+        assert!(!notice.body.contains("won't get any more texts"));
+    }
+
+    // The third permanent failure in a row gives up on the number
+    let sids = remind(1).await?;
+    let notice = delivery::notice(pool, &sids[0], Some("30005"))
+        .await?
+        .unwrap();
+    assert!(
+        notice.body.ends_with(
+            "That's 3 times in a row, so Alice Smith won't get any more texts from you here \
+             until the number is fixed."
+        ),
+        "{}",
+        notice.body
+    );
+    assert!(remind(1).await?.is_empty());
+    let response = send_message(pool, me, "contacts").await?;
+    assert!(
+        response.contains("Alice Smith (c1): (987) 654-3210 (texts to it keep failing)"),
+        "{response}"
+    );
+
+    // Until the number texts the bot
+    send_message(pool, "+19876543210", "hi").await?;
+    assert_eq!(remind(1).await?.len(), 1);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_line_types(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    add_contact(&pool, "+1234567890", "Alice Smith", "+19876543210").await?;
+    add_contact(&pool, "+1234567890", "Bob Wilson", "+19876543211").await?;
+    // Lookups are off in tests, so nothing's known until it's filled in
+    let sealed = pii::seal("+19876543210");
+    assert_eq!(lookup::known(&pool, &sealed).await?, None);
+    query!(
+        "UPDATE users SET line_type = 'landline' WHERE number = ?",
+        sealed
+    )
+    .execute(&pool)
+    .await?;
+    let sealed = pii::seal("+19876543211");
+    query!(
+        "UPDATE users SET line_type = 'mobile' WHERE number = ?",
+        sealed
+    )
+    .execute(&pool)
+    .await?;
+    assert_eq!(
+        lookup::known(&pool, &sealed).await?,
+        Some(lookup::LineType::Mobile)
+    );
+
+    let response = send_message(&pool, "+1234567890", "contacts").await?;
+    assert!(
+        response.contains("Alice Smith (c1): (987) 654-3210 (landline)"),
+        "{response}"
+    );
+    assert!(response.ends_with("Bob Wilson (c2): (987) 654-3211"));
+
+    // With lookups on, adding a number queues its lookup rather than making the user wait
+    config::use_test_config(config::Config {
+        line_type_lookup: true,
+        ..Default::default()
+    });
+    add_contact(&pool, "+1234567890", "Carol Jones", "+19876543212").await?;
+    // A number that's already known isn't looked up again
+    add_contact(&pool, "+1234567890", "Bobby", "+19876543211").await?;
+    let queued =
+        query!(r#"SELECT payload as "payload!" FROM jobs WHERE payload LIKE '%line_type_lookup%'"#)
+            .fetch_all(&pool)
+            .await?;
+    assert_eq!(queued.len(), 1);
+    assert_eq!(
+        serde_json::from_str::<jobs::Job>(&queued[0].payload)?,
+        jobs::Job::LineTypeLookup {
+            number: pii::seal("+19876543212")
+        }
+    );
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_quotas(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    config::use_test_config(config::Config {
+        quota_scheduled_per_day: 2,
+        quota_recipients: 1,
+        ..Default::default()
+    });
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    add_contact(&pool, "+1234567890", "Alice Smith", "+19876543210").await?;
+    add_contact(&pool, "+1234567890", "Bob Wilson", "+19876543211").await?;
+    send_message(&pool, "+1234567890", "group Alice, Bob").await?;
+    send_message(&pool, "+1234567890", "confirm 1,2").await?;
+
+    let response = send_message(&pool, "+1234567890", "remind group0 tomorrow 9am: hi").await?;
+    assert!(response.contains("group0 has 2 members, but messages can only go to 1 at once"));
+    let response = send_message(&pool, "+1234567890", "event group0 tomorrow 7pm: dinner").await?;
+    assert!(response.contains("group0 has 2 members"));
+
+    // Reminders and events share a daily quota, and turned-away requests don't count
+    let response = send_message(&pool, "+1234567890", "remind me tomorrow 9am: hi").await?;
+    assert!(response.contains("scheduled for"));
+    let response = send_message(&pool, "+1234567890", "remind alice tomorrow 9am: hi").await?;
+    assert!(response.contains("scheduled for"));
+    let response = send_message(&pool, "+1234567890", "remind me tomorrow 10am: hi").await?;
+    assert!(response.contains("You've scheduled 2 reminders and events in the last day"));
+
+    // Usage from over a day ago stops counting
+    query!("UPDATE quota_usage SET used_at = used_at - 24 * 60 * 60")
+        .execute(&pool)
+        .await?;
+    let response = send_message(&pool, "+1234567890", "remind me tomorrow 10am: hi").await?;
+    assert!(response.contains("scheduled for"));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_announcements(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    config::use_test_config(config::Config {
+        simulate: true,
+        ..Default::default()
+    });
+    send_message(&pool, "+1234567890", "name John Doe").await?;
+    send_message(&pool, "+19876543211", "name Bob").await?;
+    send_message(&pool, "+19876543211", "stop").await?;
+    // Alice is only in John's contacts, and never agreed to anything
+    add_contact(&pool, "+1234567890", "Alice Smith", "+19876543210").await?;
+
+    jobs::enqueue(
+        &pool,
+        &jobs::Job::Announcement {
+            body: "We're moving servers tonight".to_string(),
+        },
+    )
+    .await?;
+    assert!(jobs::run_next(&tenant(&pool)).await?);
+    let sent = query!(r#"SELECT COUNT(*) as "count!: i64" FROM message_log WHERE status = 'sent'"#)
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(sent.count, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_job_payloads() {
+    // decisionbot-admin writes these by hand, so their shape mustn't drift
+    assert_eq!(
+        serde_json::to_string(&jobs::Job::Cleanup).unwrap(),
+        r#"{"type":"cleanup"}"#
+    );
+    assert_eq!(
+        serde_json::from_value::<jobs::Job>(
+            serde_json::json!({ "type": "announcement", "body": "Hi" })
+        )
+        .unwrap(),
+        jobs::Job::Announcement {
+            body: "Hi".to_string()
+        }
+    );
+}
+
+#[sqlx::test]
+async fn test_tenants(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    // One connection, since each in-memory connection would be its own database
+    let other_pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await?;
+    sqlx::migrate!().run(&other_pool).await?;
+    let tenants = Tenants::new(vec![
+        tenant(&pool),
+        Tenant::new("+15550001111".to_string(), other_pool.clone()),
+    ]);
+    let deliver = |to: &str, body: &str| {
+        handle_incoming_sms(
+            Extension(tenants.clone()),
+            Form(SmsMessage {
+                From: "+1234567890".to_string(),
+                Body: body.to_string(),
+                To: Some(to.to_string()),
+                ..Default::default()
+            }),
+        )
+    };
+
+    assert!(deliver("+15550001111", "name Sam")
+        .await
+        .0
+        .contains("Hello, Sam!"));
+    // The same person texting the other number is someone new there
+    assert!(deliver("+15550000000", "contacts")
+        .await
+        .0
+        .contains("Greetings!"));
+    assert!(deliver("+15550001111", "contacts")
+        .await
+        .0
+        .contains("You don't have any groups or contacts"));
+
+    let count = |pool: Pool<Sqlite>| async move {
+        query!("SELECT COUNT(*) as count FROM users")
+            .fetch_one(&pool)
+            .await
+            .map(|row| row.count)
+    };
+    assert_eq!(count(pool.clone()).await?, 0);
+    assert_eq!(count(other_pool.clone()).await?, 1);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_webhook_guard(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+
+    let tenants = Tenants::new(vec![tenant(&pool)]);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, router(&tenants)).await });
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(&url)
+        .form(&[("From", "+1234567890"), ("Body", "name John Doe")])
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(response.text().await?.contains("Hello, John Doe!"));
+
+    let response = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .body(r#"{"From": "+1234567890", "Body": "contacts"}"#)
+        .send()
+        .await?;
+    assert_eq!(
+        response.status(),
+        reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+    assert!(response
+        .text()
+        .await?
+        .contains("<Message>Sorry, that message couldn't be read.</Message>"));
+
+    let response = client
+        .post(&url)
+        .form(&[
+            ("From", "+1234567890"),
+            ("Body", &"a".repeat(MAX_WEBHOOK_BYTES)),
+        ])
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    let count = query!("SELECT COUNT(*) as count FROM message_log")
+        .fetch_one(&pool)
+        .await?
+        .count;
+    assert_eq!(count, 2);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_simulated_import(pool: Pool<Sqlite>) -> Result<()> {
+    let sim = Simulator::new(&pool).await?;
+    let me = "+1234567890";
+
+    sim.expect(me, "name John Doe", "Hello, John Doe!").await?;
+    let report = sim
+        .send_vcard(
+            me,
+            "BEGIN:VCARD\nVERSION:3.0\nFN:Alice Smith\nTEL:+19876543210\nEND:VCARD\n\
+             BEGIN:VCARD\nVERSION:3.0\nFN:Bob Wilson\n\
+             TEL;TYPE=CELL:+19876543211\nTEL;TYPE=WORK:(987) 654-3212 ext. 204\nEND:VCARD\n",
+        )
+        .await?;
+    assert!(report.contains("Bob Wilson"), "{report}");
+    assert_eq!(sim.contact_names(me).await?, vec!["Alice Smith"]);
+
+    // Bob waits on picking which of his numbers to use
+    let pending = sim.expect(me, "pending", "In progress").await?;
+    assert!(pending.contains("+19876543212 (WORK x204)"), "{pending}");
+    sim.expect(me, "confirm 1a", "Bob Wilson").await?;
+    assert_eq!(
+        sim.contact_names(me).await?,
+        vec!["Alice Smith", "Bob Wilson"]
+    );
+    let contacts = sim.expect(me, "contacts", "Alice Smith").await?;
+    assert!(contacts.contains("Bob Wilson"));
+    sim.expect(me, "pending", "You don't have anything in progress.")
+        .await?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_merged_cards(pool: Pool<Sqlite>) -> Result<()> {
+    let sim = Simulator::new(&pool).await?;
+    let me = "+1234567890";
+
+    sim.expect(me, "name John Doe", "Hello, John Doe!").await?;
+    sim.send_vcard(
+        me,
+        "BEGIN:VCARD\nVERSION:3.0\nFN:Dana Lee\nTEL;TYPE=WORK:+19876543210\nEND:VCARD\n\
+         BEGIN:VCARD\nVERSION:3.0\nFN:Alice Smith\nTEL:+19876543220\nEND:VCARD\n\
+         BEGIN:VCARD\nVERSION:3.0\nFN:dana  LEE\nTEL;TYPE=HOME:+19876543211\n\
+         TEL;TYPE=WORK:+19876543210\nEND:VCARD\n",
+    )
+    .await?;
+    assert_eq!(sim.contact_names(me).await?, vec!["Alice Smith"]);
+
+    // Both of Dana's cards come up as one pick, without the number they share twice
+    let pending = sim.expect(me, "pending", "1. Dana Lee").await?;
+    assert!(pending.contains("a. +19876543210 (WORK)"), "{pending}");
+    assert!(pending.contains("b. +19876543211 (HOME)"), "{pending}");
+    assert!(!pending.contains("c. "), "{pending}");
+    sim.expect(me, "confirm 1b", "Dana Lee").await?;
+    assert_eq!(
+        sim.contact_names(me).await?,
+        vec!["Alice Smith", "Dana Lee"]
+    );
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_contact_details(pool: Pool<Sqlite>) -> Result<()> {
+    let sim = Simulator::new(&pool).await?;
+    let me = "+1234567890";
+
+    sim.expect(me, "name John Doe", "Hello, John Doe!").await?;
+    sim.send_vcard(
+        me,
+        "BEGIN:VCARD\nVERSION:3.0\nFN:Dana Lee\nORG:Acme;Sales\nTITLE:PM\n\
+         TEL:+14155550100\nEND:VCARD\n\
+         BEGIN:VCARD\nVERSION:3.0\nFN:Dana Park\nTITLE:Nurse\n\
+         TEL;TYPE=CELL:+12125550101\nTEL;TYPE=WORK:+12125550102\nEND:VCARD\n\
+         BEGIN:VCARD\nVERSION:3.0\nFN:Alice Smith\nTEL:+19876543210\nEND:VCARD\n",
+    )
+    .await?;
+    // Picking a number keeps the details from the card
+    sim.expect(me, "confirm 1a", "Dana Park").await?;
+
+    let contacts = sim.expect(me, "contacts", "Alice Smith").await?;
+    assert!(
+        contacts.contains("Dana Lee (c1): (415) 555-0100 — Acme, Sales, PM"),
+        "{contacts}"
+    );
+    assert!(contacts.contains("Dana Park (c3): (212) 555-0101 — Nurse"));
+    let found = sim
+        .expect(me, "delete dana", "Found these contacts")
+        .await?;
+    assert!(
+        found.contains("Dana Lee (415) — Acme, Sales, PM"),
+        "{found}"
+    );
+    assert!(found.contains("Dana Park (212) — Nurse"), "{found}");
+    sim.text(me, "nevermind").await?;
+
+    // A later card for the same number fills them in
+    sim.send_vcard(
+        me,
+        "BEGIN:VCARD\nVERSION:3.0\nFN:Alice Smith\nORG:Initech\n\
+         TEL:+19876543210\nEND:VCARD\n",
+    )
+    .await?;
+    sim.expect(me, "contacts", "Alice Smith (c2): (987) 654-3210 — Initech")
+        .await?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_vcard_sniffing(pool: Pool<Sqlite>) -> Result<()> {
+    let sim = Simulator::new(&pool).await?;
+    let me = "+1234567890";
+    let card = |name: &str, number: &str| {
+        format!("BEGIN:VCARD\nVERSION:3.0\nFN:{name}\nTEL:{number}\nEND:VCARD\n")
+    };
+
+    sim.expect(me, "name John Doe", "Hello, John Doe!").await?;
+    // A generic content type, but a card inside
+    let report = sim
+        .send_media(
+            me,
+            "",
+            Some("application/octet-stream"),
+            "/media/ME123",
+            &card("Alice Smith", "+19876543210"),
+        )
+        .await?;
+    assert!(report.contains("1 added"), "{report}");
+    // No content type, but a .vcf file name
+    sim.send_media(
+        me,
+        "",
+        None,
+        "/Bob.VCF",
+        &card("Bob Wilson", "+19876543211"),
+    )
+    .await?;
+    assert_eq!(
+        sim.contact_names(me).await?,
+        vec!["Alice Smith", "Bob Wilson"]
+    );
+
+    // Anything else is left alone, and the text with it handled as usual
+    sim.send_media(me, "language", Some("image/jpeg"), "/photo", "BEGIN:VCARD")
+        .await?;
+    let reply = sim
+        .send_media(
+            me,
+            "language",
+            Some("text/plain"),
+            "/note.txt",
+            "Not a card",
+        )
+        .await?;
+    assert!(reply.contains("Your language is English"), "{reply}");
+    assert_eq!(sim.contact_names(me).await?.len(), 2);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_media_downloads(pool: Pool<Sqlite>) -> Result<()> {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let sim = Simulator::new(&pool).await?;
+    let me = "+1234567890";
+    sim.expect(me, "name John Doe", "Hello, John Doe!").await?;
+
+    // Twilio failing twice is tried again, but not a third time
+    let failures = Arc::new(AtomicUsize::new(0));
+    let failing = failures.clone();
+    let media = Router::new()
+        .route(
+            "/flaky.vcf",
+            get(move || async move {
+                if failing.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(StatusCode::SERVICE_UNAVAILABLE)
+                } else {
+                    Ok("BEGIN:VCARD\nVERSION:3.0\nFN:Alice Smith\nTEL:+19876543210\nEND:VCARD\n")
+                }
+            }),
+        )
+        .route("/down.vcf", get(|| async { StatusCode::BAD_GATEWAY }))
+        .route(
+            "/huge.vcf",
+            get(|| async { "BEGIN:VCARD\n".repeat(media::MAX_BYTES / 10) }),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, media).await });
+
+    assert!(media::fetch(&format!("{base}/flaky.vcf")).await.is_ok());
+    assert_eq!(failures.load(Ordering::SeqCst), 3);
+    let error = media::fetch(&format!("{base}/down.vcf")).await.unwrap_err();
+    assert!(error.to_string().contains("after 3 attempts"), "{error}");
+
+    let reply = sim
+        .deliver(SmsMessage {
+            From: me.to_string(),
+            NumMedia: Some("1".to_string()),
+            MediaContentType0: Some("text/vcard".to_string()),
+            MediaUrl0: Some(format!("{base}/huge.vcf")),
+            ..Default::default()
+        })
+        .await?;
+    assert_eq!(
+        reply,
+        "That attachment is too big. Please send files of 5 MB or less."
+    );
+    assert!(sim.contact_names(me).await?.is_empty());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_import_replace(pool: Pool<Sqlite>) -> Result<()> {
+    let sim = Simulator::new(&pool).await?;
+    let me = "+1234567890";
+    let card = |name: &str, number: &str| {
+        format!("BEGIN:VCARD\nVERSION:3.0\nFN:{name}\nTEL:{number}\nEND:VCARD\n")
+    };
+
+    sim.expect(me, "name John Doe", "Hello, John Doe!").await?;
+    sim.expect(me, "import", "\"import replace\"").await?;
+    for (name, number) in [
+        ("Alice Smith", "+19876543210"),
+        ("Bob Wilson", "+19876543211"),
+        ("Carol Brown", "+19876543212"),
+    ] {
+        add_contact(&pool, me, name, number).await?;
+    }
+
+    // A plain import leaves everyone else alone
+    let export = card("Alice S.", "+19876543210") + &card("Dave Jones", "+19876543213");
+    let report = sim
+        .send_media(me, "", Some("text/vcard"), "/export.vcf", &export)
+        .await?;
+    assert!(!report.contains("aren't in the export"), "{report}");
+
+    let export = export + &card("Bob Wilson", "+19876543211");
+    let report = sim
+        .send_media(
+            me,
+            "Import replace",
+            Some("text/vcard"),
+            "/export.vcf",
+            &export,
+        )
+        .await?;
+    assert!(
+        report.contains("0 added, 0 updated, 3 unchanged"),
+        "{report}"
+    );
+    assert!(
+        report.contains("These contacts aren't in the export:\n1. Carol Brown (987)"),
+        "{report}"
+    );
+    assert!(
+        report.ends_with("or \"nevermind\" to keep them."),
+        "{report}"
+    );
+    sim.expect(me, "confirm 1", "Deleted 1 contact").await?;
+    assert_eq!(
+        sim.contact_names(me).await?,
+        vec!["Alice S.", "Bob Wilson", "Dave Jones"]
+    );
+
+    let report = sim
+        .send_media(
+            me,
+            "import replace",
+            Some("text/vcard"),
+            "/export.vcf",
+            &export,
+        )
+        .await?;
+    assert!(report.contains("Everyone in your contacts is in the export."));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_again(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    let me = "+1234567890";
+    let tenants = Tenants::new(vec![tenant(&pool)]);
+    // Replies are only kept on the way out, so these go through the webhook
+    let text = |body: &str| {
+        handle_incoming_sms(
+            Extension(tenants.clone()),
+            Form(SmsMessage {
+                From: me.to_string(),
+                Body: body.to_string(),
+                ..Default::default()
+            }),
+        )
+    };
+
+    let _ = text("name John Doe").await;
+    add_contact(&pool, me, "Alice Smith", "+19876543210").await?;
+    let contacts = text("contacts").await.0;
+    assert!(contacts.contains("Alice Smith"), "{contacts}");
+
+    // Asking again repeats the last reply, and asking twice doesn't replace it
+    assert_eq!(text("again").await.0, contacts);
+    assert_eq!(text("again").await.0, contacts);
+
+    // Old replies aren't kept
+    query!("UPDATE last_responses SET created_at = created_at - 86400")
+        .execute(&pool)
+        .await?;
+    let reply = text("again").await.0;
+    assert!(
+        reply.contains("There's no recent reply to send again."),
+        "{reply}"
+    );
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_sendlater(pool: Pool<Sqlite>) -> Result<()> {
+    use chrono::Timelike;
+    setup_db(&pool).await?;
+    config::use_test_config(config::Config {
+        simulate: true,
+        ..Default::default()
+    });
+    let me = "+1234567890";
+    send_message(&pool, me, "name John Doe").await?;
+    send_message(&pool, me, "timezone New York").await?;
+    add_contact(&pool, me, "Alice Smith", "+19876543210").await?;
+    add_contact(&pool, me, "Bob Wilson", "+19876543211").await?;
+    send_message(&pool, "+19876543210", "name Alice").await?;
+    send_message(&pool, "+19876543210", "timezone Los Angeles").await?;
+    send_message(&pool, me, "group Alice, Bob").await?;
+    send_message(&pool, me, "confirm 1,2").await?;
+
+    let response = send_message(&pool, me, "sendlater").await?;
+    assert!(response.contains("You don't have any messages scheduled"));
+    let response = send_message(&pool, me, "sendlater carol 8am: hi").await?;
+    assert!(response.contains("\"carol\" isn't you"));
+
+    let response = send_message(&pool, me, "sendlater group0 8am: don't forget to vote").await?;
+    assert!(
+        response.contains("Message for group0 scheduled for 8am their time"),
+        "{response}"
+    );
+    send_message(&pool, me, "sendlater me in 1 hour: stretch").await?;
+
+    // 8am where each of them is, or where the sender is for anyone who hasn't said
+    let deliveries = query!(
+        r#"SELECT d.id as "id!", d.recipient_number, d.send_at FROM later_deliveries d
+         ORDER BY d.id"#
+    )
+    .fetch_all(&pool)
+    .await?;
+    assert_eq!(deliveries.len(), 3);
+    for (delivery, zone) in deliveries[..2].iter().zip([
+        chrono_tz::America::Los_Angeles,
+        chrono_tz::America::New_York,
+    ]) {
+        let at = chrono::DateTime::from_timestamp(delivery.send_at, 0)
+            .unwrap()
+            .with_timezone(&zone);
+        assert_eq!((at.hour(), at.minute()), (8, 0));
+    }
+    let due = query!(r#"SELECT COUNT(*) as "count!: i64" FROM jobs WHERE payload LIKE '%later%'"#)
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(due.count, 3);
+
+    let response = send_message(&pool, me, "sendlater").await?;
+    assert!(response.contains("1. in 1 hour their time"), "{response}");
+    assert!(response.contains(", for me: stretch\n2. 8am their time"));
+    assert!(response.contains(", for group0: don't forget to vote"));
+
+    // Cancelling stops every recipient's copy
+    let response = send_message(&pool, me, "sendlater cancel 2").await?;
+    assert!(response.contains("Cancelled the message for group0: don't forget to vote"));
+    for delivery in &deliveries {
+        sendlater::deliver(&pool, "+15550000000", delivery.id).await?;
+    }
+    let statuses = query!("SELECT status FROM later_deliveries ORDER BY id")
+        .fetch_all(&pool)
+        .await?;
+    assert_eq!(
+        statuses
+            .iter()
+            .map(|d| d.status.as_str())
+            .collect::<Vec<_>>(),
+        vec!["cancelled", "cancelled", "sent"]
+    );
+    let messages = query!("SELECT status FROM later_messages ORDER BY id")
+        .fetch_all(&pool)
+        .await?;
+    assert_eq!(messages[0].status, "cancelled");
+    assert_eq!(messages[1].status, "sent");
+    let response = send_message(&pool, me, "sendlater").await?;
+    assert!(response.contains("You don't have any messages scheduled"));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_opt_outs(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    config::use_test_config(config::Config {
+        simulate: true,
+        ..Default::default()
+    });
+    let tenants = Tenants::new(vec![tenant(&pool)]);
+    let text = |from: &str, body: &str, opt_out_type: Option<&str>| {
+        handle_incoming_sms(
+            Extension(tenants.clone()),
+            Form(SmsMessage {
+                From: from.to_string(),
+                Body: body.to_string(),
+                OptOutType: opt_out_type.map(str::to_string),
+                ..Default::default()
+            }),
+        )
+    };
+    let send = |to: &str| {
+        let message = Outgoing {
+            to: to.to_string(),
+            body: "hi".to_string(),
+        };
+        async { outbound::send_all(&pool, "+15550000000", vec![message]).await }
+    };
+    let (alice, bob) = ("+19876543210", "+19876543211");
+    let _ = text(alice, "name Alice", None).await;
+    let _ = text(bob, "name Bob", None).await;
+
+    // Advanced Opt-Out's keywords can be anything, so its word is taken for it
+    let _ = text(alice, "arrêt", Some("STOP")).await;
+    let results = send(alice).await?;
+    assert!(results[0].1.as_ref().unwrap_err().is::<optout::OptedOut>());
+    assert!(send(bob).await?[0].1.is_ok());
+    // Nothing went out, so it isn't counted as a failed send
+    let failed =
+        query!(r#"SELECT COUNT(*) as "count!: i64" FROM message_log WHERE status = 'failed'"#)
+            .fetch_one(&pool)
+            .await?;
+    assert_eq!(failed.count, 0);
+
+    // Twilio turning a text away says they've opted out too
+    let status = delivery::handle_status(
+        Extension(tenants.clone()),
+        Form(delivery::StatusCallback {
+            MessageSid: "SMfailed".to_string(),
+            MessageStatus: "undelivered".to_string(),
+            ErrorCode: Some("21610".to_string()),
+            To: Some(bob.to_string()),
+            ..Default::default()
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(send(bob).await?[0].1.is_err());
+
+    // START opts back in, as does the keyword on its own without Advanced Opt-Out
+    let _ = text(alice, "start", Some("START")).await;
+    assert!(send(alice).await?[0].1.is_ok());
+    let _ = text(bob, "Unstop", None).await;
+    assert!(send(bob).await?[0].1.is_ok());
+    let _ = text(bob, "stop by later", None).await;
+    assert!(send(bob).await?[0].1.is_ok());
+    // Without Twilio saying so, "cancel" is just a reply to the bot
+    let _ = text(bob, "cancel", None).await;
+    assert!(!optout::opted_out(&pool, &pii::seal(bob)).await?);
+    assert!(send(bob).await?[0].1.is_ok());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_resubscribe(pool: Pool<Sqlite>) -> Result<()> {
+    let sim = Simulator::new(&pool).await?;
+    let (me, alice) = ("+1234567890", "+19876543210");
+    sim.expect(me, "name John Doe", "Hello, John Doe!").await?;
+    sim.expect(alice, "name Alice", "Hello, Alice!").await?;
+    add_contact(&pool, me, "Alice Smith", alice).await?;
+    add_contact(&pool, alice, "John Doe", me).await?;
+    sim.text(me, "group Alice").await?;
+    sim.text(me, "confirm 1").await?;
+
+    sim.expect(me, "stop", "Your contacts and groups are kept")
+        .await?;
+    sim.expect(me, "contacts", "Your account is paused").await?;
+    sim.expect(me, "group Alice", "Your account is paused")
+        .await?;
+    // Nobody else's texts reach them meanwhile
+    assert!(delivery::undeliverable(&pool, &pii::seal(me)).await?);
+
+    sim.expect(
+        me,
+        "join",
+        "Welcome back! Your 1 contact(s) and 1 group(s) are as you left them.",
+    )
+    .await?;
+    assert!(!delivery::undeliverable(&pool, &pii::seal(me)).await?);
+    sim.expect(me, "contacts", "Alice Smith").await?;
+    sim.expect(me, "start", "You're already subscribed.")
+        .await?;
+
+    // Purging deletes everything, paused or not
+    sim.text(me, "stop").await?;
+    sim.expect(me, "stop purge", "Removed 1 contact(s), 1 group(s)")
+        .await?;
+    assert_eq!(sim.contact_names(alice).await?, Vec::<String>::new());
+    sim.expect(me, "hi", "Greetings!").await?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_contact_handles(pool: Pool<Sqlite>) -> Result<()> {
+    let sim = Simulator::new(&pool).await?;
+    let me = "+1234567890";
+    sim.expect(me, "name John Doe", "Hello, John Doe!").await?;
+    add_contact(&pool, me, "Dana Lee", "+14155550100").await?;
+    add_contact(&pool, me, "Dana Park", "+12125550101").await?;
+    sim.expect(
+        me,
+        "contacts",
+        "1. Dana Lee (c1): (415) 555-0100\n2. Dana Park (c2)",
+    )
+    .await?;
+
+    // A contact added in between moves the list, but not the handles
+    add_contact(&pool, me, "Dana Abbott", "+12125550102").await?;
+    sim.expect(me, "contacts", "1. Dana Abbott (c3)").await?;
+    let found = sim.expect(me, "delete c2", "Found these contacts").await?;
+    assert!(found.contains("1. Dana Park (212)"), "{found}");
+    assert!(!found.contains("Dana Lee"), "{found}");
+    sim.expect(me, "confirm 1", "Deleted 1 contact").await?;
+
+    // Handles and names mix in a group, and work anywhere a contact is named
+    let found = sim.text(me, "group c1, abbott").await?;
+    assert!(found.contains("Dana Lee (415)"), "{found}");
+    assert!(found.contains("Dana Abbott (212)"), "{found}");
+    sim.expect(
+        me,
+        "remind C3 tomorrow 9am: call back",
+        "Reminder for Dana Abbott",
+    )
+    .await?;
+    // A handle that isn't one of theirs is just a name that matches nothing
+    sim.expect(me, "delete c2", "c2").await?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_me(pool: Pool<Sqlite>) -> Result<()> {
+    let sim = Simulator::new(&pool).await?;
+    let me = "+14155550123";
+    sim.text(me, "name Jo Smith").await?;
+    add_contact(&pool, me, "Alice Smith", "+19876543210").await?;
+    add_contact(&pool, me, "Bob Jones", "+19876543211").await?;
+    sim.text(me, "group Alice, Bob").await?;
+    sim.text(me, "confirm 1,2").await?;
+    sim.text(me, "timezone America/Chicago").await?;
+    sim.text(me, "remind Alice tomorrow 9am: call back").await?;
+
+    let profile = sim.text(me, "me").await?;
+    for expected in [
+        "Name: Jo Smith",
+        "Number: (415) 555-0123",
+        "Language: English",
+        "Time zone: America/Chicago",
+        "Messages from others: allowed",
+        "2 contacts, 1 groups, 0 blocked",
+        "1 scheduled texts, 0 upcoming events",
+    ] {
+        assert!(profile.contains(expected), "{expected} not in {profile}");
+    }
+
+    sim.text(me, "language es").await?;
+    sim.expect(me, "me", "Idioma: Español").await?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_movenumber(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    config::use_test_config(config::Config {
+        simulate: true,
+        ..Default::default()
+    });
+    let old = "+1234567890";
+    let new = "+15555550123";
+    let alice = "+19876543210";
+    send_message(&pool, old, "name John Doe").await?;
+    send_message(&pool, old, "timezone Chicago").await?;
+    add_contact(&pool, old, "Alice Smith", alice).await?;
+    send_message(&pool, old, "group Alice").await?;
+    send_message(&pool, old, "confirm 1").await?;
+    send_message(&pool, old, "decide pizza, tacos").await?;
+    send_message(&pool, alice, "name Alice").await?;
+    add_contact(&pool, alice, "John", old).await?;
+    // Alice already had the new number too, under another name
+    add_contact(&pool, alice, "Johnny", new).await?;
+
+    let response = send_message(&pool, old, "movenumber 987-654-3210").await?;
+    assert!(
+        response.contains("already has its own account"),
+        "{response}"
+    );
+    let response = send_message(&pool, old, "movenumber 123456").await?;
+    assert!(response.contains("You're not moving"), "{response}");
+    // Nor is one where someone's partway through signing up
+    send_message(&pool, "+15555550199", "hi").await?;
+    let response = send_message(&pool, old, "movenumber 555-555-0199").await?;
+    assert!(
+        response.contains("already has its own account"),
+        "{response}"
+    );
+
+    let response = send_message(&pool, old, "movenumber (555) 555-0123").await?;
+    assert!(response.contains("I've texted a code to"), "{response}");
+    let code = sent_code(&pool, new).await?;
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+    let response = send_message(&pool, old, &format!("movenumber {wrong}")).await?;
+    assert!(response.contains("4 tries left"), "{response}");
+
+    let response = send_message(&pool, old, &format!("movenumber {code}")).await?;
+    assert!(response.contains("Your account now lives at"), "{response}");
+
+    // Everything's at the new number, and the old one is a stranger
+    let response = send_message(&pool, new, "contacts").await?;
+    assert!(response.contains("group0 (1 members)"), "{response}");
+    assert!(response.contains("Alice Smith"), "{response}");
+    let response = send_message(&pool, new, "me").await?;
+    assert!(response.contains("Name: John Doe"), "{response}");
+    assert!(response.contains("America/Chicago"), "{response}");
+    let response = send_message(&pool, new, "decide").await?;
+    assert!(response.contains("pizza, tacos"), "{response}");
+    let response = send_message(&pool, old, "contacts").await?;
+    assert!(!response.contains("Alice Smith"), "{response}");
+
+    // Alice's two entries for them became one
+    let response = send_message(&pool, alice, "contacts").await?;
+    assert!(response.contains("Johnny"), "{response}");
+    assert_eq!(response.matches("John").count(), 1, "{response}");
+
+    // Every column holding a number has been decided on, one way or the other, apart from
+    // the ones that aren't a user's
+    let columns: Vec<(String, String)> = sqlx::query_as(
+        "SELECT m.name, c.name FROM sqlite_master m JOIN pragma_table_info(m.name) c
+         WHERE m.type = 'table' AND (c.name LIKE '%number%' OR c.name LIKE '%submitter%')",
+    )
+    .fetch_all(&pool)
+    .await?;
+    let known = movenumber::user_columns()
+        .chain([
+            ("deferred_contacts", "phone_number"),
+            ("verification_codes", "send_to"),
+        ])
+        .collect::<Vec<_>>();
+    assert!(columns.len() > 30);
+    for (table, column) in &columns {
+        assert!(
+            known.contains(&(table.as_str(), column.as_str())),
+            "{table}.{column} isn't in movenumber's lists"
+        );
+    }
+
+    Ok(())
+}
+
+/// Makes up and "sends" the code waiting to go to `to`, returning it
+async fn sent_code(pool: &Pool<Sqlite>, to: &str) -> Result<String> {
+    let sealed = pii::seal(to);
+    let pending = query!(
+        r#"SELECT id as "id!" FROM verification_codes WHERE send_to = ?"#,
+        sealed
+    )
+    .fetch_one(pool)
+    .await?;
+    let message = verification::compose(pool, pending.id).await?.unwrap();
+    assert_eq!(message.to, to);
+    let code = message
+        .body
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|word| word.len() == 6)
+        .unwrap();
+    Ok(code.to_string())
+}
+
+#[sqlx::test]
+async fn test_verification_codes(pool: Pool<Sqlite>) -> Result<()> {
+    use verification::{Check, Purpose};
+    setup_db(&pool).await?;
+    let me = "+1234567890";
+    let phone = "+15555550123";
+    send_message(&pool, me, "name John Doe").await?;
+    let (me, sealed_phone) = (pii::seal(me), pii::seal(phone));
+    let start = || async {
+        let mut tx = pool.begin().await?;
+        verification::start(&mut tx, &me, Purpose::MoveNumber, &sealed_phone).await?;
+        tx.commit().await?;
+        anyhow::Ok(())
+    };
+    let check = |code: String| {
+        let me = me.clone();
+        let pool = pool.clone();
+        async move { verification::check(&pool, &me, Purpose::MoveNumber, &code).await }
+    };
+
+    assert_eq!(check("123456".into()).await?, Check::NotStarted);
+    start().await?;
+    // It's queued to go out, and only its hash is ever kept
+    let queued = query!(
+        r#"SELECT COUNT(*) as "count!: i64" FROM jobs WHERE payload LIKE '%verification_code%'"#
+    )
+    .fetch_one(&pool)
+    .await?;
+    assert_eq!(queued.count, 1);
+    let code = sent_code(&pool, phone).await?;
+    let stored = query!("SELECT code_hash FROM verification_codes")
+        .fetch_one(&pool)
+        .await?;
+    assert!(!stored.code_hash.unwrap().contains(&code));
+
+    // Used up once it's right
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+    assert_eq!(check(wrong.into()).await?, Check::Wrong { left: 4 });
+    assert_eq!(
+        check(code.clone()).await?,
+        Check::Valid {
+            sent_to: sealed_phone.clone()
+        }
+    );
+    assert_eq!(check(code).await?, Check::NotStarted);
+
+    // Starting over replaces the old code, and too many wrong ones throw it away
+    start().await?;
+    start().await?;
+    let codes = query!(r#"SELECT COUNT(*) as "count!: i64" FROM verification_codes"#)
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(codes.count, 1);
+    let code = sent_code(&pool, phone).await?;
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+    for left in (1..verification::MAX_ATTEMPTS).rev() {
+        assert_eq!(check(wrong.into()).await?, Check::Wrong { left });
+    }
+    assert_eq!(check(wrong.into()).await?, Check::TooManyAttempts);
+    assert_eq!(check(code).await?, Check::NotStarted);
+
+    // And they only last so long
+    start().await?;
+    let code = sent_code(&pool, phone).await?;
+    query!("UPDATE verification_codes SET expires_at = unixepoch() - 1")
+        .execute(&pool)
+        .await?;
+    assert_eq!(check(code).await?, Check::Expired);
+    assert_eq!(check("123456".into()).await?, Check::NotStarted);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_pin(pool: Pool<Sqlite>) -> Result<()> {
+    setup_db(&pool).await?;
+    pii::use_test_key();
+    config::use_test_config(config::Config {
+        public_url: Some("https://bot.example.com/".to_string()),
+        ..Default::default()
+    });
+    let me = "+1234567890";
+    send_message(&pool, me, "name John Doe").await?;
+    add_contact(&pool, &pii::seal(me), "Alice Smith", "+19876543210").await?;
+    add_contact(&pool, &pii::seal(me), "Bob Smith", "+19876543211").await?;
+    add_contact(&pool, &pii::seal(me), "Carol Smith", "+19876543212").await?;
+
+    let response = send_message(&pool, me, "pin 12").await?;
+    assert!(response.contains("A PIN is 4 digits"), "{response}");
+    let response = send_message(&pool, me, "pin 1234").await?;
+    assert!(response.contains("Your PIN is set"), "{response}");
+    // It's stored keyed, so trying every PIN against a copy of the database finds nothing
+    let sealed = pii::seal(me);
+    let stored = query!("SELECT salt, pin_hash FROM pins WHERE number = ?", sealed)
+        .fetch_one(&pool)
+        .await?;
+    assert!(verification::matches(
+        &stored.salt,
+        "1234",
+        &stored.pin_hash
+    ));
+    assert!(!(0..10_000).any(|pin| {
+        use sha2::{Digest, Sha256};
+        let digest = Sha256::digest(format!("{}{pin:04}", stored.salt).as_bytes());
+        let unkeyed: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+        unkeyed == stored.pin_hash
+    }));
+    // Guarded commands wait for it, and a wrong one doesn't let them through
+    let response = send_message(&pool, me, "export").await?;
+    assert!(response.contains("Reply with your PIN"), "{response}");
+    let response = send_message(&pool, me, "9999").await?;
+    assert!(response.contains("4 tries left"), "{response}");
+    let response = send_message(&pool, me, "1234").await?;
+    assert!(response.contains("Your data export is ready"), "{response}");
+    // The PIN isn't written down anywhere
+    let logged = query!(
+        "SELECT COUNT(*) as count FROM audit_log WHERE detail LIKE '%pin 1234%' OR detail = '1234'"
+    )
+    .fetch_one(&pool)
+    .await?;
+    assert_eq!(logged.count, 0);
+
+    // Deleting one contact doesn't need it, but deleting several does
+    send_message(&pool, me, "delete Smith").await?;
+    let response = send_message(&pool, me, "confirm 1").await?;
+    assert!(response.contains("Deleted 1 contact"), "{response}");
+    send_message(&pool, me, "delete Smith").await?;
+    let response = send_message(&pool, me, "confirm 1,2").await?;
+    assert!(response.contains("Reply with your PIN"), "{response}");
+    let response = send_message(&pool, me, "1234").await?;
+    assert!(response.contains("Deleted 2 contacts"), "{response}");
+
+    // So does moving the account to another number, though calling a move off doesn't
+    let response = send_message(&pool, me, "movenumber +15555550123").await?;
+    assert!(response.contains("Reply with your PIN"), "{response}");
+    assert!(query!("SELECT 1 as found FROM verification_codes")
+        .fetch_optional(&pool)
+        .await?
+        .is_none());
+    let response = send_message(&pool, me, "1234").await?;
+    assert!(response.contains("I've texted a code to"), "{response}");
+    let response = send_message(&pool, me, "movenumber cancel").await?;
+    assert!(response.contains("Cancelled moving"), "{response}");
+
+    // Too many wrong PINs lock them out for a while
+    send_message(&pool, me, "stop").await?;
+    for left in (1..5).rev() {
+        let response = send_message(&pool, me, "0000").await?;
+        assert!(
+            response.contains(&format!("{left} tries left")),
+            "{response}"
+        );
+    }
+    let response = send_message(&pool, me, "0000").await?;
+    assert!(response.contains("Try again in 15 minutes"), "{response}");
+    let response = send_message(&pool, me, "stop").await?;
+    assert!(response.contains("Too many wrong PINs"), "{response}");
+    let response = send_message(&pool, me, "pin off 1234").await?;
+    assert!(response.contains("Too many wrong PINs"), "{response}");
+    query!("UPDATE pins SET locked_until = unixepoch() - 1")
+        .execute(&pool)
+        .await?;
+
+    // Changing or removing it takes the current one
+    let response = send_message(&pool, me, "pin 0000 5678").await?;
+    assert!(response.contains("That's not your PIN"), "{response}");
+    let response = send_message(&pool, me, "pin 1234 5678").await?;
+    assert!(response.contains("Your PIN is set"), "{response}");
+    send_message(&pool, me, "stop").await?;
+    let response = send_message(&pool, me, "5678").await?;
+    assert!(response.contains("You've been unsubscribed"), "{response}");
+    send_message(&pool, me, "start").await?;
+    let response = send_message(&pool, me, "pin off 5678").await?;
+    assert!(response.contains("Your PIN is removed"), "{response}");
+    let response = send_message(&pool, me, "export").await?;
+    assert!(response.contains("Your data export is ready"), "{response}");
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+//! Each user's time zone, so times they type are read, and times they're shown are written,
+//! the way their own clock reads. Users who haven't set one get UTC.
+
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::{Tz, TZ_VARIANTS};
+use sqlx::{query, Pool, Sqlite};
+
+use crate::{
+    audit::{self, Kind},
+    command::Command,
+    i18n::{t, Lang},
+};
+
+/// Common abbreviations, mapped to a zone that observes them (and their daylight counterparts)
+const ABBREVIATIONS: &[(&str, Tz)] = &[
+    ("UTC", Tz::UTC),
+    ("GMT", Tz::UTC),
+    ("HST", Tz::Pacific__Honolulu),
+    ("AKST", Tz::America__Anchorage),
+    ("AKDT", Tz::America__Anchorage),
+    ("PST", Tz::America__Los_Angeles),
+    ("PDT", Tz::America__Los_Angeles),
+    ("PT", Tz::America__Los_Angeles),
+    ("MST", Tz::America__Denver),
+    ("MDT", Tz::America__Denver),
+    ("MT", Tz::America__Denver),
+    ("CST", Tz::America__Chicago),
+    ("CDT", Tz::America__Chicago),
+    ("CT", Tz::America__Chicago),
+    ("EST", Tz::America__New_York),
+    ("EDT", Tz::America__New_York),
+    ("ET", Tz::America__New_York),
+    ("AST", Tz::America__Puerto_Rico),
+    ("BST", Tz::Europe__London),
+    ("CET", Tz::Europe__Paris),
+    ("CEST", Tz::Europe__Paris),
+];
+
+/// Reads an IANA name ("America/New_York"), an abbreviation ("PST"),
+/// or just the city of an IANA name ("new york"), ignoring case
+pub fn parse(text: &str) -> Option<Tz> {
+    let text = text.trim();
+    if let Some(zone) = TZ_VARIANTS
+        .iter()
+        .find(|zone| zone.name().eq_ignore_ascii_case(text))
+    {
+        return Some(*zone);
+    }
+    if let Some((_, zone)) = ABBREVIATIONS
+        .iter()
+        .find(|(abbreviation, _)| abbreviation.eq_ignore_ascii_case(text))
+    {
+        return Some(*zone);
+    }
+    let city = text.replace(' ', "_");
+    TZ_VARIANTS.iter().copied().find(|zone| {
+        zone.name()
+            .rsplit_once('/')
+            .is_some_and(|(_, name)| name.eq_ignore_ascii_case(&city))
+    })
+}
+
+/// A user's zone as stored, falling back to UTC if it's unset or no longer recognized
+pub fn from_stored(stored: Option<&str>) -> Tz {
+    stored.and_then(|name| name.parse().ok()).unwrap_or(Tz::UTC)
+}
+
+/// A moment as shown to someone in `zone`, e.g. "2024-12-19 09:00 PST"
+pub fn display(time: DateTime<Utc>, zone: Tz) -> String {
+    time.with_timezone(&zone)
+        .format("%Y-%m-%d %H:%M %Z")
+        .to_string()
+}
+
+/// The day a stored timestamp falls on in `zone`
+pub fn display_date(timestamp: i64, zone: Tz) -> String {
+    match Utc.timestamp_opt(timestamp, 0).single() {
+        Some(time) => time.with_timezone(&zone).format("%Y-%m-%d").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+/// `timezone` shows the user's zone and `timezone X` changes it
+pub async fn handle_timezone(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    current: Tz,
+    requested: &str,
+) -> Result<String> {
+    if requested.is_empty() {
+        return Ok(t!(
+            lang,
+            "timezone_current",
+            zone = current.name(),
+            time = display(Utc::now(), current),
+            hint = Command::timezone.hint(lang)
+        ));
+    }
+    let Some(zone) = parse(requested) else {
+        return Ok(t!(lang, "timezone_unknown", zone = requested));
+    };
+    let name = zone.name();
+    query!("UPDATE users SET timezone = ? WHERE number = ?", name, from)
+        .execute(pool)
+        .await?;
+    audit::record(
+        pool,
+        from,
+        Kind::Mutation,
+        format!("set time zone to {name}"),
+    )
+    .await?;
+    Ok(t!(
+        lang,
+        "timezone_updated",
+        zone = name,
+        time = display(Utc::now(), zone)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_zones() {
+        assert_eq!(parse("America/New_York"), Some(Tz::America__New_York));
+        assert_eq!(parse("europe/madrid"), Some(Tz::Europe__Madrid));
+        assert_eq!(parse("pst"), Some(Tz::America__Los_Angeles));
+        assert_eq!(parse("Mexico City"), Some(Tz::America__Mexico_City));
+        assert_eq!(parse("UTC"), Some(Tz::UTC));
+        assert_eq!(parse("Narnia"), None);
+        assert_eq!(from_stored(Some("Narnia")), Tz::UTC);
+    }
+
+    #[test]
+    fn displays_local_times() {
+        // 17:00 UTC in winter
+        let time = Utc.with_ymd_and_hms(2024, 12, 18, 17, 0, 0).unwrap();
+        assert_eq!(
+            display(time, Tz::America__Los_Angeles),
+            "2024-12-18 09:00 PST"
+        );
+        assert_eq!(display(time, Tz::UTC), "2024-12-18 17:00 UTC");
+        assert_eq!(
+            display_date(time.timestamp(), Tz::Asia__Tokyo),
+            "2024-12-19"
+        );
+    }
+}
@@ -0,0 +1,65 @@
+//! Serving HTTPS directly, for deployments without a reverse proxy in front.
+//!
+//! Set `TLS_CERT_PATH` and `TLS_KEY_PATH` to PEM files (e.g. from certbot) and the callback
+//! listener speaks HTTPS instead of HTTP. The files are re-read every few hours, so a renewed
+//! certificate is picked up without a restart.
+
+use std::{io, path::PathBuf, time::Duration};
+
+use anyhow::{Context, Result};
+use axum::Router;
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use tracing::*;
+
+use crate::{config, shutdown::Shutdown};
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// The configured certificate, or None to serve plain HTTP
+pub async fn configured() -> Result<Option<RustlsConfig>> {
+    let config = config::get();
+    let (Some(cert), Some(key)) = (&config.tls_cert_path, &config.tls_key_path) else {
+        return Ok(None);
+    };
+    // Only one provider is compiled in, but rustls wants it chosen explicitly
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let tls = RustlsConfig::from_pem_file(cert, key)
+        .await
+        .with_context(|| format!("While loading {} and {}", cert.display(), key.display()))?;
+    tokio::spawn(reload(tls.clone(), cert.clone(), key.clone()));
+    Ok(Some(tls))
+}
+
+async fn reload(tls: RustlsConfig, cert: PathBuf, key: PathBuf) {
+    let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+    interval.tick().await; // The first tick is immediate, and the files were just read
+    loop {
+        interval.tick().await;
+        match tls.reload_from_pem_file(&cert, &key).await {
+            Ok(()) => debug!("Reloaded {}", cert.display()),
+            // Keeps serving the old certificate, which may well still be valid
+            Err(error) => warn!("Couldn't reload {}: {error}", cert.display()),
+        }
+    }
+}
+
+/// Serves `app` over HTTPS until shutdown is requested, then finishes with the open connections
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    tls: RustlsConfig,
+    shutdown: Shutdown,
+) -> io::Result<()> {
+    let handle = Handle::new();
+    tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            shutdown.requested().await;
+            handle.graceful_shutdown(None);
+        }
+    });
+    axum_server::from_tcp_rustls(listener.into_std()?, tls)
+        .handle(handle)
+        .serve(app.into_make_service())
+        .await
+}
@@ -0,0 +1,124 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use sqlx::{query, query_as, Pool, Sqlite};
+
+use crate::{
+    audit::{self, Kind},
+    command::Command,
+    i18n::{t, Lang},
+    util::E164,
+    Contact,
+};
+
+/// How long deleted contacts can be restored before the cleanup job purges them
+pub const RETENTION_SECS: i64 = 30 * 24 * 60 * 60;
+const RETENTION_DAYS: i64 = RETENTION_SECS / (24 * 60 * 60);
+
+/// Deleted contacts, in the order they're numbered for `restore`
+async fn trashed(pool: &Pool<Sqlite>, from: &str) -> Result<Vec<Contact>> {
+    Contact::open_all(
+        query_as!(
+            Contact,
+            "SELECT id as \"id!\", contact_name, contact_user_number
+             FROM contacts
+             WHERE submitter_number = ? AND deleted_at IS NOT NULL",
+            from
+        )
+        .fetch_all(pool)
+        .await?,
+    )
+}
+
+fn line(contact: &Contact) -> String {
+    let area_code = E164::from_str(&contact.contact_user_number)
+        .map(|e| e.short_form())
+        .unwrap_or_else(|_| "???".to_string());
+    format!("{} ({})", contact.contact_name, area_code)
+}
+
+pub async fn handle_trash(pool: &Pool<Sqlite>, from: &str, lang: Lang) -> Result<String> {
+    let contacts = trashed(pool, from).await?;
+    if contacts.is_empty() {
+        return Ok(t!(lang, "trash_empty", days = RETENTION_DAYS));
+    }
+    let mut response = t!(lang, "your_trash", days = RETENTION_DAYS);
+    for (i, contact) in contacts.iter().enumerate() {
+        response.push_str(&format!("{}. {}\n", i + 1, line(contact)));
+    }
+    response.push_str(&t!(
+        lang,
+        "restore_instructions",
+        command = Command::restore
+    ));
+    Ok(response)
+}
+
+pub async fn handle_restore(
+    pool: &Pool<Sqlite>,
+    from: &str,
+    lang: Lang,
+    selections: &str,
+) -> Result<String> {
+    let contacts = trashed(pool, from).await?;
+    let mut invalid = Vec::new();
+    let mut selected = Vec::new();
+    for num_str in selections.split(',').map(str::trim) {
+        match num_str.parse::<usize>() {
+            Ok(num) if num > 0 => match contacts.get(num - 1) {
+                Some(contact) => {
+                    if !selected.iter().any(|c: &&Contact| c.id == contact.id) {
+                        selected.push(contact);
+                    }
+                }
+                None => invalid.push(t!(lang, "invalid_selection", selection = num)),
+            },
+            _ => invalid.push(t!(lang, "invalid_number", number = num_str)),
+        }
+    }
+    if selected.is_empty() && invalid.is_empty() {
+        return Ok(t!(lang, "no_valid_selections"));
+    }
+
+    let mut tx = pool.begin().await?;
+    for contact in &selected {
+        query!(
+            "UPDATE contacts SET deleted_at = NULL WHERE id = ?",
+            contact.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        audit::record(
+            &mut *tx,
+            from,
+            Kind::Mutation,
+            format!(
+                "restored contact \"{}\" ({})",
+                contact.contact_name, contact.contact_user_number
+            ),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    let mut response = String::new();
+    if !selected.is_empty() {
+        response.push_str(&t!(
+            lang,
+            "contacts_restored",
+            count = selected.len(),
+            s = if selected.len() == 1 { "" } else { "s" }
+        ));
+        for contact in &selected {
+            response.push_str(&format!("• {}\n", line(contact)));
+        }
+    }
+    if !invalid.is_empty() {
+        if !response.is_empty() {
+            response.push('\n');
+        }
+        response.push_str(&t!(lang, "errors"));
+        response.push_str(&invalid.join("\n"));
+    }
+    Ok(response)
+}
@@ -0,0 +1,189 @@
+//! Building the TwiML that answers Twilio's webhook, with everything the bot says escaped so a
+//! contact named "Tom & Jerry <3" can't break the XML. Replies too long for one text, like a big
+//! contact list, go out as several.
+
+use std::fmt::Write;
+
+use axum::response::Html;
+
+use crate::encoding::Encoding;
+
+/// Twilio recommends keeping texts to this many segments, so they arrive in one piece
+const MAX_SEGMENTS: usize = 10;
+
+/// A reply of zero or more texts
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Twiml {
+    messages: Vec<Message>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Message {
+    body: String,
+    media: Vec<String>,
+}
+
+impl Twiml {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a text to the reply
+    pub fn message(mut self, body: impl Into<String>) -> Self {
+        self.messages.push(Message {
+            body: body.into(),
+            media: Vec::new(),
+        });
+        self
+    }
+
+    /// Adds a reply, as several texts if it's too long for one
+    pub fn reply(self, body: &str) -> Self {
+        split(body).into_iter().fold(self, Twiml::message)
+    }
+
+    /// Attaches media to the last text, starting an empty one if there isn't one yet
+    pub fn media(mut self, url: impl Into<String>) -> Self {
+        if self.messages.is_empty() {
+            self = self.message("");
+        }
+        if let Some(message) = self.messages.last_mut() {
+            message.media.push(url.into());
+        }
+        self
+    }
+
+    pub fn render(&self) -> String {
+        let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?><Response>"#);
+        for message in &self.messages {
+            if message.media.is_empty() {
+                let _ = write!(xml, "<Message>{}</Message>", escape(&message.body));
+            } else {
+                xml.push_str("<Message>");
+                if !message.body.is_empty() {
+                    let _ = write!(xml, "<Body>{}</Body>", escape(&message.body));
+                }
+                for url in &message.media {
+                    let _ = write!(xml, "<Media>{}</Media>", escape(url));
+                }
+                xml.push_str("</Message>");
+            }
+        }
+        xml.push_str("</Response>");
+        xml
+    }
+}
+
+impl From<Twiml> for Html<String> {
+    fn from(twiml: Twiml) -> Self {
+        Html(twiml.render())
+    }
+}
+
+/// The texts a reply goes out as
+pub fn split(body: &str) -> Vec<String> {
+    let encoding = Encoding::of(body);
+    split_within(body, encoding, encoding.per_segment() * MAX_SEGMENTS)
+}
+
+/// Splits `body` into parts no longer than `max` in `encoding`, between lines where it can
+fn split_within(body: &str, encoding: Encoding, max: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut part_len = 0;
+    for mut line in body.split_inclusive('\n') {
+        loop {
+            let len = encoding.length(line);
+            if part_len + len <= max {
+                part.push_str(line);
+                part_len += len;
+                break;
+            }
+            if part_len > 0 {
+                parts.push(std::mem::take(&mut part));
+                part_len = 0;
+                continue;
+            }
+            // A single line too long for a text of its own
+            let mut width = 0;
+            let cut = line
+                .char_indices()
+                .find(|&(_, c)| {
+                    width += encoding.width(c);
+                    width > max
+                })
+                .map_or(line.len(), |(i, _)| i);
+            parts.push(line[..cut].to_string());
+            line = &line[cut..];
+        }
+    }
+    parts.push(part);
+    let parts = parts
+        .iter()
+        .map(|part| part.trim_matches('\n'))
+        .filter(|part| !part.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if parts.is_empty() {
+        vec![body.to_string()]
+    } else {
+        parts
+    }
+}
+
+/// Escapes the characters that mean something in XML text
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[test]
+fn renders_escaped_messages() {
+    assert_eq!(
+        Twiml::new().render(),
+        r#"<?xml version="1.0" encoding="UTF-8"?><Response></Response>"#
+    );
+    assert_eq!(
+        Twiml::new()
+            .message("Added Tom & Jerry <3")
+            .message("2 of 2")
+            .render(),
+        r#"<?xml version="1.0" encoding="UTF-8"?><Response><Message>Added Tom &amp; Jerry &lt;3</Message><Message>2 of 2</Message></Response>"#
+    );
+    assert_eq!(
+        Twiml::new()
+            .message("Your card")
+            .media("https://example.com/card.vcf?a=1&b=2")
+            .render(),
+        r#"<?xml version="1.0" encoding="UTF-8"?><Response><Message><Body>Your card</Body><Media>https://example.com/card.vcf?a=1&amp;b=2</Media></Message></Response>"#
+    );
+}
+
+#[test]
+fn splits_long_replies() {
+    let within = |body, max| split_within(body, Encoding::Gsm7, max);
+    assert_eq!(within("", 10), vec![""]);
+    assert_eq!(within("short", 10), vec!["short"]);
+    assert_eq!(
+        within("1. Alice\n2. Bob\n3. Carol", 10),
+        vec!["1. Alice", "2. Bob", "3. Carol"]
+    );
+    assert_eq!(within("ab\ncd\n\nef", 6), vec!["ab\ncd", "ef"]);
+    assert_eq!(within("ééééééééééééé", 5), vec!["ééééé", "ééééé", "ééé"]);
+    assert_eq!(within("€€€€€", 4), vec!["€€", "€€", "€"]);
+    assert_eq!(
+        split_within("😀😀😀", Encoding::Ucs2, 5),
+        vec!["😀😀", "😀"]
+    );
+    // An emoji anywhere means the whole reply gets less room per text
+    assert_eq!(split(&"a\n".repeat(1000)).len(), 2);
+    assert_eq!(split(&format!("😀\n{}", "a\n".repeat(1000))).len(), 3);
+}
@@ -1,50 +1,223 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
+use phonenumber::{country, metadata::DATABASE, Mode};
 use std::fmt::Display;
 use std::str::FromStr;
 
+/// Whether the region uses the North American Numbering Plan, i.e. country code 1
+fn north_american(region: country::Id) -> bool {
+    DATABASE
+        .by_id(region.as_ref())
+        .is_some_and(|metadata| metadata.country_code() == 1)
+}
+
 /// E164 phone number format validator and parser
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct E164(String);
+pub struct E164 {
+    number: String,
+    /// Digits dialled after the call connects, which texts can't reach
+    extension: Option<String>,
+}
 
 impl E164 {
-    /// Returns the area code (NPA) portion of the phone number
-    pub fn area_code(&self) -> &str {
-        &self.0[2..5]
+    /// A short form for telling contacts apart in lists: the area code (NPA) for North American
+    /// numbers, or the country code and first group of digits elsewhere, e.g. "+44 7911"
+    pub fn short_form(&self) -> String {
+        if self.is_north_american() {
+            return self.number[2..5].to_string();
+        }
+        match phonenumber::parse(None, &self.number) {
+            Ok(number) => number
+                .format()
+                .mode(Mode::International)
+                .to_string()
+                .split(' ')
+                .take(2)
+                .collect::<Vec<_>>()
+                .join(" "),
+            Err(_) => self.number.clone(),
+        }
+    }
+
+    /// The number the way `viewer` would write it: in national format if they're in the same
+    /// country, e.g. "(987) 654-3210" or "07911 123456", and in international format otherwise,
+    /// e.g. "+1 987-654-3210" or "+44 7911 123456"
+    pub fn display_for(&self, viewer: Option<&E164>) -> String {
+        let national = viewer.is_some_and(|viewer| viewer.country_code() == self.country_code());
+        if self.is_north_american() {
+            // Formatted by hand, since numbers that aren't in service don't format
+            let (area, exchange, line) =
+                (&self.number[2..5], &self.number[5..8], &self.number[8..]);
+            return if national {
+                format!("({area}) {exchange}-{line}")
+            } else {
+                format!("+1 {area}-{exchange}-{line}")
+            };
+        }
+        match phonenumber::parse(None, &self.number) {
+            Ok(number) => number
+                .format()
+                .mode(if national {
+                    Mode::National
+                } else {
+                    Mode::International
+                })
+                .to_string(),
+            Err(_) => self.number.clone(),
+        }
     }
 
     /// Returns the full E164 formatted string
-    #[allow(unused)]
     pub fn as_str(&self) -> &str {
-        &self.0
+        &self.number
+    }
+
+    /// The extension written after the number, like "204" in "555-0100 x204"
+    pub fn extension(&self) -> Option<&str> {
+        self.extension.as_deref()
+    }
+
+    fn is_north_american(&self) -> bool {
+        self.number.starts_with("+1")
+    }
+
+    fn country_code(&self) -> Option<u16> {
+        if self.is_north_american() {
+            return Some(1);
+        }
+        phonenumber::parse(None, &self.number)
+            .ok()
+            .map(|number| number.code().value())
     }
 }
 
 impl Display for E164 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.number)
     }
 }
 
+/// Whether a sender is someone's phone number, rather than a short code like "12345" or an
+/// alphanumeric sender ID like "ACME", neither of which can sign up or be replied to
+pub fn is_phone_number(sender: &str) -> bool {
+    sender
+        .strip_prefix('+')
+        .is_some_and(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()))
+        && E164::from_str(sender).is_ok()
+}
+
 impl FromStr for E164 {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // Strip all non-digit characters
-        let digits: String = s.chars().filter(|c| c.is_ascii_digit()).collect();
+        parse(s, crate::config::get().phone_region)
+    }
+}
+
+/// Ways an extension is marked off from the number, most specific first
+const EXTENSION_MARKERS: [&str; 6] = [";ext=", "extension", "ext.", "ext", "x", "#"];
+
+/// Splits an extension off the end of a number, e.g. "555-0100 x204" or "tel:+15550100;ext=204"
+fn split_extension(s: &str) -> (&str, Option<String>) {
+    let lower = s.to_ascii_lowercase();
+    for marker in EXTENSION_MARKERS {
+        let Some(at) = lower.rfind(marker) else {
+            continue;
+        };
+        let extension = lower[at + marker.len()..].trim();
+        if !extension.is_empty() && extension.chars().all(|c| c.is_ascii_digit()) {
+            return (&s[..at], Some(extension.to_string()));
+        }
+    }
+    (s, None)
+}
+
+/// The digit each letter shares a key with on a phone's keypad
+fn keypad_digit(letter: char) -> Option<char> {
+    Some(match letter.to_ascii_uppercase() {
+        'A'..='C' => '2',
+        'D'..='F' => '3',
+        'G'..='I' => '4',
+        'J'..='L' => '5',
+        'M'..='O' => '6',
+        'P'..='S' => '7',
+        'T'..='V' => '8',
+        'W'..='Z' => '9',
+        _ => return None,
+    })
+}
 
-        let normalized = match digits.len() {
+/// Reads a number in any common format, taking numbers without a country code to be from `region`.
+/// Vanity numbers like 1-800-FLOWERS are spelled out in digits, and an extension is kept apart.
+pub fn parse(s: &str, region: country::Id) -> Result<E164> {
+    let s = s.trim();
+    let s = s
+        .get(..4)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("tel:"))
+        .map_or(s, |_| &s[4..]);
+    let (s, extension) = split_extension(s);
+    // Everything but digits, letters and a leading "+" is punctuation, however much of it
+    // a contacts app put there
+    let plus = s.trim_start().starts_with('+');
+    // "+44 (0) 20..." shows the trunk 0 dialled within the country, which isn't part of the number
+    let s = if plus {
+        s.replace("(0)", "")
+    } else {
+        s.to_string()
+    };
+    let kept: String = s.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    if kept.is_empty() {
+        bail!("Not a phone number");
+    }
+    // Letters only spell out a vanity number when the digits alone aren't enough for one
+    let vanity = kept.chars().any(|c| c.is_ascii_alphabetic());
+    if vanity && !(3..10).contains(&kept.chars().filter(|c| c.is_ascii_digit()).count()) {
+        bail!("Not a phone number: it has letters that don't spell out a vanity number");
+    }
+    let mut digits: String = kept.chars().map(|c| keypad_digit(c).unwrap_or(c)).collect();
+    let international = plus || digits.starts_with("00");
+    if !plus {
+        if let Some(rest) = digits.strip_prefix("00") {
+            digits = rest.to_string();
+        }
+    }
+
+    // North American numbers are taken as they come, without checking that the area code and
+    // exchange are ones actually in service. A "+" in front of a 10-digit number is forgiven.
+    let north_american_number = match international {
+        true => digits.starts_with('1'),
+        false => north_american(region),
+    };
+    if north_american_number {
+        // Vanity numbers are often spelled with more letters than there are digits to dial
+        if vanity {
+            digits.truncate(if digits.starts_with('1') { 11 } else { 10 });
+        }
+        let number = match digits.len() {
             // Handle international format with country code
-            11 if digits.starts_with('1') => format!("+{}", digits),
+            11 if digits.starts_with('1') => format!("+{digits}"),
 
             // Handle 10-digit US/Canada numbers
-            10 => format!("+1{}", digits),
+            10 => format!("+1{digits}"),
 
             // Invalid length
             _ => bail!("Phone number must be 10 digits (or 11 digits starting with 1)"),
         };
+        return Ok(E164 { number, extension });
+    }
 
-        Ok(E164(normalized))
+    let written = if international {
+        format!("+{digits}")
+    } else {
+        digits
+    };
+    let number = phonenumber::parse(Some(region), written).context("Not a phone number")?;
+    if !number.is_valid() {
+        bail!("Not a valid phone number");
     }
+    Ok(E164 {
+        number: number.format().mode(Mode::E164).to_string(),
+        extension,
+    })
 }
 
 #[cfg(test)]
@@ -84,8 +257,83 @@ mod tests {
     }
 
     #[test]
-    fn test_area_code() {
+    fn test_international_parsing() {
+        for (input, expected) in [
+            ("+44 7911 123456", "+447911123456"),
+            ("0044 20 7946 0958", "+442079460958"),
+            ("+34 612 345 678", "+34612345678"),
+            ("+61 2 9374 4000", "+61293744000"),
+        ] {
+            assert_eq!(E164::from_str(input).unwrap().as_str(), expected);
+        }
+        // Not a real UK mobile number
+        assert!(E164::from_str("+44 1").is_err());
+        // UK numbers written the way they're dialled within the UK
+        assert_eq!(
+            parse("07911 123456", country::Id::GB).unwrap().as_str(),
+            "+447911123456"
+        );
+        assert_eq!(
+            parse("+1 416 555 0199", country::Id::GB).unwrap().as_str(),
+            "+14165550199"
+        );
+        assert!(parse("4165550199", country::Id::GB).is_err());
+    }
+
+    #[test]
+    fn test_messy_numbers() {
+        for (input, expected, extension) in [
+            ("1-800-FLOWERS", "+18003569377", None),
+            ("1 (800) MATTRESS", "+18006288737", None),
+            ("800-555-0100 x204", "+18005550100", Some("204")),
+            ("(800) 555-0100 ext. 7", "+18005550100", Some("7")),
+            ("+1 800 555 0100 #12", "+18005550100", Some("12")),
+            ("tel:+1-800-555-0100;ext=99", "+18005550100", Some("99")),
+            (
+                "\u{202d}+1\u{a0}(800)\u{2011}555\u{2011}0100\u{202c}",
+                "+18005550100",
+                None,
+            ),
+            ("1-800-TAXI-CAB", "+18008294222", None),
+            ("+44 (0) 7911-123-456 x3", "+447911123456", Some("3")),
+        ] {
+            let number = E164::from_str(input).unwrap();
+            assert_eq!(number.as_str(), expected, "{input}");
+            assert_eq!(number.extension(), extension, "{input}");
+        }
+        assert!(E164::from_str("John Smith").is_err());
+        assert!(E164::from_str("x204").is_err());
+        assert!(E164::from_str("---").is_err());
+        assert!(E164::from_str("800-555-0100 ext").is_err());
+    }
+
+    #[test]
+    fn test_senders() {
+        assert!(is_phone_number("+19876543210"));
+        assert!(is_phone_number("+447911123456"));
+        assert!(!is_phone_number("12345"));
+        assert!(!is_phone_number("+12345"));
+        assert!(!is_phone_number("ACME"));
+        assert!(!is_phone_number("AMAZON1234"));
+        assert!(!is_phone_number(""));
+    }
+
+    #[test]
+    fn test_short_form() {
         let number = E164::from_str("123-456-7890").unwrap();
-        assert_eq!(number.area_code(), "123");
+        assert_eq!(number.short_form(), "123");
+        let number = E164::from_str("+44 7911 123456").unwrap();
+        assert_eq!(number.short_form(), "+44 7911");
+    }
+
+    #[test]
+    fn test_display_for() {
+        let us = E164::from_str("987-654-3210").unwrap();
+        let uk = E164::from_str("+44 7911 123456").unwrap();
+        assert_eq!(us.display_for(Some(&us)), "(987) 654-3210");
+        assert_eq!(us.display_for(Some(&uk)), "+1 987-654-3210");
+        assert_eq!(uk.display_for(Some(&uk)), "07911 123456");
+        assert_eq!(uk.display_for(Some(&us)), "+44 7911 123456");
+        assert_eq!(uk.display_for(None), "+44 7911 123456");
     }
 }
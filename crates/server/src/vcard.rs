@@ -0,0 +1,170 @@
+//! Recognizing vCard attachments and cleaning them up before they're parsed.
+//!
+//! Some carriers deliver vCards as `application/octet-stream`, so attachments without a useful
+//! content type are fetched and checked for a card. Older Android and Outlook exports (vCard 2.1)
+//! write values as QUOTED-PRINTABLE, often in a CHARSET other than UTF-8, which the parser would
+//! otherwise pass through as "Jos=C3=A9" or mangle into replacement characters.
+
+use anyhow::Result;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
+use crate::media;
+
+/// Content types that are always vCards
+const VCARD_TYPES: [&str; 3] = ["text/vcard", "text/x-vcard", "text/directory"];
+
+/// Content types that say nothing about what's inside, so could be vCards
+const GENERIC_TYPES: [&str; 3] = [
+    "application/octet-stream",
+    "text/plain",
+    "binary/octet-stream",
+];
+
+/// The attachment at `url`, if it's a vCard, judging by its content type, the file name at the
+/// end of its URL, or failing those, what's in it
+pub async fn attached(content_type: Option<&str>, url: &str) -> Result<Option<Vec<u8>>> {
+    let content_type = content_type
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase());
+    let declared = content_type
+        .as_deref()
+        .is_some_and(|value| VCARD_TYPES.contains(&value));
+    let named = url
+        .split(['?', '#'])
+        .next()
+        .is_some_and(|path| path.to_ascii_lowercase().ends_with(".vcf"));
+    let generic = content_type
+        .as_deref()
+        .is_none_or(|value| GENERIC_TYPES.contains(&value));
+    if !declared && !named && !generic {
+        return Ok(None);
+    }
+    let data = media::fetch(url).await?;
+    Ok((declared || named || sniff(&data)).then_some(data))
+}
+
+/// Whether `data` starts like a vCard file
+fn sniff(data: &[u8]) -> bool {
+    let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+    let start = data
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(data.len());
+    data[start..]
+        .get(..b"BEGIN:VCARD".len())
+        .is_some_and(|begin| begin.eq_ignore_ascii_case(b"BEGIN:VCARD"))
+}
+
+/// The file as UTF-8 text, with every quoted-printable value decoded and every value in another
+/// charset converted
+pub fn decode(data: &[u8]) -> String {
+    let mut lines = data
+        .split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+    let mut decoded = Vec::new();
+    while let Some(line) = lines.next() {
+        let Some(colon) = line.iter().position(|&b| b == b':') else {
+            decoded.push(text(line, None));
+            continue;
+        };
+        let (head, value) = (&line[..colon], &line[colon + 1..]);
+        let head = String::from_utf8_lossy(head);
+        let mut params = head.split(';');
+        let name = params.next().unwrap_or_default();
+        let mut quoted_printable = false;
+        let mut charset = None;
+        let mut kept = vec![name];
+        for param in params {
+            let (key, param_value) = param.split_once('=').unwrap_or(("", param));
+            if param_value.eq_ignore_ascii_case("QUOTED-PRINTABLE")
+                && (key.is_empty() || key.eq_ignore_ascii_case("ENCODING"))
+            {
+                quoted_printable = true;
+            } else if key.eq_ignore_ascii_case("CHARSET") {
+                charset = Encoding::for_label(param_value.trim_matches('"').as_bytes());
+            } else {
+                kept.push(param);
+            }
+        }
+        let value = if quoted_printable {
+            // A value that ends in "=" carries on onto the next line
+            let mut value = value.to_vec();
+            while value.last() == Some(&b'=') {
+                value.pop();
+                match lines.next() {
+                    Some(next) => value.extend_from_slice(next),
+                    None => break,
+                }
+            }
+            text(&unquote(&value), charset)
+                .replace("\r\n", " ")
+                .replace('\n', " ")
+        } else {
+            text(value, charset)
+        };
+        decoded.push(format!("{}:{value}", kept.join(";")));
+    }
+    decoded.join("\n")
+}
+
+/// Bytes as text in `charset`, or when it isn't given, UTF-8 if they're valid UTF-8 and
+/// otherwise Windows-1252, which is what Outlook writes
+fn text(bytes: &[u8], charset: Option<&'static Encoding>) -> String {
+    let charset = charset.unwrap_or(match std::str::from_utf8(bytes) {
+        Ok(_) => UTF_8,
+        Err(_) => WINDOWS_1252,
+    });
+    charset.decode_without_bom_handling(bytes).0.into_owned()
+}
+
+/// Decodes quoted-printable "=XX" escapes, leaving anything malformed as it is
+fn unquote(value: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut i = 0;
+    while i < value.len() {
+        let escaped = (value[i] == b'=')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(value[i]);
+                i += 1;
+            }
+        }
+    }
+    bytes
+}
+
+#[test]
+fn decodes_old_exports() {
+    let exported = b"BEGIN:VCARD\r\nVERSION:2.1\r\n\
+        N;CHARSET=UTF-8;ENCODING=QUOTED-PRINTABLE:Garc=C3=ADa;Jos=C3=A9;;;\r\n\
+        FN;CHARSET=ISO-8859-1;QUOTED-PRINTABLE:Jos=E9 Garc=EDa =\r\n\
+        (m=F3vil)\r\n\
+        TEL;CELL:+19876543210\r\nEND:VCARD\r\n\
+        BEGIN:VCARD\r\nVERSION:2.1\r\nFN:Ren\xe9e Fran\xe7ois\r\nTEL:+19876543211\r\nEND:VCARD\r\n";
+    let decoded = decode(exported);
+    assert!(decoded.contains("\nN:García;José;;;\n"), "{decoded}");
+    assert!(decoded.contains("\nFN:José García (móvil)\n"), "{decoded}");
+    assert!(decoded.contains("\nTEL;CELL:+19876543210\n"), "{decoded}");
+    assert!(decoded.contains("\nFN:Renée François\n"), "{decoded}");
+
+    // Already clean files come through unchanged
+    let clean = "BEGIN:VCARD\nVERSION:3.0\nFN:Zoë Ünal\nTEL;TYPE=CELL:+19876543210\nEND:VCARD";
+    assert_eq!(decode(clean.as_bytes()), clean);
+}
+
+#[test]
+fn sniffs_vcards() {
+    assert!(sniff(b"BEGIN:VCARD\nVERSION:3.0\nEND:VCARD"));
+    assert!(sniff(b"\xef\xbb\xbf\r\n  begin:vcard\r\n"));
+    assert!(!sniff(b"\x89PNG\r\n"));
+    assert!(!sniff(b"BEGIN:VCALENDAR"));
+    assert!(!sniff(b""));
+}
@@ -0,0 +1,261 @@
+//! One-time codes texted to a number to prove whoever's asking can read texts there,
+//! shared by everything that needs that rather than each feature rolling its own.
+//!
+//! [start] records what the code is for and where it goes, and queues a
+//! [Job::VerificationCode] that makes up the code and texts it. Only a salted hash of the code,
+//! keyed with [pii::hash_secret], is stored, so it's never in the database, backups or the job
+//! queue, and can't be worked out from them either. [check] takes the code
+//! back, allowing [MAX_ATTEMPTS] wrong ones before the code is thrown away, and the code only
+//! works until it expires. Each user has at most one code per [Purpose]; starting again
+//! replaces it.
+
+use anyhow::{bail, Result};
+use rand::{thread_rng, Rng, RngCore};
+use sqlx::{query, Pool, Sqlite, SqliteExecutor};
+use subtle::ConstantTimeEq;
+use tracing::*;
+
+use crate::{
+    i18n::{t, Lang},
+    jobs::{self, Job},
+    outbound::{self, Outgoing},
+    pii,
+};
+
+/// Wrong codes allowed before the code is thrown away
+pub const MAX_ATTEMPTS: i64 = 5;
+
+/// What a code proves, which decides what it says and how long it lasts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    /// Moving an account to the number the code goes to, with `movenumber`
+    MoveNumber,
+}
+
+impl Purpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Purpose::MoveNumber => "move_number",
+        }
+    }
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "move_number" => Ok(Purpose::MoveNumber),
+            _ => bail!("Unknown verification purpose \"{s}\""),
+        }
+    }
+
+    pub fn ttl_secs(&self) -> i64 {
+        match self {
+            Purpose::MoveNumber => 15 * 60,
+        }
+    }
+
+    /// The text the code goes out in, for the user named `name`
+    fn message(&self, lang: Lang, name: &str, code: &str) -> String {
+        match self {
+            Purpose::MoveNumber => t!(lang, "move_code", name = name, code = code),
+        }
+    }
+}
+
+/// How a code checked out
+#[derive(Debug, PartialEq, Eq)]
+pub enum Check {
+    /// Right, and now used up. Holds where it was sent (sealed).
+    Valid { sent_to: String },
+    /// Wrong, with this many tries left
+    Wrong { left: i64 },
+    /// Wrong too many times, so it's gone
+    TooManyAttempts,
+    /// Too late, so it's gone
+    Expired,
+    /// There's no code to check
+    NotStarted,
+}
+
+/// Hashes a code (or a PIN) with its salt, for storing and comparing
+pub fn hash(salt: &str, code: &str) -> String {
+    pii::hash_secret(&format!("{salt}{code}"))
+}
+
+/// Whether `code` (or a PIN) is the one `stored` is the [hash] of. Takes as long however much
+/// of it matches, so timing it gives nothing away.
+pub fn matches(salt: &str, code: &str, stored: &str) -> bool {
+    hash(salt, code).as_bytes().ct_eq(stored.as_bytes()).into()
+}
+
+/// A fresh random salt for [hash]
+pub fn salt() -> String {
+    let mut salt = [0u8; 16];
+    thread_rng().fill_bytes(&mut salt);
+    salt.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Queues a code for `number` (sealed) to be texted to `send_to` (sealed), replacing any
+/// they already had for the same purpose. Meant to be part of the caller's transaction,
+/// so the code only goes out if whatever asked for it happens.
+pub async fn start(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    number: &str,
+    purpose: Purpose,
+    send_to: &str,
+) -> Result<()> {
+    let (purpose_name, ttl) = (purpose.as_str(), purpose.ttl_secs());
+    let salt = salt();
+    cancel(&mut **tx, number, purpose).await?;
+    let id = query!(
+        "INSERT INTO verification_codes (number, purpose, send_to, salt, expires_at)
+         VALUES (?, ?, ?, ?, unixepoch() + ?)",
+        number,
+        purpose_name,
+        send_to,
+        salt,
+        ttl
+    )
+    .execute(&mut **tx)
+    .await?
+    .last_insert_rowid();
+    jobs::enqueue(&mut **tx, &Job::VerificationCode { id }).await
+}
+
+/// Texts a code, from the bot number `sender`, unless it's been replaced, cancelled or has
+/// expired since. A retry sends a new code, and only that one works.
+pub async fn send(pool: &Pool<Sqlite>, sender: &str, id: i64) -> Result<()> {
+    let Some(message) = compose(pool, id).await? else {
+        return Ok(());
+    };
+    for (message, result) in outbound::send_all(pool, sender, vec![message]).await? {
+        if let Err(error) = result {
+            warn!(
+                "Couldn't send a verification code to {}",
+                pii::redact(&message.to)
+            );
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// Makes up a new code and stores its hash, returning the text it goes out in
+pub async fn compose(pool: &Pool<Sqlite>, id: i64) -> Result<Option<Outgoing>> {
+    let Some(pending) = query!(
+        "SELECT v.purpose, v.send_to, v.salt, u.name, u.lang FROM verification_codes v
+         JOIN users u ON u.number = v.number
+         WHERE v.id = ? AND v.expires_at > unixepoch()",
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let purpose = Purpose::from_str(&pending.purpose)?;
+    let lang: Lang = pending.lang.parse()?;
+    let code = format!("{:06}", thread_rng().gen_range(0..1_000_000));
+    let code_hash = hash(&pending.salt, &code);
+    query!(
+        "UPDATE verification_codes SET code_hash = ? WHERE id = ?",
+        code_hash,
+        id
+    )
+    .execute(pool)
+    .await?;
+    Ok(Some(Outgoing {
+        to: pii::open(&pending.send_to)?,
+        body: purpose.message(lang, &pii::open(&pending.name)?, &code),
+    }))
+}
+
+/// Checks a code `number` (sealed) typed in, using it up if it's right
+pub async fn check(
+    pool: &Pool<Sqlite>,
+    number: &str,
+    purpose: Purpose,
+    code: &str,
+) -> Result<Check> {
+    let purpose_name = purpose.as_str();
+    let Some(pending) = query!(
+        r#"SELECT id as "id!", send_to, salt, code_hash, attempts,
+            expires_at <= unixepoch() as "expired!: bool"
+         FROM verification_codes WHERE number = ? AND purpose = ?"#,
+        number,
+        purpose_name
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(Check::NotStarted);
+    };
+    let delete = || query!("DELETE FROM verification_codes WHERE id = ?", pending.id);
+    if pending.expired {
+        delete().execute(pool).await?;
+        return Ok(Check::Expired);
+    }
+    if pending
+        .code_hash
+        .as_deref()
+        .is_some_and(|stored| matches(&pending.salt, code.trim(), stored))
+    {
+        delete().execute(pool).await?;
+        return Ok(Check::Valid {
+            sent_to: pending.send_to,
+        });
+    }
+    let attempts = pending.attempts + 1;
+    if attempts >= MAX_ATTEMPTS {
+        delete().execute(pool).await?;
+        return Ok(Check::TooManyAttempts);
+    }
+    query!(
+        "UPDATE verification_codes SET attempts = ? WHERE id = ?",
+        attempts,
+        pending.id
+    )
+    .execute(pool)
+    .await?;
+    Ok(Check::Wrong {
+        left: MAX_ATTEMPTS - attempts,
+    })
+}
+
+/// Throws away `number`'s code for `purpose`. False if they didn't have one.
+pub async fn cancel(
+    executor: impl SqliteExecutor<'_>,
+    number: &str,
+    purpose: Purpose,
+) -> Result<bool> {
+    let purpose = purpose.as_str();
+    Ok(query!(
+        "DELETE FROM verification_codes WHERE number = ? AND purpose = ?",
+        number,
+        purpose
+    )
+    .execute(executor)
+    .await?
+    .rows_affected()
+        > 0)
+}
+
+/// Whether `text` looks like a code, as opposed to whatever else a command might take
+pub fn is_code(text: &str) -> bool {
+    let text = text.trim();
+    text.len() == 6 && text.chars().all(|c| c.is_ascii_digit())
+}
+
+#[test]
+fn hashes() {
+    let (first, second) = (salt(), salt());
+    let stored = hash(&first, "123456");
+    assert!(matches(&first, "123456", &stored));
+    assert!(!matches(&first, "123457", &stored));
+    assert!(!matches(&second, "123456", &stored));
+    assert!(!matches(&first, "123456", ""));
+
+    // With a key, it's not the plain hash anyone could work out from the salt
+    pii::use_test_key();
+    let keyed = hash(&first, "123456");
+    assert_ne!(keyed, stored);
+    assert!(matches(&first, "123456", &keyed));
+}
@@ -0,0 +1,229 @@
+//! Reading times like "tomorrow 9am" or "in 2 hours" out of messages, in English or Spanish.
+
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Weekday};
+
+use crate::search::fold;
+
+/// When a day is given without a time
+const DEFAULT_TIME: (u32, u32) = (9, 0);
+
+/// Splits a command like "me tomorrow 9:30: call mom" into the part before the colon and the
+/// trimmed message after it. The colon is the first one not starting the minutes of a time.
+pub fn split_message(text: &str) -> Option<(&str, &str)> {
+    text.char_indices()
+        .find(|(i, c)| {
+            *c == ':'
+                && !text[i + 1..]
+                    .chars()
+                    .next()
+                    .is_some_and(|next| next.is_ascii_digit())
+        })
+        .map(|(i, _)| (&text[..i], text[i + 1..].trim()))
+}
+
+/// The moment `text` describes, relative to `now` and in its time zone.
+///
+/// Understands:
+/// - "in 10 minutes", "in 2 hours", "en 3 días", ...
+/// - a day ("today", "tomorrow", "friday", "mañana", "viernes"), a time ("9am", "9:30 pm",
+///   "21:00", "noon"), or both, in either order
+///
+/// A day without a time means 9am. A time without a day means the next time the clock reads
+/// that, as does a weekday without a time. Returns None if any of the text isn't understood.
+pub fn parse<Tz: TimeZone>(text: &str, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+    let folded = fold(text);
+    let words = join_meridiems(
+        folded
+            .split_whitespace()
+            .filter(|word| !FILLER.contains(word))
+            .collect(),
+    );
+    match words.as_slice() {
+        [] => None,
+        [first, amount, unit] if ["in", "en"].contains(&first.as_str()) => now
+            .clone()
+            .checked_add_signed(duration(amount.parse().ok()?, unit)?),
+        _ => {
+            let mut day = None;
+            let mut time = None;
+            for word in &words {
+                if let (None, Some(offset)) = (day, day_offset(word, now.weekday())) {
+                    day = Some(offset);
+                } else if let (None, Some(t)) = (time, parse_time(word)) {
+                    time = Some(t);
+                } else {
+                    return None;
+                }
+            }
+            let (mut days, explicit_day) = match day {
+                Some(Day::Offset(days)) => (days, true),
+                Some(Day::Weekday(days)) => (days, false),
+                None => (0, false),
+            };
+            let time = time.unwrap_or_else(|| {
+                NaiveTime::from_hms_opt(DEFAULT_TIME.0, DEFAULT_TIME.1, 0).unwrap()
+            });
+            let at = |days: i64| {
+                let date = now
+                    .date_naive()
+                    .checked_add_signed(Duration::try_days(days)?)?;
+                now.timezone()
+                    .from_local_datetime(&date.and_time(time))
+                    .earliest()
+            };
+            let mut result = at(days)?;
+            if !explicit_day && result <= *now {
+                days += if day.is_some() { 7 } else { 1 };
+                result = at(days)?;
+            }
+            Some(result)
+        }
+    }
+}
+
+/// Words that can be left out without changing the meaning
+const FILLER: &[&str] = &["at", "on", "a", "la", "las", "el"];
+
+/// Turns "9 pm" into "9pm"
+fn join_meridiems(words: Vec<&str>) -> Vec<String> {
+    let mut joined: Vec<String> = Vec::new();
+    for word in words {
+        match joined.last_mut() {
+            Some(last)
+                if meridiem(word).is_some_and(|(_, rest)| rest.is_empty())
+                    && last.starts_with(|c: char| c.is_ascii_digit()) =>
+            {
+                last.push_str(word)
+            }
+            _ => joined.push(word.to_string()),
+        }
+    }
+    joined
+}
+
+/// None for units it doesn't know, and amounts too big to be a time
+fn duration(amount: i64, unit: &str) -> Option<Duration> {
+    match unit {
+        "minute" | "minutes" | "min" | "mins" | "minuto" | "minutos" => {
+            Duration::try_minutes(amount)
+        }
+        "hour" | "hours" | "hr" | "hrs" | "hora" | "horas" => Duration::try_hours(amount),
+        "day" | "days" | "dia" | "dias" => Duration::try_days(amount),
+        "week" | "weeks" | "semana" | "semanas" => Duration::try_weeks(amount),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Day {
+    /// Named outright ("today", "tomorrow")
+    Offset(i64),
+    /// Days until the next such weekday, which is 0 if it's today
+    Weekday(i64),
+}
+
+fn day_offset(word: &str, today: Weekday) -> Option<Day> {
+    let weekday = match word {
+        "today" | "hoy" => return Some(Day::Offset(0)),
+        "tomorrow" | "manana" => return Some(Day::Offset(1)),
+        "monday" | "mon" | "lunes" => Weekday::Mon,
+        "tuesday" | "tue" | "tues" | "martes" => Weekday::Tue,
+        "wednesday" | "wed" | "miercoles" => Weekday::Wed,
+        "thursday" | "thu" | "thurs" | "jueves" => Weekday::Thu,
+        "friday" | "fri" | "viernes" => Weekday::Fri,
+        "saturday" | "sat" | "sabado" => Weekday::Sat,
+        "sunday" | "sun" | "domingo" => Weekday::Sun,
+        _ => return None,
+    };
+    Some(Day::Weekday(
+        (weekday.num_days_from_monday() as i64 - today.num_days_from_monday() as i64).rem_euclid(7),
+    ))
+}
+
+/// Splits "9pm" into (true, "9"), i.e. (is afternoon, rest)
+fn meridiem(word: &str) -> Option<(bool, &str)> {
+    for (suffix, pm) in [("am", false), ("a.m.", false), ("pm", true), ("p.m.", true)] {
+        if let Some(rest) = word.strip_suffix(suffix) {
+            return Some((pm, rest));
+        }
+    }
+    None
+}
+
+fn parse_time(word: &str) -> Option<NaiveTime> {
+    if ["noon", "mediodia"].contains(&word) {
+        return NaiveTime::from_hms_opt(12, 0, 0);
+    }
+    let (pm, clock) = match meridiem(word) {
+        Some((pm, clock)) => (Some(pm), clock),
+        None => (None, word),
+    };
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) if minute.len() == 2 => (hour, minute.parse().ok()?),
+        Some(_) => return None,
+        // A bare number is too likely to be something other than a time
+        None if pm.is_none() => return None,
+        None => (clock, 0),
+    };
+    let hour: u32 = hour.parse().ok()?;
+    let hour = match pm {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(pm) => hour % 12 + if pm { 12 } else { 0 },
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn parses_times() {
+        // A Wednesday
+        let now = Utc.with_ymd_and_hms(2024, 12, 18, 14, 30, 0).unwrap();
+        let at = |y, mo, d, h, mi| Some(Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap());
+        assert_eq!(parse("in 10 minutes", &now), at(2024, 12, 18, 14, 40));
+        assert_eq!(parse("en 2 horas", &now), at(2024, 12, 18, 16, 30));
+        assert_eq!(parse("tomorrow 9am", &now), at(2024, 12, 19, 9, 0));
+        assert_eq!(
+            parse("Mañana a las 9:30 pm", &now),
+            at(2024, 12, 19, 21, 30)
+        );
+        assert_eq!(parse("tomorrow", &now), at(2024, 12, 19, 9, 0));
+        assert_eq!(parse("5 pm", &now), at(2024, 12, 18, 17, 0));
+        // Already past today
+        assert_eq!(parse("noon", &now), at(2024, 12, 19, 12, 0));
+        assert_eq!(parse("today 8am", &now), at(2024, 12, 18, 8, 0));
+        assert_eq!(parse("friday", &now), at(2024, 12, 20, 9, 0));
+        assert_eq!(parse("at 21:15 on Sábado", &now), at(2024, 12, 21, 21, 15));
+        assert_eq!(parse("wednesday 3pm", &now), at(2024, 12, 18, 15, 0));
+        assert_eq!(parse("wednesday 2pm", &now), at(2024, 12, 25, 14, 0));
+        for nonsense in [
+            "",
+            "9",
+            "13pm",
+            "in a while",
+            "tomorrow tomorrow",
+            "someday",
+            "in 99999999999 days",
+            "in 9223372036854775807 minutes",
+        ] {
+            assert_eq!(parse(nonsense, &now), None, "{nonsense}");
+        }
+    }
+
+    #[test]
+    fn splits_messages() {
+        assert_eq!(
+            split_message("me tomorrow 9:30: call mom: soon"),
+            Some(("me tomorrow 9:30", "call mom: soon"))
+        );
+        assert_eq!(
+            split_message("family 7pm:dinner"),
+            Some(("family 7pm", "dinner"))
+        );
+        assert_eq!(split_message("me at 9:30"), None);
+    }
+}